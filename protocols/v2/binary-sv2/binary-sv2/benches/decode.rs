@@ -0,0 +1,61 @@
+use binary_sv2::{
+    binary_codec_sv2,
+    decodable::{DecodableField, FieldMarker},
+    from_bytes, to_bytes, Decodable, Deserialize, Error, Serialize,
+};
+use core::convert::TryInto;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// Same layout of SubmitSharesStandard, every field has a fixed size so the derived from_bytes is
+// the specialized one
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct SubmitShares {
+    channel_id: u32,
+    sequence_number: u32,
+    job_id: u32,
+    nonce: u32,
+    ntime: u32,
+    version: u32,
+}
+
+// Decoded with the default field by field `Decodable::from_bytes`
+struct Generic(SubmitShares);
+
+impl<'decoder> Decodable<'decoder> for Generic {
+    fn get_structure(data: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+        SubmitShares::get_structure(data)
+    }
+
+    fn from_decoded_fields(data: Vec<DecodableField<'decoder>>) -> Result<Self, Error> {
+        SubmitShares::from_decoded_fields(data).map(Generic)
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let message = SubmitShares {
+        channel_id: 1,
+        sequence_number: 2,
+        job_id: 3,
+        nonce: 4,
+        ntime: 5,
+        version: 6,
+    };
+    let mut bytes = to_bytes(message).unwrap();
+
+    c.bench_function("decode fixed size struct specialized", |b| {
+        b.iter(|| {
+            let decoded: SubmitShares = from_bytes(black_box(&mut bytes[..])).unwrap();
+            black_box(decoded)
+        })
+    });
+
+    c.bench_function("decode fixed size struct generic", |b| {
+        b.iter(|| {
+            let decoded: Generic = from_bytes(black_box(&mut bytes[..])).unwrap();
+            black_box(decoded)
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
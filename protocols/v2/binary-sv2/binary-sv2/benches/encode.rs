@@ -0,0 +1,68 @@
+use binary_sv2::{
+    binary_codec_sv2, to_bytes, to_bytes_ref, to_bytes_unzeroed, Deserialize, Seq0255, Serialize,
+    B016M, B064K, U256,
+};
+use core::convert::TryInto;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// Same layout of NewExtendedMiningJob
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct NewExtendedJob<'decoder> {
+    channel_id: u32,
+    job_id: u32,
+    future_job: bool,
+    version: u32,
+    version_rolling_allowed: bool,
+    merkle_path: Seq0255<'decoder, U256<'decoder>>,
+    coinbase_tx_prefix: B064K<'decoder>,
+    coinbase_tx_suffix: B064K<'decoder>,
+}
+
+// Downstreams that receive the same job
+const DOWNSTREAMS: usize = 100;
+
+// Size of the large payload
+const PAYLOAD_SIZE: usize = 1024 * 1024;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let merkle_path: Vec<U256> = (0..12).map(|i| [i; 32].into()).collect();
+    let job = NewExtendedJob {
+        channel_id: 1,
+        job_id: 2,
+        future_job: false,
+        version: 3,
+        version_rolling_allowed: true,
+        merkle_path: Seq0255::new(merkle_path).unwrap(),
+        coinbase_tx_prefix: vec![4; 64].try_into().unwrap(),
+        coinbase_tx_suffix: vec![5; 128].try_into().unwrap(),
+    };
+
+    c.bench_function("broadcast job clone and encode for each downstream", |b| {
+        b.iter(|| {
+            for _ in 0..DOWNSTREAMS {
+                black_box(to_bytes(black_box(&job).clone()).unwrap());
+            }
+        })
+    });
+
+    c.bench_function("broadcast job borrow and encode for each downstream", |b| {
+        b.iter(|| {
+            for _ in 0..DOWNSTREAMS {
+                black_box(to_bytes_ref(black_box(&job)).unwrap());
+            }
+        })
+    });
+
+    let payload: B016M = vec![6; PAYLOAD_SIZE].try_into().unwrap();
+
+    c.bench_function("encode 1 MB payload zeroed", |b| {
+        b.iter(|| black_box(to_bytes(black_box(&payload).clone()).unwrap()))
+    });
+
+    c.bench_function("encode 1 MB payload unzeroed", |b| {
+        b.iter(|| black_box(to_bytes_unzeroed(black_box(&payload).clone()).unwrap()))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
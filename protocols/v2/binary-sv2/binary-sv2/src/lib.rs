@@ -92,6 +92,244 @@ mod test {
 
             assert_eq!(deserialized, expected);
         }
+
+        #[cfg(not(feature = "with_serde"))]
+        #[test]
+        fn test_non_finite() {
+            for c in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+                let test = Test {
+                    c,
+                    a: 9,
+                    b: 67_u32.try_into().unwrap(),
+                };
+                assert!(matches!(to_bytes(test.clone()), Err(Error::NonFiniteFloat)));
+                assert!(matches!(
+                    to_bytes_unzeroed(test.clone()),
+                    Err(Error::NonFiniteFloat)
+                ));
+                assert!(test.to_writer(&mut Vec::new()).is_err());
+            }
+        }
+
+        #[cfg(not(feature = "with_serde"))]
+        #[test]
+        fn test_finite() {
+            let mut bytes = to_bytes(12_345.5_f32).unwrap();
+            let deserialized: f32 = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, 12_345.5);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_signed {
+        use super::*;
+
+        #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            a: u8,
+            b: i32,
+            c: i64,
+        }
+
+        #[test]
+        fn test_struct() {
+            for (b, c) in [(0, 0), (-1, -1), (i32::MIN, i64::MIN), (i32::MAX, i64::MAX)] {
+                let expected = Test { a: 9, b, c };
+                let mut bytes = to_bytes(expected.clone()).unwrap();
+                assert_eq!(bytes.len(), 13);
+                let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+                assert_eq!(deserialized, expected);
+            }
+        }
+
+        #[test]
+        fn test_little_endian() {
+            assert_eq!(to_bytes(-2_i32).unwrap(), vec![0xfe, 0xff, 0xff, 0xff]);
+            assert_eq!(to_bytes(0x0102_i64).unwrap(), vec![2, 1, 0, 0, 0, 0, 0, 0]);
+            let mut bytes = [0, 0, 0, 0x80];
+            let deserialized: i32 = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, i32::MIN);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_to_bytes_unzeroed {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u8,
+            b: B0255<'decoder>,
+            c: B064K<'decoder>,
+            d: B016M<'decoder>,
+            e: Seq0255<'decoder, U256<'decoder>>,
+        }
+
+        #[test]
+        fn test_to_bytes_unzeroed() {
+            let test = Test {
+                a: 1,
+                b: vec![2; 255].try_into().unwrap(),
+                c: vec![3; 300].try_into().unwrap(),
+                d: vec![4; 70_000].try_into().unwrap(),
+                e: Seq0255::new(vec![[5; 32].into()]).unwrap(),
+            };
+            let bytes = to_bytes_unzeroed(test.clone()).unwrap();
+            assert_eq!(bytes, to_bytes(test.clone()).unwrap());
+
+            let mut bytes = bytes;
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, test);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_to_bytes_ref {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Inner<'decoder> {
+            a: U24,
+            b: Seq064K<'decoder, u16>,
+        }
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u32,
+            b: bool,
+            c: B0255<'decoder>,
+            d: B064K<'decoder>,
+            e: Seq0255<'decoder, U256<'decoder>>,
+            f: Inner<'decoder>,
+            g: i64,
+        }
+
+        #[test]
+        fn test_to_bytes_ref() {
+            let mut borrowed = [6; 32];
+            let test = Test {
+                a: 1,
+                b: true,
+                c: vec![2; 255].try_into().unwrap(),
+                d: vec![3; 300].try_into().unwrap(),
+                e: Seq0255::new(vec![[4; 32].into(), (&mut borrowed).into()]).unwrap(),
+                f: Inner {
+                    a: 5_u32.try_into().unwrap(),
+                    b: Seq064K::new(vec![7, 8]).unwrap(),
+                },
+                g: -9,
+            };
+            let bytes = to_bytes_ref(&test).unwrap();
+            assert_eq!(bytes, to_bytes(test.clone()).unwrap());
+
+            let mut bytes = bytes;
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, test);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_into_static {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: U256<'decoder>,
+            b: Str0255<'decoder>,
+            c: B064K<'decoder>,
+            d: Seq0255<'decoder, U256<'decoder>>,
+            e: Seq064K<'decoder, u32>,
+        }
+
+        #[test]
+        fn test_into_static() {
+            let expected = Test {
+                a: [1; 32].into(),
+                b: "user".to_string().try_into().unwrap(),
+                c: vec![2; 300].try_into().unwrap(),
+                d: Seq0255::new(vec![[3; 32].into(), [4; 32].into()]).unwrap(),
+                e: Seq064K::new(vec![5, 6, 7]).unwrap(),
+            };
+            let expected_bytes = to_bytes(expected.clone()).unwrap();
+
+            let static_test: Test<'static> = {
+                // Decoded fields borrow `bytes`
+                let mut bytes = expected_bytes.clone();
+                let decoded: Test = from_bytes(&mut bytes[..]).unwrap();
+                Test {
+                    a: decoded.a.into_static(),
+                    b: decoded.b.into_static(),
+                    c: decoded.c.into_static(),
+                    d: decoded.d.into_static(),
+                    e: decoded.e.into_static(),
+                }
+            };
+
+            assert_eq!(to_bytes(static_test).unwrap(), expected_bytes);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_seq_iter {
+        use super::*;
+
+        #[test]
+        fn test_seq0255_iter() {
+            let seq: Seq0255<u32> = Seq0255::new(vec![1, 2, 3]).unwrap();
+            assert_eq!(seq.len(), 3);
+            assert!(!seq.is_empty());
+            assert_eq!(seq.iter().sum::<u32>(), 6);
+            let mut refs = Vec::new();
+            for x in &seq {
+                refs.push(*x);
+            }
+            assert_eq!(refs, vec![1, 2, 3]);
+            assert_eq!(seq.into_iter().collect::<Vec<u32>>(), vec![1, 2, 3]);
+        }
+
+        #[test]
+        fn test_seq064k_iter() {
+            let seq: Seq064K<U256> = Seq064K::new(vec![[1; 32].into(), [2; 32].into()]).unwrap();
+            assert_eq!(seq.len(), 2);
+            let bytes: Vec<Vec<u8>> = seq.iter().map(|x| x.to_vec()).collect();
+            assert_eq!(bytes, seq.to_vec());
+            assert_eq!(seq.into_iter().count(), 2);
+            assert!(Seq064K::<u32>::new(vec![]).unwrap().is_empty());
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_hash {
+        use super::*;
+        use core::convert::TryInto;
+        use std::collections::HashMap;
+
+        #[test]
+        fn test_ref_and_owned_are_the_same_key() {
+            let mut prev_hash = [7; 32];
+            let owned: U256 = [7; 32].into();
+            let ref_: U256 = (&mut prev_hash).into();
+            assert_eq!(owned, ref_);
+
+            let mut jobs = HashMap::new();
+            jobs.insert(owned, 1);
+            assert_eq!(jobs.get(&ref_), Some(&1));
+
+            let mut user = *b"user";
+            let owned: Str0255 = "user".to_string().try_into().unwrap();
+            let ref_: Str0255 = (&mut user[..]).try_into().unwrap();
+            let mut users = HashMap::new();
+            users.insert(ref_, 2);
+            assert_eq!(users.get(&owned), Some(&2));
+
+            let mut identities = HashMap::new();
+            identities.insert(Seq0255::new(vec![owned]).unwrap(), 3);
+            let key = Seq0255::new(vec!["user".to_string().try_into().unwrap()]).unwrap();
+            assert_eq!(identities.get(&key), Some(&3));
+        }
     }
 
     mod test_b0255 {
@@ -165,6 +403,31 @@ mod test {
 
             assert_eq!(deserialized, expected);
         }
+
+        #[cfg(not(feature = "with_serde"))]
+        #[test]
+        fn test_u256_ord() {
+            let zero: U256 = [0; 32].into();
+            let max: U256 = [0xff; 32].into();
+            let mut high_bit = [0; 32];
+            high_bit[0] = 0x80;
+            let high_bit: U256 = high_bit.into();
+            let mut one = [0; 32];
+            one[31] = 1;
+            let one: U256 = one.into();
+
+            assert!(zero < one);
+            assert!(one < high_bit);
+            assert!(high_bit < max);
+            assert_eq!(max.cmp(&[0xff; 32].into()), core::cmp::Ordering::Equal);
+            assert_eq!(zero.clone().max(max.clone()), max);
+            assert_eq!(high_bit.clone().min(zero.clone()), zero);
+
+            // Ref and Owned with the same bytes are equal
+            let mut bytes = [0xff; 32];
+            let max_ref: U256 = (&mut bytes).into();
+            assert_eq!(max_ref.cmp(&max), core::cmp::Ordering::Equal);
+        }
     }
 
     mod test_signature {
@@ -684,30 +947,1215 @@ mod test {
             assert_eq!(deserialized, expected);
         }
     }
-    mod test_seq_0255_in_struct {
+    #[cfg(not(feature = "with_serde"))]
+    mod test_from_array {
         use super::*;
 
-        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        fn bytes() -> [u8; 32] {
+            let mut bytes = [0_u8; 32];
+            for (i, b) in bytes.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+            bytes
+        }
+
+        #[test]
+        fn test_u256_from_array() {
+            let mut array = bytes();
+            let owned = U256::from(array);
+            let from_ref = U256::from(&array);
+            assert_eq!(owned.to_vec(), array.to_vec());
+            assert_eq!(from_ref.to_vec(), array.to_vec());
+            assert_eq!(to_bytes(owned).unwrap().len(), 32);
+            assert_eq!(to_bytes(from_ref).unwrap(), array.to_vec());
+
+            let borrowed = U256::from(&mut array);
+            assert_eq!(to_bytes(borrowed).unwrap(), bytes().to_vec());
+        }
+
+        #[test]
+        fn test_b032_from_array() {
+            let mut array = bytes();
+            let owned = B032::from(array);
+            let from_ref = B032::from(&array);
+            assert_eq!(owned.to_vec(), array.to_vec());
+            assert_eq!(from_ref.to_vec(), array.to_vec());
+            // B032 has a 1 byte length header
+            let encoded = to_bytes(owned).unwrap();
+            assert_eq!(encoded.len(), 33);
+            assert_eq!(encoded[0], 32);
+            assert_eq!(&encoded[1..], &array[..]);
+            assert_eq!(to_bytes(from_ref).unwrap(), encoded);
+
+            let borrowed = B032::from(&mut array);
+            assert_eq!(to_bytes(borrowed).unwrap(), encoded);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_seq_count {
+        use super::*;
+
+        #[derive(Deserialize, Serialize, Debug, Clone)]
         struct Test<'decoder> {
-            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            a: Seq0255<'decoder, U256<'decoder>>,
+        }
+
+        #[derive(Deserialize, Serialize, Debug, Clone)]
+        struct Test64K<'decoder> {
+            a: Seq064K<'decoder, U256<'decoder>>,
+        }
+
+        #[test]
+        fn test_seq_exceeds_max_size() {
+            let u256 = || U256::from([0; 32]);
+            let seq = Seq0255::new((0..256).map(|_| u256()).collect());
+            assert!(matches!(seq, Err(Error::SeqExceedsMaxSize)));
+            let seq = Seq064K::new((0..65536).map(|_| u256()).collect());
+            assert!(matches!(seq, Err(Error::SeqExceedsMaxSize)));
+        }
+
+        // The 1 and 2 bytes headers can not declare 256 and 65536 elements, the biggest count
+        // that they can declare is used with less elements than declared
+        #[test]
+        fn test_seq0255_count_bigger_than_data() {
+            let mut data = vec![255];
+            data.extend([1; 32]);
+            let seq = Seq0255::<U256>::from_bytes(&mut data[..]);
+            assert!(matches!(seq, Err(Error::OutOfBound)));
+            let message = from_bytes::<Test>(&mut data[..]);
+            assert!(matches!(message, Err(Error::OutOfBound)));
+        }
+
+        #[test]
+        fn test_seq064k_count_bigger_than_data() {
+            let mut data = vec![255, 255];
+            data.extend([1; 32]);
+            let seq = Seq064K::<U256>::from_bytes(&mut data[..]);
+            assert!(matches!(seq, Err(Error::OutOfBound)));
+            let message = from_bytes::<Test64K>(&mut data[..]);
+            assert!(matches!(message, Err(Error::OutOfBound)));
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_decode_fields {
+        use super::*;
+
+        fn payload() -> Vec<u8> {
+            let mut data = 42_u32.to_le_bytes().to_vec();
+            data.extend([9; 32]);
+            data.extend([3, 1, 2, 3]);
+            data
+        }
+
+        #[test]
+        fn test_decode_u32_u256_b0255() {
+            let mut data = payload();
+            let (a, b, c) = decode_fields!(&mut data[..], u32, U256, B0255).unwrap();
+            assert_eq!(a, 42);
+            assert_eq!(b.to_vec(), vec![9; 32]);
+            assert_eq!(c.to_vec(), vec![1, 2, 3]);
+        }
+
+        #[test]
+        fn test_decode_fields_error_index() {
+            let mut data = payload();
+            let len = data.len();
+            let (field, error) =
+                decode_fields!(&mut data[..len - 1], u32, U256, B0255).unwrap_err();
+            assert_eq!(field, 2);
+            assert!(matches!(error, Error::OutOfBound));
+            let (field, _) = decode_fields!(&mut data[..20], u32, U256, B0255).unwrap_err();
+            assert_eq!(field, 1);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_decode_limits {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, Debug, Clone)]
+        struct Level1<'decoder> {
             a: u8,
-            b: Seq0255<'decoder, u8>,
-            c: u32,
+            b: Seq064K<'decoder, u32>,
+        }
+
+        #[derive(Deserialize, Serialize, Debug, Clone)]
+        struct Level2<'decoder> {
+            a: u8,
+            b: Level1<'decoder>,
+        }
+
+        #[derive(Deserialize, Serialize, Debug, Clone)]
+        struct Level3<'decoder> {
+            a: u8,
+            b: Level2<'decoder>,
+        }
+
+        // 3 u8 followed by a Seq064K that declare `count` u32
+        fn nested_payload(count: u16) -> Vec<u8> {
+            let mut data = vec![1, 2, 3];
+            data.extend(count.to_le_bytes());
+            data.extend(vec![7; count as usize * 4]);
+            data
         }
 
         #[test]
-        fn test_seq_0255_in_struct() {
+        fn test_default_limits() {
+            let mut data = nested_payload(10);
+            let message: Level3 =
+                from_bytes_limited(&mut data[..], DecodeLimits::default()).unwrap();
+            assert_eq!(message.b.b.a, 3);
+        }
+
+        #[test]
+        fn test_max_depth() {
+            let limits = DecodeLimits {
+                max_depth: 3,
+                ..DecodeLimits::default()
+            };
+            let mut data = nested_payload(10);
+            let message = from_bytes_limited::<Level3>(&mut data[..], limits);
+            assert!(matches!(message, Err(Error::DecodeLimitExceeded)));
+            let message = from_bytes_limited::<Level2>(&mut data[1..], limits);
+            assert!(message.is_ok());
+        }
+
+        #[test]
+        fn test_max_elements() {
+            let limits = DecodeLimits {
+                max_elements: 1000,
+                ..DecodeLimits::default()
+            };
+            let mut data = nested_payload(1000);
+            let message = from_bytes_limited::<Level3>(&mut data[..], limits);
+            assert!(matches!(message, Err(Error::DecodeLimitExceeded)));
+            let mut data = nested_payload(990);
+            let message = from_bytes_limited::<Level3>(&mut data[..], limits);
+            assert!(message.is_ok());
+        }
+
+        #[test]
+        fn test_max_total_bytes() {
+            let limits = DecodeLimits {
+                max_total_bytes: 100,
+                ..DecodeLimits::default()
+            };
+            let mut data = nested_payload(30);
+            let message = from_bytes_limited::<Level3>(&mut data[..], limits);
+            assert!(matches!(message, Err(Error::DecodeLimitExceeded)));
+        }
+
+        #[test]
+        fn test_limits_checked_while_walking() {
+            let limits = DecodeLimits {
+                max_elements: 1000,
+                ..DecodeLimits::default()
+            };
+            // The sequence declare 65535 u32 but the payload end after the length, the limit
+            // must be hit before the markers of the elements are walked
+            let mut data = vec![1, 2, 3, 0xff, 0xff];
+            let message = from_bytes_limited::<Level3>(&mut data[..], limits);
+            assert!(matches!(message, Err(Error::DecodeLimitExceeded)));
+
+            let mut budget = DecodeBudget::new(limits);
+            let structure = Level3::get_structure_limited(&data, &mut budget, 0);
+            assert!(matches!(structure, Err(Error::DecodeLimitExceeded)));
+
+            let mut budget = DecodeBudget::new(DecodeLimits::default());
+            let structure = Level3::get_structure_limited(&data, &mut budget, 0).unwrap();
+            assert_eq!(structure.len(), 2);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_to_array {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+        struct Test {
+            a: u32,
+            b: u8,
+            c: U24,
+        }
+
+        fn test_struct() -> Test {
+            Test {
+                a: 456,
+                b: 9,
+                c: 67_u32.try_into().unwrap(),
+            }
+        }
+
+        #[test]
+        fn test_to_array() {
+            let (mut array, len) = test_struct().to_array::<64>().unwrap();
+            assert_eq!(len, 8);
+            assert_eq!(&array[..len], &to_bytes(test_struct()).unwrap()[..]);
+            let decoded: Test = from_bytes(&mut array[..len]).unwrap();
+            assert_eq!(decoded, test_struct());
+        }
+
+        #[test]
+        fn test_to_array_too_small() {
+            match test_struct().to_array::<4>() {
+                Err(Error::WriteError(4, 8)) => (),
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_checked_constructor {
+        use super::*;
+        use core::convert::TryFrom;
+
+        fn assert_exceeds_max_size(e: Error, max: usize, len: usize) {
+            match e {
+                Error::ValueExceedsMaxSize(false, _, _, max_size, _, bad_len) => {
+                    assert_eq!(max_size, max);
+                    assert_eq!(bad_len, len);
+                }
+                e => panic!("unexpected error {:?}", e),
+            }
+        }
+
+        #[test]
+        fn test_b0255_oversized() {
+            let mut data = vec![0; 256];
+            assert_exceeds_max_size(B0255::new_ref(&mut data[..]).unwrap_err(), 255, 256);
+            assert_exceeds_max_size(B0255::new_owned(data.clone()).unwrap_err(), 255, 256);
+            assert!(B0255::new_ref(&mut data[..255]).is_ok());
+        }
+
+        #[test]
+        fn test_b064k_oversized() {
+            let mut data = vec![0; 65536];
+            assert_exceeds_max_size(B064K::new_ref(&mut data[..]).unwrap_err(), 65535, 65536);
+            assert_exceeds_max_size(B064K::new_owned(data.clone()).unwrap_err(), 65535, 65536);
+            assert!(B064K::new_ref(&mut data[..65535]).is_ok());
+        }
+
+        #[test]
+        fn test_b016m_oversized() {
+            let max = 2_usize.pow(24) - 1;
+            let mut data = vec![0; max + 1];
+            assert_exceeds_max_size(B016M::new_ref(&mut data[..]).unwrap_err(), max, max + 1);
+            let e = B016M::try_from(&mut data[..]).unwrap_err();
+            assert_exceeds_max_size(e, max, max + 1);
+            assert!(B016M::new_ref(&mut data[..max]).is_ok());
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_size_of {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[test]
+        fn test_size_of_bytes() {
+            let b032: B032 = vec![1; 20].try_into().unwrap();
+            assert_eq!(size_of_b032(20), b032.get_size());
+            let b0255: B0255 = vec![1; 200].try_into().unwrap();
+            assert_eq!(size_of_b0255(200), b0255.get_size());
+            let b064k: B064K = vec![1; 1000].try_into().unwrap();
+            assert_eq!(size_of_b064k(1000), b064k.get_size());
+            let b016m: B016M = vec![1; 70000].try_into().unwrap();
+            assert_eq!(size_of_b016m(70000), b016m.get_size());
+        }
+
+        #[test]
+        fn test_size_of_seq() {
+            let u256: Vec<U256> = (0..10).map(|_| [0; 32].into()).collect();
+            let seq: Seq0255<U256> = Seq0255::new(u256).unwrap();
+            assert_eq!(size_of_seq0255(10, 32), seq.get_size());
+
+            let b064k: Vec<B064K> = (0..5).map(|_| vec![1; 300].try_into().unwrap()).collect();
+            let seq: Seq064K<B064K> = Seq064K::new(b064k).unwrap();
+            assert_eq!(size_of_seq064k(5, size_of_b064k(300)), seq.get_size());
+            assert_eq!(
+                size_of_seq064k(5, size_of_b064k(300)),
+                to_bytes(seq).unwrap().len()
+            );
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_u32_as_ref {
+        use super::*;
+
+        #[test]
+        fn test_u32_as_ref_conversions() {
+            let v: U32AsRef = 0xdead_beef_u32.into();
+            assert_eq!(v.as_u32(), 0xdead_beef);
+            assert_eq!(u32::from(&v), 0xdead_beef);
+            assert_eq!(u32::from(v), 0xdead_beef);
+        }
+
+        #[test]
+        fn test_u32_as_ref_encode_as_u32() {
+            let as_ref: U32AsRef = 567_u32.into();
+            let bytes_as_ref = to_bytes(as_ref).unwrap();
+            let bytes_u32 = to_bytes(567_u32).unwrap();
+            assert_eq!(bytes_as_ref, bytes_u32);
+        }
+
+        #[test]
+        fn test_u32_as_ref_round_trip() {
+            let mut bytes = to_bytes(98_765_u32).unwrap();
+            let decoded: U32AsRef = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(decoded.as_u32(), 98_765);
+
+            let mut bytes = to_bytes(decoded).unwrap();
+            let decoded: u32 = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(decoded, 98_765);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_multiple_lifetimes {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'a, 'b> {
+            a: B0255<'a>,
+            b: u32,
+            c: U256<'b>,
+        }
+
+        #[test]
+        fn test_multiple_lifetimes() {
+            let mut a = [1_u8; 10];
+            let mut c = [2_u8; 32];
             let expected = Test {
-                a: 89,
-                b: Seq0255::new(vec![]).unwrap(),
-                c: 32,
+                a: (&mut a[..]).try_into().unwrap(),
+                b: 78,
+                c: (&mut c[..]).try_into().unwrap(),
             };
-            let len = expected.get_size();
-            let mut buffer = Vec::new();
-            buffer.resize(len, 0);
-            to_writer(expected.clone(), &mut buffer).unwrap();
-            let deserialized: Test = from_bytes(&mut buffer[..]).unwrap();
+
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            assert_eq!(bytes.len(), expected.get_size());
+
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+
             assert_eq!(deserialized, expected);
         }
     }
+
+    mod test_seq064k_b0255 {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            a: Seq064K<'decoder, B0255<'decoder>>,
+        }
+
+        #[test]
+        fn test_seq064k_b0255() {
+            let mut bytes_1 = [88_u8; 1];
+            let mut bytes_2 = [99_u8; 64];
+            let mut bytes_3 = [220_u8; 255];
+            let mut bytes_4 = [0_u8; 0];
+            let bytes_1: B0255 = (&mut bytes_1[..]).try_into().unwrap();
+            let bytes_2: B0255 = (&mut bytes_2[..]).try_into().unwrap();
+            let bytes_3: B0255 = (&mut bytes_3[..]).try_into().unwrap();
+            let bytes_4: B0255 = (&mut bytes_4[..]).try_into().unwrap();
+
+            let val = vec![bytes_1, bytes_2, bytes_3, bytes_4];
+            let s: Seq064K<B0255> = Seq064K::new(val).unwrap();
+
+            let expected = Test { a: s };
+            // 2 bytes of seq header and 1 byte of header for each element
+            assert_eq!(expected.get_size(), 2 + (1 + 1) + (1 + 64) + (1 + 255) + 1);
+
+            #[cfg(not(feature = "with_serde"))]
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            #[cfg(feature = "with_serde")]
+            let mut bytes = to_bytes(&expected.clone()).unwrap();
+            assert_eq!(bytes.len(), expected.get_size());
+
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+
+            #[cfg(not(feature = "with_serde"))]
+            {
+                let elements = deserialized.a.to_vec();
+                assert_eq!(elements.len(), 4);
+                assert_eq!(elements[0], vec![88_u8; 1]);
+                assert_eq!(elements[1], vec![99_u8; 64]);
+                assert_eq!(elements[2], vec![220_u8; 255]);
+                assert!(elements[3].is_empty());
+            }
+            assert_eq!(deserialized, expected);
+        }
+    }
+
+    mod test_seq064k_b064k {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            a: Seq064K<'decoder, B064K<'decoder>>,
+        }
+
+        #[test]
+        fn test_seq064k_b064k() {
+            let mut bytes_1 = [1_u8; 3];
+            let mut bytes_2 = [2_u8; 300];
+            let mut bytes_3 = vec![3_u8; 65535];
+            let bytes_1: B064K = (&mut bytes_1[..]).try_into().unwrap();
+            let bytes_2: B064K = (&mut bytes_2[..]).try_into().unwrap();
+            let bytes_3: B064K = (&mut bytes_3[..]).try_into().unwrap();
+
+            let val = vec![bytes_1, bytes_2, bytes_3];
+            let s: Seq064K<B064K> = Seq064K::new(val).unwrap();
+
+            let expected = Test { a: s };
+            // 2 bytes of seq header and 2 bytes of header for each element
+            assert_eq!(expected.get_size(), 2 + (2 + 3) + (2 + 300) + (2 + 65535));
+
+            #[cfg(not(feature = "with_serde"))]
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            #[cfg(feature = "with_serde")]
+            let mut bytes = to_bytes(&expected.clone()).unwrap();
+            assert_eq!(bytes.len(), expected.get_size());
+
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+
+            #[cfg(not(feature = "with_serde"))]
+            {
+                let elements = deserialized.a.to_vec();
+                assert_eq!(elements.len(), 3);
+                assert_eq!(elements[0], vec![1_u8; 3]);
+                assert_eq!(elements[1], vec![2_u8; 300]);
+                assert_eq!(elements[2], vec![3_u8; 65535]);
+            }
+            assert_eq!(deserialized, expected);
+        }
+    }
+
+    mod test_seq_0255_in_struct {
+        use super::*;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            #[cfg_attr(feature = "with_serde", serde(borrow))]
+            a: u8,
+            b: Seq0255<'decoder, u8>,
+            c: u32,
+        }
+
+        #[test]
+        fn test_seq_0255_in_struct() {
+            let expected = Test {
+                a: 89,
+                b: Seq0255::new(vec![]).unwrap(),
+                c: 32,
+            };
+            let len = expected.get_size();
+            let mut buffer = Vec::new();
+            buffer.resize(len, 0);
+            to_writer(expected.clone(), &mut buffer).unwrap();
+            let deserialized: Test = from_bytes(&mut buffer[..]).unwrap();
+            assert_eq!(deserialized, expected);
+        }
+    }
+    #[cfg(not(feature = "with_serde"))]
+    mod test_cvec2_from_vec {
+        use super::*;
+
+        #[test]
+        fn test_cvec2_from_vec() {
+            let b1: &[u8] = &[1, 2, 3];
+            let b2: &[u8] = &[];
+            let b3: &[u8] = &[9; 300];
+            let cvecs: Vec<CVec> = vec![b1.into(), b2.into(), b3.into()];
+
+            let mut cvec2: CVec2 = cvecs.into();
+
+            let inner = cvec2.as_mut_slice();
+            assert_eq!(inner.len(), 3);
+            assert_eq!(inner[0].as_mut_slice(), b1);
+            assert_eq!(inner[1].as_mut_slice(), b2);
+            assert_eq!(inner[2].as_mut_slice(), b3);
+
+            free_vec_2(&mut cvec2);
+        }
+    }
+    #[cfg(not(feature = "with_serde"))]
+    mod test_u256_macro {
+        use super::*;
+
+        #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+        struct Test<'decoder> {
+            a: U256<'decoder>,
+        }
+
+        #[test]
+        fn test_u256_macro() {
+            let a = u256!("000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F");
+            let bytes: Vec<u8> = (0..32).collect();
+            assert_eq!(a.to_vec(), bytes);
+
+            let expected = Test { a };
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+
+            assert_eq!(deserialized, expected);
+        }
+    }
+    #[cfg(not(feature = "with_serde"))]
+    mod test_envelope {
+        use super::*;
+
+        #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+        struct TestV1 {
+            a: u32,
+            b: u8,
+        }
+
+        #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+        struct TestV2 {
+            a: u32,
+            b: u8,
+            c: u64,
+        }
+
+        #[test]
+        fn test_envelope() {
+            let expected = TestV1 { a: 456, b: 9 };
+
+            let mut bytes = to_bytes(Envelope(expected.clone())).unwrap();
+            assert_eq!(bytes.len(), 4 + 5);
+            let deserialized: Envelope<TestV1> = from_bytes(&mut bytes[..]).unwrap();
+
+            assert_eq!(deserialized.into_inner(), expected);
+        }
+
+        #[test]
+        fn test_envelope_skip_trailing_bytes() {
+            let new = TestV2 {
+                a: 456,
+                b: 9,
+                c: u64::MAX,
+            };
+            let expected = TestV1 { a: 456, b: 9 };
+
+            let mut bytes = to_bytes(Envelope(new)).unwrap();
+            let deserialized: Envelope<TestV1> = from_bytes(&mut bytes[..]).unwrap();
+
+            assert_eq!(deserialized.into_inner(), expected);
+        }
+
+        #[test]
+        fn test_from_reader() {
+            let first = TestV1 { a: 1, b: 2 };
+            let second = TestV1 { a: 3, b: 4 };
+            let mut stream = to_bytes(Envelope(first.clone())).unwrap();
+            stream.extend(to_bytes(Envelope(second.clone())).unwrap());
+
+            let mut reader = &stream[..];
+            let deserialized: TestV1 = from_reader(&mut reader).unwrap();
+            assert_eq!(deserialized, first);
+            let deserialized: TestV1 = from_reader(&mut reader).unwrap();
+            assert_eq!(deserialized, second);
+            assert!(reader.is_empty());
+        }
+
+        #[test]
+        fn test_from_reader_premature_eof() {
+            let bytes = to_bytes(Envelope(TestV1 { a: 1, b: 2 })).unwrap();
+            // Truncated size prefix and truncated message
+            for len in [2, bytes.len() - 1] {
+                let mut reader = &bytes[..len];
+                let result: Result<TestV1, Error> = from_reader(&mut reader);
+                assert!(matches!(result, Err(Error::OutOfBound)));
+            }
+        }
+
+        #[test]
+        fn test_envelope_from_reader_len_too_big() {
+            // Declare a 4GB envelope followed by a few bytes
+            let bytes = [0xff, 0xff, 0xff, 0xff, 1, 2, 3];
+            let mut reader = &bytes[..];
+            let result = Envelope::<TestV1>::from_reader(&mut reader);
+            assert!(matches!(
+                result,
+                Err(Error::ReadError(len, max)) if len == u32::MAX as usize && max == 2_usize.pow(24) - 1
+            ));
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_fixed_size_struct {
+        use super::*;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u8,
+            b: bool,
+            c: U24,
+            d: u32,
+            e: f32,
+            f: u64,
+            g: U256<'decoder>,
+        }
+
+        // Decoded with the default field by field `Decodable::from_bytes`
+        #[derive(Debug)]
+        struct Generic<'decoder>(Test<'decoder>);
+
+        impl<'decoder> Decodable<'decoder> for Generic<'decoder> {
+            fn get_structure(data: &[u8]) -> Result<Vec<decodable::FieldMarker>, Error> {
+                Test::get_structure(data)
+            }
+
+            fn from_decoded_fields(
+                data: Vec<decodable::DecodableField<'decoder>>,
+            ) -> Result<Self, Error> {
+                Test::from_decoded_fields(data).map(Generic)
+            }
+        }
+
+        fn test_value() -> Test<'static> {
+            Test {
+                a: 7,
+                b: true,
+                c: 9_u32.try_into().unwrap(),
+                d: 567,
+                e: 0.5,
+                f: u64::MAX,
+                g: [3_u8; 32].to_vec().try_into().unwrap(),
+            }
+        }
+
+        #[test]
+        fn test_fixed_size_struct() {
+            let expected = test_value();
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            let mut bytes_2 = bytes.clone();
+
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            let generic: Generic = from_bytes(&mut bytes_2[..]).unwrap();
+
+            assert_eq!(deserialized, expected);
+            assert_eq!(generic.0, expected);
+        }
+
+        #[test]
+        fn test_fixed_size_struct_short_data() {
+            let mut bytes = to_bytes(test_value()).unwrap();
+            let len = bytes.len();
+            let deserialized: Result<Test, _> = from_bytes(&mut bytes[..len - 1]);
+            assert!(matches!(deserialized, Err(Error::OutOfBound)));
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_empty {
+        use super::*;
+        use core::convert::TryInto;
+
+        // Each empty value is the last field so that its header ends exactly at the end of the
+        // buffer
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u8,
+            b: Seq0255<'decoder, u32>,
+            c: Seq064K<'decoder, U256<'decoder>>,
+            d: Str0255<'decoder>,
+            e: B0255<'decoder>,
+            f: B064K<'decoder>,
+            g: B016M<'decoder>,
+        }
+
+        fn empty_value() -> Test<'static> {
+            Test {
+                a: 1,
+                b: Seq0255::new(vec![]).unwrap(),
+                c: Seq064K::new(vec![]).unwrap(),
+                d: vec![].try_into().unwrap(),
+                e: vec![].try_into().unwrap(),
+                f: vec![].try_into().unwrap(),
+                g: vec![].try_into().unwrap(),
+            }
+        }
+
+        #[test]
+        fn test_empty_struct() {
+            let expected = empty_value();
+            let mut bytes = to_bytes(expected.clone()).unwrap();
+            assert_eq!(bytes, vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            let deserialized: Test = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(deserialized, expected);
+        }
+
+        #[test]
+        fn test_empty_struct_from_reader() {
+            let expected = empty_value();
+            let bytes = to_bytes(expected.clone()).unwrap();
+            let deserialized = Test::from_reader(&mut &bytes[..]).unwrap();
+            assert_eq!(deserialized, expected);
+        }
+
+        #[test]
+        fn test_empty_seq0255() {
+            let mut bytes = [0_u8; 1];
+            let seq: Seq0255<u32> = Decodable::from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(seq, Seq0255::new(vec![]).unwrap());
+            let seq: Seq0255<u32> = Decodable::from_reader(&mut &bytes[..]).unwrap();
+            assert_eq!(seq, Seq0255::new(vec![]).unwrap());
+        }
+
+        #[test]
+        fn test_empty_seq064k() {
+            let mut bytes = [0_u8; 2];
+            let seq: Seq064K<U256> = Decodable::from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(seq, Seq064K::new(vec![]).unwrap());
+            let seq: Seq064K<U256> = Decodable::from_reader(&mut &bytes[..]).unwrap();
+            assert_eq!(seq, Seq064K::new(vec![]).unwrap());
+        }
+
+        #[test]
+        fn test_empty_str0255() {
+            let mut bytes = [0_u8; 1];
+            let s: Str0255 = Decodable::from_bytes(&mut bytes[..]).unwrap();
+            assert!(s.to_vec().is_empty());
+            let s: Str0255 = Decodable::from_reader(&mut &bytes[..]).unwrap();
+            assert!(s.to_vec().is_empty());
+        }
+
+        #[test]
+        fn test_empty_b0255() {
+            let mut bytes = [0_u8; 1];
+            let b: B0255 = Decodable::from_bytes(&mut bytes[..]).unwrap();
+            assert!(b.to_vec().is_empty());
+            let b: B0255 = Decodable::from_reader(&mut &bytes[..]).unwrap();
+            assert!(b.to_vec().is_empty());
+        }
+
+        #[test]
+        fn test_empty_b064k() {
+            let mut bytes = [0_u8; 2];
+            let b: B064K = Decodable::from_bytes(&mut bytes[..]).unwrap();
+            assert!(b.to_vec().is_empty());
+            let b: B064K = Decodable::from_reader(&mut &bytes[..]).unwrap();
+            assert!(b.to_vec().is_empty());
+        }
+
+        #[test]
+        fn test_empty_b016m() {
+            let mut bytes = [0_u8; 3];
+            let b: B016M = Decodable::from_bytes(&mut bytes[..]).unwrap();
+            assert!(b.to_vec().is_empty());
+            let b: B016M = Decodable::from_reader(&mut &bytes[..]).unwrap();
+            assert!(b.to_vec().is_empty());
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_bitmap {
+        use super::*;
+        use core::convert::TryInto;
+
+        // A message with 3 optional fields: field 0 (u32) and 2 (B0255) present, field 1 (u16)
+        // absent
+        fn encoded_message() -> Vec<u8> {
+            let mut bitmap = Bitmap::<1>::new();
+            bitmap.set(0).unwrap();
+            bitmap.set(2).unwrap();
+            let b0255: B0255 = vec![1, 2, 3].try_into().unwrap();
+            let mut bytes = to_bytes(bitmap).unwrap();
+            bytes.extend(to_bytes(7_u32).unwrap());
+            bytes.extend(to_bytes(b0255).unwrap());
+            bytes
+        }
+
+        #[test]
+        fn test_bitmap_decode_optional_fields() {
+            let mut bytes = encoded_message();
+            assert_eq!(bytes, vec![0b101, 7, 0, 0, 0, 3, 1, 2, 3]);
+
+            let mut decoder = BitmapDecoder::<1>::new(&mut bytes[..]).unwrap();
+            assert!(decoder.bitmap().is_set(0));
+            assert!(!decoder.bitmap().is_set(1));
+
+            let a: Option<u32> = decoder.next_field().unwrap();
+            let b: Option<u16> = decoder.next_field().unwrap();
+            let c: Option<B0255> = decoder.next_field().unwrap();
+            assert_eq!(a, Some(7));
+            assert_eq!(b, None);
+            assert_eq!(c.unwrap().to_vec(), vec![1, 2, 3]);
+            // The bits 3..8 are not set
+            let d: Option<u64> = decoder.next_field().unwrap();
+            assert_eq!(d, None);
+            assert!(decoder.into_remaining().is_empty());
+        }
+
+        #[test]
+        fn test_bitmap_decode_short_data() {
+            let mut bytes = encoded_message();
+            let len = bytes.len();
+            let mut decoder = BitmapDecoder::<1>::new(&mut bytes[..len - 1]).unwrap();
+            let _: Option<u32> = decoder.next_field().unwrap();
+            let _: Option<u16> = decoder.next_field().unwrap();
+            let c: Result<Option<B0255>, _> = decoder.next_field();
+            assert!(c.is_err());
+        }
+
+        #[test]
+        fn test_bitmap_out_of_bits() {
+            let mut bytes = [0_u8; 1];
+            let mut decoder = BitmapDecoder::<1>::new(&mut bytes[..]).unwrap();
+            for _ in 0..8 {
+                let field: Option<u8> = decoder.next_field().unwrap();
+                assert_eq!(field, None);
+            }
+            let field: Result<Option<u8>, _> = decoder.next_field();
+            assert!(matches!(field, Err(Error::OutOfBound)));
+            assert!(Bitmap::<1>::new().set(8).is_err());
+        }
+
+        #[test]
+        fn test_bitmap_roundtrip() {
+            let mut bitmap = Bitmap::<2>::new();
+            bitmap.set(9).unwrap();
+            let mut bytes = to_bytes(bitmap).unwrap();
+            assert_eq!(bytes, vec![0, 0b10]);
+            let decoded: Bitmap<2> = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(decoded, bitmap);
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_ip_addr {
+        use super::*;
+        use std::net::{Ipv4Addr, Ipv6Addr};
+
+        #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+        struct Endpoint {
+            host: Ipv4Addr,
+            port: u16,
+        }
+
+        #[test]
+        fn test_ipv4_roundtrip() {
+            for addr in [
+                Ipv4Addr::UNSPECIFIED,
+                Ipv4Addr::LOCALHOST,
+                Ipv4Addr::new(192, 168, 1, 10),
+            ] {
+                let mut bytes = to_bytes(addr).unwrap();
+                assert_eq!(bytes, addr.octets());
+                let decoded: Ipv4Addr = from_bytes(&mut bytes[..]).unwrap();
+                assert_eq!(decoded, addr);
+            }
+        }
+
+        #[test]
+        fn test_ipv6_roundtrip() {
+            for addr in [
+                Ipv6Addr::UNSPECIFIED,
+                Ipv6Addr::LOCALHOST,
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+            ] {
+                let mut bytes = to_bytes(addr).unwrap();
+                assert_eq!(bytes, addr.octets());
+                let decoded: Ipv6Addr = from_bytes(&mut bytes[..]).unwrap();
+                assert_eq!(decoded, addr);
+            }
+        }
+
+        #[test]
+        fn test_ip_addr_field() {
+            let endpoint = Endpoint {
+                host: Ipv4Addr::LOCALHOST,
+                port: 34254,
+            };
+            let mut bytes = to_bytes(endpoint.clone()).unwrap();
+            assert_eq!(bytes, vec![127, 0, 0, 1, 0xce, 0x85]);
+            let decoded: Endpoint = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(decoded, endpoint);
+
+            let short: Result<Ipv6Addr, _> = from_bytes(&mut [0; 15][..]);
+            assert!(short.is_err());
+        }
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    mod test_cow {
+        use super::*;
+        use std::borrow::Cow;
+
+        #[test]
+        fn test_as_cow() {
+            let mut data = [6; 3];
+            let ptr = data.as_ptr();
+            let b0255 = B0255::new_ref(&mut data[..]).unwrap();
+            match b0255.as_cow() {
+                // Same bytes of the buffer, nothing allocated
+                Cow::Borrowed(b) => assert_eq!(b.as_ptr(), ptr),
+                Cow::Owned(_) => panic!("Ref value copied"),
+            }
+            assert!(matches!(b0255.into_cow(), Cow::Borrowed(b) if b.as_ptr() == ptr));
+
+            let b064k = B064K::new_owned(vec![7; 300]).unwrap();
+            assert_eq!(b064k.as_cow(), &[7; 300][..]);
+            assert!(matches!(b064k.into_cow(), Cow::Owned(v) if v == vec![7; 300]));
+        }
+    }
+
+    // Every test that count allocations share this allocator, only one global allocator can be
+    // installed
+    #[cfg(all(feature = "alloc-metrics", not(feature = "with_serde")))]
+    #[global_allocator]
+    static GLOBAL: binary_codec_sv2::alloc_metrics::CountingAllocator<std::alloc::System> =
+        binary_codec_sv2::alloc_metrics::CountingAllocator(std::alloc::System);
+
+    // The allocations are counted with alloc-metrics
+    #[cfg(all(
+        feature = "arena",
+        feature = "alloc-metrics",
+        not(feature = "with_serde")
+    ))]
+    mod test_arena {
+        use super::*;
+        use binary_codec_sv2::{
+            alloc_metrics::measure,
+            arena::{from_bytes_in, Arena},
+        };
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u32,
+            b: U256<'decoder>,
+            c: u64,
+        }
+
+        #[test]
+        fn test_arena() {
+            let expected = Test {
+                a: 9,
+                b: [7_u8; 32].to_vec().try_into().unwrap(),
+                c: 1234,
+            };
+            let bytes = to_bytes(expected.clone()).unwrap();
+            let mut buffer = [0_u8; 100];
+
+            let mut arena = Arena::new(&mut buffer);
+            let ((decoded_1, decoded_2, exhausted), stats) = measure(|| {
+                let decoded_1: Test = from_bytes_in(&bytes, &mut arena).unwrap();
+                let decoded_2: Test = from_bytes_in(&bytes, &mut arena).unwrap();
+                let exhausted: Result<Test, _> = from_bytes_in(&bytes, &mut arena);
+                (decoded_1, decoded_2, exhausted)
+            });
+            assert_eq!(stats.total_bytes, 0);
+
+            assert_eq!(arena.remaining(), 100 - 2 * bytes.len());
+            assert!(matches!(exhausted, Err(Error::WriteError(44, 12))));
+            assert_eq!(decoded_1, expected);
+            assert_eq!(decoded_2, expected);
+        }
+    }
+
+    #[cfg(all(feature = "alloc-metrics", not(feature = "with_serde")))]
+    mod test_alloc_metrics {
+        use super::*;
+        use binary_codec_sv2::alloc_metrics::from_bytes_measured;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u32,
+            b: Seq064K<'decoder, u32>,
+        }
+
+        #[test]
+        fn test_alloc_metrics() {
+            let elements: Vec<u32> = (0..10_000).collect();
+            let message = Test {
+                a: 1,
+                b: Seq064K::new(elements).unwrap(),
+            };
+            let mut bytes = to_bytes(message.clone()).unwrap();
+            let (decoded, stats): (Test, _) = from_bytes_measured(&mut bytes[..]).unwrap();
+
+            assert_eq!(decoded, message);
+            // At least the vector of the decoded elements is alive at the same time
+            assert!(stats.peak_bytes >= 10_000 * 4);
+            assert!(stats.total_bytes >= stats.peak_bytes);
+
+            let mut bytes = to_bytes(7_u32).unwrap();
+            let (_, stats): (u32, _) = from_bytes_measured(&mut bytes[..]).unwrap();
+            assert!(stats.peak_bytes < 1000);
+        }
+    }
+
+    #[cfg(all(feature = "crc", not(feature = "with_serde")))]
+    mod test_crc {
+        use super::*;
+        use binary_codec_sv2::crc::crc32;
+        use core::convert::TryInto;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test<'decoder> {
+            a: u32,
+            b: B0255<'decoder>,
+            c: u64,
+        }
+
+        fn test_value() -> Test<'static> {
+            Test {
+                a: 9,
+                b: vec![7_u8; 20].try_into().unwrap(),
+                c: 1234,
+            }
+        }
+
+        #[test]
+        fn test_crc32_check_value() {
+            assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        }
+
+        #[test]
+        fn test_crc_roundtrip() {
+            let expected = test_value();
+            let mut bytes = to_bytes_with_crc(expected.clone()).unwrap();
+            assert_eq!(bytes.len(), expected.get_size() + 4);
+            let decoded: Test = from_bytes_with_crc(&mut bytes).unwrap();
+            assert_eq!(decoded, expected);
+        }
+
+        #[test]
+        fn test_crc_corrupted_byte() {
+            let mut bytes = to_bytes_with_crc(test_value()).unwrap();
+            let expected = crc32(&bytes[..bytes.len() - 4]);
+            bytes[5] ^= 0x01;
+            let actual = crc32(&bytes[..bytes.len() - 4]);
+            let decoded: Result<Test, _> = from_bytes_with_crc(&mut bytes);
+            match decoded {
+                Err(Error::CrcMismatch {
+                    expected: e,
+                    actual: a,
+                }) => {
+                    assert_eq!(e, expected);
+                    assert_eq!(a, actual);
+                }
+                _ => panic!("corrupted message decoded"),
+            }
+        }
+
+        #[test]
+        fn test_crc_corrupted_trailer() {
+            let mut bytes = to_bytes_with_crc(test_value()).unwrap();
+            let len = bytes.len();
+            bytes[len - 1] ^= 0x80;
+            let decoded: Result<Test, _> = from_bytes_with_crc(&mut bytes);
+            assert!(matches!(decoded, Err(Error::CrcMismatch { .. })));
+        }
+
+        #[test]
+        fn test_crc_too_short() {
+            let mut bytes = [0_u8; 3];
+            let decoded: Result<Test, _> = from_bytes_with_crc(&mut bytes);
+            assert!(matches!(decoded, Err(Error::OutOfBound)));
+        }
+    }
+
+    #[cfg(all(feature = "bitcoin-interop", not(feature = "with_serde")))]
+    mod test_compact_size {
+        use super::*;
+        use binary_codec_sv2::compact_size::{read_compact_size, write_compact_size};
+
+        // Canonical encodings of the boundary values
+        fn boundaries() -> Vec<(u64, Vec<u8>)> {
+            vec![
+                (0, vec![0]),
+                (0xfc, vec![0xfc]),
+                (0xfd, vec![0xfd, 0xfd, 0x00]),
+                (0xffff, vec![0xfd, 0xff, 0xff]),
+                (0x1_0000, vec![0xfe, 0x00, 0x00, 0x01, 0x00]),
+                (0xffff_ffff, vec![0xfe, 0xff, 0xff, 0xff, 0xff]),
+                (
+                    0x1_0000_0000,
+                    vec![0xff, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+                ),
+                (u64::MAX, vec![0xff; 9]),
+            ]
+        }
+
+        #[test]
+        fn test_write_compact_size() {
+            for (value, expected) in boundaries() {
+                let mut encoded = Vec::new();
+                write_compact_size(value, &mut encoded);
+                assert_eq!(encoded, expected, "value {:#x}", value);
+            }
+        }
+
+        #[test]
+        fn test_read_compact_size() {
+            for (expected, encoded) in boundaries() {
+                let value = read_compact_size(&mut &encoded[..]).unwrap();
+                assert_eq!(value, expected);
+            }
+        }
+
+        #[test]
+        fn test_read_non_canonical_compact_size() {
+            let non_canonical: [&[u8]; 3] = [
+                &[0xfd, 0xfc, 0x00],
+                &[0xfe, 0xff, 0xff, 0x00, 0x00],
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00],
+            ];
+            for mut encoded in non_canonical {
+                let value = read_compact_size(&mut encoded);
+                assert!(matches!(value, Err(Error::NonCanonicalCompactSize(_))));
+            }
+        }
+
+        #[test]
+        fn test_read_short_compact_size() {
+            let value = read_compact_size(&mut &[0xfe, 0x00, 0x00][..]);
+            assert!(matches!(value, Err(Error::OutOfBound)));
+        }
+    }
+
+    #[cfg(all(feature = "metrics", not(feature = "with_serde")))]
+    mod test_metrics {
+        use super::*;
+
+        #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+        struct Test {
+            a: u32,
+            b: u64,
+        }
+
+        #[test]
+        fn test_metrics() {
+            // Other tests can run in parallel and update the counters, so only a lower bound can
+            // be checked
+            let before = metrics::snapshot();
+            for i in 0..10 {
+                let mut bytes = to_bytes(Test { a: i, b: 7 }).unwrap();
+                let mut buffer = [0_u8; 12];
+                to_writer(Test { a: i, b: 7 }, &mut buffer).unwrap();
+                let _: Test = from_bytes(&mut bytes[..]).unwrap();
+            }
+            let after = metrics::snapshot();
+
+            assert!(after.encoded.messages >= before.encoded.messages + 20);
+            assert!(after.encoded.bytes >= before.encoded.bytes + 20 * 12);
+            assert!(after.decoded.messages >= before.decoded.messages + 10);
+            assert!(after.decoded.bytes >= before.decoded.bytes + 10 * 12);
+        }
+    }
 }
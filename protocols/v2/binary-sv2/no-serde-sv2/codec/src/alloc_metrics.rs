@@ -0,0 +1,102 @@
+//! Measure the memory allocated by the decoding of a message, meant to size the per connection
+//! memory limits of a relay. The allocations are counted by [`CountingAllocator`], that must be
+//! installed as the global allocator by the binary:
+//!
+//! ```ignore
+//! use binary_codec_sv2::alloc_metrics::CountingAllocator;
+//! use std::alloc::System;
+//!
+//! #[global_allocator]
+//! static GLOBAL: CountingAllocator<System> = CountingAllocator(System);
+//! ```
+//!
+//! Without it [`from_bytes_measured`] always report zero bytes. Only the allocations of the
+//! thread that decode are counted and only while [`from_bytes_measured`] (or [`measure`]) is
+//! running, so the allocator is cheap when nothing is measured. Needs std for the thread locals
+//! and `std::alloc`, the module is not available with `no_std`.
+use crate::{Decodable, Error};
+use core::cell::Cell;
+use std::alloc::{GlobalAlloc, Layout};
+
+thread_local! {
+    static MEASURING: Cell<bool> = Cell::new(false);
+    // Can be negative if the decoder free memory allocated before the measure started
+    static CURRENT: Cell<isize> = Cell::new(0);
+    static PEAK: Cell<isize> = Cell::new(0);
+    static TOTAL: Cell<usize> = Cell::new(0);
+}
+
+/// Memory allocated by a decode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Sum of the bytes of every allocation, freed or not
+    pub total_bytes: usize,
+    /// High-water mark of the bytes allocated and not yet freed
+    pub peak_bytes: usize,
+}
+
+/// Wrap the global allocator `A` counting the allocations of the threads that are measuring
+#[derive(Debug, Default)]
+pub struct CountingAllocator<A>(pub A);
+
+// The thread locals are accessed with `try_with` because they can be destroyed before the last
+// allocations of the thread
+fn record(allocated: usize, freed: usize) {
+    if !MEASURING.try_with(|m| m.get()).unwrap_or(false) {
+        return;
+    }
+    let _ = TOTAL.try_with(|t| t.set(t.get() + allocated));
+    let _ = CURRENT.try_with(|c| {
+        let current = c.get() + allocated as isize - freed as isize;
+        c.set(current);
+        let _ = PEAK.try_with(|p| p.set(p.get().max(current)));
+    });
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size(), 0);
+        self.0.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size(), 0);
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record(0, layout.size());
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size, layout.size());
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Run `f` and return the memory allocated by the current thread while it runs. Measures can
+/// not be nested.
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, AllocStats) {
+    CURRENT.with(|c| c.set(0));
+    PEAK.with(|p| p.set(0));
+    TOTAL.with(|t| t.set(0));
+    MEASURING.with(|m| m.set(true));
+    let result = f();
+    MEASURING.with(|m| m.set(false));
+    let stats = AllocStats {
+        total_bytes: TOTAL.with(|t| t.get()),
+        peak_bytes: PEAK.with(|p| p.get()) as usize,
+    };
+    (result, stats)
+}
+
+/// Like [`crate::from_bytes`] but also return the memory allocated while decoding. The memory
+/// owned by the decoded message is counted, as it is allocated by the decode. Measures can not be
+/// nested.
+pub fn from_bytes_measured<'a, T: Decodable<'a>>(
+    data: &'a mut [u8],
+) -> Result<(T, AllocStats), Error> {
+    let (decoded, stats) = measure(move || crate::from_bytes(data));
+    Ok((decoded?, stats))
+}
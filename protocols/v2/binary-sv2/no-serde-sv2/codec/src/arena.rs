@@ -0,0 +1,45 @@
+//! Decode messages into a caller provided buffer instead of the global allocator, meant for
+//! constrained devices that want to bound and reuse the memory used by the decoder.
+//!
+//! The encoded bytes are copied in the arena and the message is decoded from there, so the
+//! decoded `Inner` are `Inner::Ref` pointing to the arena and not `Inner::Owned` vectors. Messages
+//! with only fixed size fields (see the derived `Decodable::from_bytes`) are decoded without any
+//! global allocation, for the other messages the decoder still allocates the field markers.
+//!
+//! The arena is a bump allocator: the memory is reused by dropping the decoded messages and the
+//! arena and building a new arena on the same buffer.
+use crate::{codec::decodable::Decodable, Error};
+
+pub struct Arena<'a> {
+    free: &'a mut [u8],
+}
+
+impl<'a> Arena<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self { free: buffer }
+    }
+
+    /// Bytes that can still be allocated
+    pub fn remaining(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Reserve `len` bytes of the arena, if the arena do not have enough free bytes return
+    /// `Error::WriteError(len, remaining)`
+    pub fn alloc(&mut self, len: usize) -> Result<&'a mut [u8], Error> {
+        if len > self.free.len() {
+            return Err(Error::WriteError(len, self.free.len()));
+        }
+        let free = core::mem::take(&mut self.free);
+        let (allocated, free) = free.split_at_mut(len);
+        self.free = free;
+        Ok(allocated)
+    }
+}
+
+/// Copy `data` in `arena` and decode `T` from the copy, the decoded message borrow the arena
+pub fn from_bytes_in<'a, T: Decodable<'a>>(data: &[u8], arena: &mut Arena<'a>) -> Result<T, Error> {
+    let dst = arena.alloc(data.len())?;
+    dst.copy_from_slice(data);
+    T::from_bytes(dst)
+}
@@ -1,6 +1,7 @@
 use crate::{
     codec::{GetSize, SizeHint},
     datatypes::{Signature, Sv2DataType, U32AsRef, B016M, B0255, B032, B064K, U24, U256},
+    limits::DecodeBudget,
     Error,
 };
 use alloc::vec::Vec;
@@ -15,18 +16,32 @@ pub trait Decodable<'a>: Sized {
 
     fn from_decoded_fields(data: Vec<DecodableField<'a>>) -> Result<Self, Error>;
 
-    fn from_bytes(data: &'a mut [u8]) -> Result<Self, Error> {
+    /// Like `get_structure` but count the values and the nesting of the structure in `budget`
+    /// while it is walked, so that the walk stop as soon as a limit is exceeded. `depth` is the
+    /// depth of the value, its fields are at `depth + 1`. The sequences and the derived structs
+    /// check the limits before walking (and allocating) their elements, the other types are
+    /// checked once their fixed structure is returned.
+    fn get_structure_limited(
+        data: &[u8],
+        budget: &mut DecodeBudget,
+        depth: usize,
+    ) -> Result<Vec<FieldMarker>, Error> {
         let structure = Self::get_structure(data)?;
-        let mut fields = Vec::new();
-        let mut tail = data;
-
-        for field in structure {
-            let field_size = field.size_hint_(tail, 0)?;
-            let (head, t) = tail.split_at_mut(field_size);
-            tail = t;
-            fields.push(field.decode(head)?);
+        match structure.len() {
+            1 => budget.count(&structure[0], depth, 1)?,
+            _ => {
+                budget.check_depth(depth)?;
+                for field in &structure {
+                    budget.count(field, depth + 1, 1)?;
+                }
+            }
         }
-        Self::from_decoded_fields(fields)
+        Ok(structure)
+    }
+
+    fn from_bytes(data: &'a mut [u8]) -> Result<Self, Error> {
+        let structure = Self::get_structure(data)?;
+        Self::from_decoded_fields(decode_fields(&structure, data)?)
     }
 
     #[cfg(not(feature = "no_std"))]
@@ -46,8 +61,44 @@ pub trait Decodable<'a>: Sized {
     }
 }
 
+/// Decode `data` as a `T` and return the decoded fields together with the markers that describe
+/// them, one marker for each field of `T`. The codec is not aware of the message types so the
+/// caller select `T` from the message type of the frame.
+///
+/// The fields are not converted into a `T`, so that they can be inspected or changed before being
+/// passed to `T::from_decoded_fields` (`Vec::from` turn the returned `DecodableField::Struct` into
+/// the fields) and re-encoded.
+pub fn decode_with_markers<'a, T: Decodable<'a>>(
+    data: &'a mut [u8],
+) -> Result<(DecodableField<'a>, Vec<FieldMarker>), Error> {
+    let structure = T::get_structure(data)?;
+    let fields = decode_fields(&structure, data)?;
+    Ok((DecodableField::Struct(fields), structure))
+}
+
+/// Decode `data` field by field following `structure`
+pub(crate) fn decode_fields<'a>(
+    structure: &[FieldMarker],
+    data: &'a mut [u8],
+) -> Result<Vec<DecodableField<'a>>, Error> {
+    let mut fields = Vec::with_capacity(structure.len());
+    let mut tail = data;
+    for field in structure {
+        let field_size = field.size_hint_(tail, 0)?;
+        // A malformed length (eg a sequence that declare more elements than the ones in data)
+        // must be an error and not a panic
+        if field_size > tail.len() {
+            return Err(Error::OutOfBound);
+        }
+        let (head, t) = tail.split_at_mut(field_size);
+        tail = t;
+        fields.push(field.decode(head)?);
+    }
+    Ok(fields)
+}
+
 /// Passed to a decoder to define the structure of the data to be decoded
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PrimitiveMarker {
     U8,
     U16,
@@ -59,6 +110,8 @@ pub enum PrimitiveMarker {
     U32AsRef,
     F32,
     U64,
+    I32,
+    I64,
     B032,
     B0255,
     B064K,
@@ -66,7 +119,7 @@ pub enum PrimitiveMarker {
 }
 
 /// Passed to a decoder to define the structure of the data to be decoded
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FieldMarker {
     Primitive(PrimitiveMarker),
     Struct(Vec<FieldMarker>),
@@ -88,6 +141,8 @@ pub enum DecodablePrimitive<'a> {
     U32AsRef(U32AsRef<'a>),
     F32(f32),
     U64(u64),
+    I32(i32),
+    I64(i64),
     B032(B032<'a>),
     B0255(B0255<'a>),
     B064K(B064K<'a>),
@@ -119,6 +174,8 @@ impl SizeHint for PrimitiveMarker {
             Self::U32AsRef => U32AsRef::size_hint(data, offset),
             Self::F32 => f32::size_hint(data, offset),
             Self::U64 => u64::size_hint(data, offset),
+            Self::I32 => i32::size_hint(data, offset),
+            Self::I64 => i64::size_hint(data, offset),
             Self::B032 => B032::size_hint(data, offset),
             Self::B0255 => B0255::size_hint(data, offset),
             Self::B064K => B064K::size_hint(data, offset),
@@ -211,6 +268,8 @@ impl PrimitiveMarker {
             }
             Self::F32 => DecodablePrimitive::F32(f32::from_bytes_unchecked(&mut data[offset..])),
             Self::U64 => DecodablePrimitive::U64(u64::from_bytes_unchecked(&mut data[offset..])),
+            Self::I32 => DecodablePrimitive::I32(i32::from_bytes_unchecked(&mut data[offset..])),
+            Self::I64 => DecodablePrimitive::I64(i64::from_bytes_unchecked(&mut data[offset..])),
             Self::B032 => DecodablePrimitive::B032(B032::from_bytes_unchecked(&mut data[offset..])),
             Self::B0255 => {
                 DecodablePrimitive::B0255(B0255::from_bytes_unchecked(&mut data[offset..]))
@@ -241,6 +300,8 @@ impl PrimitiveMarker {
             )?)),
             Self::F32 => Ok(DecodablePrimitive::F32(f32::from_reader_(reader)?)),
             Self::U64 => Ok(DecodablePrimitive::U64(u64::from_reader_(reader)?)),
+            Self::I32 => Ok(DecodablePrimitive::I32(i32::from_reader_(reader)?)),
+            Self::I64 => Ok(DecodablePrimitive::I64(i64::from_reader_(reader)?)),
             Self::B032 => Ok(DecodablePrimitive::B032(B032::from_reader_(reader)?)),
             Self::B0255 => Ok(DecodablePrimitive::B0255(B0255::from_reader_(reader)?)),
             Self::B064K => Ok(DecodablePrimitive::B064K(B064K::from_reader_(reader)?)),
@@ -262,6 +323,8 @@ impl<'a> GetSize for DecodablePrimitive<'a> {
             DecodablePrimitive::U32AsRef(v) => v.get_size(),
             DecodablePrimitive::F32(v) => v.get_size(),
             DecodablePrimitive::U64(v) => v.get_size(),
+            DecodablePrimitive::I32(v) => v.get_size(),
+            DecodablePrimitive::I64(v) => v.get_size(),
             DecodablePrimitive::B032(v) => v.get_size(),
             DecodablePrimitive::B0255(v) => v.get_size(),
             DecodablePrimitive::B064K(v) => v.get_size(),
@@ -279,6 +342,9 @@ impl FieldMarker {
                 let mut tail = data;
                 for p in ps {
                     let field_size = p.size_hint_(tail, 0)?;
+                    if field_size > tail.len() {
+                        return Err(Error::OutOfBound);
+                    }
                     let (head, t) = tail.split_at_mut(field_size);
                     tail = t;
                     decodeds.push(p.decode(head)?);
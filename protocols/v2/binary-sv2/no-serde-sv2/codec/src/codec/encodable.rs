@@ -5,7 +5,7 @@ use crate::{
 };
 use alloc::vec::Vec;
 #[cfg(not(feature = "no_std"))]
-use std::io::{Error as E, Write};
+use std::io::{Error as E, ErrorKind, Write};
 
 pub trait Encodable {
     #[allow(clippy::wrong_self_convention)]
@@ -14,6 +14,12 @@ pub trait Encodable {
     #[cfg(not(feature = "no_std"))]
     #[allow(clippy::wrong_self_convention)]
     fn to_writer(self, dst: &mut impl Write) -> Result<(), E>;
+
+    /// Encode into a stack array of capacity `N` and return it with the number of written bytes,
+    /// so that small messages can be encoded without allocating the output buffer. Return
+    /// `Error::WriteError(N, needed)` if the encoded value does not fit in `N` bytes.
+    #[allow(clippy::wrong_self_convention)]
+    fn to_array<const N: usize>(self) -> Result<([u8; N], usize), Error>;
 }
 
 //
@@ -27,8 +33,21 @@ impl<'a, T: Into<EncodableField<'a>>> Encodable for T {
     #[cfg(not(feature = "no_std"))]
     #[allow(clippy::wrong_self_convention)]
     fn to_writer(self, dst: &mut impl Write) -> Result<(), E> {
-        let encoded_field = self.into();
-        encoded_field.to_writer(dst)
+        let encoded_field: EncodableField = self.into();
+        // Call the inherent method, `encoded_field.to_writer` would resolve to this trait method
+        EncodableField::to_writer(&encoded_field, dst)
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn to_array<const N: usize>(self) -> Result<([u8; N], usize), Error> {
+        let encoded_field: EncodableField = self.into();
+        let needed = encoded_field.get_size();
+        if needed > N {
+            return Err(Error::WriteError(N, needed));
+        }
+        let mut dst = [0; N];
+        let len = encoded_field.encode(&mut dst, 0)?;
+        Ok((dst, len))
     }
 }
 
@@ -45,10 +64,15 @@ pub enum EncodablePrimitive<'a> {
     U32AsRef(U32AsRef<'a>),
     F32(f32),
     U64(u64),
+    I32(i32),
+    I64(i64),
     B032(B032<'a>),
     B0255(B0255<'a>),
     B064K(B064K<'a>),
     B016M(B016M<'a>),
+    /// Bytes of an `Inner` (`U256`, `B064K`, ...) encoded from a reference, with a length prefix of
+    /// the given size (0 for the fixed size types)
+    BytesRef(usize, &'a [u8]),
 }
 
 impl<'a> EncodablePrimitive<'a> {
@@ -63,12 +87,25 @@ impl<'a> EncodablePrimitive<'a> {
             Self::Signature(v) => v.to_slice(dst),
             Self::U32(v) => v.to_slice(dst),
             Self::U32AsRef(v) => v.to_slice(dst),
+            // NaN and infinite are not valid hash rates (the only f32 fields in the protocol)
+            Self::F32(v) if !v.is_finite() => Err(Error::NonFiniteFloat),
             Self::F32(v) => v.to_slice(dst),
             Self::U64(v) => v.to_slice(dst),
+            Self::I32(v) => v.to_slice(dst),
+            Self::I64(v) => v.to_slice(dst),
             Self::B032(v) => v.to_slice(dst),
             Self::B0255(v) => v.to_slice(dst),
             Self::B064K(v) => v.to_slice(dst),
             Self::B016M(v) => v.to_slice(dst),
+            Self::BytesRef(header_size, v) => {
+                let size = header_size + v.len();
+                if dst.len() < size {
+                    return Err(Error::WriteError(size, dst.len()));
+                }
+                dst[..*header_size].copy_from_slice(&v.len().to_le_bytes()[..*header_size]);
+                dst[*header_size..size].copy_from_slice(v);
+                Ok(size)
+            }
         }
     }
 
@@ -84,12 +121,21 @@ impl<'a> EncodablePrimitive<'a> {
             Self::Signature(v) => v.to_writer_(writer),
             Self::U32(v) => v.to_writer_(writer),
             Self::U32AsRef(v) => v.to_writer_(writer),
+            Self::F32(v) if !v.is_finite() => {
+                Err(E::new(ErrorKind::InvalidInput, Error::NonFiniteFloat))
+            }
             Self::F32(v) => v.to_writer_(writer),
             Self::U64(v) => v.to_writer_(writer),
+            Self::I32(v) => v.to_writer_(writer),
+            Self::I64(v) => v.to_writer_(writer),
             Self::B032(v) => v.to_writer_(writer),
             Self::B0255(v) => v.to_writer_(writer),
             Self::B064K(v) => v.to_writer_(writer),
             Self::B016M(v) => v.to_writer_(writer),
+            Self::BytesRef(header_size, v) => {
+                writer.write_all(&v.len().to_le_bytes()[..*header_size])?;
+                writer.write_all(v)
+            }
         }
     }
 }
@@ -108,10 +154,13 @@ impl<'a> GetSize for EncodablePrimitive<'a> {
             Self::U32AsRef(v) => v.get_size(),
             Self::F32(v) => v.get_size(),
             Self::U64(v) => v.get_size(),
+            Self::I32(v) => v.get_size(),
+            Self::I64(v) => v.get_size(),
             Self::B032(v) => v.get_size(),
             Self::B0255(v) => v.get_size(),
             Self::B064K(v) => v.get_size(),
             Self::B016M(v) => v.get_size(),
+            Self::BytesRef(header_size, v) => header_size + v.len(),
         }
     }
 }
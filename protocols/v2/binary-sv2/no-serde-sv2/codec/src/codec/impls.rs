@@ -4,6 +4,7 @@ use crate::{
             Decodable, DecodableField, DecodablePrimitive, FieldMarker, GetMarker, PrimitiveMarker,
         },
         encodable::{EncodableField, EncodablePrimitive},
+        IntoStatic,
     },
     datatypes::*,
     Error,
@@ -47,6 +48,16 @@ impl GetMarker for u64 {
         FieldMarker::Primitive(PrimitiveMarker::U64)
     }
 }
+impl GetMarker for i32 {
+    fn get_marker() -> FieldMarker {
+        FieldMarker::Primitive(PrimitiveMarker::I32)
+    }
+}
+impl GetMarker for i64 {
+    fn get_marker() -> FieldMarker {
+        FieldMarker::Primitive(PrimitiveMarker::I64)
+    }
+}
 impl<'a> GetMarker for U256<'a> {
     fn get_marker() -> FieldMarker {
         FieldMarker::Primitive(PrimitiveMarker::U256)
@@ -130,6 +141,24 @@ impl<'a> Decodable<'a> for u64 {
         data.pop().ok_or(Error::NoDecodableFieldPassed)?.try_into()
     }
 }
+impl<'a> Decodable<'a> for i32 {
+    fn get_structure(_: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+        Ok(vec![PrimitiveMarker::I32.into()])
+    }
+
+    fn from_decoded_fields(mut data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+        data.pop().ok_or(Error::NoDecodableFieldPassed)?.try_into()
+    }
+}
+impl<'a> Decodable<'a> for i64 {
+    fn get_structure(_: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+        Ok(vec![PrimitiveMarker::I64.into()])
+    }
+
+    fn from_decoded_fields(mut data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+        data.pop().ok_or(Error::NoDecodableFieldPassed)?.try_into()
+    }
+}
 impl<'a> Decodable<'a> for bool {
     fn get_structure(_: &[u8]) -> Result<Vec<FieldMarker>, Error> {
         Ok(vec![PrimitiveMarker::Bool.into()])
@@ -265,6 +294,26 @@ impl<'a> TryFrom<DecodablePrimitive<'a>> for u64 {
         }
     }
 }
+impl<'a> TryFrom<DecodablePrimitive<'a>> for i32 {
+    type Error = Error;
+
+    fn try_from(value: DecodablePrimitive<'a>) -> Result<Self, Self::Error> {
+        match value {
+            DecodablePrimitive::I32(val) => Ok(val),
+            _ => Err(Error::PrimitiveConversionError),
+        }
+    }
+}
+impl<'a> TryFrom<DecodablePrimitive<'a>> for i64 {
+    type Error = Error;
+
+    fn try_from(value: DecodablePrimitive<'a>) -> Result<Self, Self::Error> {
+        match value {
+            DecodablePrimitive::I64(val) => Ok(val),
+            _ => Err(Error::PrimitiveConversionError),
+        }
+    }
+}
 impl<'a> TryFrom<DecodablePrimitive<'a>> for bool {
     type Error = Error;
 
@@ -409,6 +458,26 @@ impl<'a> TryFrom<DecodableField<'a>> for u64 {
         }
     }
 }
+impl<'a> TryFrom<DecodableField<'a>> for i32 {
+    type Error = Error;
+
+    fn try_from(value: DecodableField<'a>) -> Result<Self, Self::Error> {
+        match value {
+            DecodableField::Primitive(p) => p.try_into(),
+            _ => Err(Error::DecodableConversionError),
+        }
+    }
+}
+impl<'a> TryFrom<DecodableField<'a>> for i64 {
+    type Error = Error;
+
+    fn try_from(value: DecodableField<'a>) -> Result<Self, Self::Error> {
+        match value {
+            DecodableField::Primitive(p) => p.try_into(),
+            _ => Err(Error::DecodableConversionError),
+        }
+    }
+}
 impl<'a> TryFrom<DecodableField<'a>> for bool {
     type Error = Error;
 
@@ -597,6 +666,16 @@ impl<'a> From<u64> for EncodableField<'a> {
         EncodableField::Primitive(EncodablePrimitive::U64(v))
     }
 }
+impl<'a> From<i32> for EncodableField<'a> {
+    fn from(v: i32) -> Self {
+        EncodableField::Primitive(EncodablePrimitive::I32(v))
+    }
+}
+impl<'a> From<i64> for EncodableField<'a> {
+    fn from(v: i64) -> Self {
+        EncodableField::Primitive(EncodablePrimitive::I64(v))
+    }
+}
 impl<'a> TryFrom<EncodableField<'a>> for u64 {
     type Error = Error;
 
@@ -607,6 +686,26 @@ impl<'a> TryFrom<EncodableField<'a>> for u64 {
         }
     }
 }
+impl<'a> TryFrom<EncodableField<'a>> for i32 {
+    type Error = Error;
+
+    fn try_from(value: EncodableField<'a>) -> Result<Self, Self::Error> {
+        match value {
+            EncodableField::Primitive(EncodablePrimitive::I32(v)) => Ok(v),
+            _ => Err(Error::NonPrimitiveTypeCannotBeEncoded),
+        }
+    }
+}
+impl<'a> TryFrom<EncodableField<'a>> for i64 {
+    type Error = Error;
+
+    fn try_from(value: EncodableField<'a>) -> Result<Self, Self::Error> {
+        match value {
+            EncodableField::Primitive(EncodablePrimitive::I64(v)) => Ok(v),
+            _ => Err(Error::NonPrimitiveTypeCannotBeEncoded),
+        }
+    }
+}
 impl<'a> From<U256<'a>> for EncodableField<'a> {
     fn from(v: U256<'a>) -> Self {
         EncodableField::Primitive(EncodablePrimitive::U256(v))
@@ -697,11 +796,47 @@ impl<'a> TryFrom<EncodableField<'a>> for B016M<'a> {
         }
     }
 }
-//impl<'a> From<&'a Seq0255<'a, U24>> for EncodableField<'a> {
-//    fn from(v: &'a Seq0255<'a, U24>) -> Self {
-//        EncodableField::Primitive(EncodablePrimitive::Seq0255u24(v))
-//    }
-//}
+
+// IMPL FROM REFERENCES FOR ENCODED FIELD, the message can be encoded without being cloned
+
+macro_rules! impl_into_encodable_field_for_copy_ref {
+    ($a:ty) => {
+        impl<'a, 'b> From<&'b $a> for EncodableField<'a> {
+            fn from(v: &'b $a) -> Self {
+                (*v).into()
+            }
+        }
+    };
+}
+
+impl_into_encodable_field_for_copy_ref!(bool);
+impl_into_encodable_field_for_copy_ref!(u8);
+impl_into_encodable_field_for_copy_ref!(u16);
+impl_into_encodable_field_for_copy_ref!(U24);
+impl_into_encodable_field_for_copy_ref!(u32);
+impl_into_encodable_field_for_copy_ref!(f32);
+impl_into_encodable_field_for_copy_ref!(u64);
+impl_into_encodable_field_for_copy_ref!(i32);
+impl_into_encodable_field_for_copy_ref!(i64);
+
+impl<
+        'a,
+        'b: 'a,
+        const ISFIXED: bool,
+        const SIZE: usize,
+        const HEADERSIZE: usize,
+        const MAXSIZE: usize,
+    > From<&'a Inner<'b, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>> for EncodableField<'a>
+{
+    fn from(v: &'a Inner<'b, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>) -> Self {
+        let data: &'a [u8] = match v {
+            Inner::Ref(data) => data,
+            Inner::Owned(data) => data,
+        };
+        EncodableField::Primitive(EncodablePrimitive::BytesRef(HEADERSIZE, data))
+    }
+}
+
 impl<'a> From<U32AsRef<'a>> for EncodableField<'a> {
     fn from(v: U32AsRef<'a>) -> Self {
         EncodableField::Primitive(EncodablePrimitive::U32AsRef(v))
@@ -754,6 +889,18 @@ impl From<u64> for FieldMarker {
     }
 }
 
+impl From<i32> for FieldMarker {
+    fn from(_: i32) -> Self {
+        FieldMarker::Primitive(PrimitiveMarker::I32)
+    }
+}
+
+impl From<i64> for FieldMarker {
+    fn from(_: i64) -> Self {
+        FieldMarker::Primitive(PrimitiveMarker::I64)
+    }
+}
+
 impl From<U24> for FieldMarker {
     fn from(_: U24) -> Self {
         FieldMarker::Primitive(PrimitiveMarker::U24)
@@ -800,3 +947,58 @@ impl<'a> From<U32AsRef<'a>> for FieldMarker {
         FieldMarker::Primitive(PrimitiveMarker::U32AsRef)
     }
 }
+
+// IMPL INTO STATIC FOR PRIMITIVES
+macro_rules! impl_into_static_for_copy {
+    ($($t:ty),+) => {
+        $(
+            impl IntoStatic for $t {
+                type Owned = $t;
+
+                fn into_static(self) -> Self::Owned {
+                    self
+                }
+            }
+        )+
+    };
+}
+
+impl_into_static_for_copy!(bool, u8, u16, U24, u32, f32, u64, i32, i64);
+
+impl<'a> IntoStatic for DecodablePrimitive<'a> {
+    type Owned = DecodablePrimitive<'static>;
+
+    fn into_static(self) -> Self::Owned {
+        match self {
+            DecodablePrimitive::U8(v) => DecodablePrimitive::U8(v),
+            DecodablePrimitive::U16(v) => DecodablePrimitive::U16(v),
+            DecodablePrimitive::Bool(v) => DecodablePrimitive::Bool(v),
+            DecodablePrimitive::U24(v) => DecodablePrimitive::U24(v),
+            DecodablePrimitive::U256(v) => DecodablePrimitive::U256(v.into_static()),
+            DecodablePrimitive::Signature(v) => DecodablePrimitive::Signature(v.into_static()),
+            DecodablePrimitive::U32(v) => DecodablePrimitive::U32(v),
+            DecodablePrimitive::U32AsRef(v) => DecodablePrimitive::U32AsRef(v.into_static()),
+            DecodablePrimitive::F32(v) => DecodablePrimitive::F32(v),
+            DecodablePrimitive::U64(v) => DecodablePrimitive::U64(v),
+            DecodablePrimitive::I32(v) => DecodablePrimitive::I32(v),
+            DecodablePrimitive::I64(v) => DecodablePrimitive::I64(v),
+            DecodablePrimitive::B032(v) => DecodablePrimitive::B032(v.into_static()),
+            DecodablePrimitive::B0255(v) => DecodablePrimitive::B0255(v.into_static()),
+            DecodablePrimitive::B064K(v) => DecodablePrimitive::B064K(v.into_static()),
+            DecodablePrimitive::B016M(v) => DecodablePrimitive::B016M(v.into_static()),
+        }
+    }
+}
+
+impl<'a> IntoStatic for DecodableField<'a> {
+    type Owned = DecodableField<'static>;
+
+    fn into_static(self) -> Self::Owned {
+        match self {
+            DecodableField::Primitive(p) => DecodableField::Primitive(p.into_static()),
+            DecodableField::Struct(fs) => {
+                DecodableField::Struct(fs.into_iter().map(|f| f.into_static()).collect())
+            }
+        }
+    }
+}
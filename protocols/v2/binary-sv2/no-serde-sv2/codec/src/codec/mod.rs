@@ -22,6 +22,15 @@ pub trait GetSize {
     fn get_size(&self) -> usize;
 }
 
+/// Implemented by the `Decodable` types, `Owned` is the same type with every borrowed data copied
+/// so that it do not depend on the decoded buffer, eg `Owned` of `U256<'a>` is `U256<'static>`.
+/// It is derived together with `Decodable`, see [`crate::from_bytes_owned`].
+pub trait IntoStatic {
+    type Owned: 'static;
+
+    fn into_static(self) -> Self::Owned;
+}
+
 #[cfg(feature = "with_buffer_pool")]
 impl GetSize for Slice {
     fn get_size(&self) -> usize {
@@ -29,6 +38,40 @@ impl GetSize for Slice {
     }
 }
 
+/// On wire size of a `B032` with `len` bytes, it can be used to check if a message fit in a frame
+/// before building it. The max size of the type is not checked.
+pub const fn size_of_b032(len: usize) -> usize {
+    1 + len
+}
+
+/// On wire size of a `B0255` (or a `Str0255`) with `len` bytes, the max size of the type is not
+/// checked.
+pub const fn size_of_b0255(len: usize) -> usize {
+    1 + len
+}
+
+/// On wire size of a `B064K` with `len` bytes, the max size of the type is not checked.
+pub const fn size_of_b064k(len: usize) -> usize {
+    2 + len
+}
+
+/// On wire size of a `B016M` with `len` bytes, the max size of the type is not checked.
+pub const fn size_of_b016m(len: usize) -> usize {
+    3 + len
+}
+
+/// On wire size of a `Seq0255` of `count` elements each one of `element_size` bytes (header
+/// included, eg `size_of_b064k(len)` for a sequence of `B064K`).
+pub const fn size_of_seq0255(count: usize, element_size: usize) -> usize {
+    1 + count * element_size
+}
+
+/// On wire size of a `Seq064K` of `count` elements each one of `element_size` bytes (header
+/// included, eg `size_of_b064k(len)` for a sequence of `B064K`).
+pub const fn size_of_seq064k(count: usize, element_size: usize) -> usize {
+    2 + count * element_size
+}
+
 /// Implemented by all the primitives with a fixed size
 pub trait Fixed {
     const SIZE: usize;
@@ -0,0 +1,65 @@
+//! Bitcoin compact size (the varint used for the lengths in the bitcoin transactions and
+//! blocks), needed to assemble or parse the coinbase and the block bytes. It is not used by the
+//! Sv2 datatypes.
+//!
+//! ```txt
+//! value                 encoding
+//! 0..=0xfc              value as u8
+//! 0xfd..=0xffff         0xfd + value as little endian u16
+//! 0x10000..=0xffffffff  0xfe + value as little endian u32
+//! bigger                0xff + value as little endian u64
+//! ```
+//! As in bitcoin a value must use the shortest encoding, non canonical encodings are refused.
+use crate::Error;
+use alloc::vec::Vec;
+#[cfg(not(feature = "no_std"))]
+use std::io::Read;
+
+/// Read a compact size, return `Error::NonCanonicalCompactSize` if the value is not encoded
+/// with the shortest encoding
+#[cfg(not(feature = "no_std"))]
+pub fn read_compact_size(reader: &mut impl Read) -> Result<u64, Error> {
+    let mut prefix = [0_u8; 1];
+    reader.read_exact(&mut prefix)?;
+    let (value, min) = match prefix[0] {
+        0xfd => {
+            let mut bytes = [0_u8; 2];
+            reader.read_exact(&mut bytes)?;
+            (u16::from_le_bytes(bytes) as u64, 0xfd)
+        }
+        0xfe => {
+            let mut bytes = [0_u8; 4];
+            reader.read_exact(&mut bytes)?;
+            (u32::from_le_bytes(bytes) as u64, 0x1_0000)
+        }
+        0xff => {
+            let mut bytes = [0_u8; 8];
+            reader.read_exact(&mut bytes)?;
+            (u64::from_le_bytes(bytes), 0x1_0000_0000)
+        }
+        v => return Ok(v as u64),
+    };
+    if value < min {
+        return Err(Error::NonCanonicalCompactSize(value));
+    }
+    Ok(value)
+}
+
+/// Append `value` encoded as compact size to `dst`
+pub fn write_compact_size(value: u64, dst: &mut Vec<u8>) {
+    match value {
+        0..=0xfc => dst.push(value as u8),
+        0xfd..=0xffff => {
+            dst.push(0xfd);
+            dst.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            dst.push(0xfe);
+            dst.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        _ => {
+            dst.push(0xff);
+            dst.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Optional CRC32 trailer for links where an integrity check beyond TCP is wanted. This is not
+//! part of the Sv2 spec: both ends of the link must agree to use it.
+//!
+//! The trailer is the CRC32 (IEEE 802.3, the one used by zlib and ethernet) of the encoded message
+//! appended as a little endian u32.
+use crate::{codec::decodable::Decodable, Encodable, Error, GetSize};
+use alloc::vec::Vec;
+
+const TRAILER_SIZE: usize = 4;
+
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0_u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for b in data {
+        crc = (crc >> 8) ^ TABLE[((crc ^ *b as u32) & 0xff) as usize];
+    }
+    !crc
+}
+
+/// Encode `src` and append the CRC32 of the encoded bytes
+#[allow(clippy::wrong_self_convention)]
+pub fn to_bytes_with_crc<T: Encodable + GetSize>(src: T) -> Result<Vec<u8>, Error> {
+    let size = src.get_size();
+    let mut result = vec![0_u8; size + TRAILER_SIZE];
+    src.to_bytes(&mut result[..size])?;
+    let crc = crc32(&result[..size]);
+    result[size..].copy_from_slice(&crc.to_le_bytes());
+    Ok(result)
+}
+
+/// Verify the CRC32 trailer of `data` and decode `T` from the bytes that precede it. If the
+/// trailer do not match return `Error::CrcMismatch`, `expected` is the CRC in the trailer and
+/// `actual` the CRC of the received bytes.
+pub fn from_bytes_with_crc<'a, T: Decodable<'a>>(data: &'a mut [u8]) -> Result<T, Error> {
+    if data.len() < TRAILER_SIZE {
+        return Err(Error::OutOfBound);
+    }
+    let (payload, trailer) = data.split_at_mut(data.len() - TRAILER_SIZE);
+    let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let actual = crc32(payload);
+    if expected != actual {
+        return Err(Error::CrcMismatch { expected, actual });
+    }
+    T::from_bytes(payload)
+}
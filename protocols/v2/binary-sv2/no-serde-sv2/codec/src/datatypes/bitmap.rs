@@ -0,0 +1,145 @@
+use crate::{
+    codec::{
+        decodable::{Decodable, DecodableField, DecodablePrimitive, FieldMarker, PrimitiveMarker},
+        encodable::{EncodableField, EncodablePrimitive},
+        GetSize, IntoStatic, SizeHint,
+    },
+    Error,
+};
+use alloc::vec::Vec;
+
+/// Presence bitmap of `N` bytes for the optional fields of a message, the bit `i` is the bit
+/// `i % 8` (least significant first) of the byte `i / 8`. It is encoded as `N` raw bytes.
+///
+/// The optional fields that follow a bitmap are decoded with [`BitmapDecoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bitmap<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Bitmap<N> {
+    /// Number of fields that the bitmap can flag
+    pub const BITS: usize = N * 8;
+
+    pub fn new() -> Self {
+        Self([0; N])
+    }
+
+    /// Return false also if `bit` is not in the bitmap
+    pub fn is_set(&self, bit: usize) -> bool {
+        bit < Self::BITS && self.0[bit / 8] & (1 << (bit % 8)) != 0
+    }
+
+    pub fn set(&mut self, bit: usize) -> Result<(), Error> {
+        if bit >= Self::BITS {
+            return Err(Error::OutOfBound);
+        }
+        self.0[bit / 8] |= 1 << (bit % 8);
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for Bitmap<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> IntoStatic for Bitmap<N> {
+    type Owned = Self;
+
+    fn into_static(self) -> Self::Owned {
+        self
+    }
+}
+
+impl<'a, const N: usize> Decodable<'a> for Bitmap<N> {
+    fn get_structure(_: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+        Ok(vec![PrimitiveMarker::U8.into(); N])
+    }
+
+    fn from_decoded_fields(data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+        if data.len() != N {
+            return Err(Error::DecodableConversionError);
+        }
+        let mut bitmap = [0; N];
+        for (byte, field) in bitmap.iter_mut().zip(data) {
+            match field {
+                DecodableField::Primitive(DecodablePrimitive::U8(b)) => *byte = b,
+                _ => return Err(Error::DecodableConversionError),
+            }
+        }
+        Ok(Self(bitmap))
+    }
+}
+
+impl<const N: usize> GetSize for Bitmap<N> {
+    fn get_size(&self) -> usize {
+        N
+    }
+}
+
+impl<'a, const N: usize> From<Bitmap<N>> for EncodableField<'a> {
+    fn from(v: Bitmap<N>) -> Self {
+        EncodableField::Struct(
+            v.0.iter()
+                .map(|b| EncodableField::Primitive(EncodablePrimitive::U8(*b)))
+                .collect(),
+        )
+    }
+}
+
+/// Decode the optional fields that follow a [`Bitmap`], the field `i` is present (and take
+/// bytes on the wire) only if the bit `i` of the bitmap is set. The fields must be decoded in order
+/// with [`BitmapDecoder::next_field`].
+#[derive(Debug)]
+pub struct BitmapDecoder<'a, const N: usize> {
+    bitmap: Bitmap<N>,
+    next_bit: usize,
+    tail: &'a mut [u8],
+}
+
+impl<'a, const N: usize> BitmapDecoder<'a, N> {
+    /// Read the bitmap from the start of `data`
+    pub fn new(data: &'a mut [u8]) -> Result<Self, Error> {
+        if data.len() < N {
+            return Err(Error::OutOfBound);
+        }
+        let (head, tail) = data.split_at_mut(N);
+        let mut bitmap = [0; N];
+        bitmap.copy_from_slice(head);
+        Ok(Self {
+            bitmap: Bitmap(bitmap),
+            next_bit: 0,
+            tail,
+        })
+    }
+
+    pub fn bitmap(&self) -> Bitmap<N> {
+        self.bitmap
+    }
+
+    /// Decode the next optional field, return None if its bit is not set. Return
+    /// `Error::OutOfBound` if every bit of the bitmap has already been used.
+    pub fn next_field<T: Decodable<'a>>(&mut self) -> Result<Option<T>, Error> {
+        if self.next_bit >= Bitmap::<N>::BITS {
+            return Err(Error::OutOfBound);
+        }
+        let bit = self.next_bit;
+        self.next_bit += 1;
+        if !self.bitmap.is_set(bit) {
+            return Ok(None);
+        }
+        let tail = core::mem::take(&mut self.tail);
+        let size = T::get_structure(tail)?.size_hint_(tail, 0)?;
+        if size > tail.len() {
+            return Err(Error::OutOfBound);
+        }
+        let (head, tail) = tail.split_at_mut(size);
+        self.tail = tail;
+        Ok(Some(T::from_bytes(head)?))
+    }
+
+    /// Bytes that follow the last decoded field
+    pub fn into_remaining(self) -> &'a mut [u8] {
+        self.tail
+    }
+}
@@ -138,8 +138,21 @@ impl Fixed for f32 {
 
 impl_sv2_for_unsigned!(f32);
 
+// Impl signed as a primitives, not in the spec, little endian two's complement like the unsigned
+
+impl Fixed for i32 {
+    const SIZE: usize = 4;
+}
+
+impl Fixed for i64 {
+    const SIZE: usize = 8;
+}
+
+impl_sv2_for_unsigned!(i32);
+impl_sv2_for_unsigned!(i64);
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct U24(pub(crate) u32);
 
 impl Fixed for U24 {
@@ -147,6 +160,26 @@ impl Fixed for U24 {
 }
 
 impl U24 {
+    /// Return `Error::InvalidU24` if `value` is bigger than `0xFFFFFF`
+    ///
+    /// ```
+    /// use binary_codec_sv2::{Error, U24};
+    ///
+    /// assert_eq!(U24::new(0xFFFFFF).unwrap().as_u32(), 0xFFFFFF);
+    /// assert!(matches!(U24::new(0x1000000), Err(Error::InvalidU24(0x1000000))));
+    /// ```
+    pub fn new(value: u32) -> Result<Self, Error> {
+        if value <= 16777215 {
+            Ok(Self(value))
+        } else {
+            Err(Error::InvalidU24(value))
+        }
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+
     fn from_le_bytes(b: [u8; Self::SIZE]) -> Self {
         let inner = u32::from_le_bytes([b[0], b[1], b[2], 0]);
         Self(inner)
@@ -164,11 +197,7 @@ impl TryFrom<u32> for U24 {
     type Error = Error;
 
     fn try_from(value: u32) -> Result<Self, Self::Error> {
-        if value <= 16777215 {
-            Ok(Self(value))
-        } else {
-            Err(Error::InvalidU24(value))
-        }
+        Self::new(value)
     }
 }
 
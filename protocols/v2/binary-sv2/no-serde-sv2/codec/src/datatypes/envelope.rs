@@ -0,0 +1,108 @@
+use crate::{
+    codec::{
+        decodable::{Decodable, DecodableField, DecodablePrimitive, FieldMarker, PrimitiveMarker},
+        encodable::{EncodableField, EncodablePrimitive},
+        GetSize, IntoStatic,
+    },
+    limits::MAX_FRAME_PAYLOAD,
+    Error,
+};
+use alloc::vec::Vec;
+#[cfg(not(feature = "no_std"))]
+use std::io::Read;
+
+/// Wrap `T` in a length delimited envelope encoded as:
+/// ```txt
+/// len: u32 // size of the encoded T, little endian
+/// T
+/// ```
+/// When decoding, the bytes of the envelope that are not part of `T` are skipped, so a newer
+/// version of a message (with more fields at the end) can be decoded by an older decoder.
+///
+/// The trailing bytes are skipped only by `from_bytes` and `from_reader` so an `Envelope` must be
+/// the outermost type and not a field of a derived struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope<T>(pub T);
+
+impl<T> Envelope<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: IntoStatic> IntoStatic for Envelope<T> {
+    type Owned = Envelope<T::Owned>;
+
+    fn into_static(self) -> Self::Owned {
+        Envelope(self.0.into_static())
+    }
+}
+
+const LEN_SIZE: usize = 4;
+
+fn envelope_len(data: &[u8]) -> Result<usize, Error> {
+    if data.len() < LEN_SIZE {
+        return Err(Error::OutOfBound);
+    }
+    let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if data.len() < LEN_SIZE + len {
+        return Err(Error::OutOfBound);
+    }
+    Ok(len)
+}
+
+impl<'a, T: Decodable<'a>> Decodable<'a> for Envelope<T> {
+    fn get_structure(data: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+        let len = envelope_len(data)?;
+        let mut structure = vec![PrimitiveMarker::U32.into()];
+        structure.extend(T::get_structure(&data[LEN_SIZE..LEN_SIZE + len])?);
+        Ok(structure)
+    }
+
+    fn from_decoded_fields(mut data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+        if data.is_empty() {
+            return Err(Error::NoDecodableFieldPassed);
+        }
+        match data.remove(0) {
+            DecodableField::Primitive(DecodablePrimitive::U32(_)) => {
+                Ok(Self(T::from_decoded_fields(data)?))
+            }
+            _ => Err(Error::DecodableConversionError),
+        }
+    }
+
+    fn from_bytes(data: &'a mut [u8]) -> Result<Self, Error> {
+        let len = envelope_len(data)?;
+        Ok(Self(T::from_bytes(&mut data[LEN_SIZE..LEN_SIZE + len])?))
+    }
+
+    #[cfg(not(feature = "no_std"))]
+    fn from_reader(reader: &mut impl Read) -> Result<Self, Error> {
+        let mut len = [0_u8; LEN_SIZE];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        // The length is not trusted, an envelope can not be bigger than the frame that carry it
+        if len > MAX_FRAME_PAYLOAD {
+            return Err(Error::ReadError(len, MAX_FRAME_PAYLOAD));
+        }
+        let mut inner = vec![0_u8; len];
+        reader.read_exact(&mut inner)?;
+        Ok(Self(T::from_reader(&mut &inner[..])?))
+    }
+}
+
+impl<T: GetSize> GetSize for Envelope<T> {
+    fn get_size(&self) -> usize {
+        LEN_SIZE + self.0.get_size()
+    }
+}
+
+impl<'a, T: GetSize + Into<EncodableField<'a>>> From<Envelope<T>> for EncodableField<'a> {
+    fn from(v: Envelope<T>) -> Self {
+        let len = v.0.get_size() as u32;
+        EncodableField::Struct(vec![
+            EncodableField::Primitive(EncodablePrimitive::U32(len)),
+            v.0.into(),
+        ])
+    }
+}
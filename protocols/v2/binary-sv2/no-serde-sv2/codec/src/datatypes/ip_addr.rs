@@ -0,0 +1,71 @@
+//! `Ipv4Addr` and `Ipv6Addr` encoded as their octets in network order (4 and 16 bytes), not in
+//! the spec. `core::net` is not available on the supported toolchain so they need std.
+use crate::{
+    codec::{
+        decodable::{Decodable, DecodableField, DecodablePrimitive, FieldMarker, PrimitiveMarker},
+        encodable::{EncodableField, EncodablePrimitive},
+        GetSize, IntoStatic,
+    },
+    Error,
+};
+use alloc::vec::Vec;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+fn octets_from_fields<const N: usize>(data: Vec<DecodableField>) -> Result<[u8; N], Error> {
+    if data.len() != N {
+        return Err(Error::DecodableConversionError);
+    }
+    let mut octets = [0; N];
+    for (octet, field) in octets.iter_mut().zip(data) {
+        match field {
+            DecodableField::Primitive(DecodablePrimitive::U8(b)) => *octet = b,
+            _ => return Err(Error::DecodableConversionError),
+        }
+    }
+    Ok(octets)
+}
+
+fn octets_to_field<'a>(octets: &[u8]) -> EncodableField<'a> {
+    EncodableField::Struct(
+        octets
+            .iter()
+            .map(|b| EncodableField::Primitive(EncodablePrimitive::U8(*b)))
+            .collect(),
+    )
+}
+
+macro_rules! impl_codec_for_ip_addr {
+    ($a:ty, $size:expr) => {
+        impl<'a> Decodable<'a> for $a {
+            fn get_structure(_: &[u8]) -> Result<Vec<FieldMarker>, Error> {
+                Ok(vec![PrimitiveMarker::U8.into(); $size])
+            }
+
+            fn from_decoded_fields(data: Vec<DecodableField<'a>>) -> Result<Self, Error> {
+                Ok(octets_from_fields::<$size>(data)?.into())
+            }
+        }
+
+        impl GetSize for $a {
+            fn get_size(&self) -> usize {
+                $size
+            }
+        }
+
+        impl<'a> From<$a> for EncodableField<'a> {
+            fn from(v: $a) -> Self {
+                octets_to_field(&v.octets())
+            }
+        }
+
+        impl IntoStatic for $a {
+            type Owned = Self;
+
+            fn into_static(self) -> Self::Owned {
+                self
+            }
+        }
+    };
+}
+impl_codec_for_ip_addr!(Ipv4Addr, 4);
+impl_codec_for_ip_addr!(Ipv6Addr, 16);
@@ -4,11 +4,21 @@ use crate::{
 };
 mod non_copy_data_types;
 
+mod envelope;
+pub use envelope::Envelope;
+
+mod bitmap;
+pub use bitmap::{Bitmap, BitmapDecoder};
+
+#[cfg(not(feature = "no_std"))]
+mod ip_addr;
+
 mod copy_data_types;
 use crate::codec::decodable::FieldMarker;
 pub use copy_data_types::U24;
 pub use non_copy_data_types::{
-    Inner, PubKey, Seq0255, Seq064K, Signature, Str0255, U32AsRef, B016M, B0255, B032, B064K, U256,
+    u256_bytes_from_hex, Inner, PubKey, Seq0255, Seq064K, Signature, Str0255, U32AsRef, B016M,
+    B0255, B032, B064K, U256,
 };
 
 #[cfg(not(feature = "no_std"))]
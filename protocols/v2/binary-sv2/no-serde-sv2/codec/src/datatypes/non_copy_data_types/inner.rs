@@ -1,9 +1,10 @@
 use super::IntoOwned;
 use crate::{
-    codec::{GetSize, SizeHint},
+    codec::{GetSize, IntoStatic, SizeHint},
     datatypes::Sv2DataType,
     Error,
 };
+use alloc::borrow::Cow;
 use core::convert::TryFrom;
 use std::convert::TryInto;
 
@@ -80,6 +81,19 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 {
 }
 
+/// Hash the bytes so that a `Ref` and an `Owned` with the same bytes have the same hash, like
+/// they are equal for `PartialEq`
+impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    core::hash::Hash for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            Inner::Ref(data) => data[..].hash(state),
+            Inner::Owned(data) => data[..].hash(state),
+        }
+    }
+}
+
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
@@ -159,24 +173,17 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 }
 
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
-    TryFrom<&'a mut [u8]> for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+    Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
-    type Error = Error;
-
-    fn try_from(value: &'a mut [u8]) -> Result<Self, Self::Error> {
-        if ISFIXED && value.len() == SIZE {
-            Ok(Self::Ref(value))
-        } else if ISFIXED {
-            Err(Error::ValueExceedsMaxSize(
-                ISFIXED,
-                SIZE,
-                HEADERSIZE,
-                MAXSIZE,
-                value.to_vec(),
-                value.len(),
-            ))
-        } else if value.len() <= MAXSIZE {
-            Ok(Self::Ref(value))
+    /// Return `Error::ValueExceedsMaxSize` if `value` has not exactly `SIZE` bytes for fixed size
+    /// types or if it is longer than `MAXSIZE` for variable size types.
+    fn check_len(value: &[u8]) -> Result<(), Error> {
+        let is_valid = match ISFIXED {
+            true => value.len() == SIZE,
+            false => value.len() <= MAXSIZE,
+        };
+        if is_valid {
+            Ok(())
         } else {
             Err(Error::ValueExceedsMaxSize(
                 ISFIXED,
@@ -188,6 +195,29 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
             ))
         }
     }
+
+    /// Borrow `value` without copying it, fail if the length of `value` is not valid for the type
+    /// so that an oversized value is refused here and not when the message is encoded.
+    pub fn new_ref(value: &'a mut [u8]) -> Result<Self, Error> {
+        Self::check_len(value)?;
+        Ok(Self::Ref(value))
+    }
+
+    /// Like `new_ref` but take the ownership of `value`
+    pub fn new_owned(value: Vec<u8>) -> Result<Self, Error> {
+        Self::check_len(&value)?;
+        Ok(Self::Owned(value))
+    }
+}
+
+impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    TryFrom<&'a mut [u8]> for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    type Error = Error;
+
+    fn try_from(value: &'a mut [u8]) -> Result<Self, Self::Error> {
+        Self::new_ref(value)
+    }
 }
 
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
@@ -196,29 +226,7 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
     type Error = Error;
 
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        if ISFIXED && value.len() == SIZE {
-            Ok(Self::Owned(value))
-        } else if ISFIXED {
-            Err(Error::ValueExceedsMaxSize(
-                ISFIXED,
-                SIZE,
-                HEADERSIZE,
-                MAXSIZE,
-                value.to_vec(),
-                value.len(),
-            ))
-        } else if value.len() <= MAXSIZE {
-            Ok(Self::Owned(value))
-        } else {
-            Err(Error::ValueExceedsMaxSize(
-                ISFIXED,
-                SIZE,
-                HEADERSIZE,
-                MAXSIZE,
-                value.to_vec(),
-                value.len(),
-            ))
-        }
+        Self::new_owned(value)
     }
 }
 
@@ -237,11 +245,11 @@ impl<'a, const ISFIXED: bool, const HEADERSIZE: usize, const SIZE: usize, const
     SizeHint for Inner<'a, ISFIXED, HEADERSIZE, SIZE, MAXSIZE>
 {
     fn size_hint(data: &[u8], offset: usize) -> Result<usize, Error> {
-        Self::expected_length(&data[offset..])
+        Self::expected_length(data.get(offset..).ok_or(Error::OutOfBound)?)
     }
 
     fn size_hint_(&self, data: &[u8], offset: usize) -> Result<usize, Error> {
-        Self::expected_length(&data[offset..])
+        Self::expected_length(data.get(offset..).ok_or(Error::OutOfBound)?)
     }
 }
 use crate::codec::decodable::FieldMarker;
@@ -296,6 +304,8 @@ where
 
     #[cfg(not(feature = "no_std"))]
     fn to_writer_(&self, writer: &mut impl Write) -> Result<(), E> {
+        // Same length prefix of `to_slice_unchecked`, empty for fixed size types
+        writer.write_all(&self.len().to_le_bytes()[..HEADERSIZE])?;
         match self {
             Inner::Ref(data) => {
                 writer.write_all(data)?;
@@ -325,6 +335,9 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
+    /// Copy the borrowed bytes, if any, so that the value does not borrow the decode buffer
+    /// anymore, eg to keep a decoded `U256`, `Str0255` or `B064K` after the frame is dropped
+    /// without going through `to_vec().try_into()`. An owned value is returned as it is.
     pub fn into_static(self) -> Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE> {
         match self {
             Inner::Ref(data) => {
@@ -337,6 +350,17 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
     }
 }
 
+impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    IntoStatic for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    type Owned = Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>;
+
+    // `Self::Owned` would be ambiguous with the `Inner::Owned` variant
+    fn into_static(self) -> Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE> {
+        Inner::into_static(self)
+    }
+}
+
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Clone for Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
 {
@@ -362,3 +386,21 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
         }
     }
 }
+
+impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>
+{
+    /// The bytes of the value as a `Cow`, always `Cow::Borrowed` so that an `Owned` value is not
+    /// copied. Use `into_cow` to keep the `Ref`/`Owned` split.
+    pub fn as_cow(&self) -> Cow<[u8]> {
+        Cow::Borrowed(self.as_ref())
+    }
+
+    /// `Cow::Borrowed` for a `Ref` value and `Cow::Owned` for an `Owned` one, nothing is copied
+    pub fn into_cow(self) -> Cow<'a, [u8]> {
+        match self {
+            Inner::Ref(data) => Cow::Borrowed(data),
+            Inner::Owned(data) => Cow::Owned(data),
+        }
+    }
+}
@@ -11,6 +11,11 @@ trait IntoOwned {
 pub use inner::Inner;
 pub use seq_inner::{Seq0255, Seq064K};
 
+/// A u32 encoded as the sv2 U32 (4 bytes little endian) that when decoded keep a reference to the
+/// bytes of the frame instead of copying them. Use it instead of `u32` for fields that must be
+/// rewritten in place in an already received frame, eg `request_id` is replaced by a proxy
+/// with `inner_as_mut` before relaying the message without having to re-encode it. Everywhere
+/// else use `u32`.
 pub type U32AsRef<'a> = Inner<'a, true, 4, 0, 0>;
 pub type U256<'a> = Inner<'a, true, 32, 0, 0>;
 pub type PubKey<'a> = Inner<'a, true, 32, 0, 0>;
@@ -27,6 +32,85 @@ impl<'decoder> From<[u8; 32]> for U256<'decoder> {
     }
 }
 
+impl<'decoder> From<&[u8; 32]> for U256<'decoder> {
+    fn from(v: &[u8; 32]) -> Self {
+        Inner::Owned(v.to_vec())
+    }
+}
+
+/// Borrow the array without copying it, like a decoded `U256`
+impl<'decoder> From<&'decoder mut [u8; 32]> for U256<'decoder> {
+    fn from(v: &'decoder mut [u8; 32]) -> Self {
+        Inner::Ref(v)
+    }
+}
+
+/// Compare the 32 bytes as a big endian unsigned integer, the first byte is the most
+/// significant. It is the order used by the roles for targets, but note that the Sv2 spec encode
+/// the U256 integers in little endian: a little endian value must be reversed to be compared as a
+/// number.
+impl<'decoder> PartialOrd for U256<'decoder> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'decoder> Ord for U256<'decoder> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.inner_as_ref().cmp(other.inner_as_ref())
+    }
+}
+
+// 32 bytes are always a valid B032 so there is no need of the size check done by TryFrom
+impl<'decoder> From<[u8; 32]> for B032<'decoder> {
+    fn from(v: [u8; 32]) -> Self {
+        Inner::Owned(v.into())
+    }
+}
+
+impl<'decoder> From<&[u8; 32]> for B032<'decoder> {
+    fn from(v: &[u8; 32]) -> Self {
+        Inner::Owned(v.to_vec())
+    }
+}
+
+/// Borrow the array without copying it, like a decoded `B032`
+impl<'decoder> From<&'decoder mut [u8; 32]> for B032<'decoder> {
+    fn from(v: &'decoder mut [u8; 32]) -> Self {
+        Inner::Ref(v)
+    }
+}
+
+/// Parse a 64 chars hex string into the 32 bytes of an U256, the first two chars are the first
+/// byte and so on, that is the same order of `U256::to_vec`. It is a const fn so that `u256!` can
+/// evaluate it at compile time.
+///
+/// # Panics
+///
+/// If `hex` is not 64 chars long or if it contains a non hex char, when evaluated in a const
+/// context the panic is a compilation error.
+pub const fn u256_bytes_from_hex(hex: &str) -> [u8; 32] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("U256 hex literal contains a non hex char"),
+        }
+    }
+    let hex = hex.as_bytes();
+    if hex.len() != 64 {
+        panic!("U256 hex literal must be 64 chars long");
+    }
+    let mut bytes = [0_u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        bytes[i] = (nibble(hex[2 * i]) << 4) | nibble(hex[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
+
 #[cfg(not(feature = "with_serde"))]
 #[cfg(feature = "prop_test")]
 impl<'a> U256<'a> {
@@ -79,3 +163,9 @@ impl<'a> From<&'a U32AsRef<'a>> for u32 {
         u32::from_le_bytes([b[0], b[1], b[2], b[3]])
     }
 }
+
+impl<'a> From<U32AsRef<'a>> for u32 {
+    fn from(v: U32AsRef<'a>) -> Self {
+        v.as_u32()
+    }
+}
@@ -2,9 +2,10 @@ use crate::{
     codec::{
         decodable::{Decodable, DecodableField, FieldMarker, GetMarker, PrimitiveMarker},
         encodable::{EncodableField, EncodablePrimitive},
-        Fixed, GetSize,
+        Fixed, GetSize, IntoStatic,
     },
     datatypes::{Sv2DataType, *},
+    limits::DecodeBudget,
     Error,
 };
 use core::marker::PhantomData;
@@ -31,34 +32,68 @@ impl<'a, const SIZE: usize> Seq0255<'a, super::inner::Inner<'a, true, SIZE, 0, 0
     }
 }
 
+impl<'a, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
+    Seq064K<'a, super::inner::Inner<'a, false, SIZE, HEADERSIZE, MAXSIZE>>
+{
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.0.iter().map(|x| x.to_vec()).collect()
+    }
+    pub fn inner_as_ref(&self) -> Vec<&[u8]> {
+        self.0.iter().map(|x| x.inner_as_ref()).collect()
+    }
+}
+
+impl<'a, const SIZE: usize> Seq064K<'a, super::inner::Inner<'a, true, SIZE, 0, 0>> {
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.0.iter().map(|x| x.to_vec()).collect()
+    }
+    pub fn inner_as_ref(&self) -> Vec<&[u8]> {
+        self.0.iter().map(|x| x.inner_as_ref()).collect()
+    }
+}
+
 #[cfg(not(feature = "no_std"))]
 use std::io::Read;
 
 /// The liftime is here only for type compatibility with serde-sv2
 #[repr(C)]
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Seq0255<'a, T>(pub Vec<T>, PhantomData<&'a T>);
 
 impl<'a, T: 'a> Seq0255<'a, T> {
     const HEADERSIZE: usize = 1;
+    const MAXSIZE: usize = 255;
 
-    /// Return the len of the inner vector
+    /// Return the len of the inner vector, `Error::SeqExceedsMaxSize` if it is bigger than
+    /// `MAXSIZE`
     fn expected_len(data: &[u8]) -> Result<usize, Error> {
         if data.len() >= Self::HEADERSIZE {
-            Ok(data[0] as usize)
+            Self::check_len(data[0] as usize)
         } else {
             Err(Error::ReadError(data.len(), Self::HEADERSIZE))
         }
     }
 
     pub fn new(inner: Vec<T>) -> Result<Self, Error> {
-        if inner.len() <= 255 {
+        if inner.len() <= Self::MAXSIZE {
             Ok(Self(inner, PhantomData))
         } else {
             Err(Error::SeqExceedsMaxSize)
         }
     }
 
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     //pub fn try_from_slice(inner: &'a mut [T]) -> Result<Self, Error> {
     //    if inner.len() <= 255 {
     //        let inner_: Vec<T> = vec![];
@@ -83,28 +118,42 @@ impl<'a, T: GetSize> GetSize for Seq0255<'a, T> {
 }
 
 /// The liftime is here only for type compatibility with serde-sv2
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Seq064K<'a, T>(pub(crate) Vec<T>, PhantomData<&'a T>);
 
 impl<'a, T: 'a> Seq064K<'a, T> {
     const HEADERSIZE: usize = 2;
+    const MAXSIZE: usize = 65535;
 
-    /// Return the len of the inner vector
+    /// Return the len of the inner vector, `Error::SeqExceedsMaxSize` if it is bigger than
+    /// `MAXSIZE`
     fn expected_len(data: &[u8]) -> Result<usize, Error> {
         if data.len() >= Self::HEADERSIZE {
-            Ok(u16::from_le_bytes([data[0], data[1]]) as usize)
+            Self::check_len(u16::from_le_bytes([data[0], data[1]]) as usize)
         } else {
             Err(Error::ReadError(data.len(), Self::HEADERSIZE))
         }
     }
 
     pub fn new(inner: Vec<T>) -> Result<Self, Error> {
-        if inner.len() <= 65535 {
+        if inner.len() <= Self::MAXSIZE {
             Ok(Self(inner, PhantomData))
         } else {
             Err(Error::SeqExceedsMaxSize)
         }
     }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 impl<'a, T: GetSize> GetSize for Seq064K<'a, T> {
@@ -119,6 +168,18 @@ impl<'a, T: GetSize> GetSize for Seq064K<'a, T> {
 
 macro_rules! impl_codec_for_sequence {
     ($a:ty) => {
+        impl<'a, T: 'a> $a {
+            // The header can not encode a count bigger than `MAXSIZE` but the count is checked
+            // anyway so that a change of the header size can not silently allow longer sequences
+            fn check_len(len: usize) -> Result<usize, Error> {
+                if len <= Self::MAXSIZE {
+                    Ok(len)
+                } else {
+                    Err(Error::SeqExceedsMaxSize)
+                }
+            }
+        }
+
         impl<'a, T: 'a + Sv2DataType<'a> + GetMarker + GetSize + Decodable<'a>> Decodable<'a>
             for $a
         {
@@ -135,6 +196,26 @@ macro_rules! impl_codec_for_sequence {
                 Ok(inner)
             }
 
+            fn get_structure_limited(
+                data: &[u8],
+                budget: &mut DecodeBudget,
+                depth: usize,
+            ) -> Result<Vec<FieldMarker>, Error> {
+                let len = Self::expected_len(data)?;
+                let header = FieldMarker::Primitive(PrimitiveMarker::U8);
+                let inner_type = T::get_marker();
+                budget.check_depth(depth)?;
+                budget.count(&header, depth + 1, Self::HEADERSIZE)?;
+                if len > 0 {
+                    budget.count(&inner_type, depth + 1, len)?;
+                }
+                // Allocated only once the declared elements are within the limits
+                let mut inner = Vec::with_capacity(len + Self::HEADERSIZE);
+                inner.resize(Self::HEADERSIZE, header);
+                inner.resize(len + Self::HEADERSIZE, inner_type);
+                Ok(inner)
+            }
+
             fn from_decoded_fields(
                 data: Vec<crate::codec::decodable::DecodableField<'a>>,
             ) -> Result<Self, Error> {
@@ -165,6 +246,10 @@ macro_rules! impl_codec_for_sequence {
 
                 for _ in 0..len {
                     let element_size = T::size_hint(tail, 0)?;
+                    // The header can declare more elements than the ones in data
+                    if element_size > tail.len() {
+                        return Err(Error::OutOfBound);
+                    }
                     let (head, t) = tail.split_at_mut(element_size);
                     tail = t;
                     inner.push(T::from_bytes_unchecked(head));
@@ -236,12 +321,66 @@ impl_into_encodable_field_for_seq!(u16);
 impl_into_encodable_field_for_seq!(U24);
 impl_into_encodable_field_for_seq!(u32);
 impl_into_encodable_field_for_seq!(u64);
+impl_into_encodable_field_for_seq!(i32);
+impl_into_encodable_field_for_seq!(i64);
 impl_into_encodable_field_for_seq!(U256<'a>);
 impl_into_encodable_field_for_seq!(Signature<'a>);
 impl_into_encodable_field_for_seq!(B0255<'a>);
 impl_into_encodable_field_for_seq!(B064K<'a>);
 impl_into_encodable_field_for_seq!(B016M<'a>);
 
+// Encode a sequence from a reference, `'b` is the lifetime of the sequence and of its elements
+macro_rules! impl_into_encodable_field_for_seq_ref {
+    ($a:ty) => {
+        impl<'a, 'b: 'a> From<&'a Seq064K<'b, $a>> for EncodableField<'a> {
+            fn from(v: &'a Seq064K<'b, $a>) -> Self {
+                let inner_len = v.0.len() as u16;
+                let mut as_encodable: Vec<EncodableField> =
+                    Vec::with_capacity((inner_len as usize) + 2);
+                as_encodable.push(EncodableField::Primitive(EncodablePrimitive::OwnedU8(
+                    inner_len.to_le_bytes()[0],
+                )));
+                as_encodable.push(EncodableField::Primitive(EncodablePrimitive::OwnedU8(
+                    inner_len.to_le_bytes()[1],
+                )));
+                for element in &v.0 {
+                    as_encodable.push(element.into());
+                }
+                EncodableField::Struct(as_encodable)
+            }
+        }
+
+        impl<'a, 'b: 'a> From<&'a Seq0255<'b, $a>> for EncodableField<'a> {
+            fn from(v: &'a Seq0255<'b, $a>) -> Self {
+                let inner_len = v.0.len() as u8;
+                let mut as_encodable: Vec<EncodableField> =
+                    Vec::with_capacity((inner_len as usize) + 1);
+                as_encodable.push(EncodableField::Primitive(EncodablePrimitive::OwnedU8(
+                    inner_len,
+                )));
+                for element in &v.0 {
+                    as_encodable.push(element.into());
+                }
+                EncodableField::Struct(as_encodable)
+            }
+        }
+    };
+}
+
+impl_into_encodable_field_for_seq_ref!(bool);
+impl_into_encodable_field_for_seq_ref!(u8);
+impl_into_encodable_field_for_seq_ref!(u16);
+impl_into_encodable_field_for_seq_ref!(U24);
+impl_into_encodable_field_for_seq_ref!(u32);
+impl_into_encodable_field_for_seq_ref!(u64);
+impl_into_encodable_field_for_seq_ref!(i32);
+impl_into_encodable_field_for_seq_ref!(i64);
+impl_into_encodable_field_for_seq_ref!(U256<'b>);
+impl_into_encodable_field_for_seq_ref!(Signature<'b>);
+impl_into_encodable_field_for_seq_ref!(B0255<'b>);
+impl_into_encodable_field_for_seq_ref!(B064K<'b>);
+impl_into_encodable_field_for_seq_ref!(B016M<'b>);
+
 #[cfg(feature = "prop_test")]
 impl<'a, T> std::convert::TryFrom<Seq0255<'a, T>> for Vec<T> {
     type Error = &'static str;
@@ -278,7 +417,44 @@ impl<'a, T> From<Vec<T>> for Seq064K<'a, T> {
     }
 }
 
+impl<'a, T> IntoIterator for Seq0255<'a, T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, 'b, T> IntoIterator for &'b Seq0255<'a, T> {
+    type Item = &'b T;
+    type IntoIter = core::slice::Iter<'b, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for Seq064K<'a, T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, 'b, T> IntoIterator for &'b Seq064K<'a, T> {
+    type Item = &'b T;
+    type IntoIter = core::slice::Iter<'b, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
 impl<'a, T: Fixed> Seq0255<'a, T> {
+    /// Fixed size elements are always owned so only the lifetime changes
     pub fn into_static(self) -> Seq0255<'static, T> {
         // Safe unwrap cause the initial value is a valid Seq0255
         Seq0255::new(self.0).unwrap()
@@ -288,6 +464,7 @@ impl<'a, T: Fixed> Seq0255<'a, T> {
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Seq0255<'a, Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>>
 {
+    /// Call `Inner::into_static` on every element
     pub fn into_static(
         self,
     ) -> Seq0255<'static, Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>> {
@@ -299,6 +476,7 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
 }
 
 impl<'a, T: Fixed> Seq064K<'a, T> {
+    /// Fixed size elements are always owned so only the lifetime changes
     pub fn into_static(self) -> Seq064K<'static, T> {
         // Safe unwrap cause the initial value is a valid Seq064K
         Seq064K::new(self.0).unwrap()
@@ -308,6 +486,7 @@ impl<'a, T: Fixed> Seq064K<'a, T> {
 impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize>
     Seq064K<'a, Inner<'a, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>>
 {
+    /// Call `Inner::into_static` on every element
     pub fn into_static(
         self,
     ) -> Seq064K<'static, Inner<'static, ISFIXED, SIZE, HEADERSIZE, MAXSIZE>> {
@@ -317,3 +496,25 @@ impl<'a, const ISFIXED: bool, const SIZE: usize, const HEADERSIZE: usize, const
         Seq064K::new(static_seq).unwrap()
     }
 }
+
+impl<'a, T: IntoStatic> IntoStatic for Seq0255<'a, T> {
+    type Owned = Seq0255<'static, T::Owned>;
+
+    fn into_static(self) -> Self::Owned {
+        Seq0255(
+            self.0.into_iter().map(IntoStatic::into_static).collect(),
+            PhantomData,
+        )
+    }
+}
+
+impl<'a, T: IntoStatic> IntoStatic for Seq064K<'a, T> {
+    type Owned = Seq064K<'static, T::Owned>;
+
+    fn into_static(self) -> Self::Owned {
+        Seq064K(
+            self.0.into_iter().map(IntoStatic::into_static).collect(),
+            PhantomData,
+        )
+    }
+}
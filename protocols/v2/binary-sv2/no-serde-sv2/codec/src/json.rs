@@ -0,0 +1,124 @@
+//! One way projection of the Sv2 datatypes into JSON, it is meant for debugging and tooling (eg a
+//! protocol inspector) and do not need the serde codec.
+//!
+//! Bytes (`U256`, `B0255`, `Str0255`, ...) are rendered as lowercase hex strings and sequences as
+//! arrays. The field names of a message are not known by the codec so a message is rendered as an
+//! array with the values of its fields in declaration order.
+use crate::{
+    codec::encodable::{EncodableField, EncodablePrimitive},
+    datatypes::{Inner, Seq0255, Seq064K, U24},
+};
+use alloc::{string::String, vec::Vec};
+pub use serde_json::Value;
+
+pub trait ToJsonValue {
+    fn to_json_value(&self) -> Value;
+}
+
+macro_rules! impl_to_json_value_for_primitive {
+    ($a:ty) => {
+        impl ToJsonValue for $a {
+            fn to_json_value(&self) -> Value {
+                Value::from(*self)
+            }
+        }
+    };
+}
+
+impl_to_json_value_for_primitive!(bool);
+impl_to_json_value_for_primitive!(u8);
+impl_to_json_value_for_primitive!(u16);
+impl_to_json_value_for_primitive!(u32);
+impl_to_json_value_for_primitive!(u64);
+impl_to_json_value_for_primitive!(i32);
+impl_to_json_value_for_primitive!(i64);
+impl_to_json_value_for_primitive!(f32);
+
+impl ToJsonValue for U24 {
+    fn to_json_value(&self) -> Value {
+        Value::from(self.0)
+    }
+}
+
+impl<'a, const SIZE: usize, const HEADERSIZE: usize, const MAXSIZE: usize> ToJsonValue
+    for Inner<'a, false, SIZE, HEADERSIZE, MAXSIZE>
+{
+    fn to_json_value(&self) -> Value {
+        Value::String(to_hex(self.inner_as_ref()))
+    }
+}
+
+impl<'a, const SIZE: usize> ToJsonValue for Inner<'a, true, SIZE, 0, 0> {
+    fn to_json_value(&self) -> Value {
+        Value::String(to_hex(self.inner_as_ref()))
+    }
+}
+
+impl<'a, T: ToJsonValue> ToJsonValue for Seq0255<'a, T> {
+    fn to_json_value(&self) -> Value {
+        Value::Array(self.iter().map(|x| x.to_json_value()).collect())
+    }
+}
+
+impl<'a, T: ToJsonValue> ToJsonValue for Seq064K<'a, T> {
+    fn to_json_value(&self) -> Value {
+        Value::Array(self.iter().map(|x| x.to_json_value()).collect())
+    }
+}
+
+impl<'a> ToJsonValue for EncodablePrimitive<'a> {
+    fn to_json_value(&self) -> Value {
+        match self {
+            Self::U8(v) => v.to_json_value(),
+            Self::OwnedU8(v) => v.to_json_value(),
+            Self::U16(v) => v.to_json_value(),
+            Self::Bool(v) => v.to_json_value(),
+            Self::U24(v) => v.to_json_value(),
+            Self::U256(v) => v.to_json_value(),
+            Self::Signature(v) => v.to_json_value(),
+            Self::U32(v) => v.to_json_value(),
+            Self::U32AsRef(v) => v.to_json_value(),
+            Self::F32(v) => v.to_json_value(),
+            Self::U64(v) => v.to_json_value(),
+            Self::I32(v) => v.to_json_value(),
+            Self::I64(v) => v.to_json_value(),
+            Self::B032(v) => v.to_json_value(),
+            Self::B0255(v) => v.to_json_value(),
+            Self::B064K(v) => v.to_json_value(),
+            Self::B016M(v) => v.to_json_value(),
+            Self::BytesRef(_, v) => Value::String(to_hex(v)),
+        }
+    }
+}
+
+impl<'a> ToJsonValue for EncodableField<'a> {
+    fn to_json_value(&self) -> Value {
+        match self {
+            Self::Primitive(p) => p.to_json_value(),
+            Self::Struct(fields) => {
+                // `OwnedU8` is used only for the length header of the sequences, the header is
+                // not rendered
+                let elements = fields
+                    .iter()
+                    .skip_while(|f| matches!(f, Self::Primitive(EncodablePrimitive::OwnedU8(_))));
+                Value::Array(elements.map(|f| f.to_json_value()).collect())
+            }
+        }
+    }
+}
+
+/// Render any message (or datatype) that can be encoded as JSON
+pub fn to_json_value<'a, T: Into<EncodableField<'a>>>(message: T) -> Value {
+    message.into().to_json_value()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = Vec::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        hex.push(HEX[(b >> 4) as usize]);
+        hex.push(HEX[(b & 0x0f) as usize]);
+    }
+    // Safe unwrap cause hex contains only ascii chars
+    String::from_utf8(hex).unwrap()
+}
@@ -0,0 +1,83 @@
+//! Decode the big fields of a message only when they are needed, see [`LazyMessage`]
+use crate::{
+    codec::decodable::{Decodable, DecodableField, FieldMarker, PrimitiveMarker},
+    Error, FieldRanges, SizeHint,
+};
+use alloc::vec::Vec;
+
+#[derive(Debug)]
+enum LazyField<'a> {
+    Decoded(DecodableField<'a>),
+    // Encoded bytes of a field that contain a `B016M`, decoded when taken
+    Encoded(&'a mut [u8]),
+    Taken,
+}
+
+/// A message `T` decoded field by field. The fields that contain a `B016M` (eg the transaction
+/// list of `RequestTransactionDataSuccess`) are only delimited when the message is built, and are
+/// decoded by [`LazyMessage::take`]. Every other field is decoded in [`LazyMessage::new`]. Useful
+/// when only the small fields of a big message are needed.
+#[derive(Debug)]
+pub struct LazyMessage<'a> {
+    fields: Vec<LazyField<'a>>,
+    ranges: FieldRanges,
+}
+
+impl<'a> LazyMessage<'a> {
+    /// Split `data` in the fields of `T` and decode the ones that do not contain a `B016M`
+    pub fn new<T: Decodable<'a>>(data: &'a mut [u8]) -> Result<Self, Error> {
+        let structure = T::get_structure(data)?;
+        let mut fields = Vec::with_capacity(structure.len());
+        let mut ranges = Vec::with_capacity(structure.len());
+        let mut offset = 0;
+        let mut tail = data;
+        for (index, field) in structure.iter().enumerate() {
+            let len = field.size_hint_(tail, 0)?;
+            if len > tail.len() {
+                return Err(Error::OutOfBound);
+            }
+            let (head, t) = tail.split_at_mut(len);
+            tail = t;
+            ranges.push((index, offset, len));
+            offset += len;
+            if contains_b016m(field) {
+                fields.push(LazyField::Encoded(head));
+            } else {
+                fields.push(LazyField::Decoded(field.decode(head)?));
+            }
+        }
+        Ok(Self { fields, ranges })
+    }
+
+    /// Byte range of each field of the message, see [`crate::field_ranges`]
+    pub fn ranges(&self) -> &FieldRanges {
+        &self.ranges
+    }
+
+    /// Encoded bytes of the field `index` if it is not decoded yet
+    pub fn encoded(&self, index: usize) -> Option<&[u8]> {
+        match self.fields.get(index) {
+            Some(LazyField::Encoded(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Move the field `index` out of the message as a `D`, the type of the field in `T`. A field
+    /// that contain a `B016M` is decoded now. Return `Error::NoDecodableFieldPassed` if the field
+    /// has already been taken and `Error::OutOfBound` if `T` has no field `index`.
+    pub fn take<D: Decodable<'a>>(&mut self, index: usize) -> Result<D, Error> {
+        let field = self.fields.get_mut(index).ok_or(Error::OutOfBound)?;
+        match core::mem::replace(field, LazyField::Taken) {
+            LazyField::Decoded(decoded) => D::from_decoded_fields(decoded.into()),
+            LazyField::Encoded(data) => D::from_bytes(data),
+            LazyField::Taken => Err(Error::NoDecodableFieldPassed),
+        }
+    }
+}
+
+fn contains_b016m(field: &FieldMarker) -> bool {
+    match field {
+        FieldMarker::Primitive(p) => *p == PrimitiveMarker::B016M,
+        FieldMarker::Struct(fields) => fields.iter().any(contains_b016m),
+    }
+}
@@ -7,6 +7,8 @@
 //! u32      <-> u32
 //! f32      <-> f32 // not in the spec but used
 //! u64      <-> u64 // not in the spec but used
+//! i32      <-> i32 // not in the spec, little endian two's complement
+//! i64      <-> i64 // not in the spec, little endian two's complement
 //! U256     <-> U256
 //! Str0255  <-> STRO_255
 //! Signature<-> SIGNATURE
@@ -18,42 +20,332 @@
 //! Pubkey   <-> PUBKEY
 //! Seq0255  <-> SEQ0_255[T]
 //! Seq064K  <-> SEQ0_64K[T]
+//! Ipv4Addr <-> 4 bytes in network order // not in the spec, needs std
+//! Ipv6Addr <-> 16 bytes in network order // not in the spec, needs std
 //! ```
 #[cfg(not(feature = "no_std"))]
-use std::io::{Error as E, ErrorKind};
+use std::io::{Error as E, ErrorKind, Read};
 
+#[cfg(all(feature = "alloc-metrics", not(feature = "no_std")))]
+pub mod alloc_metrics;
+#[cfg(feature = "arena")]
+pub mod arena;
 mod codec;
+#[cfg(feature = "bitcoin-interop")]
+pub mod compact_size;
+#[cfg(feature = "crc")]
+pub mod crc;
 mod datatypes;
+#[cfg(feature = "json")]
+pub mod json;
+mod lazy;
+mod limits;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 pub use datatypes::{
-    PubKey, Seq0255, Seq064K, Signature, Str0255, U32AsRef, B016M, B0255, B032, B064K, U24, U256,
+    u256_bytes_from_hex, Bitmap, BitmapDecoder, Envelope, PubKey, Seq0255, Seq064K, Signature,
+    Str0255, U32AsRef, B016M, B0255, B032, B064K, U24, U256,
 };
 
 pub use crate::codec::{
     decodable::Decodable,
     encodable::{Encodable, EncodableField},
-    GetSize, SizeHint,
+    size_of_b016m, size_of_b0255, size_of_b032, size_of_b064k, size_of_seq0255, size_of_seq064k,
+    GetSize, IntoStatic, SizeHint,
 };
 
+#[cfg(feature = "crc")]
+pub use crate::crc::{from_bytes_with_crc, to_bytes_with_crc};
+pub use crate::lazy::LazyMessage;
+pub use crate::limits::{from_bytes_limited, DecodeBudget, DecodeLimits};
+
 #[allow(clippy::wrong_self_convention)]
 pub fn to_bytes<T: Encodable + GetSize>(src: T) -> Result<Vec<u8>, Error> {
     let mut result = vec![0_u8; src.get_size()];
     src.to_bytes(&mut result)?;
+    #[cfg(feature = "metrics")]
+    metrics::ENCODED.record(result.len());
+    Ok(result)
+}
+
+/// Like [`to_bytes`] but do not zero initialize the output buffer before encoding into it. The
+/// buffer is allocated with `Vec::with_capacity` and every field is appended with
+/// `Encodable::to_writer`, so each byte is written only once. Useful for large payloads like
+/// `B016M`. Without std it fallback to [`to_bytes`].
+///
+/// ```
+/// use binary_codec_sv2::{to_bytes, to_bytes_unzeroed, B016M};
+/// use core::convert::TryInto;
+///
+/// let payload: B016M = vec![7; 1024].try_into().unwrap();
+/// let encoded = to_bytes_unzeroed(payload.clone()).unwrap();
+/// assert_eq!(encoded, to_bytes(payload).unwrap());
+/// ```
+#[allow(clippy::wrong_self_convention)]
+pub fn to_bytes_unzeroed<T: Encodable + GetSize>(src: T) -> Result<Vec<u8>, Error> {
+    #[cfg(feature = "no_std")]
+    return to_bytes(src);
+    #[cfg(not(feature = "no_std"))]
+    {
+        let mut result = Vec::with_capacity(src.get_size());
+        // The encoder errors (eg a NaN or infinite f32) are wrapped in the io error, unwrap them
+        // so they are the same errors returned by `to_bytes`
+        src.to_writer(&mut result).map_err(|e| {
+            match e.get_ref().map_or(false, |inner| inner.is::<Error>()) {
+                true => *e.into_inner().unwrap().downcast::<Error>().unwrap(),
+                false => Error::from(e),
+            }
+        })?;
+        #[cfg(feature = "metrics")]
+        metrics::ENCODED.record(result.len());
+        Ok(result)
+    }
+}
+
+/// Like [`to_bytes`] but take a reference, so that the caller can keep the message. The fields
+/// are encoded from the reference, nothing is cloned, so a message that is sent to many receivers
+/// does not have to be cloned for each one.
+///
+/// ```
+/// use binary_codec_sv2::{to_bytes_ref, B064K};
+/// use core::convert::TryInto;
+///
+/// let coinbase: B064K = vec![1, 2, 3].try_into().unwrap();
+/// let encoded = to_bytes_ref(&coinbase).unwrap();
+/// assert_eq!(encoded, vec![3, 0, 1, 2, 3]);
+/// assert_eq!(coinbase.to_vec(), vec![1, 2, 3]);
+/// ```
+pub fn to_bytes_ref<'a, T>(src: &'a T) -> Result<Vec<u8>, Error>
+where
+    T: GetSize,
+    &'a T: Into<EncodableField<'a>>,
+{
+    let mut result = vec![0_u8; src.get_size()];
+    src.to_bytes(&mut result)?;
+    #[cfg(feature = "metrics")]
+    metrics::ENCODED.record(result.len());
     Ok(result)
 }
 
 #[allow(clippy::wrong_self_convention)]
 pub fn to_writer<T: Encodable>(src: T, dst: &mut [u8]) -> Result<(), Error> {
-    src.to_bytes(dst)?;
+    let _written = src.to_bytes(dst)?;
+    #[cfg(feature = "metrics")]
+    metrics::ENCODED.record(_written);
     Ok(())
 }
 
+/// Decode a `T` that borrow its non fixed size fields from `data`, nothing is copied. `data` is
+/// `&mut` because the decoded values keep a mutable reference to their bytes (`Inner::Ref` is a
+/// `&'a mut [u8]`), so that they can be changed in place, eg by the noise layer that decrypt the
+/// payload of a frame before decoding it. Use [`from_bytes_copy`] to decode read only bytes.
 pub fn from_bytes<'a, T: Decodable<'a>>(data: &'a mut [u8]) -> Result<T, Error> {
-    T::from_bytes(data)
+    #[cfg(feature = "metrics")]
+    let len = data.len();
+    let decoded = T::from_bytes(data)?;
+    #[cfg(feature = "metrics")]
+    metrics::DECODED.record(len);
+    Ok(decoded)
+}
+
+/// Like [`from_bytes`] but take read only bytes, eg a shared `Arc<[u8]>`. `data` is copied in a
+/// scratch buffer and every field is decoded from it like in `Decodable::from_bytes`, then made
+/// owned so that the returned value do not borrow the scratch buffer.
+///
+/// ```
+/// use binary_codec_sv2::{from_bytes_copy, B0255};
+///
+/// let data: std::sync::Arc<[u8]> = vec![2, 0xaa, 0xbb].into();
+/// let decoded: B0255<'static> = from_bytes_copy(&data).unwrap();
+/// assert_eq!(decoded.to_vec(), vec![0xaa, 0xbb]);
+/// ```
+pub fn from_bytes_copy<T: Decodable<'static>>(data: &[u8]) -> Result<T, Error> {
+    let structure = T::get_structure(data)?;
+    let mut scratch = data.to_vec();
+    let mut fields = Vec::with_capacity(structure.len());
+    let mut tail = &mut scratch[..];
+    for field in structure {
+        let field_size = field.size_hint_(tail, 0)?;
+        if field_size > tail.len() {
+            return Err(Error::OutOfBound);
+        }
+        let (head, t) = tail.split_at_mut(field_size);
+        tail = t;
+        fields.push(field.decode(head)?.into_static());
+    }
+    let decoded = T::from_decoded_fields(fields)?;
+    #[cfg(feature = "metrics")]
+    metrics::DECODED.record(data.len());
+    Ok(decoded)
+}
+
+/// Decode a `T` read from `reader`, eg a socket, without reading more bytes than the message.
+/// The message must be size prefixed like an [`Envelope`]: first the size of the encoded `T` as
+/// a little endian u32, then exactly that many bytes are read and decoded with
+/// [`from_bytes_copy`]. A premature EOF return `Error::OutOfBound`.
+///
+/// ```
+/// use binary_codec_sv2::{from_reader, to_bytes, Envelope, B0255};
+/// use core::convert::TryInto;
+///
+/// let message: B0255 = vec![0xaa, 0xbb].try_into().unwrap();
+/// let mut stream = to_bytes(Envelope(message)).unwrap();
+/// stream.extend_from_slice(&[1, 2, 3]);
+/// let mut reader = &stream[..];
+/// let decoded: B0255<'static> = from_reader(&mut reader).unwrap();
+/// assert_eq!(decoded.to_vec(), vec![0xaa, 0xbb]);
+/// // The bytes after the message are still in the reader
+/// assert_eq!(reader, &[1, 2, 3]);
+/// ```
+#[cfg(not(feature = "no_std"))]
+pub fn from_reader<R: Read, T: Decodable<'static>>(reader: &mut R) -> Result<T, Error> {
+    let mut len = [0_u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    // Do not allocate `len` bytes upfront, the size prefix is not trusted
+    let mut data = Vec::new();
+    reader.by_ref().take(len as u64).read_to_end(&mut data)?;
+    if data.len() < len {
+        return Err(Error::OutOfBound);
+    }
+    from_bytes_copy(&data)
+}
+
+/// Like [`from_bytes`] but return the fully owned version of the message, that do not borrow
+/// `data` and can be kept after the buffer is released (eg `NewExtendedMiningJob<'static>` for
+/// `T = NewExtendedMiningJob`). Every field is copied so prefer [`from_bytes`] when the message
+/// do not need to outlive the buffer.
+pub fn from_bytes_owned<'a, T>(data: &[u8]) -> Result<T::Owned, Error>
+where
+    T: Decodable<'a> + IntoStatic,
+    T::Owned: Decodable<'static>,
+{
+    // `T::Owned` is the same message as `T` so it has the same structure
+    from_bytes_copy::<T::Owned>(data)
+}
+
+/// Byte ranges of the fields of an encoded message as `(field_index, offset, len)`, the offsets
+/// are relative to the start of the encoded message
+pub type FieldRanges = Vec<(usize, usize, usize)>;
+
+/// Return the byte range of each field of the message `T` encoded in `data`, it use the same
+/// `FieldMarker`s used to decode `T` but nothing is decoded or copied. Useful for protocol
+/// analyzers that need to know where a field is in a frame.
+pub fn field_ranges<'a, T: Decodable<'a>>(data: &[u8]) -> Result<FieldRanges, Error> {
+    let structure = T::get_structure(data)?;
+    let mut ranges = Vec::with_capacity(structure.len());
+    let mut offset = 0;
+    for (index, field) in structure.iter().enumerate() {
+        let len = field.size_hint_(data, offset)?;
+        if offset + len > data.len() {
+            return Err(Error::OutOfBound);
+        }
+        ranges.push((index, offset, len));
+        offset += len;
+    }
+    Ok(ranges)
+}
+
+/// Decode a `T` from the start of `data` and advance `data` past the decoded bytes, used by
+/// [`decode_fields`] to decode several values one after the other
+pub fn decode_prefix<'a, T: Decodable<'a>>(data: &mut &'a mut [u8]) -> Result<T, Error> {
+    let size = T::get_structure(data)?.size_hint_(data, 0)?;
+    if size > data.len() {
+        return Err(Error::OutOfBound);
+    }
+    let (head, tail) = core::mem::take(data).split_at_mut(size);
+    *data = tail;
+    from_bytes(head)
+}
+
+/// Implemented for the tuples of up to 8 `Decodable`, see [`decode_fields`]
+pub trait DecodeFields<'a>: Sized {
+    /// Decode the elements of the tuple one after the other from `data`. On failure return the
+    /// index of the element that can not be decoded and the error.
+    fn decode_fields(data: &'a mut [u8]) -> Result<Self, (usize, Error)>;
+}
+
+macro_rules! impl_decode_fields {
+    ($($t:ident $index:tt),+) => {
+        impl<'a, $($t: Decodable<'a>),+> DecodeFields<'a> for ($($t,)+) {
+            fn decode_fields(data: &'a mut [u8]) -> Result<Self, (usize, Error)> {
+                let mut tail = data;
+                Ok(($(decode_prefix::<$t>(&mut tail).map_err(|e| ($index, e))?,)+))
+            }
+        }
+    };
+}
+
+impl_decode_fields!(A 0);
+impl_decode_fields!(A 0, B 1);
+impl_decode_fields!(A 0, B 1, C 2);
+impl_decode_fields!(A 0, B 1, C 2, D 3);
+impl_decode_fields!(A 0, B 1, C 2, D 3, E 4);
+impl_decode_fields!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_decode_fields!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_decode_fields!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// Decode values of the given types one after the other from a `&mut [u8]` and return them as a
+/// tuple. On failure return the index of the field that can not be decoded and the error. Bytes
+/// after the last field are ignored.
+///
+/// ```
+/// use binary_codec_sv2::{decode_fields, B0255};
+///
+/// let mut data = [7, 0, 0, 0, 2, 0xaa, 0xbb];
+/// let (a, b) = decode_fields!(&mut data[..], u32, B0255).unwrap();
+/// assert_eq!(a, 7);
+/// assert_eq!(b.to_vec(), vec![0xaa, 0xbb]);
+///
+/// let mut truncated = [7, 0, 0, 0, 2, 0xaa];
+/// let (field, _) = decode_fields!(&mut truncated[..], u32, B0255).unwrap_err();
+/// assert_eq!(field, 1);
+/// ```
+#[macro_export]
+macro_rules! decode_fields {
+    ($data:expr, $($t:ty),+ $(,)?) => {
+        <($($t,)+) as $crate::DecodeFields>::decode_fields($data)
+    };
+}
+
+/// Build an owned `U256<'static>` from a 64 chars hex string literal, the bytes are parsed at
+/// compile time so a literal with the wrong length or with non hex chars do not compile.
+///
+/// ```
+/// use binary_codec_sv2::u256;
+///
+/// let target = u256!("00000000ffff0000000000000000000000000000000000000000000000000000");
+/// assert_eq!(target.to_vec()[4], 0xff);
+/// ```
+///
+/// ```compile_fail
+/// use binary_codec_sv2::u256;
+///
+/// let too_short = u256!("00ff");
+/// ```
+///
+/// ```compile_fail
+/// use binary_codec_sv2::u256;
+///
+/// let not_hex = u256!("zz000000ffff0000000000000000000000000000000000000000000000000000");
+/// ```
+#[macro_export]
+macro_rules! u256 {
+    ($hex:literal) => {{
+        const BYTES: [u8; 32] = $crate::u256_bytes_from_hex($hex);
+        $crate::U256::<'static>::from(BYTES)
+    }};
 }
 
 pub mod decodable {
-    pub use crate::codec::decodable::{Decodable, DecodableField, FieldMarker};
-    //pub use crate::codec::decodable::PrimitiveMarker;
+    pub use crate::codec::decodable::{
+        decode_with_markers, Decodable, DecodableField, DecodablePrimitive, FieldMarker,
+        PrimitiveMarker,
+    };
+    /// Used by the derived `Decodable::from_bytes` of the structs with only fixed size fields
+    pub use crate::datatypes::Sv2DataType;
 }
 
 pub mod encodable {
@@ -94,6 +386,101 @@ pub enum Error {
     NoDecodableFieldPassed,
     ValueIsNotAValidProtocol(u8),
     UnknownMessageType(u8),
+    /// Error when trying to encode a NaN or infinite f32
+    NonFiniteFloat,
+    /// Error when the CRC32 trailer do not match the received bytes, `expected` is the CRC in
+    /// the trailer and `actual` the CRC of the received bytes
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// Error when a bitcoin compact size is not encoded with the shortest encoding, it contains
+    /// the decoded value
+    NonCanonicalCompactSize(u64),
+    /// Error when the data passed to `from_bytes_limited` exceed one of the `DecodeLimits`
+    DecodeLimitExceeded,
+}
+
+/// ```
+/// use binary_codec_sv2::Error;
+///
+/// let e = Error::ValueExceedsMaxSize(false, 1, 1, 255, vec![0; 256], 256);
+/// assert_eq!(
+///     e.to_string(),
+///     "Value of 256 bytes exceeds the max size 255 of a non fixed size type"
+/// );
+/// ```
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use Error::*;
+        match self {
+            OutOfBound => write!(f, "Not enough bytes to decode the value"),
+            NotABool(u) => write!(f, "`{}` is not a valid bool, expected 0 or 1", u),
+            WriteError(expected, actual) => write!(
+                f,
+                "Can not write `{}` bytes in a buffer of `{}` bytes",
+                expected, actual
+            ),
+            U24TooBig(u) => write!(f, "`{}` is too big for a U24", u),
+            InvalidSignatureSize(u) => write!(f, "Invalid Signature size: `{}`", u),
+            InvalidU256(u) => write!(f, "Invalid U256 size: `{}`", u),
+            InvalidU24(u) => write!(f, "Invalid U24: `{}`", u),
+            InvalidB0255Size(u) => write!(f, "Invalid B0255 size: `{}`", u),
+            InvalidB064KSize(u) => write!(f, "Invalid B064K size: `{}`", u),
+            InvalidB016MSize(u) => write!(f, "Invalid B016M size: `{}`", u),
+            InvalidSeq0255Size(u) => write!(f, "Invalid Seq0255 size: `{}`", u),
+            NonPrimitiveTypeCannotBeEncoded => {
+                write!(f, "A non primitive data type can not be encoded")
+            }
+            PrimitiveConversionError => write!(f, "Can not convert the primitive value"),
+            DecodableConversionError => write!(f, "Can not convert the decoded value"),
+            UnInitializedDecoder => write!(f, "Decoder not initialized"),
+            #[cfg(not(feature = "no_std"))]
+            IoError(e) => write!(f, "IO error: {}", e),
+            ReadError(expected, actual) => write!(
+                f,
+                "Can not read `{}` bytes from a buffer of `{}` bytes",
+                expected, actual
+            ),
+            VoidFieldMarker => write!(f, "Can not decode a void field marker"),
+            ValueExceedsMaxSize(is_fixed, size, _, max_size, _, len) => match is_fixed {
+                true => write!(
+                    f,
+                    "Value of {} bytes exceeds the size {} of a fixed size type",
+                    len, size
+                ),
+                false => write!(
+                    f,
+                    "Value of {} bytes exceeds the max size {} of a non fixed size type",
+                    len, max_size
+                ),
+            },
+            SeqExceedsMaxSize => write!(f, "Sequence exceeds its max size"),
+            NoDecodableFieldPassed => write!(f, "No decodable field passed"),
+            ValueIsNotAValidProtocol(u) => write!(f, "`{}` is not a valid protocol", u),
+            UnknownMessageType(u) => write!(f, "Unknown message type: `{:#04x}`", u),
+            NonFiniteFloat => write!(f, "Can not encode a NaN or infinite f32"),
+            CrcMismatch { expected, actual } => write!(
+                f,
+                "CRC mismatch: expected `{:#010x}`, received bytes have `{:#010x}`",
+                expected, actual
+            ),
+            NonCanonicalCompactSize(v) => {
+                write!(f, "Compact size `{}` is not canonically encoded", v)
+            }
+            DecodeLimitExceeded => write!(f, "The data exceeds the decode limits"),
+        }
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(not(feature = "no_std"))]
@@ -139,6 +526,19 @@ pub enum CError {
     NoDecodableFieldPassed,
     ValueIsNotAValidProtocol(u8),
     UnknownMessageType(u8),
+    /// Error when trying to encode a NaN or infinite f32
+    NonFiniteFloat,
+    /// Error when the CRC32 trailer do not match the received bytes, `expected` is the CRC in
+    /// the trailer and `actual` the CRC of the received bytes
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// Error when a bitcoin compact size is not encoded with the shortest encoding, it contains
+    /// the decoded value
+    NonCanonicalCompactSize(u64),
+    /// Error when the data passed to `from_bytes_limited` exceed one of the `DecodeLimits`
+    DecodeLimitExceeded,
 }
 
 impl From<Error> for CError {
@@ -171,6 +571,10 @@ impl From<Error> for CError {
             Error::NoDecodableFieldPassed => CError::NoDecodableFieldPassed,
             Error::ValueIsNotAValidProtocol(u) => CError::ValueIsNotAValidProtocol(u),
             Error::UnknownMessageType(u) => CError::UnknownMessageType(u),
+            Error::NonFiniteFloat => CError::NonFiniteFloat,
+            Error::CrcMismatch { expected, actual } => CError::CrcMismatch { expected, actual },
+            Error::NonCanonicalCompactSize(v) => CError::NonCanonicalCompactSize(v),
+            Error::DecodeLimitExceeded => CError::DecodeLimitExceeded,
         }
     }
 }
@@ -201,6 +605,10 @@ impl Drop for CError {
             Self::NoDecodableFieldPassed => (),
             Self::ValueIsNotAValidProtocol(_) => (),
             Self::UnknownMessageType(_) => (),
+            Self::NonFiniteFloat => (),
+            Self::CrcMismatch { .. } => (),
+            Self::NonCanonicalCompactSize(_) => (),
+            Self::DecodeLimitExceeded => (),
         };
     }
 }
@@ -316,6 +724,23 @@ impl From<CVec2> for Vec<CVec> {
     }
 }
 
+/// Build a CVec2 in one shot from an already assembled vector of CVec, the vector is forgetted
+/// from the allocator so the returned CVec2 must be freed with `free_vec_2`.
+impl From<Vec<CVec>> for CVec2 {
+    fn from(mut v: Vec<CVec>) -> Self {
+        // Get the length, first, then the pointer (doing it the other way around **currently** doesn't cause UB, but it may be unsound due to unclear (to me, at least) guarantees of the std lib)
+        let len = v.len();
+        let capacity = v.capacity();
+        let data = v.as_mut_ptr();
+        std::mem::forget(v);
+        Self {
+            data,
+            len,
+            capacity,
+        }
+    }
+}
+
 pub fn free_vec(buf: &mut CVec) {
     let _: Vec<u8> = unsafe { Vec::from_raw_parts(buf.data, buf.len, buf.capacity) };
 }
@@ -401,7 +826,7 @@ pub unsafe extern "C" fn cvec2_push(cvec2: &mut CVec2, cvec: CVec) {
 
 impl<'a, T: Into<CVec>> From<Seq0255<'a, T>> for CVec2 {
     fn from(v: Seq0255<'a, T>) -> Self {
-        let mut v: Vec<CVec> = v.0.into_iter().map(|x| x.into()).collect();
+        let mut v: Vec<CVec> = v.into_iter().map(|x| x.into()).collect();
         // Get the length, first, then the pointer (doing it the other way around **currently** doesn't cause UB, but it may be unsound due to unclear (to me, at least) guarantees of the std lib)
         let len = v.len();
         let capacity = v.capacity();
@@ -416,7 +841,7 @@ impl<'a, T: Into<CVec>> From<Seq0255<'a, T>> for CVec2 {
 }
 impl<'a, T: Into<CVec>> From<Seq064K<'a, T>> for CVec2 {
     fn from(v: Seq064K<'a, T>) -> Self {
-        let mut v: Vec<CVec> = v.0.into_iter().map(|x| x.into()).collect();
+        let mut v: Vec<CVec> = v.into_iter().map(|x| x.into()).collect();
         // Get the length, first, then the pointer (doing it the other way around **currently** doesn't cause UB, but it may be unsound due to unclear (to me, at least) guarantees of the std lib)
         let len = v.len();
         let capacity = v.capacity();
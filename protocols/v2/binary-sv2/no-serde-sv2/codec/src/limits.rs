@@ -0,0 +1,120 @@
+//! Decode untrusted data with a budget, see [`from_bytes_limited`].
+use crate::{
+    codec::decodable::{decode_fields, FieldMarker},
+    Decodable, Error,
+};
+
+/// Max payload of an SV2 frame, the length field of the frame header is a U24
+pub(crate) const MAX_FRAME_PAYLOAD: usize = 2_usize.pow(24) - 1;
+
+/// Limits enforced by [`from_bytes_limited`]
+///
+/// - `max_depth`: max nesting of the decoded structure, a message with primitive fields has
+///   depth 1, a message with a sequence field depth 2 and so on
+/// - `max_total_bytes`: max length of the data to decode
+/// - `max_elements`: max number of primitive values to decode, the elements declared by the
+///   sequence headers are counted one by one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_depth: usize,
+    pub max_total_bytes: usize,
+    pub max_elements: usize,
+}
+
+impl Default for DecodeLimits {
+    /// Generous limits that accept every message that fit in an SV2 frame: no message of the
+    /// spec is nested more than 3 levels and every primitive value takes at least one byte.
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_total_bytes: MAX_FRAME_PAYLOAD,
+            max_elements: MAX_FRAME_PAYLOAD,
+        }
+    }
+}
+
+impl DecodeLimits {
+    // Walk `field` counting the primitive markers in `elements`, fail as soon as a limit is
+    // exceeded
+    fn check(&self, field: &FieldMarker, depth: usize, elements: &mut usize) -> Result<(), Error> {
+        if depth > self.max_depth {
+            return Err(Error::DecodeLimitExceeded);
+        }
+        match field {
+            FieldMarker::Primitive(_) => {
+                *elements += 1;
+                if *elements > self.max_elements {
+                    return Err(Error::DecodeLimitExceeded);
+                }
+            }
+            FieldMarker::Struct(fields) => {
+                for field in fields {
+                    self.check(field, depth + 1, elements)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Values and nesting counted while the structure of a message is walked by
+/// [`from_bytes_limited`], see [`Decodable::get_structure_limited`]
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeBudget {
+    limits: DecodeLimits,
+    elements: usize,
+}
+
+impl DecodeBudget {
+    pub fn new(limits: DecodeLimits) -> Self {
+        Self {
+            limits,
+            elements: 0,
+        }
+    }
+
+    /// Fail if a value at `depth` is nested deeper than the limit
+    pub fn check_depth(&self, depth: usize) -> Result<(), Error> {
+        if depth > self.limits.max_depth {
+            return Err(Error::DecodeLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Count `n` values with the structure `field` at `depth`. The values are counted without
+    /// walking `field` `n` times, so that a sequence is checked before its markers are allocated.
+    pub fn count(&mut self, field: &FieldMarker, depth: usize, n: usize) -> Result<(), Error> {
+        let mut elements = 0;
+        self.limits.check(field, depth, &mut elements)?;
+        let elements = elements
+            .checked_mul(n)
+            .and_then(|e| e.checked_add(self.elements))
+            .ok_or(Error::DecodeLimitExceeded)?;
+        if elements > self.limits.max_elements {
+            return Err(Error::DecodeLimitExceeded);
+        }
+        self.elements = elements;
+        Ok(())
+    }
+}
+
+/// Like [`crate::from_bytes`] but return `Error::DecodeLimitExceeded` if `data` or the structure
+/// of `T` that it declares exceed `limits`. The limits are checked while the structure is walked,
+/// before any field is decoded and before the markers of a sequence are allocated, so a crafted
+/// payload can not make the decoder do more work than the limits allow.
+pub fn from_bytes_limited<'a, T: Decodable<'a>>(
+    data: &'a mut [u8],
+    limits: DecodeLimits,
+) -> Result<T, Error> {
+    if data.len() > limits.max_total_bytes {
+        return Err(Error::DecodeLimitExceeded);
+    }
+    #[cfg(feature = "metrics")]
+    let len = data.len();
+    // The message is at depth 0 and its fields at depth 1
+    let structure = T::get_structure_limited(data, &mut DecodeBudget::new(limits), 0)?;
+    let decoded = T::from_decoded_fields(decode_fields(&structure, data)?)?;
+    #[cfg(feature = "metrics")]
+    crate::metrics::DECODED.record(len);
+    Ok(decoded)
+}
@@ -0,0 +1,69 @@
+//! Counters of the messages and bytes encoded by `to_bytes`/`to_writer` and decoded by
+//! `from_bytes`, meant for capacity planning. The counters are only incremented, the throughput
+//! is the difference between two snapshots divided by the time elapsed between them.
+//! Every call is counted as a message, note that the Sv2 frames encode the header and the payload
+//! with two different calls.
+//!
+//! The counters are relaxed atomics so they do not synchronize anything and are cheap to update
+//! from the hot paths, when the `metrics` feature is off they are not compiled at all. They are
+//! `usize` so that they build on the targets without 64 bit atomics, on a 32 bit target they wrap
+//! so the difference between two snapshots must be computed with `wrapping_sub`.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Messages and bytes processed by a code path
+#[derive(Debug)]
+pub struct Counter {
+    messages: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self {
+            messages: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record a message of `bytes` bytes
+    pub fn record(&self, bytes: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> CounterSnapshot {
+        CounterSnapshot {
+            messages: self.messages.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CounterSnapshot {
+    pub messages: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub encoded: CounterSnapshot,
+    pub decoded: CounterSnapshot,
+}
+
+pub(crate) static ENCODED: Counter = Counter::new();
+pub(crate) static DECODED: Counter = Counter::new();
+
+/// Current value of the codec counters
+pub fn snapshot() -> Metrics {
+    Metrics {
+        encoded: ENCODED.snapshot(),
+        decoded: DECODED.snapshot(),
+    }
+}
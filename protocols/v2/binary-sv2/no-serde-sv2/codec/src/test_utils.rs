@@ -0,0 +1,45 @@
+//! Round-trip tests for the messages of a subprotocol, see [`crate::roundtrip_all`].
+use crate::{Encodable, GetSize};
+use alloc::vec::Vec;
+
+/// Encode `value` and check that the encoder wrote exactly `get_size` bytes
+pub fn encode_checked<T: Encodable + GetSize>(value: T) -> Vec<u8> {
+    let size = value.get_size();
+    let mut encoded = vec![0; size];
+    let written = value
+        .to_bytes(&mut encoded)
+        .unwrap_or_else(|e| panic!("Can not encode a value of size {}: {:?}", size, e));
+    assert_eq!(written, size, "get_size does not match the encoded length");
+    encoded
+}
+
+/// Generate a round-trip test for each `name: Type = value;` entry. The test encodes `value`,
+/// decodes the bytes into a `Type`, encodes the decoded value again and checks that the two
+/// encodings are equal. Both encodings must write exactly `get_size` bytes, so a `GetSize` that
+/// do not match the encoder fail the test.
+///
+/// ```
+/// use binary_codec_sv2::{roundtrip_all, B0255};
+/// use core::convert::TryInto;
+///
+/// roundtrip_all! {
+///     roundtrip_u32: u32 = 7;
+///     roundtrip_b0255: B0255 = vec![1, 2, 3].try_into().unwrap();
+/// }
+/// ```
+#[macro_export]
+macro_rules! roundtrip_all {
+    ($($name:ident: $ty:ty = $value:expr;)+) => {
+        $(
+            #[test]
+            fn $name() {
+                let value: $ty = $value;
+                let encoded = $crate::test_utils::encode_checked(value);
+                let mut data = encoded.clone();
+                let decoded: $ty = $crate::from_bytes(&mut data[..])
+                    .unwrap_or_else(|e| panic!("Can not decode {}: {:?}", stringify!($ty), e));
+                assert_eq!($crate::test_utils::encode_checked(decoded), encoded);
+            }
+        )+
+    };
+}
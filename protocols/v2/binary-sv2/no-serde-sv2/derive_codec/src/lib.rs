@@ -136,6 +136,22 @@ impl ParsedField {
     }
 }
 
+/// Encoded size of the fixed size primitives, a `Decodable` struct that contain only these types
+/// get a `from_bytes` that check the length of the data once and then read the fields directly.
+/// U256 and Signature are not `Fixed` in the codec but they have no header.
+fn fixed_size(type_: &str) -> Option<usize> {
+    match type_ {
+        "bool" | "u8" => Some(1),
+        "u16" => Some(2),
+        "U24" => Some(3),
+        "u32" | "i32" | "f32" | "U32AsRef" => Some(4),
+        "u64" | "i64" => Some(8),
+        "U256" => Some(32),
+        "Signature" => Some(64),
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug)]
 struct ParsedStruct {
     pub name: String,
@@ -143,6 +159,169 @@ struct ParsedStruct {
     pub fields: Vec<ParsedField>,
 }
 
+impl ParsedStruct {
+    /// Lifetimes of the struct other than `'decoder`, eg `["'a", "'b"]` for `Foo<'a, 'b>`
+    fn other_lifetimes(&self) -> Vec<String> {
+        self.generics
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .split(',')
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty() && l != "'decoder")
+            .collect()
+    }
+
+    /// Generics of the `Decodable<'decoder>` impl, the data decoded with lifetime `'decoder` must
+    /// outlive every lifetime of the struct: `<'decoder: 'a + 'b, 'a, 'b>`
+    fn decodable_impl_generics(&self) -> String {
+        let lifetimes = self.other_lifetimes();
+        if lifetimes.is_empty() {
+            "<'decoder>".to_string()
+        } else {
+            format!(
+                "<'decoder: {}, {}>",
+                lifetimes.join(" + "),
+                lifetimes.join(", ")
+            )
+        }
+    }
+
+    /// Generics of the `From<Struct> for EncodableField<'decoder>` impl, every lifetime of the
+    /// struct must outlive the `'decoder` lifetime of the field: `<'decoder, 'a: 'decoder>`
+    fn encodable_impl_generics(&self) -> String {
+        let lifetimes: Vec<String> = self
+            .other_lifetimes()
+            .iter()
+            .map(|l| format!(", {}: 'decoder", l))
+            .collect();
+        format!("<'decoder{}>", lifetimes.concat())
+    }
+
+    /// Generics of the `From<&Struct> for EncodableField<'encoder>` impl, the struct is borrowed
+    /// for `'encoder` so every lifetime of the struct must outlive it:
+    /// `<'encoder, 'decoder: 'encoder, 'a: 'encoder>`
+    fn encodable_ref_impl_generics(&self) -> String {
+        let lifetimes: Vec<String> = self
+            .other_lifetimes()
+            .iter()
+            .map(|l| format!(", {}: 'encoder", l))
+            .collect();
+        format!("<'encoder, 'decoder: 'encoder{}>", lifetimes.concat())
+    }
+
+    /// Encoded size of the struct if every field is a fixed size primitive
+    fn fixed_size(&self) -> Option<usize> {
+        if self.fields.is_empty() {
+            return None;
+        }
+        self.fields.iter().map(|f| fixed_size(&f.type_)).sum()
+    }
+
+    /// Specialized `Decodable::from_bytes` for structs with only fixed size fields: the len of
+    /// the data is checked once, then every field is read without asking for its size hint.
+    fn fixed_size_from_bytes(&self) -> String {
+        let size = match self.fixed_size() {
+            Some(size) => size,
+            None => return "".to_string(),
+        };
+        let mut derive_fields = String::new();
+        for f in &self.fields {
+            let field = format!(
+                "
+            let (head_, t_) = tail_.split_at_mut({});
+            tail_ = t_;
+            let field_{} = <{}{} as binary_codec_sv2::decodable::Sv2DataType>::from_bytes_unchecked(head_);
+            ",
+                // Safe unwrap fixed_size is Some only if every field has a fixed size
+                fixed_size(&f.type_).unwrap(),
+                f.name,
+                f.type_,
+                f.get_generics(),
+            );
+            derive_fields.push_str(&field)
+        }
+        let struct_fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|f| format!("{}: field_{},", f.name, f.name))
+            .collect();
+        format!(
+            "
+        fn from_bytes(data: &'decoder mut [u8]) -> Result<Self, Error> {{
+            if data.len() < {} {{
+                return Err(Error::OutOfBound);
+            }}
+            let mut tail_ = data;
+            {}
+            let _ = tail_;
+            Ok(Self {{
+                {}
+            }})
+        }}
+            ",
+            size,
+            derive_fields,
+            struct_fields.concat(),
+        )
+    }
+
+    /// Generics of the `IntoStatic::Owned` of the struct, every lifetime is replaced by
+    /// `'static`: `<'static, 'static>` for `Foo<'a, 'b>`
+    fn owned_generics(&self) -> String {
+        let lifetimes: Vec<&str> = self
+            .generics
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .split(',')
+            .filter(|l| !l.trim().is_empty())
+            .map(|_| "'static")
+            .collect();
+        if lifetimes.is_empty() {
+            "".to_string()
+        } else {
+            format!("<{}>", lifetimes.join(", "))
+        }
+    }
+
+    /// `IntoStatic` impl of the struct, every field is converted with its own `IntoStatic`
+    fn impl_into_static(&self) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|f| format!("{}: IntoStatic::into_static(self.{}),", f.name, f.name))
+            .collect();
+        format!(
+            "
+    impl{} IntoStatic for {}{} {{
+        type Owned = {}{};
+
+        fn into_static(self) -> Self::Owned {{
+            {} {{
+                {}
+            }}
+        }}
+    }}
+            ",
+            self.generics,
+            self.name,
+            self.generics,
+            self.name,
+            self.owned_generics(),
+            self.name,
+            fields.concat(),
+        )
+    }
+
+    /// Generics of the `GetSize` impl
+    fn get_size_impl_generics(&self) -> String {
+        if self.generics.is_empty() {
+            "<'decoder>".to_string()
+        } else {
+            self.generics.clone()
+        }
+    }
+}
+
 // impl ParsedStruct {
 //     pub fn new() -> Self {
 //         ParsedStruct {
@@ -235,6 +414,27 @@ pub fn decodable(item: TokenStream) -> TokenStream {
         derive_fields.push_str(&field)
     }
 
+    let mut derive_fields_limited = String::new();
+
+    for f in parsed_struct.fields.clone() {
+        let field = format!(
+            "
+            let {}: Vec<FieldMarker> = {}{}::get_structure_limited(& data[offset..], budget, depth + 1)?;
+            offset += {}.size_hint_(&data, offset)?;
+            let {} =  {}.try_into()?;
+            fields.push({});
+            ",
+            f.name,
+            f.type_,
+            f.get_generics(),
+            f.name,
+            f.name,
+            f.name,
+            f.name
+        );
+        derive_fields_limited.push_str(&field)
+    }
+
     let mut derive_decoded_fields = String::new();
     let mut fields = parsed_struct.fields.clone();
 
@@ -255,16 +455,12 @@ pub fn decodable(item: TokenStream) -> TokenStream {
         derive_decoded_fields.push_str(&field)
     }
 
-    let impl_generics = if !parsed_struct.generics.is_empty() {
-        parsed_struct.clone().generics
-    } else {
-        "<'decoder>".to_string()
-    };
+    let impl_generics = parsed_struct.decodable_impl_generics();
 
     let result = format!(
         "mod impl_parse_decodable_{} {{
 
-    use super::binary_codec_sv2::{{decodable::DecodableField, decodable::FieldMarker, Decodable, Error, SizeHint}};
+    use super::binary_codec_sv2::{{decodable::DecodableField, decodable::FieldMarker, Decodable, DecodeBudget, Error, IntoStatic, SizeHint}};
     use super::*;
 
     impl{} Decodable<'decoder> for {}{} {{
@@ -275,12 +471,22 @@ pub fn decodable(item: TokenStream) -> TokenStream {
             Ok(fields)
         }}
 
+        fn get_structure_limited(data: &[u8], budget: &mut DecodeBudget, depth: usize) -> Result<Vec<FieldMarker>, Error> {{
+            budget.check_depth(depth)?;
+            let mut fields = Vec::new();
+            let mut offset = 0;
+            {}
+            Ok(fields)
+        }}
+
         fn from_decoded_fields(mut data: Vec<DecodableField<'decoder>>) -> Result<Self, Error> {{
             Ok(Self {{
                 {}
             }})
         }}
+        {}
     }}
+    {}
     }}",
         // imports
         parsed_struct.name.to_lowercase(),
@@ -289,7 +495,11 @@ pub fn decodable(item: TokenStream) -> TokenStream {
         parsed_struct.name,
         parsed_struct.generics,
         derive_fields,
+        derive_fields_limited,
         derive_decoded_fields,
+        parsed_struct.fixed_size_from_bytes(),
+        // derive into static
+        parsed_struct.impl_into_static(),
     );
 
     // Never executed at runtime it ok to panic
@@ -315,6 +525,27 @@ pub fn encodable(item: TokenStream) -> TokenStream {
         field_into_decoded_field.push_str(&field)
     }
 
+    // Encode the fields from a reference, the impl exist only if every field can be encoded from
+    // a reference
+    let mut field_ref_into_decoded_field = String::new();
+    let mut field_ref_bounds = String::new();
+
+    for f in fields.clone() {
+        let field = format!(
+            "
+            fields.push((&v.{}).into());
+            ",
+            f.name
+        );
+        field_ref_into_decoded_field.push_str(&field);
+        let bound = format!(
+            "
+            &'encoder {}{}: Into<EncodableField<'encoder>>,",
+            f.type_, f.generics
+        );
+        field_ref_bounds.push_str(&bound);
+    }
+
     let mut sizes = String::new();
 
     for f in fields {
@@ -326,17 +557,15 @@ pub fn encodable(item: TokenStream) -> TokenStream {
         );
         sizes.push_str(&field)
     }
-    let impl_generics = if !parsed_struct.generics.is_empty() {
-        parsed_struct.clone().generics
-    } else {
-        "<'decoder>".to_string()
-    };
+    let impl_generics = parsed_struct.encodable_impl_generics();
+    let ref_impl_generics = parsed_struct.encodable_ref_impl_generics();
+    let get_size_generics = parsed_struct.get_size_impl_generics();
 
     let result = format!(
         "mod impl_parse_encodable_{} {{
 
     use super::binary_codec_sv2::{{encodable::EncodableField, GetSize}};
-    use super::{};
+    use super::*;
     extern crate alloc;
     use alloc::vec::Vec;
 
@@ -348,6 +577,16 @@ pub fn encodable(item: TokenStream) -> TokenStream {
         }}
     }}
 
+    impl{} From<&'encoder {}{}> for EncodableField<'encoder>
+    where {}
+    {{
+        fn from(v: &'encoder {}{}) -> Self {{
+            let mut fields: Vec<EncodableField> = Vec::new();
+            {}
+            Self::Struct(fields)
+        }}
+    }}
+
 
     impl{} GetSize for {}{} {{
         fn get_size(&self) -> usize {{
@@ -360,7 +599,6 @@ pub fn encodable(item: TokenStream) -> TokenStream {
     }}",
         // imports
         parsed_struct.name.to_lowercase(),
-        parsed_struct.name,
         // impl From<Struct> for DecodableField
         impl_generics,
         parsed_struct.name,
@@ -368,13 +606,21 @@ pub fn encodable(item: TokenStream) -> TokenStream {
         parsed_struct.name,
         parsed_struct.generics,
         field_into_decoded_field,
+        // impl From<&Struct> for DecodableField
+        ref_impl_generics,
+        parsed_struct.name,
+        parsed_struct.generics,
+        field_ref_bounds,
+        parsed_struct.name,
+        parsed_struct.generics,
+        field_ref_into_decoded_field,
         // impl Encodable for Struct
         //impl{} Encodable<'decoder> for {}{} {{}}
         //impl_generics,
         //parsed_struct.name,
         //parsed_struct.generics,
         // impl GetSize for Struct
-        impl_generics,
+        get_size_generics,
         parsed_struct.name,
         parsed_struct.generics,
         sizes,
@@ -32,6 +32,16 @@ pub type StandardEitherFrame<T> = EitherFrame<T, <Buffer as IsBuffer>::Slice>;
 pub type StandardSv2Frame<T> = Sv2Frame<T, <Buffer as IsBuffer>::Slice>;
 pub type StandardDecoder<T> = WithoutNoise<Buffer, T>;
 
+/// Return an error if the `buffered` bytes of the frame being decoded are more than
+/// `max_buffered_bytes`. The length declared in the frame header is not trusted, only the bytes
+/// actually received are counted.
+fn check_buffer_limit(buffered: usize, max_buffered_bytes: Option<usize>) -> Result<()> {
+    match max_buffered_bytes {
+        Some(max) if buffered > max => Err(Error::BufferLimitExceeded(buffered)),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(feature = "noise_sv2")]
 pub struct WithNoise<B: IsBuffer, T: Serialize + binary_sv2::GetSize> {
     frame: PhantomData<T>,
@@ -39,6 +49,7 @@ pub struct WithNoise<B: IsBuffer, T: Serialize + binary_sv2::GetSize> {
     noise_buffer: B,
     sv2_buffer: B,
     sv2_frame_size: usize,
+    max_buffered_bytes: Option<usize>,
 }
 
 #[cfg(feature = "noise_sv2")]
@@ -78,6 +89,7 @@ impl<'a, T: Serialize + GetSize + Deserialize<'a>, B: IsBuffer> WithNoise<B, T>
                 let len = TransportMode::size_hint_decrypt(src.len())?;
                 let decrypted = self.sv2_buffer.get_writable(len);
                 transport_mode.read(src, decrypted)?;
+                check_buffer_limit(self.sv2_buffer.len(), self.max_buffered_bytes)?;
 
                 // IF THE DECODER IS RECEIVING A FRAGMENTED FRAME ADD THE DECRYPTED DATA TO THE
                 // PARTIAL FRAME AND CHECK IF READY
@@ -158,6 +170,16 @@ impl<T: Serialize + binary_sv2::GetSize> WithNoise<Buffer, T> {
             noise_buffer: Buffer::new(2_usize.pow(16) * 5),
             sv2_buffer: Buffer::new(2_usize.pow(16) * 5),
             sv2_frame_size: 0,
+            max_buffered_bytes: None,
+        }
+    }
+
+    /// Like `new` but `next_frame` return `Error::BufferLimitExceeded` as soon as more than
+    /// `max_buffered_bytes` of a frame are buffered, after that the decoder MUST not be used.
+    pub fn with_max_buffered_bytes(max_buffered_bytes: usize) -> Self {
+        Self {
+            max_buffered_bytes: Some(max_buffered_bytes),
+            ..Self::new()
         }
     }
 }
@@ -174,12 +196,14 @@ pub struct WithoutNoise<B: IsBuffer, T: Serialize + binary_sv2::GetSize> {
     frame: PhantomData<T>,
     missing_b: usize,
     buffer: B,
+    max_buffered_bytes: Option<usize>,
 }
 
 impl<T: Serialize + binary_sv2::GetSize, B: IsBuffer> WithoutNoise<B, T> {
     #[inline]
     pub fn next_frame(&mut self) -> Result<Sv2Frame<T, B::Slice>> {
         let len = self.buffer.len();
+        check_buffer_limit(len, self.max_buffered_bytes)?;
         let src = self.buffer.get_data_by_ref(len);
         let hint = Sv2Frame::<T, B::Slice>::size_hint(src) as usize;
 
@@ -191,7 +215,12 @@ impl<T: Serialize + binary_sv2::GetSize, B: IsBuffer> WithoutNoise<B, T> {
                 Ok(frame)
             }
             _ => {
-                self.missing_b = hint;
+                // Never ask for more bytes than the limit allow, so a frame bigger than the limit
+                // is detected when one byte too many is buffered and not allocated upfront
+                self.missing_b = match self.max_buffered_bytes {
+                    Some(max) => hint.min(max + 1 - len),
+                    None => hint,
+                };
                 Err(Error::MissingBytes(self.missing_b))
             }
         }
@@ -208,6 +237,16 @@ impl<T: Serialize + binary_sv2::GetSize> WithoutNoise<Buffer, T> {
             frame: PhantomData,
             missing_b: Header::SIZE,
             buffer: Buffer::new(2_usize.pow(16) * 5),
+            max_buffered_bytes: None,
+        }
+    }
+
+    /// Like `new` but `next_frame` return `Error::BufferLimitExceeded` as soon as more than
+    /// `max_buffered_bytes` of a frame are buffered, after that the decoder MUST not be used.
+    pub fn with_max_buffered_bytes(max_buffered_bytes: usize) -> Self {
+        Self {
+            max_buffered_bytes: Some(max_buffered_bytes),
+            ..Self::new()
         }
     }
 }
@@ -217,3 +256,43 @@ impl<T: Serialize + binary_sv2::GetSize> Default for WithoutNoise<Buffer, T> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use binary_sv2::from_bytes;
+
+    #[test]
+    fn test_buffer_limit_exceeded() {
+        let mut decoder = StandardDecoder::<u32>::with_max_buffered_bytes(1024);
+
+        // Header of a frame that declare a payload of 2^24 - 1 bytes, the decoder only ask for
+        // the bytes that fit in the limit plus one
+        let writable = decoder.writable();
+        writable.copy_from_slice(&[0, 0, 1, 0xff, 0xff, 0xff]);
+        let missing = 1024 + 1 - Header::SIZE;
+        assert!(matches!(decoder.next_frame(), Err(Error::MissingBytes(m)) if m == missing));
+
+        let writable = decoder.writable();
+        assert_eq!(writable.len(), missing);
+        match decoder.next_frame() {
+            Err(Error::BufferLimitExceeded(size)) => assert_eq!(size, 1024 + 1),
+            _ => panic!("frame bigger than the limit has been accepted"),
+        }
+    }
+
+    #[test]
+    fn test_buffer_limit_not_exceeded() {
+        let mut decoder = StandardDecoder::<u32>::with_max_buffered_bytes(1024);
+
+        let writable = decoder.writable();
+        writable.copy_from_slice(&[0, 0, 1, 4, 0, 0]);
+        assert!(matches!(decoder.next_frame(), Err(Error::MissingBytes(4))));
+
+        let writable = decoder.writable();
+        writable.copy_from_slice(&[1, 0, 0, 0]);
+        let mut frame = decoder.next_frame().unwrap();
+        let payload: u32 = from_bytes(frame.payload()).unwrap();
+        assert_eq!(payload, 1);
+    }
+}
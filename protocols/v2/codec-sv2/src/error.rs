@@ -23,6 +23,9 @@ pub enum Error {
     SnowError(NoiseSv2SnowError),
     /// Error if Noise protocol state is not as expected
     UnexpectedNoiseState,
+    /// Error if more than the decoder's `max_buffered_bytes` of a frame are buffered, it contains
+    /// the number of buffered bytes
+    BufferLimitExceeded(usize),
     CodecTodo,
 }
 
@@ -40,6 +43,9 @@ impl fmt::Display for Error {
             UnexpectedNoiseState => {
                 write!(f, "Noise state is incorrect")
             }
+            BufferLimitExceeded(u) => {
+                write!(f, "`{}` buffered bytes exceed the buffer limit", u)
+            }
             CodecTodo => write!(f, "Codec Sv2 Error: TODO"),
         }
     }
@@ -55,6 +61,7 @@ impl cmp::PartialEq for Error {
             (NoiseSv2Error(_), NoiseSv2Error(_)) => true,
             (SnowError(_), SnowError(_)) => true,
             (UnexpectedNoiseState, UnexpectedNoiseState) => true,
+            (BufferLimitExceeded(a), BufferLimitExceeded(b)) => a == b,
             (CodecTodo, CodecTodo) => true,
             _ => false,
         }
@@ -102,6 +109,8 @@ pub enum CError {
     SnowError,
     /// Error if Noise protocol state is not as expected
     UnexpectedNoiseState,
+    /// Error if more than the decoder's `max_buffered_bytes` of a frame are buffered
+    BufferLimitExceeded(usize),
     CodecTodo,
 }
 
@@ -122,6 +131,7 @@ impl From<Error> for CError {
             #[cfg(feature = "noise_sv2")]
             Error::SnowError(_) => CError::SnowError,
             Error::UnexpectedNoiseState => CError::UnexpectedNoiseState,
+            Error::BufferLimitExceeded(u) => CError::BufferLimitExceeded(u),
             Error::CodecTodo => CError::CodecTodo,
         }
     }
@@ -136,6 +146,7 @@ impl Drop for CError {
             CError::NoiseSv2Error => (),
             CError::SnowError => (),
             CError::UnexpectedNoiseState => (),
+            CError::BufferLimitExceeded(_) => (),
             CError::CodecTodo => (),
         };
     }
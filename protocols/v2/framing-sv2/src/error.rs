@@ -9,6 +9,8 @@ pub enum Error {
     ExpectedHandshakeFrame,
     ExpectedSv2Frame,
     UnexpectedHeaderLength(isize),
+    /// Less bytes than the ones needed to parse the value
+    OutOfBound,
 }
 
 impl fmt::Display for Error {
@@ -27,6 +29,45 @@ impl fmt::Display for Error {
             UnexpectedHeaderLength(i) => {
                 write!(f, "Unexpected `Header` length: `{}`", i)
             }
+            OutOfBound => {
+                write!(f, "Not enough bytes to parse the value")
+            }
+        }
+    }
+}
+
+/// Error returned when an `EitherFrame` is converted into a frame of the other type, for example
+/// when a noise handshake frame is received after that the handshake is completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameConversionError {
+    /// Expected an `Sv2Frame`, received an handshake frame of `len` bytes
+    ExpectedSv2Frame { len: usize },
+    /// Expected an handshake frame, received an `Sv2Frame` with message type `msg_type`
+    ExpectedHandshakeFrame { msg_type: u8 },
+}
+
+impl fmt::Display for FrameConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameConversionError::ExpectedSv2Frame { len } => write!(
+                f,
+                "Expected `Sv2Frame`, received `HandshakeFrame` of {} bytes",
+                len
+            ),
+            FrameConversionError::ExpectedHandshakeFrame { msg_type } => write!(
+                f,
+                "Expected `HandshakeFrame`, received `Sv2Frame` with message type `{}`",
+                msg_type
+            ),
+        }
+    }
+}
+
+impl From<FrameConversionError> for Error {
+    fn from(e: FrameConversionError) -> Self {
+        match e {
+            FrameConversionError::ExpectedSv2Frame { .. } => Error::ExpectedSv2Frame,
+            FrameConversionError::ExpectedHandshakeFrame { .. } => Error::ExpectedHandshakeFrame,
         }
     }
 }
@@ -1,6 +1,6 @@
 use crate::{
     header::{Header, NoiseHeader},
-    Error,
+    Error, FrameConversionError,
 };
 use alloc::vec::Vec;
 use binary_sv2::{to_writer, GetSize, Serialize};
@@ -27,6 +27,34 @@ impl<A, B> Sv2Frame<A, B> {
     }
 }
 
+#[cfg(not(feature = "with_serde"))]
+impl<T, B: From<Vec<u8>>> Sv2Frame<T, B> {
+    /// Like `Frame::from_message` but `message` is encoded from a reference and the returned frame
+    /// is already serialized, so that the caller can keep the message without cloning it.
+    pub fn from_message_ref<'a, M>(
+        message: &'a M,
+        message_type: u8,
+        extension_type: u16,
+        channel_msg: bool,
+    ) -> Option<Self>
+    where
+        M: GetSize,
+        &'a M: Into<binary_sv2::encodable::EncodableField<'a>>,
+    {
+        let extension_type = update_extension_type(extension_type, channel_msg);
+        let len = message.get_size();
+        let header = Header::from_len(len as u32, message_type, extension_type)?;
+        let mut serialized = alloc::vec![0; Header::SIZE + len];
+        to_writer(header, &mut serialized).ok()?;
+        to_writer(message, &mut serialized[Header::SIZE..]).ok()?;
+        Some(Self {
+            header,
+            payload: None,
+            serialized: Some(serialized.into()),
+        })
+    }
+}
+
 pub trait Frame<'a, T: Serialize + GetSize>: Sized {
     type Buffer: AsMut<[u8]>;
     type Deserialized;
@@ -338,23 +366,27 @@ impl<T: Serialize + GetSize, B: AsMut<[u8]> + AsRef<[u8]>> EitherFrame<T, B> {
 }
 
 impl<T, B> TryFrom<EitherFrame<T, B>> for HandShakeFrame {
-    type Error = Error;
+    type Error = FrameConversionError;
 
-    fn try_from(v: EitherFrame<T, B>) -> Result<Self, Error> {
+    fn try_from(v: EitherFrame<T, B>) -> Result<Self, FrameConversionError> {
         match v {
             EitherFrame::HandShake(frame) => Ok(frame),
-            EitherFrame::Sv2(_) => Err(Error::ExpectedHandshakeFrame),
+            EitherFrame::Sv2(frame) => Err(FrameConversionError::ExpectedHandshakeFrame {
+                msg_type: frame.header.msg_type(),
+            }),
         }
     }
 }
 
 impl<T, B> TryFrom<EitherFrame<T, B>> for Sv2Frame<T, B> {
-    type Error = Error;
+    type Error = FrameConversionError;
 
-    fn try_from(v: EitherFrame<T, B>) -> Result<Self, Error> {
+    fn try_from(v: EitherFrame<T, B>) -> Result<Self, FrameConversionError> {
         match v {
             EitherFrame::Sv2(frame) => Ok(frame),
-            EitherFrame::HandShake(_) => Err(Error::ExpectedSv2Frame),
+            EitherFrame::HandShake(frame) => Err(FrameConversionError::ExpectedSv2Frame {
+                len: frame.payload.len(),
+            }),
         }
     }
 }
@@ -370,3 +402,48 @@ impl<T, B> From<Sv2Frame<T, B>> for EitherFrame<T, B> {
         Self::Sv2(v)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+    use core::convert::TryInto;
+
+    type TestFrame = Sv2Frame<u32, Vec<u8>>;
+
+    #[test]
+    fn test_either_frame_conversion_error() {
+        // extension type 0, message type 7, payload of 4 bytes
+        let bytes = vec![0, 0, 7, 4, 0, 0, 1, 2, 3, 4];
+        let frame: EitherFrame<u32, Vec<u8>> = TestFrame::from_bytes(bytes).unwrap().into();
+        let handshake: Result<HandShakeFrame, _> = frame.try_into();
+        assert_eq!(
+            handshake.unwrap_err(),
+            FrameConversionError::ExpectedHandshakeFrame { msg_type: 7 }
+        );
+
+        let bytes = vec![3, 0, 1, 2, 3];
+        let frame: EitherFrame<u32, Vec<u8>> =
+            HandShakeFrame::from_bytes(bytes.into()).unwrap().into();
+        let sv2: Result<TestFrame, _> = frame.try_into();
+        assert_eq!(
+            sv2.unwrap_err(),
+            FrameConversionError::ExpectedSv2Frame { len: 5 }
+        );
+    }
+
+    #[test]
+    fn test_from_message_ref() {
+        let message = 0x0102_0304_u32;
+        let frame = TestFrame::from_message(message, 7, 0, true).unwrap();
+        let mut expected = vec![0; frame.encoded_length()];
+        frame.serialize(&mut expected).unwrap();
+
+        let frame = TestFrame::from_message_ref(&message, 7, 0, true).unwrap();
+        assert_eq!(frame.get_header().unwrap().msg_type(), 7);
+        let mut serialized = vec![0; frame.encoded_length()];
+        frame.serialize(&mut serialized).unwrap();
+        assert_eq!(serialized, expected);
+        assert_eq!(serialized, vec![0, 0x80, 7, 4, 0, 0, 4, 3, 2, 1]);
+    }
+}
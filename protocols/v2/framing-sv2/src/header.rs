@@ -77,6 +77,33 @@ impl Header {
     }
 }
 
+/// The fields of an Sv2 frame header, returned by `parse_header`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub extension_type: u16,
+    pub msg_type: u8,
+    pub msg_length: u32,
+    pub channel_msg: bool,
+}
+
+/// Read only the first `Header::SIZE` bytes of `bytes`, useful when just the message type or the
+/// channel bit are needed, eg to route a frame that is forwarded unchanged. The payload is not
+/// looked at so `bytes` can also be a not yet complete frame.
+#[inline]
+pub fn parse_header(bytes: &[u8]) -> Result<FrameHeader, Error> {
+    if bytes.len() < Header::SIZE {
+        return Err(Error::OutOfBound);
+    };
+    let extension_type = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let channel_mask = 0b1000_0000_0000_0000;
+    Ok(FrameHeader {
+        extension_type: extension_type & !channel_mask,
+        msg_type: bytes[2],
+        msg_length: u32::from_le_bytes([bytes[3], bytes[4], bytes[5], 0]),
+        channel_msg: extension_type & channel_mask != 0,
+    })
+}
+
 pub struct NoiseHeader {}
 
 impl NoiseHeader {
@@ -84,3 +111,26 @@ impl NoiseHeader {
     pub const LEN_OFFSET: usize = const_sv2::NOISE_FRAME_HEADER_LEN_OFFSET;
     pub const LEN_END: usize = const_sv2::NOISE_FRAME_HEADER_LEN_END;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let bytes = [0x01, 0x80, 0x1f, 0x03, 0x00, 0x00, 0xaa, 0xbb, 0xcc];
+        let expected = FrameHeader {
+            extension_type: 1,
+            msg_type: 0x1f,
+            msg_length: 3,
+            channel_msg: true,
+        };
+        assert_eq!(parse_header(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_truncated_header() {
+        let bytes = [0x00, 0x00, 0x1f, 0x03, 0x00];
+        assert!(matches!(parse_header(&bytes), Err(Error::OutOfBound)));
+    }
+}
@@ -22,4 +22,4 @@ pub mod framing2;
 
 pub mod error;
 pub mod header;
-pub use error::Error;
+pub use error::{Error, FrameConversionError};
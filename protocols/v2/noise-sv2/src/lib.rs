@@ -16,6 +16,10 @@ use snow::{params::NoiseParams, Builder, HandshakeState, TransportState};
 // Export for use in `codec_sv2::error::Error::SnowError`
 pub use snow::Error as NoiseSv2SnowError;
 use std::fmt;
+#[cfg(feature = "zeroize")]
+pub use zeroize;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, Zeroizing};
 
 pub use auth::{SignatureNoiseMessage, SignedPartHeader};
 pub use formats::Certificate;
@@ -231,7 +235,11 @@ impl Authority {
 
     /// Create an Authority from pub_k and priv_k (32 bytes keys)
     pub fn from_raw_k(pub_k: &[u8], priv_k: &[u8]) -> Result<Self> {
-        let kp = ed25519_dalek::Keypair::from_bytes(&[priv_k, pub_k].concat())?;
+        #[cfg(feature = "zeroize")]
+        let bytes = Zeroizing::new([priv_k, pub_k].concat());
+        #[cfg(not(feature = "zeroize"))]
+        let bytes = [priv_k, pub_k].concat();
+        let kp = ed25519_dalek::Keypair::from_bytes(&bytes)?;
         Ok(Self { kp })
     }
 
@@ -324,9 +332,27 @@ impl Responder {
     }
 }
 
+/// Wipe the static secret key, the snow `Keypair` does not do it on its own
+#[cfg(feature = "zeroize")]
+impl Drop for Responder {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Responder {
+    // Zero the buffer of the static secret key, the buffer is freed only when the responder is
+    // dropped
+    fn wipe(&mut self) {
+        self.static_keypair.private.zeroize();
+    }
+}
+
 impl handshake::Step for Responder {
-    fn into_handshake_state(self) -> HandshakeState {
+    fn into_handshake_state(mut self) -> HandshakeState {
         self.handshake_state
+            .take()
             .expect("BUG: Handshake must be set at this moment")
     }
 
@@ -479,9 +505,17 @@ pub(crate) mod test {
         let (signature_noise_message, authority_keypair, static_keypair) =
             build_serialized_signature_noise_message_and_keypairs();
 
-        let mut initiator = Initiator::new(authority_keypair.public).unwrap();
+        let initiator = Initiator::new(authority_keypair.public).unwrap();
 
-        let mut responder = Responder::new(static_keypair, signature_noise_message).unwrap();
+        let responder = Responder::new(static_keypair, signature_noise_message).unwrap();
+        handshake_transport_modes(initiator, responder)
+    }
+
+    /// Run the handshake between `initiator` and `responder` and return their transport modes
+    fn handshake_transport_modes(
+        mut initiator: Initiator,
+        mut responder: Responder,
+    ) -> (TransportMode, TransportMode) {
         let mut initiator_in_msg: Option<handshake::Message> = None;
 
         loop {
@@ -622,4 +656,52 @@ pub(crate) mod test {
 
         assert_eq!(&message[..], &decrypted_msg[..], "Messages don't match");
     }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_static_key_zeroed_on_drop() {
+        let (signature_noise_message, _, static_keypair) =
+            build_serialized_signature_noise_message_and_keypairs();
+        let mut responder = Responder::new(static_keypair, signature_noise_message).unwrap();
+        let key = responder.static_keypair.private.as_ptr();
+        let len = responder.static_keypair.private.len();
+        assert!(unsafe { core::slice::from_raw_parts(key, len) }
+            .iter()
+            .any(|b| *b != 0));
+
+        // What the Drop impl does before the buffer is freed, so that it can still be read
+        responder.wipe();
+
+        assert!(unsafe { core::slice::from_raw_parts(key, len) }
+            .iter()
+            .all(|b| *b == 0));
+    }
+
+    /// The keypairs copied when building a responder from the authority keys are wiped with the
+    /// `zeroize` feature, the handshake must work the same
+    #[test]
+    fn test_handshake_from_authority_kp() {
+        let (authority_public_k, authority_private_k) = random_keypair();
+        let initiator = Initiator::from_raw_k(authority_public_k).unwrap();
+        let responder = Responder::from_authority_kp(
+            &authority_public_k,
+            &authority_private_k,
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        let (mut initiator_transport_mode, mut responder_transport_mode) =
+            handshake_transport_modes(initiator, responder);
+
+        let message = b"test message";
+        let mut encrypted_msg = vec![0; TransportMode::size_hint_encrypt(message.len())];
+        initiator_transport_mode
+            .write(&message[..], &mut encrypted_msg)
+            .unwrap();
+        let mut decrypted_msg =
+            vec![0; TransportMode::size_hint_decrypt(encrypted_msg.len()).unwrap()];
+        responder_transport_mode
+            .read(&encrypted_msg[..], &mut decrypted_msg[..])
+            .unwrap();
+        assert_eq!(&message[..], &decrypted_msg[..]);
+    }
 }
@@ -26,6 +26,25 @@ pub enum Error {
     UnexpectedPoolMessage,
     UnknownRequestId(u32),
     NoMoreExtranonces,
+    /// (`min_v`, `max_v`) protocol versions supported, the downstream asked for a version outside
+    /// of this range
+    UnsupportedProtocolVersion((u16, u16)),
+    /// The nbits is not a valid compact target (zero, negative or bigger than 256 bits)
+    InvalidNBits(u32),
+    /// (`expected`, `actual`) length of the extranonce prefix of an extended channel
+    InvalidExtranoncePrefixLen((usize, usize)),
+    /// (`max`, `actual`) length of the extranonce suffix of a share, the suffix is longer than
+    /// the extranonce of the job
+    InvalidExtranonceSuffixLen((usize, usize)),
+    /// The coinbase built from the job and the extranonce is not a valid transaction
+    InvalidCoinbase,
+    /// The `coinbase_tx_outputs` of a template are not `coinbase_tx_outputs_count` valid outputs
+    InvalidCoinbaseOutputs,
+    /// The transaction at this index of a transaction list is not a valid transaction
+    InvalidTransaction(usize),
+    /// A downstream reconnected with a reconnect token but its `SetupConnection` flags differ
+    /// from the ones of the disconnected downstream
+    ReconnectDataMismatch,
 }
 
 impl From<BinarySv2Error> for Error {
@@ -81,6 +100,29 @@ impl Display for Error {
                 id
             ),
             NoMoreExtranonces => write!(f, "No more extranonces"),
+            UnsupportedProtocolVersion((min_v, max_v)) => write!(
+                f,
+                "Unsupported protocol version, supported versions are {}..={}",
+                min_v, max_v
+            ),
+            InvalidNBits(nbits) => write!(f, "Invalid nbits {:#010x}", nbits),
+            InvalidExtranoncePrefixLen((expected, actual)) => write!(
+                f,
+                "Invalid extranonce prefix length {}, expected {}",
+                actual, expected
+            ),
+            InvalidExtranonceSuffixLen((max, actual)) => write!(
+                f,
+                "Invalid extranonce suffix length {}, max is {}",
+                actual, max
+            ),
+            InvalidCoinbase => write!(f, "Coinbase is not a valid transaction"),
+            InvalidCoinbaseOutputs => write!(f, "Invalid coinbase outputs in the template"),
+            InvalidTransaction(index) => write!(f, "Transaction {} is not valid", index),
+            ReconnectDataMismatch => write!(
+                f,
+                "Reconnect refused, the setup connection flags differ from the previous connection"
+            ),
         }
     }
 }
@@ -49,15 +49,20 @@ pub trait ParseDownstreamMiningMessages<
     where
         Self: IsMiningDownstream + Sized,
     {
-        let (channel_type, is_work_selection_enabled, downstream_mining_data) = self_mutex
-            .safe_lock(|self_| {
-                (
-                    self_.get_channel_type(),
-                    self_.is_work_selection_enabled(),
-                    self_.get_downstream_mining_data(),
-                )
-            })
-            .unwrap();
+        let (channel_type, is_work_selection_enabled, downstream_mining_data, is_allowed) =
+            self_mutex
+                .safe_lock(|self_| {
+                    (
+                        self_.get_channel_type(),
+                        self_.is_work_selection_enabled(),
+                        self_.get_downstream_mining_data(),
+                        self_.is_message_type_allowed(message_type),
+                    )
+                })
+                .unwrap();
+        if !is_allowed {
+            return Err(Error::WrongMessageType(message_type));
+        }
         // Is fine to unwrap on safe_lock
         match (message_type, payload).try_into() {
             Ok(Mining::OpenStandardMiningChannel(mut m)) => {
@@ -156,6 +161,12 @@ pub trait ParseDownstreamMiningMessages<
 
     fn is_work_selection_enabled(&self) -> bool;
 
+    /// If false the message is refused with `Error::WrongMessageType` before being parsed, see
+    /// `MessageTypeAllowlist`. Every message type is allowed by default.
+    fn is_message_type_allowed(&self, _message_type: u8) -> bool {
+        true
+    }
+
     fn handle_open_standard_mining_channel(
         &mut self,
         m: OpenStandardMiningChannel,
@@ -26,6 +26,7 @@ pub mod common;
 pub mod mining;
 pub mod template_distribution;
 use crate::utils::Mutex;
+use common_messages_sv2::Protocol;
 use std::sync::Arc;
 
 /// Message is a serializable entity ant rapresent the means of communication between Remote(s)
@@ -90,3 +91,21 @@ impl<SubProtocol, Remote> SendTo_<SubProtocol, Remote> {
         }
     }
 }
+
+/// (sub)protocol and message type pairs that a role accept. The handlers that consult it (eg
+/// `ParseDownstreamMiningMessages::handle_message_mining`) refuse every other message, even if it
+/// is a valid message of the protocol, with `Error::WrongMessageType`.
+#[derive(Debug, Clone, Default)]
+pub struct MessageTypeAllowlist {
+    allowed: Vec<(Protocol, u8)>,
+}
+
+impl MessageTypeAllowlist {
+    pub fn new(allowed: Vec<(Protocol, u8)>) -> Self {
+        Self { allowed }
+    }
+
+    pub fn is_allowed(&self, protocol: Protocol, message_type: u8) -> bool {
+        self.allowed.contains(&(protocol, message_type))
+    }
+}
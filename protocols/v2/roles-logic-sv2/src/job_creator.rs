@@ -5,6 +5,8 @@ use bitcoin::{
         script::Script,
         transaction::{OutPoint, Transaction, TxIn, TxOut},
     },
+    consensus::{deserialize, Decodable},
+    hashes::{sha256d::Hash as DHash, Hash},
     util::psbt::serialize::Serialize,
 };
 pub use bitcoin::{
@@ -12,12 +14,110 @@ pub use bitcoin::{
     util::ecdsa::{PrivateKey, PublicKey},
 };
 use mining_sv2::NewExtendedMiningJob;
-use std::{collections::HashMap, convert::TryInto};
+use std::{collections::HashMap, convert::TryInto, io::Cursor};
 use template_distribution_sv2::{NewTemplate, SetNewPrevHash};
 
 const SCRIPT_PREFIX_LEN: usize = 4;
 const PREV_OUT_LEN: usize = 38;
 const EXTRANONCE_LEN: usize = 32;
+/// OP_RETURN, push 36 bytes and the BIP141 commitment header 0xaa21a9ed
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+/// Values used in the coinbase transaction in place of the ones in the template, they change only
+/// fixed size fields that come after the extranonce so the extranonce offset do not change
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CoinbaseOverrides {
+    pub locktime: Option<u32>,
+    pub input_sequence: Option<u32>,
+}
+
+/// Witness data of a segwit template, see `JobsCreators::set_template_witness`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateWitness {
+    /// wtxids of the transactions of the template in block order, the coinbase excluded
+    pub wtxids: Vec<[u8; 32]>,
+    /// Value committed together with the witness root, the block must have it as coinbase
+    /// witness, it is usually all zeros
+    pub witness_reserved_value: [u8; 32],
+}
+
+impl TemplateWitness {
+    /// Witness data of a template from its transactions in block order, as sent by the template
+    /// provider in `RequestTransactionData.Success`
+    pub fn from_transactions<'a>(
+        transactions: impl IntoIterator<Item = &'a [u8]>,
+        witness_reserved_value: [u8; 32],
+    ) -> Result<Self, Error> {
+        let wtxids = transactions
+            .into_iter()
+            .enumerate()
+            .map(|(index, transaction)| {
+                deserialize::<Transaction>(transaction)
+                    .map(|t| t.wtxid().as_hash().into_inner())
+                    .map_err(|_| Error::InvalidTransaction(index))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            wtxids,
+            witness_reserved_value,
+        })
+    }
+
+    /// True if `output` is a BIP141 witness commitment
+    pub fn is_commitment(output: &TxOut) -> bool {
+        output.script_pubkey.len() >= WITNESS_COMMITMENT_HEADER.len() + 32
+            && output
+                .script_pubkey
+                .as_bytes()
+                .starts_with(&WITNESS_COMMITMENT_HEADER)
+    }
+
+    /// Merkle root of the wtxids, the wtxid of the coinbase is all zeros (BIP141)
+    pub fn witness_root(&self) -> [u8; 32] {
+        let mut level: Vec<[u8; 32]> = Vec::with_capacity(self.wtxids.len() + 1);
+        level.push([0; 32]);
+        level.extend_from_slice(&self.wtxids);
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| {
+                    // The last node of a level with an odd number of nodes is hashed with itself
+                    let right = pair.get(1).unwrap_or(&pair[0]);
+                    DHash::hash(&[&pair[0][..], &right[..]].concat()).into_inner()
+                })
+                .collect();
+        }
+        level[0]
+    }
+
+    /// Double SHA256 of the witness root followed by the witness reserved value
+    pub fn commitment(&self) -> [u8; 32] {
+        let root = self.witness_root();
+        DHash::hash(&[&root[..], &self.witness_reserved_value[..]].concat()).into_inner()
+    }
+
+    /// Zero value `OP_RETURN` output with the commitment, it is the last output of the coinbase
+    pub fn commitment_output(&self) -> TxOut {
+        let mut script_pubkey = WITNESS_COMMITMENT_HEADER.to_vec();
+        script_pubkey.extend_from_slice(&self.commitment());
+        TxOut {
+            value: 0,
+            script_pubkey: script_pubkey.into(),
+        }
+    }
+}
+
+/// Outputs that the template provider asks to add to the coinbase, eg the witness commitment
+pub fn template_outputs(template: &NewTemplate) -> Result<Vec<TxOut>, Error> {
+    let mut outputs = Cursor::new(template.coinbase_tx_outputs.inner_as_ref());
+    let decoded = (0..template.coinbase_tx_outputs_count)
+        .map(|_| TxOut::consensus_decode(&mut outputs).map_err(|_| Error::InvalidCoinbaseOutputs))
+        .collect::<Result<Vec<TxOut>, Error>>()?;
+    if outputs.position() as usize != outputs.get_ref().len() {
+        return Err(Error::InvalidCoinbaseOutputs);
+    }
+    Ok(decoded)
+}
 
 /// Used by pool one for each group channel
 /// extended and standard channel not supported
@@ -34,11 +134,9 @@ impl JobCreator {
         &mut self,
         new_template: &mut NewTemplate,
         coinbase_outputs: &[TxOut],
+        overrides: CoinbaseOverrides,
+        witness: Option<&TemplateWitness>,
     ) -> Result<NewExtendedMiningJob<'static>, Error> {
-        assert!(
-            new_template.coinbase_tx_outputs_count == 0,
-            "node provided outputs not supported yet"
-        );
         let script_prefix = new_template.coinbase_prefix.to_vec();
         // Is ok to panic here cause condition will be always true when not in a test chain
         // (regtest ecc ecc)
@@ -49,15 +147,31 @@ impl JobCreator {
         let bip34_len = script_prefix[1] as usize;
         let bip34_bytes = script_prefix[1..2 + bip34_len].to_vec();
 
+        // The outputs of the template come after the ones of the pool, they are all in the
+        // coinbase suffix so the extranonce offset do not change. A commitment computed by the
+        // pool replace the one of the template.
+        let mut coinbase_outputs = coinbase_outputs.to_vec();
+        for output in template_outputs(new_template)? {
+            if witness.is_none() || !TemplateWitness::is_commitment(&output) {
+                coinbase_outputs.push(output);
+            }
+        }
+        if let Some(witness) = witness {
+            coinbase_outputs.push(witness.commitment_output());
+        }
         let coinbase = self.coinbase(
             bip34_bytes,
             new_template
                 .coinbase_tx_version
                 .try_into()
                 .expect("invalid version"),
-            new_template.coinbase_tx_locktime,
-            new_template.coinbase_tx_input_sequence,
-            coinbase_outputs,
+            overrides
+                .locktime
+                .unwrap_or(new_template.coinbase_tx_locktime),
+            overrides
+                .input_sequence
+                .unwrap_or(new_template.coinbase_tx_input_sequence),
+            &coinbase_outputs,
         );
         let new_extended_mining_job: NewExtendedMiningJob<'static> = NewExtendedMiningJob {
             channel_id: self.group_channel_id,
@@ -104,7 +218,6 @@ impl JobCreator {
     }
 
     /// coinbase_tx_input_script_prefix: extranonce prefix (script lenght + bip34 block height) provided by the node
-    /// It assume that NewTemplate.coinbase_tx_outputs == 0
     fn coinbase(
         &self,
         mut bip34_bytes: Vec<u8>,
@@ -139,6 +252,9 @@ pub struct JobsCreators {
     block_reward_staoshi: u64,
     pub_key: PublicKey,
     lasts_new_template: Vec<NewTemplate<'static>>,
+    coinbase_overrides: CoinbaseOverrides,
+    // template_id -> witness data of the template
+    template_witnesses: HashMap<u64, TemplateWitness>,
     //last_prev_hash: Pr
 }
 
@@ -150,9 +266,23 @@ impl JobsCreators {
             block_reward_staoshi,
             pub_key,
             lasts_new_template: Vec::new(),
+            coinbase_overrides: CoinbaseOverrides::default(),
+            template_witnesses: HashMap::new(),
         })
     }
 
+    /// Use `overrides` in the coinbase of the jobs created from now on
+    pub fn set_coinbase_overrides(&mut self, overrides: CoinbaseOverrides) {
+        self.coinbase_overrides = overrides;
+    }
+
+    /// Add the witness commitment of `witness` to the coinbase of the jobs created from the
+    /// template `template_id`, it must be set before the template is passed to `on_new_template`.
+    /// Without it the blocks found on a template with segwit transactions are invalid.
+    pub fn set_template_witness(&mut self, template_id: u64, witness: TemplateWitness) {
+        self.template_witnesses.insert(template_id, witness);
+    }
+
     fn new_output(block_reward_staoshi: u64, pub_key: PublicKey) -> Option<TxOut> {
         let script_pubkey = Script::new_v0_wpkh(&pub_key.wpubkey_hash()?);
         Some(TxOut {
@@ -177,7 +307,12 @@ impl JobsCreators {
 
         let mut new_extended_jobs = HashMap::new();
         for creator in &mut self.jobs_creators {
-            let job = creator.new_extended_job(template, &self.coinbase_outputs)?;
+            let job = creator.new_extended_job(
+                template,
+                &self.coinbase_outputs,
+                self.coinbase_overrides,
+                self.template_witnesses.get(&template.template_id),
+            )?;
             new_extended_jobs.insert(job.channel_id, job);
         }
         self.lasts_new_template.push(template.as_static());
@@ -205,6 +340,12 @@ impl JobsCreators {
             // TODO how many templates can we have at max
             _ => todo!("{:#?}", template.len()),
         }
+        let kept: Vec<u64> = self
+            .lasts_new_template
+            .iter()
+            .map(|t| t.template_id)
+            .collect();
+        self.template_witnesses.retain(|id, _| kept.contains(id));
     }
 
     pub fn new_group_channel(
@@ -221,7 +362,12 @@ impl JobsCreators {
         let mut res = Vec::new();
         for mut template in self.lasts_new_template.clone() {
             res.push((
-                jc.new_extended_job(&mut template, &self.coinbase_outputs)?,
+                jc.new_extended_job(
+                    &mut template,
+                    &self.coinbase_outputs,
+                    self.coinbase_overrides,
+                    self.template_witnesses.get(&template.template_id),
+                )?,
                 template.template_id,
             ));
         }
@@ -229,6 +375,14 @@ impl JobsCreators {
         Ok(res)
     }
 
+    /// True if `template_id` is one of the templates received since the last prev hash, a prev
+    /// hash for another template can not be activated
+    pub fn has_template(&self, template_id: u64) -> bool {
+        self.lasts_new_template
+            .iter()
+            .any(|t| t.template_id == template_id)
+    }
+
     pub fn job_id_from_template(&self, template_id: u64, group_id: u32) -> Option<u32> {
         for jc in &self.jobs_creators {
             if jc.group_channel_id == group_id {
@@ -238,3 +392,225 @@ impl JobsCreators {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::merkle_root_from_path;
+    use bitcoin::{consensus::serialize, secp256k1::Secp256k1, Network};
+
+    fn new_pub_key() -> PublicKey {
+        let priv_k = PrivateKey::from_slice(&[34; 32], Network::Testnet).unwrap();
+        PublicKey::from_private_key(&Secp256k1::default(), &priv_k)
+    }
+
+    fn new_template() -> NewTemplate<'static> {
+        NewTemplate {
+            template_id: 1,
+            future_template: true,
+            version: 2,
+            coinbase_tx_version: 2,
+            // script len + bip34 height
+            coinbase_prefix: vec![3, 3, 0x10, 0x27, 0x00].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: binary_sv2::Seq0255::new(vec![]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_coinbase_overrides() {
+        let mut jobs_creators = JobsCreators::new(5_000_000_000, new_pub_key()).unwrap();
+        jobs_creators.set_coinbase_overrides(CoinbaseOverrides {
+            locktime: Some(700_000),
+            input_sequence: Some(0xffff_fffe),
+        });
+        jobs_creators.new_group_channel(1, true).unwrap();
+        let mut template = new_template();
+        let job = jobs_creators
+            .on_new_template(&mut template)
+            .unwrap()
+            .remove(&1)
+            .unwrap();
+
+        let extranonce = [7_u8; EXTRANONCE_LEN];
+        let coinbase = [
+            job.coinbase_tx_prefix.inner_as_ref(),
+            &extranonce[..],
+            job.coinbase_tx_suffix.inner_as_ref(),
+        ]
+        .concat();
+        let coinbase: Transaction = deserialize(&coinbase).unwrap();
+        assert_eq!(coinbase.lock_time, 700_000);
+        assert_eq!(coinbase.input[0].sequence, 0xffff_fffe);
+        // The extranonce is still where the shares put it
+        assert!(coinbase.input[0]
+            .script_sig
+            .as_bytes()
+            .ends_with(&extranonce));
+
+        let path: Vec<Vec<u8>> = vec![];
+        let merkle_root = merkle_root_from_path(
+            job.coinbase_tx_prefix.inner_as_ref(),
+            job.coinbase_tx_suffix.inner_as_ref(),
+            &extranonce,
+            &path,
+        )
+        .unwrap();
+        assert_eq!(merkle_root, coinbase.txid().as_hash().into_inner().to_vec());
+    }
+
+    #[test]
+    fn test_witness_commitment() {
+        let witness = TemplateWitness {
+            wtxids: vec![[1; 32], [2; 32]],
+            witness_reserved_value: [0; 32],
+        };
+        let mut plain_creators = JobsCreators::new(5_000_000_000, new_pub_key()).unwrap();
+        plain_creators.new_group_channel(1, true).unwrap();
+        let plain_job = plain_creators
+            .on_new_template(&mut new_template())
+            .unwrap()
+            .remove(&1)
+            .unwrap();
+        let mut jobs_creators = JobsCreators::new(5_000_000_000, new_pub_key()).unwrap();
+        jobs_creators.new_group_channel(1, true).unwrap();
+        let mut template = new_template();
+        jobs_creators.set_template_witness(template.template_id, witness);
+        let job = jobs_creators
+            .on_new_template(&mut template)
+            .unwrap()
+            .remove(&1)
+            .unwrap();
+
+        // The extranonce offset do not change
+        assert_eq!(
+            job.coinbase_tx_prefix.inner_as_ref(),
+            plain_job.coinbase_tx_prefix.inner_as_ref()
+        );
+        let extranonce = [7_u8; EXTRANONCE_LEN];
+        let coinbase = [
+            job.coinbase_tx_prefix.inner_as_ref(),
+            &extranonce[..],
+            job.coinbase_tx_suffix.inner_as_ref(),
+        ]
+        .concat();
+        let coinbase: Transaction = deserialize(&coinbase).unwrap();
+        assert_eq!(coinbase.output.len(), 2);
+        assert_eq!(coinbase.output[0].value, 5_000_000_000);
+
+        // Merkle tree of [coinbase wtxid, wtxid 1, wtxid 2], the last node is hashed with itself
+        let hash = |a: &[u8], b: &[u8]| DHash::hash(&[a, b].concat()).into_inner();
+        let root = hash(&hash(&[0; 32], &[1; 32]), &hash(&[2; 32], &[2; 32]));
+        let commitment = hash(&root, &[0; 32]);
+        let commitment_output = &coinbase.output[1];
+        assert_eq!(commitment_output.value, 0);
+        assert_eq!(
+            commitment_output.script_pubkey.as_bytes(),
+            &[&WITNESS_COMMITMENT_HEADER[..], &commitment[..]].concat()[..]
+        );
+    }
+
+    fn coinbase_of(job: &NewExtendedMiningJob) -> Transaction {
+        let extranonce = [7_u8; EXTRANONCE_LEN];
+        let coinbase = [
+            job.coinbase_tx_prefix.inner_as_ref(),
+            &extranonce[..],
+            job.coinbase_tx_suffix.inner_as_ref(),
+        ]
+        .concat();
+        deserialize(&coinbase).unwrap()
+    }
+
+    #[test]
+    fn test_template_commitment_output() {
+        let template_commitment = TemplateWitness {
+            wtxids: vec![[3; 32]],
+            witness_reserved_value: [0; 32],
+        }
+        .commitment_output();
+        let mut template = new_template();
+        template.coinbase_tx_outputs_count = 1;
+        template.coinbase_tx_outputs = serialize(&template_commitment).try_into().unwrap();
+
+        let mut jobs_creators = JobsCreators::new(5_000_000_000, new_pub_key()).unwrap();
+        jobs_creators.new_group_channel(1, true).unwrap();
+        let job = jobs_creators
+            .on_new_template(&mut template.clone())
+            .unwrap()
+            .remove(&1)
+            .unwrap();
+        let coinbase = coinbase_of(&job);
+        assert_eq!(coinbase.output.len(), 2);
+        assert_eq!(coinbase.output[0].value, 5_000_000_000);
+        assert_eq!(coinbase.output[1], template_commitment);
+        assert!(TemplateWitness::is_commitment(&coinbase.output[1]));
+
+        // The commitment of the witness set by the pool replace the one of the template
+        let witness = TemplateWitness {
+            wtxids: vec![[4; 32]],
+            witness_reserved_value: [0; 32],
+        };
+        let mut template = template;
+        template.template_id = 2;
+        jobs_creators.set_template_witness(2, witness.clone());
+        let job = jobs_creators
+            .on_new_template(&mut template)
+            .unwrap()
+            .remove(&1)
+            .unwrap();
+        let coinbase = coinbase_of(&job);
+        assert_eq!(coinbase.output.len(), 2);
+        assert_eq!(coinbase.output[1], witness.commitment_output());
+    }
+
+    #[test]
+    fn test_invalid_template_outputs() {
+        let mut template = new_template();
+        template.coinbase_tx_outputs_count = 2;
+        template.coinbase_tx_outputs = serialize(
+            &TemplateWitness {
+                wtxids: vec![],
+                witness_reserved_value: [0; 32],
+            }
+            .commitment_output(),
+        )
+        .try_into()
+        .unwrap();
+        assert!(matches!(
+            template_outputs(&template),
+            Err(Error::InvalidCoinbaseOutputs)
+        ));
+    }
+
+    #[test]
+    fn test_witness_from_transactions() {
+        let transaction = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: vec![1].into(),
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 1,
+                script_pubkey: vec![0x51].into(),
+            }],
+        };
+        let transaction = serialize(&transaction);
+        let witness = TemplateWitness::from_transactions(vec![&transaction[..]], [0; 32]).unwrap();
+        // Without witness the wtxid is the txid
+        let txid = deserialize::<Transaction>(&transaction).unwrap().txid();
+        assert_eq!(witness.wtxids, vec![txid.as_hash().into_inner()]);
+
+        assert!(matches!(
+            TemplateWitness::from_transactions(vec![&transaction[..], &[1, 2, 3][..]], [0; 32]),
+            Err(Error::InvalidTransaction(1))
+        ));
+    }
+}
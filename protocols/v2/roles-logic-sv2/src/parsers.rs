@@ -182,6 +182,82 @@ pub trait IsSv2Message {
     fn channel_bit(&self) -> bool;
 }
 
+/// A message struct decoded only from the frames of its own message type
+pub trait Sv2Message<'a>: Deserialize<'a> {
+    const MESSAGE_TYPE: u8;
+
+    /// Decode the payload of `frame` as `Self`, return `Error::WrongMessageType` with the message
+    /// type of the header if it is not `Self::MESSAGE_TYPE`, in that case nothing is decoded
+    fn from_frame<T, B>(frame: &'a mut Sv2Frame<T, B>) -> Result<Self, Error>
+    where
+        Sv2Frame<T, B>: Frame<'a, T>,
+    {
+        let message_type = frame
+            .get_header()
+            .ok_or(Error::UnexpectedMessage)?
+            .msg_type();
+        if message_type != Self::MESSAGE_TYPE {
+            return Err(Error::WrongMessageType(message_type));
+        }
+        Ok(from_bytes(frame.payload())?)
+    }
+}
+
+macro_rules! impl_sv2_message {
+    ($($message:ty => $message_type:expr,)*) => {
+        $(
+            impl<'a> Sv2Message<'a> for $message {
+                const MESSAGE_TYPE: u8 = $message_type;
+            }
+        )*
+    };
+}
+
+impl_sv2_message!(
+    SetupConnection<'a> => MESSAGE_TYPE_SETUP_CONNECTION,
+    SetupConnectionSuccess => MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS,
+    SetupConnectionError<'a> => MESSAGE_TYPE_SETUP_CONNECTION_ERROR,
+    ChannelEndpointChanged => MESSAGE_TYPE_CHANNEL_ENDPOINT_CHANGED,
+    CoinbaseOutputDataSize => MESSAGE_TYPE_COINBASE_OUTPUT_DATA_SIZE,
+    NewTemplate<'a> => MESSAGE_TYPE_NEW_TEMPLATE,
+    SetNewPrevHash<'a> => MESSAGE_TYPE_SET_NEW_PREV_HASH,
+    RequestTransactionData => MESSAGE_TYPE_REQUEST_TRANSACTION_DATA,
+    RequestTransactionDataSuccess<'a> => MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_SUCCESS,
+    RequestTransactionDataError<'a> => MESSAGE_TYPE_REQUEST_TRANSACTION_DATA_ERROR,
+    SubmitSolution<'a> => MESSAGE_TYPE_SUBMIT_SOLUTION,
+    AllocateMiningJobToken<'a> => MESSAGE_TYPE_ALLOCATE_MINING_JOB_TOKEN,
+    AllocateMiningJobTokenSuccess => MESSAGE_TYPE_ALLOCATE_MINING_JOB_SUCCESS,
+    CommitMiningJob<'a> => MESSAGE_TYPE_COMMIT_MINING_JOB,
+    CommitMiningJobSuccess => MESSAGE_TYPE_COMMIT_MINING_JOB_SUCCESS,
+    CommitMiningJobError<'a> => MESSAGE_TYPE_COMMIT_MINING_JOB_ERROR,
+    IdentifyTransactions => MESSAGE_TYPE_IDENTIFY_TRANSACTIONS,
+    IdentifyTransactionsSuccess<'a> => MESSAGE_TYPE_IDENTIFY_TRANSACTIONS_SUCCESS,
+    ProvideMissingTransactions<'a> => MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTION,
+    ProvideMissingTransactionsSuccess<'a> => MESSAGE_TYPE_PROVIDE_MISSING_TRANSACTION_SUCCESS,
+    CloseChannel<'a> => MESSAGE_TYPE_CLOSE_CHANNEL,
+    NewExtendedMiningJob<'a> => MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB,
+    NewMiningJob<'a> => MESSAGE_TYPE_NEW_MINING_JOB,
+    OpenExtendedMiningChannel<'a> => MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+    OpenExtendedMiningChannelSuccess<'a> => MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL_SUCCES,
+    OpenMiningChannelError<'a> => MESSAGE_TYPE_OPEN_MINING_CHANNEL_ERROR,
+    OpenStandardMiningChannel<'a> => MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL,
+    OpenStandardMiningChannelSuccess<'a> => MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+    Reconnect<'a> => MESSAGE_TYPE_RECONNECT,
+    SetCustomMiningJob<'a> => MESSAGE_TYPE_SET_CUSTOM_MINING_JOB,
+    SetCustomMiningJobError<'a> => MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_ERROR,
+    SetCustomMiningJobSuccess<'a> => MESSAGE_TYPE_SET_CUSTOM_MINING_JOB_SUCCESS,
+    SetExtranoncePrefix<'a> => MESSAGE_TYPE_SET_EXTRANONCE_PREFIX,
+    SetGroupChannel<'a> => MESSAGE_TYPE_SET_GROUP_CHANNEL,
+    MiningSetNewPrevHash<'a> => MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH,
+    SetTarget<'a> => MESSAGE_TYPE_SET_TARGET,
+    SubmitSharesError<'a> => MESSAGE_TYPE_SUBMIT_SHARES_ERROR,
+    SubmitSharesExtended<'a> => MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+    SubmitSharesStandard => MESSAGE_TYPE_SUBMIT_SHARES_STANDARD,
+    SubmitSharesSuccess => MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+    UpdateChannel<'a> => MESSAGE_TYPE_UPDATE_CHANNEL,
+    UpdateChannelError<'a> => MESSAGE_TYPE_UPDATE_CHANNEL_ERROR,
+);
+
 impl<'a> IsSv2Message for CommonMessages<'a> {
     fn message_type(&self) -> u8 {
         match self {
@@ -971,6 +1047,24 @@ pub enum PoolMessages<'a> {
     TemplateDistribution(TemplateDistribution<'a>),
 }
 
+impl<'a> PoolMessages<'a> {
+    /// Build an `Sv2Frame` for the message, return `Error::BadPayloadSize` if the encoded message
+    /// do not fit into a frame
+    pub fn into_frame<B: AsMut<[u8]> + AsRef<[u8]>>(
+        self,
+    ) -> Result<Sv2Frame<PoolMessages<'a>, B>, Error> {
+        self.try_into()
+    }
+
+    /// Length of the encoded message, that is the length written in the frame header. It can be
+    /// checked against the max frame payload or used to pre-size a buffer without encoding the
+    /// message.
+    #[cfg(not(feature = "with_serde"))]
+    pub fn payload_len(&self) -> usize {
+        self.get_size()
+    }
+}
+
 impl<'a> TryFrom<MiningDeviceMessages<'a>> for PoolMessages<'a> {
     type Error = Error;
 
@@ -1159,3 +1253,107 @@ impl<'a> TryFrom<PoolMessages<'a>> for MiningDeviceMessages<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binary_sv2::{Seq064K, B016M, B064K};
+
+    #[test]
+    fn test_into_frame_oversized_message() {
+        // Two transactions of the max B016M size, the payload is bigger than the max frame payload
+        let tx = || B016M::try_from(vec![0; 16_777_215]).unwrap();
+        let message = PoolMessages::TemplateDistribution(
+            TemplateDistribution::RequestTransactionDataSuccess(RequestTransactionDataSuccess {
+                template_id: 1,
+                excess_data: B064K::try_from(vec![]).unwrap(),
+                transaction_list: Seq064K::new(vec![tx(), tx()]).unwrap(),
+            }),
+        );
+        let frame: Result<Sv2Frame<PoolMessages, Vec<u8>>, Error> = message.into_frame();
+        assert!(matches!(frame, Err(Error::BadPayloadSize)));
+    }
+
+    #[test]
+    fn test_lazy_request_transaction_data_success() {
+        use binary_sv2::LazyMessage;
+        let message = RequestTransactionDataSuccess {
+            template_id: 7,
+            excess_data: B064K::try_from(vec![1, 2]).unwrap(),
+            transaction_list: Seq064K::new(vec![B016M::try_from(vec![3; 16_777_215]).unwrap()])
+                .unwrap(),
+        };
+        let mut data = binary_sv2::to_bytes(message.clone()).unwrap();
+        let mut lazy = LazyMessage::new::<RequestTransactionDataSuccess>(&mut data[..]).unwrap();
+
+        let template_id: u64 = lazy.take(0).unwrap();
+        assert_eq!(template_id, 7);
+        // The transaction list is not decoded until it is taken
+        let (_, _, transactions_len) = lazy.ranges()[2];
+        assert_eq!(lazy.encoded(2).unwrap().len(), transactions_len);
+        assert!(lazy.encoded(1).is_none());
+
+        let transaction_list: Seq064K<B016M> = lazy.take(2).unwrap();
+        assert_eq!(transaction_list, message.transaction_list);
+        assert!(lazy.encoded(2).is_none());
+        assert!(lazy.take::<Seq064K<B016M>>(2).is_err());
+    }
+
+    #[test]
+    fn test_from_frame_wrong_message_type() {
+        let message = PoolMessages::TemplateDistribution(TemplateDistribution::SetNewPrevHash(
+            SetNewPrevHash {
+                template_id: 1,
+                prev_hash: [1; 32].into(),
+                header_timestamp: 2,
+                n_bits: 3,
+                target: [4; 32].into(),
+            },
+        ));
+        let frame: Sv2Frame<PoolMessages, Vec<u8>> = message.into_frame().unwrap();
+        let mut encoded = vec![0; frame.encoded_length()];
+        frame.serialize(&mut encoded).unwrap();
+
+        let mut frame: Sv2Frame<PoolMessages, Vec<u8>> =
+            Sv2Frame::from_bytes(encoded.clone()).unwrap();
+        let wrong = NewExtendedMiningJob::from_frame(&mut frame);
+        assert!(matches!(
+            wrong,
+            Err(Error::WrongMessageType(MESSAGE_TYPE_SET_NEW_PREV_HASH))
+        ));
+
+        let mut frame: Sv2Frame<PoolMessages, Vec<u8>> = Sv2Frame::from_bytes(encoded).unwrap();
+        let prev_hash = SetNewPrevHash::from_frame(&mut frame).unwrap();
+        assert_eq!(prev_hash.template_id, 1);
+    }
+
+    #[test]
+    fn test_payload_len() {
+        let messages = vec![
+            PoolMessages::Common(CommonMessages::SetupConnectionSuccess(
+                SetupConnectionSuccess {
+                    used_version: 2,
+                    flags: 0,
+                },
+            )),
+            PoolMessages::Mining(Mining::SubmitSharesError(SubmitSharesError {
+                channel_id: 1,
+                sequence_number: 2,
+                error_code: "difficulty-too-low".to_string().try_into().unwrap(),
+            })),
+            PoolMessages::TemplateDistribution(TemplateDistribution::SetNewPrevHash(
+                SetNewPrevHash {
+                    template_id: 1,
+                    prev_hash: [1; 32].into(),
+                    header_timestamp: 2,
+                    n_bits: 3,
+                    target: [4; 32].into(),
+                },
+            )),
+        ];
+        for message in messages {
+            let payload_len = message.payload_len();
+            assert_eq!(payload_len, binary_sv2::to_bytes(message).unwrap().len());
+        }
+    }
+}
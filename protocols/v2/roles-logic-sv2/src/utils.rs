@@ -13,18 +13,29 @@ use std::{
     sync::{Mutex as Mutex_, MutexGuard, PoisonError},
 }; //compact_target_from_u256
 
-/// Generator of unique ids
-#[derive(Debug, PartialEq)]
+/// Generator of unique ids, it yields 1, 2, 3, ... and never `Id::RESERVED`
+#[derive(Debug, PartialEq, Clone)]
 pub struct Id {
     state: u32,
 }
 
 impl Id {
+    /// Never yielded so that it can be used as a sentinel, eg the pool use it as the group
+    /// channel id of the header only channels
+    pub const RESERVED: u32 = u32::MAX;
+
     pub fn new() -> Self {
         Self { state: 0 }
     }
+
+    /// # Panics
+    ///
+    /// If every id below `Id::RESERVED` has already been yielded
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> u32 {
+        if self.state + 1 == Self::RESERVED {
+            panic!("Id space exhausted, {} is reserved", Self::RESERVED);
+        }
         self.state += 1;
         self.state
     }
@@ -255,6 +266,64 @@ pub fn target_from_hash_rate(hash_per_second: f32, share_per_min: f32) -> U256<'
     target.into()
 }
 
+/// Check that `nbits` is a well formed compact target, as decoded by bitcoin: the target must
+/// not be zero, the sign bit must not be set and the target must fit in 256 bits.
+pub fn validate_nbits(nbits: u32) -> Result<(), Error> {
+    let exponent = nbits >> 24;
+    let mantissa = nbits & 0x007f_ffff;
+    let negative = nbits & 0x0080_0000 != 0;
+    let target_is_zero = if exponent <= 3 {
+        mantissa >> (8 * (3 - exponent)) == 0
+    } else {
+        mantissa == 0
+    };
+    let overflow =
+        exponent > 34 || (mantissa > 0xff && exponent > 33) || (mantissa > 0xffff && exponent > 32);
+    if target_is_zero || negative || overflow {
+        Err(Error::InvalidNBits(nbits))
+    } else {
+        Ok(())
+    }
+}
+
+// Bitcoin difficulty 1 target (nbits 0x1d00ffff), it is the target of the SV1 difficulty 1
+fn difficulty_1_target() -> f64 {
+    65_535.0 * 2_f64.powi(208)
+}
+
+/// SV2 target of the SV1 difficulty `difficulty`: the difficulty 1 target divided by
+/// `difficulty`. The division is done in f64 and the quotient is truncated to an integer, so only
+/// the 53 most significant bits of the target are exact and the target is rounded toward zero
+/// (slightly harder). Difficulties that make the target overflow 256 bits, 0, negative
+/// difficulties and NaN give the easiest target. The target is big endian.
+pub fn difficulty_to_target(difficulty: f64) -> U256<'static> {
+    let target = difficulty_1_target() / difficulty;
+    if difficulty.is_nan() || difficulty <= 0.0 || target >= 2_f64.powi(256) {
+        return [0xff; 32].into();
+    }
+    let mut target = target.trunc();
+    let mut bytes = [0_u8; 32];
+    // The target is big endian like in the pool and in the `Ord` of U256, the most significant
+    // word is the first one
+    for (i, chunk) in bytes.chunks_exact_mut(8).enumerate() {
+        let unit = 2_f64.powi(64 * (3 - i as i32));
+        let word = (target / unit).trunc();
+        target -= word * unit;
+        chunk.copy_from_slice(&(word as u64).to_be_bytes());
+    }
+    bytes.into()
+}
+
+/// SV1 difficulty of the SV2 `target`, the inverse of [`difficulty_to_target`]. The target is
+/// rounded to an f64 before the division, the zero target has an infinite difficulty.
+pub fn target_to_difficulty(target: &U256) -> f64 {
+    let target = target
+        .inner_as_ref()
+        .iter()
+        .fold(0.0, |acc, byte| acc * 256.0 + *byte as f64);
+    difficulty_1_target() / target
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(feature = "serde")]
@@ -379,6 +448,68 @@ mod tests {
             path,
         }
     }
+    #[test]
+    fn test_validate_nbits() {
+        use super::validate_nbits;
+        // mainnet difficulty 1, regtest and a recent mainnet block
+        assert!(validate_nbits(0x1d00_ffff).is_ok());
+        assert!(validate_nbits(0x207f_ffff).is_ok());
+        assert!(validate_nbits(0x1703_a30c).is_ok());
+
+        assert!(validate_nbits(0).is_err());
+        // zero mantissa
+        assert!(validate_nbits(0x1d00_0000).is_err());
+        // sign bit set
+        assert!(validate_nbits(0x1d80_ffff).is_err());
+        // bigger than 256 bits
+        assert!(validate_nbits(0x2300_ffff).is_err());
+        assert!(validate_nbits(0xff00_ffff).is_err());
+        // mantissa shifted out
+        assert!(validate_nbits(0x0100_3456).is_err());
+    }
+
+    #[test]
+    fn test_difficulty_to_target() {
+        use super::{difficulty_to_target, target_to_difficulty};
+        // 0x00000000ffff0000..., big endian
+        let mut difficulty_1: [u8; 32] = [0; 32];
+        difficulty_1[4] = 0xff;
+        difficulty_1[5] = 0xff;
+        assert_eq!(difficulty_to_target(1.0).to_vec(), difficulty_1);
+        // 0x000000007fff8000...
+        let mut difficulty_2: [u8; 32] = [0; 32];
+        difficulty_2[4] = 0x7f;
+        difficulty_2[5] = 0xff;
+        difficulty_2[6] = 0x80;
+        assert_eq!(difficulty_to_target(2.0).to_vec(), difficulty_2);
+        assert_eq!(target_to_difficulty(&difficulty_1.into()), 1.0);
+        assert_eq!(target_to_difficulty(&difficulty_2.into()), 2.0);
+        // A higher difficulty is a smaller target
+        assert!(difficulty_to_target(2.0) < difficulty_to_target(1.0));
+
+        for difficulty in [0.001, 0.5, 1024.0, 65_536.0, 1e12] {
+            let target = difficulty_to_target(difficulty);
+            let error = (target_to_difficulty(&target) - difficulty) / difficulty;
+            assert!(error.abs() < 1e-12);
+        }
+
+        assert_eq!(difficulty_to_target(0.0).to_vec(), [0xff; 32]);
+        assert_eq!(difficulty_to_target(f64::NAN).to_vec(), [0xff; 32]);
+        assert_eq!(difficulty_to_target(1e-80).to_vec(), [0xff; 32]);
+        assert!(target_to_difficulty(&[0; 32].into()).is_infinite());
+    }
+
+    #[test]
+    fn test_id_never_yields_reserved() {
+        use super::Id;
+        let mut id = Id {
+            state: Id::RESERVED - 3,
+        };
+        assert_eq!(id.next(), Id::RESERVED - 2);
+        assert_eq!(id.next(), Id::RESERVED - 1);
+        assert!(std::panic::catch_unwind(move || id.next()).is_err());
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn gets_merkle_root_from_path() {
@@ -338,6 +338,15 @@ impl GetSize for Protocol {
     }
 }
 
+#[cfg(not(feature = "with_serde"))]
+impl binary_sv2::IntoStatic for Protocol {
+    type Owned = Self;
+
+    fn into_static(self) -> Self::Owned {
+        self
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -353,4 +362,26 @@ mod test {
             flag_required
         ));
     }
+
+    #[cfg(not(feature = "with_serde"))]
+    #[test]
+    fn test_unknown_protocol() {
+        let setup_connection = SetupConnection {
+            protocol: Protocol::MiningProtocol,
+            min_version: 2,
+            max_version: 2,
+            flags: 0,
+            endpoint_host: b"0.0.0.0".to_vec().try_into().unwrap(),
+            endpoint_port: 8081,
+            vendor: b"Bitmain".to_vec().try_into().unwrap(),
+            hardware_version: b"901".to_vec().try_into().unwrap(),
+            firmware: b"abcX".to_vec().try_into().unwrap(),
+            device_id: b"89567".to_vec().try_into().unwrap(),
+        };
+        let mut bytes = binary_sv2::to_bytes(setup_connection).unwrap();
+        // The protocol is the first field
+        bytes[0] = 99;
+        let decoded: Result<SetupConnection, _> = binary_sv2::from_bytes(&mut bytes[..]);
+        assert!(matches!(decoded, Err(Error::ValueIsNotAValidProtocol(99))));
+    }
 }
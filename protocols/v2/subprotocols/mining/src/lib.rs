@@ -144,7 +144,7 @@ pub use set_target::SetTarget;
 pub use submit_shares::{
     SubmitSharesError, SubmitSharesExtended, SubmitSharesStandard, SubmitSharesSuccess,
 };
-pub use update_channel::{UpdateChannel, UpdateChannelError};
+pub use update_channel::{UpdateChannel, UpdateChannelError, UpdateChannelErrorCode};
 const MAX_EXTRANONCE_LEN: usize = 32;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -465,6 +465,51 @@ impl ExtendedExtranonce {
         }
     }
 
+    /// Build an ExtendedExtranonce that continue the allocation of extended extranonces from
+    /// `next`, the value returned by [Self::current_cursor] before the restart. The next call to
+    /// [Self::next_extended] returns the extranonce that would have been returned by the
+    /// ExtendedExtranonce that produced `next`, so that reconnecting downstreams do not get
+    /// already assigned extranonces. Returns None if `next` can not be represented in range_1 or
+    /// if range_2.end is greater than 32.
+    pub fn from_cursor(
+        range_0: Range<usize>,
+        range_1: Range<usize>,
+        range_2: Range<usize>,
+        next: u64,
+    ) -> Option<Self> {
+        if range_2.end > MAX_EXTRANONCE_LEN {
+            return None;
+        }
+        let cursor = next.to_be_bytes();
+        let len = range_1.end - range_1.start;
+        let mut extended_extranonce = Self::new(range_0, range_1.clone(), range_2);
+        let range_1 = &mut extended_extranonce.inner[range_1];
+        if len < cursor.len() {
+            if cursor[..cursor.len() - len].iter().any(|b| *b != 0) {
+                return None;
+            }
+            range_1.copy_from_slice(&cursor[cursor.len() - len..]);
+        } else {
+            range_1[len - cursor.len()..].copy_from_slice(&cursor);
+        }
+        Some(extended_extranonce)
+    }
+
+    /// Return the allocation cursor: the number represented by the bytes in range_1 that is the
+    /// number of extended extranonces already allocated. If range_1 is longer than 8 bytes only
+    /// the last 8 bytes are considered. It can be persisted and used with [Self::from_cursor].
+    pub fn current_cursor(&self) -> u64 {
+        let range_1 = &self.inner[self.range_1.start..self.range_1.end];
+        let mut cursor = [0; 8];
+        let len = range_1.len();
+        if len < 8 {
+            cursor[8 - len..].copy_from_slice(range_1);
+        } else {
+            cursor.copy_from_slice(&range_1[len - 8..]);
+        }
+        u64::from_be_bytes(cursor)
+    }
+
     /// Specular of [Self::from_downstream_extranonce]
     /// Suppose that P receives from the upstream an extranonce that needs to be converted into any
     /// ExtendedExtranonce, eg when an extended channel is opened. Then range_0 (that should
@@ -804,6 +849,31 @@ mod tests {
             }
         }
     }
+    #[test]
+    fn test_extended_extranonce_from_cursor() {
+        let mut extended_extranonce = ExtendedExtranonce::new(0..0, 0..16, 16..32);
+        for _ in 0..10 {
+            extended_extranonce.next_extended(16).unwrap();
+        }
+        let cursor = extended_extranonce.current_cursor();
+        assert_eq!(cursor, 10);
+
+        let mut restored = ExtendedExtranonce::from_cursor(0..0, 0..16, 16..32, cursor).unwrap();
+        assert_eq!(restored.current_cursor(), 10);
+        assert_eq!(
+            restored.next_extended(16),
+            extended_extranonce.next_extended(16)
+        );
+        assert_eq!(restored.current_cursor(), 11);
+    }
+
+    #[test]
+    fn test_extended_extranonce_from_cursor_too_big() {
+        assert!(ExtendedExtranonce::from_cursor(0..0, 0..1, 1..32, 256).is_none());
+        let mut restored = ExtendedExtranonce::from_cursor(0..0, 0..1, 1..32, 255).unwrap();
+        assert!(restored.next_extended(31).is_none());
+    }
+
     #[quickcheck_macros::quickcheck]
     fn test_target_from_u256(input: (u128, u128)) -> bool {
         let target_expected = Target {
@@ -894,4 +964,314 @@ mod tests {
             result[..].try_into().unwrap()
         }
     }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_new_extended_mining_job_to_json() {
+        use binary_sv2::{json::to_json_value, Seq0255, B064K};
+
+        let merkle_path: Seq0255<U256> = Seq0255::new(vec![[0xab; 32].into()]).unwrap();
+        let coinbase_tx_prefix: B064K = vec![1, 2].try_into().unwrap();
+        let coinbase_tx_suffix: B064K = vec![0xff].try_into().unwrap();
+        let job = NewExtendedMiningJob {
+            channel_id: 7,
+            job_id: 42,
+            future_job: true,
+            version: 2,
+            version_rolling_allowed: false,
+            merkle_path,
+            coinbase_tx_prefix,
+            coinbase_tx_suffix,
+        };
+
+        let json = to_json_value(job);
+
+        assert_eq!(json[0], 7);
+        assert_eq!(json[1], 42);
+        assert_eq!(json[2], true);
+        assert_eq!(json[5][0], "ab".repeat(32).as_str());
+        assert_eq!(json[6], "0102");
+        assert_eq!(json[7], "ff");
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    #[test]
+    fn test_new_extended_mining_job_field_ranges() {
+        use binary_sv2::{field_ranges, to_bytes, Seq0255, B064K};
+
+        let merkle_path: Seq0255<U256> = Seq0255::new(vec![[0xab; 32].into()]).unwrap();
+        let coinbase_tx_prefix: B064K = vec![1, 2].try_into().unwrap();
+        let coinbase_tx_suffix: B064K = vec![0xff].try_into().unwrap();
+        let job = NewExtendedMiningJob {
+            channel_id: 7,
+            job_id: 42,
+            future_job: true,
+            version: 2,
+            version_rolling_allowed: false,
+            merkle_path,
+            coinbase_tx_prefix,
+            coinbase_tx_suffix,
+        };
+        let data = to_bytes(job).unwrap();
+
+        let ranges = field_ranges::<NewExtendedMiningJob>(&data[..]).unwrap();
+
+        assert_eq!(ranges.len(), 8);
+        assert_eq!(ranges[5], (5, 14, 33));
+        assert_eq!(ranges[6], (6, 47, 4));
+        assert_eq!(ranges[7], (7, 51, 3));
+        assert_eq!(data.len(), 54);
+        // The ranges include the length prefix of the fields
+        let (_, offset, len) = ranges[5];
+        assert_eq!(data[offset], 1);
+        assert_eq!(&data[offset + 1..offset + len], &[0xab; 32][..]);
+        let (_, offset, len) = ranges[6];
+        assert_eq!(&data[offset + 2..offset + len], &[1, 2][..]);
+        let (_, offset, len) = ranges[7];
+        assert_eq!(&data[offset + 2..offset + len], &[0xff][..]);
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    #[test]
+    fn test_update_channel_decode_with_markers() {
+        use binary_sv2::{
+            decodable::{
+                decode_with_markers, Decodable, DecodableField, DecodablePrimitive, FieldMarker,
+                PrimitiveMarker,
+            },
+            to_bytes,
+        };
+
+        let message = UpdateChannel {
+            channel_id: 7,
+            nominal_hash_rate: 1.5,
+            maximum_target: [0xff; 32].into(),
+        };
+        let mut data = to_bytes(message).unwrap();
+
+        let (fields, markers) = decode_with_markers::<UpdateChannel>(&mut data[..]).unwrap();
+
+        assert_eq!(
+            markers,
+            vec![
+                FieldMarker::Primitive(PrimitiveMarker::U32),
+                FieldMarker::Primitive(PrimitiveMarker::F32),
+                FieldMarker::Primitive(PrimitiveMarker::U256),
+            ]
+        );
+        // Change a field and rebuild the message
+        let mut fields: Vec<DecodableField> = fields.into();
+        fields[0] = DecodableField::Primitive(DecodablePrimitive::U32(8));
+        let updated = UpdateChannel::from_decoded_fields(fields).unwrap();
+        assert_eq!(updated.channel_id, 8);
+        assert_eq!(updated.nominal_hash_rate, 1.5);
+        assert_eq!(updated.maximum_target.inner_as_ref(), &[0xff; 32][..]);
+    }
+
+    #[cfg(not(feature = "with_serde"))]
+    #[test]
+    fn test_new_extended_mining_job_from_bytes_owned() {
+        use binary_sv2::{from_bytes_owned, to_bytes, Seq0255, B064K};
+
+        fn keep<T: 'static>(value: T) -> T {
+            value
+        }
+
+        let merkle_path: Seq0255<U256> = Seq0255::new(vec![[0xab; 32].into()]).unwrap();
+        let coinbase_tx_prefix: B064K = vec![1, 2].try_into().unwrap();
+        let coinbase_tx_suffix: B064K = vec![0xff].try_into().unwrap();
+        let job = NewExtendedMiningJob {
+            channel_id: 7,
+            job_id: 42,
+            future_job: true,
+            version: 2,
+            version_rolling_allowed: false,
+            merkle_path,
+            coinbase_tx_prefix,
+            coinbase_tx_suffix,
+        };
+
+        let owned = {
+            let data = to_bytes(job).unwrap();
+            keep(from_bytes_owned::<NewExtendedMiningJob>(&data[..]).unwrap())
+        };
+
+        assert_eq!(owned.channel_id, 7);
+        assert_eq!(owned.job_id, 42);
+        assert!(owned.future_job);
+        assert_eq!(owned.merkle_path.to_vec(), vec![vec![0xab; 32]]);
+        assert_eq!(owned.coinbase_tx_prefix.to_vec(), vec![1, 2]);
+        assert_eq!(owned.coinbase_tx_suffix.to_vec(), vec![0xff]);
+    }
+}
+
+/// One round-trip test for every message of the subprotocol, see `binary_sv2::roundtrip_all`
+#[cfg(all(test, not(feature = "with_serde")))]
+mod roundtrip {
+    use super::*;
+    use alloc::string::ToString;
+    use binary_sv2::{roundtrip_all, Seq0255, Seq064K, Str0255, B064K};
+
+    fn str0255(s: &str) -> Str0255<'static> {
+        s.to_string().try_into().unwrap()
+    }
+
+    fn b064k(len: usize) -> B064K<'static> {
+        vec![0xab; len].try_into().unwrap()
+    }
+
+    fn merkle_path() -> Seq0255<'static, U256<'static>> {
+        Seq0255::new(vec![[1; 32].into(), [2; 32].into()]).unwrap()
+    }
+
+    roundtrip_all! {
+        roundtrip_close_channel: CloseChannel = CloseChannel {
+            channel_id: 1,
+            reason_code: str0255("shutdown"),
+        };
+        roundtrip_new_mining_job: NewMiningJob = NewMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: true,
+            version: 0x2000_0000,
+            merkle_root: vec![3; 32].try_into().unwrap(),
+        };
+        roundtrip_new_extended_mining_job: NewExtendedMiningJob = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: merkle_path(),
+            coinbase_tx_prefix: b064k(50),
+            coinbase_tx_suffix: b064k(300),
+        };
+        roundtrip_open_standard_mining_channel: OpenStandardMiningChannel =
+            OpenStandardMiningChannel {
+                request_id: 1.into(),
+                user_identity: str0255("user"),
+                nominal_hash_rate: 1.5,
+                max_target: [0xff; 32].into(),
+            };
+        roundtrip_open_standard_mining_channel_success: OpenStandardMiningChannelSuccess =
+            OpenStandardMiningChannelSuccess {
+                request_id: 1.into(),
+                channel_id: 2,
+                target: [0x0f; 32].into(),
+                extranonce_prefix: vec![4; 8].try_into().unwrap(),
+                group_channel_id: 3,
+            };
+        roundtrip_open_extended_mining_channel: OpenExtendedMiningChannel =
+            OpenExtendedMiningChannel {
+                request_id: 1.into(),
+                user_identity: str0255("user"),
+                nominal_hash_rate: 1.5,
+                max_target: [0xff; 32].into(),
+                min_extranonce_size: 8,
+            };
+        roundtrip_open_extended_mining_channel_success: OpenExtendedMiningChannelSuccess =
+            OpenExtendedMiningChannelSuccess {
+                request_id: 1,
+                channel_id: 2,
+                target: [0x0f; 32].into(),
+                extranonce_size: 16,
+                extranonce_prefix: vec![4; 16].try_into().unwrap(),
+            };
+        roundtrip_open_mining_channel_error: OpenMiningChannelError = OpenMiningChannelError {
+            request_id: 1,
+            error_code: str0255("unknown-user"),
+        };
+        roundtrip_reconnect: Reconnect = Reconnect {
+            new_host: str0255("pool.example.com"),
+            new_port: 3336,
+        };
+        roundtrip_set_custom_mining_job: SetCustomMiningJob = SetCustomMiningJob {
+            channel_id: 1,
+            request_id: 2,
+            mining_job_token: vec![5; 10].try_into().unwrap(),
+            version: 0x2000_0000,
+            prev_hash: [6; 32].into(),
+            min_ntime: 1_600_000_000,
+            nbits: 0x1d00_ffff,
+            coinbase_tx_version: 2,
+            coinbase_prefix: 7,
+            coinbase_tx_input_n_sequence: 0xffff_ffff,
+            coinbase_tx_value_remaining: 625_000_000,
+            coinbase_tx_outputs: Seq064K::new(vec![b064k(34), b064k(43)]).unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: merkle_path(),
+            extranonce_size: 16,
+            future_job: true,
+        };
+        roundtrip_set_custom_mining_job_success: SetCustomMiningJobSuccess =
+            SetCustomMiningJobSuccess {
+                channel_id: 1,
+                request_id: 2,
+                job_id: 3,
+                coinbase_tx_prefix: b064k(50),
+                coinbase_tx_suffix: b064k(300),
+            };
+        roundtrip_set_custom_mining_job_error: SetCustomMiningJobError =
+            SetCustomMiningJobError {
+                channel_id: 1,
+                request_id: 2,
+                error_code: str0255("invalid-job-param-value-version"),
+            };
+        roundtrip_set_extranonce_prefix: SetExtranoncePrefix = SetExtranoncePrefix {
+            channel_id: 1,
+            extranonce_prefix: vec![4; 8].try_into().unwrap(),
+        };
+        roundtrip_set_group_channel: SetGroupChannel = SetGroupChannel {
+            group_channel_id: 1,
+            channel_ids: Seq064K::new(vec![2, 3, 4]).unwrap(),
+        };
+        roundtrip_set_new_prev_hash: SetNewPrevHash = SetNewPrevHash {
+            channel_id: 1,
+            job_id: 2,
+            prev_hash: [6; 32].into(),
+            min_ntime: 1_600_000_000,
+            nbits: 0x1d00_ffff,
+        };
+        roundtrip_set_target: SetTarget = SetTarget {
+            channel_id: 1,
+            maximum_target: [0x0f; 32].into(),
+        };
+        roundtrip_submit_shares_standard: SubmitSharesStandard = SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 4,
+            ntime: 1_600_000_000,
+            version: 0x2000_0000,
+        };
+        roundtrip_submit_shares_extended: SubmitSharesExtended = SubmitSharesExtended {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 4,
+            ntime: 1_600_000_000,
+            version: 0x2000_0000,
+            extranonce: vec![7; 16].try_into().unwrap(),
+        };
+        roundtrip_submit_shares_success: SubmitSharesSuccess = SubmitSharesSuccess {
+            channel_id: 1,
+            last_sequence_number: 2,
+            new_submits_accepted_count: 3,
+            new_shares_sum: 4,
+        };
+        roundtrip_submit_shares_error: SubmitSharesError = SubmitSharesError {
+            channel_id: 1,
+            sequence_number: 2,
+            error_code: str0255("stale-share"),
+        };
+        roundtrip_update_channel: UpdateChannel = UpdateChannel {
+            channel_id: 1,
+            nominal_hash_rate: 1.5,
+            maximum_target: [0xff; 32].into(),
+        };
+        roundtrip_update_channel_error: UpdateChannelError = UpdateChannelError {
+            channel_id: 1,
+            error_code: str0255("max-target-out-of-range"),
+        };
+    }
 }
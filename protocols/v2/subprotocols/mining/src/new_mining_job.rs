@@ -100,6 +100,16 @@ impl<'a> NewExtendedMiningJob<'a> {
             coinbase_tx_suffix: self.coinbase_tx_suffix.clone().into_static(),
         }
     }
+
+    /// Bytes of the coinbase before the extranonce, borrowed from the job
+    pub fn coinbase_prefix_bytes(&self) -> &[u8] {
+        self.coinbase_tx_prefix.inner_as_ref()
+    }
+
+    /// Bytes of the coinbase after the extranonce, borrowed from the job
+    pub fn coinbase_suffix_bytes(&self) -> &[u8] {
+        self.coinbase_tx_suffix.inner_as_ref()
+    }
 }
 
 impl<'a> NewMiningJob<'a> {
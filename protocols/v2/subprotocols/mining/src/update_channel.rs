@@ -3,7 +3,9 @@ use alloc::vec::Vec;
 #[cfg(not(feature = "with_serde"))]
 use binary_sv2::binary_codec_sv2;
 use binary_sv2::{Deserialize, Serialize, Str0255, U256};
-use core::convert::TryInto;
+#[cfg(not(feature = "with_serde"))]
+use core::convert::TryFrom;
+use core::{convert::TryInto, str::FromStr};
 
 /// # UpdateChannel (Client -> Server)
 ///
@@ -43,3 +45,94 @@ pub struct UpdateChannelError<'decoder> {
     #[cfg_attr(feature = "with_serde", serde(borrow))]
     pub error_code: Str0255<'decoder>,
 }
+
+/// Error codes of [`UpdateChannelError`] defined by the spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateChannelErrorCode {
+    MaxTargetOutOfRange,
+    InvalidChannelId,
+}
+
+impl UpdateChannelErrorCode {
+    /// The error code as written in the `error_code` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MaxTargetOutOfRange => "max-target-out-of-range",
+            Self::InvalidChannelId => "invalid-channel-id",
+        }
+    }
+}
+
+impl FromStr for UpdateChannelErrorCode {
+    type Err = ();
+
+    /// Fail for the codes that are not defined by the spec
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max-target-out-of-range" => Ok(Self::MaxTargetOutOfRange),
+            "invalid-channel-id" => Ok(Self::InvalidChannelId),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(not(feature = "with_serde"))]
+impl<'decoder> UpdateChannelError<'decoder> {
+    /// Build an error with a code defined by the spec
+    pub fn new(channel_id: u32, code: UpdateChannelErrorCode) -> UpdateChannelError<'static> {
+        UpdateChannelError {
+            channel_id,
+            // Safe unwrap the codes are shorter than 255 bytes
+            error_code: Str0255::try_from(code.as_str().as_bytes().to_vec()).unwrap(),
+        }
+    }
+
+    /// The error code if it is one of the codes defined by the spec, None for the unknown codes
+    /// sent by a peer (that are still available in `error_code`)
+    pub fn code(&self) -> Option<UpdateChannelErrorCode> {
+        core::str::from_utf8(self.error_code.inner_as_ref())
+            .ok()?
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(all(test, not(feature = "with_serde")))]
+mod tests {
+    use super::*;
+    use binary_sv2::{from_bytes, to_bytes};
+
+    #[test]
+    fn test_update_channel_error_code() {
+        for (code, expected) in [
+            (
+                UpdateChannelErrorCode::MaxTargetOutOfRange,
+                "max-target-out-of-range",
+            ),
+            (
+                UpdateChannelErrorCode::InvalidChannelId,
+                "invalid-channel-id",
+            ),
+        ] {
+            assert_eq!(code.as_str(), expected);
+            assert_eq!(expected.parse(), Ok(code));
+
+            let mut bytes = to_bytes(UpdateChannelError::new(3, code)).unwrap();
+            // channel_id, then the Str0255 length prefix and the exact spec string
+            assert_eq!(
+                &bytes[4..],
+                &[&[expected.len() as u8], expected.as_bytes()].concat()[..]
+            );
+            let decoded: UpdateChannelError = from_bytes(&mut bytes[..]).unwrap();
+            assert_eq!(decoded.code(), Some(code));
+        }
+
+        let unknown = UpdateChannelError {
+            channel_id: 3,
+            error_code: b"some-other-code".to_vec().try_into().unwrap(),
+        };
+        assert_eq!(unknown.code(), None);
+        assert_eq!(unknown.error_code.to_vec(), b"some-other-code".to_vec());
+        assert_eq!("some-other-code".parse::<UpdateChannelErrorCode>(), Err(()));
+    }
+}
@@ -22,7 +22,14 @@ pub enum Error {
     NoSv1VersionBits,
     /// Errors on bad `String` to `int` conversion.
     ParseInt(std::num::ParseIntError),
-    /// Errors from `roles_logic_sv2` crate.
+    /// Errors if the upstream do not support any of the requested versions and flags:
+    /// (`min_v`, `max_v`, all flags supported).
+    NoPairableUpstream((u16, u16, u32)),
+    /// Errors if the upstream answers with a request id that was never sent.
+    UnknownRequestId(u32),
+    /// Errors if there are no more extranonces to assign to the downstreams.
+    NoMoreExtranonces,
+    /// Errors from `roles_logic_sv2` crate, except the ones mapped above.
     RolesSv2Logic(roles_logic_sv2::errors::Error),
     /// SV1 protocol library error
     V1Protocol(v1::error::Error),
@@ -44,6 +51,11 @@ impl fmt::Display for Error {
                 "`mining.submit` received from SV1 downstream does not contain `version_bits`"
             ),
             ParseInt(ref e) => write!(f, "Bad convert from `String` to `int`: `{:?}`", e),
+            NoPairableUpstream(ref a) => write!(f, "Upstream rejected the connection: `{:?}`", a),
+            UnknownRequestId(ref id) => {
+                write!(f, "Upstream answered with unknown request id `{}`", id)
+            }
+            NoMoreExtranonces => write!(f, "No more extranonces to assign to the downstreams"),
             RolesSv2Logic(ref e) => write!(f, "Roles SV2 Logic Error: `{:?}`", e),
             V1Protocol(ref e) => write!(f, "V1 Protocol Error: `{:?}`", e),
         }
@@ -68,6 +80,12 @@ impl From<framing_sv2::Error> for Error {
     }
 }
 
+impl From<framing_sv2::FrameConversionError> for Error {
+    fn from(e: framing_sv2::FrameConversionError) -> Self {
+        Error::FramingSv2(e.into())
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Error::Io(e)
@@ -82,7 +100,13 @@ impl From<std::num::ParseIntError> for Error {
 
 impl From<roles_logic_sv2::errors::Error> for Error {
     fn from(e: roles_logic_sv2::errors::Error) -> Self {
-        Error::RolesSv2Logic(e)
+        use roles_logic_sv2::errors::Error as RolesLogicError;
+        match e {
+            RolesLogicError::NoPairableUpstream(a) => Error::NoPairableUpstream(a),
+            RolesLogicError::UnknownRequestId(id) => Error::UnknownRequestId(id),
+            RolesLogicError::NoMoreExtranonces => Error::NoMoreExtranonces,
+            e => Error::RolesSv2Logic(e),
+        }
     }
 }
 
@@ -103,3 +127,42 @@ impl From<v1::error::Error> for Error {
         Error::V1Protocol(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roles_logic_sv2::errors::Error as RolesLogicError;
+
+    #[test]
+    fn test_roles_logic_error_mapping() {
+        let pairable: Error = RolesLogicError::NoPairableUpstream((2, 2, 0b0111)).into();
+        assert!(matches!(
+            pairable,
+            Error::NoPairableUpstream((2, 2, 0b0111))
+        ));
+        let request_id: Error = RolesLogicError::UnknownRequestId(7).into();
+        assert!(matches!(request_id, Error::UnknownRequestId(7)));
+        let extranonces: Error = RolesLogicError::NoMoreExtranonces.into();
+        assert!(matches!(extranonces, Error::NoMoreExtranonces));
+        // The other errors are kept in the catch-all
+        let other: Error = RolesLogicError::UnexpectedMessage.into();
+        assert!(matches!(
+            other,
+            Error::RolesSv2Logic(RolesLogicError::UnexpectedMessage)
+        ));
+
+        let messages: Vec<String> = [pairable, request_id, extranonces, other]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(messages[0], "Upstream rejected the connection: `(2, 2, 7)`");
+        assert_eq!(messages[1], "Upstream answered with unknown request id `7`");
+        assert_eq!(
+            messages[2],
+            "No more extranonces to assign to the downstreams"
+        );
+        for (i, message) in messages.iter().enumerate() {
+            assert!(!messages[i + 1..].contains(message));
+        }
+    }
+}
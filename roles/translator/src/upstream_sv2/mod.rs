@@ -12,8 +12,7 @@ pub type EitherFrame = StandardEitherFrame<Message>;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Sv2MiningConnection {
-    _version: u16,
+    version: u16,
     _setup_connection_flags: u32,
-    #[allow(dead_code)]
     setup_connection_success_flags: u32,
 }
@@ -1,15 +1,14 @@
 use crate::{
-    downstream_sv1::Downstream,
-    upstream_sv2::{EitherFrame, Message, StdFrame, UpstreamConnection},
+    downstream_sv1::{self, Downstream},
+    upstream_sv2::{EitherFrame, Message, StdFrame, Sv2MiningConnection, UpstreamConnection},
     ProxyResult,
 };
 use async_channel::{Receiver, Sender};
 use async_std::{net::TcpStream, task};
-use binary_sv2::u256_from_int;
 use codec_sv2::{Frame, HandshakeRole, Initiator};
 use network_helpers::Connection;
 use roles_logic_sv2::{
-    common_messages_sv2::{Protocol, SetupConnection},
+    common_messages_sv2::Protocol,
     common_properties::{IsMiningUpstream, IsUpstream},
     handlers::{
         common::{ParseUpstreamCommonMessages, SendTo as SendToCommon},
@@ -19,9 +18,9 @@ use roles_logic_sv2::{
         NewExtendedMiningJob, OpenExtendedMiningChannel, SetNewPrevHash, SubmitSharesExtended,
     },
     parsers::Mining,
-    routing_logic::{CommonRoutingLogic, MiningRoutingLogic, NoRouting},
+    routing_logic::{MiningRoutingLogic, NoRouting},
     selectors::NullDownstreamMiningSelector,
-    utils::Mutex,
+    utils::{difficulty_to_target, Mutex},
 };
 use std::{net::SocketAddr, sync::Arc};
 
@@ -29,6 +28,8 @@ use std::{net::SocketAddr, sync::Arc};
 pub struct Upstream {
     channel_id: Option<u32>,
     connection: UpstreamConnection,
+    /// Parameters negotiated with the SV2 Upstream role, `None` until `Upstream::connect` succeed
+    mining_connection: Option<Sv2MiningConnection>,
     submit_from_dowstream: Receiver<SubmitSharesExtended<'static>>,
     new_prev_hash_sender: Sender<SetNewPrevHash<'static>>,
     new_extended_mining_job_sender: Sender<NewExtendedMiningJob<'static>>,
@@ -69,6 +70,7 @@ impl Upstream {
             new_prev_hash_sender,
             new_extended_mining_job_sender,
             channel_id: None,
+            mining_connection: None,
         })))
     }
 
@@ -78,41 +80,25 @@ impl Upstream {
         min_version: u16,
         max_version: u16,
     ) -> ProxyResult<()> {
-        // Get the `SetupConnection` message with Mining Device information (currently hard coded)
-        let setup_connection = Self::get_setup_connection_message(min_version, max_version)?;
         let mut connection = self_.safe_lock(|s| s.connection.clone()).unwrap();
-
-        // Put the `SetupConnection` message in a `StdFrame` to be sent over the wire
-        let sv2_frame: StdFrame = Message::Common(setup_connection.into()).try_into()?;
-        // Send the `SetupConnection` frame to the SV2 Upstream role
-        // We support only one upstream if is not possible to connect we can just panic and let the
-        // user know which is the issue
-        connection.send(sv2_frame).await.unwrap();
-
-        // Wait for the SV2 Upstream to respond with either a `SetupConnectionSuccess` or a
-        // `SetupConnectionError` inside a SV2 binary message frame
-        let mut incoming: StdFrame = connection.receiver.recv().await.unwrap().try_into()?;
-        // Gets the binary frame message type from the message header
-        let message_type = incoming.get_header().unwrap().msg_type();
-        // Gets the message payload
-        let payload = incoming.payload();
-
-        // Handle the incoming message (should be either `SetupConnectionSuccess` or
-        // `SetupConnectionError`)
-        ParseUpstreamCommonMessages::handle_message_common(
-            self_.clone(),
-            message_type,
-            payload,
-            CommonRoutingLogic::None,
-        )?;
+        let flags = 0b0111_0000_0000_0000_0000_0000_0000_0000;
+        // We support only one upstream if is not possible to connect we can just return the
+        // error and let the user know which is the issue
+        let mining_connection = connection
+            .setup(flags, min_version, max_version, "0.0.0.0".to_string(), 50)
+            .await?;
+        self_
+            .safe_lock(|s| s.mining_connection = Some(mining_connection))
+            .unwrap();
 
         // Send open channel request before returning
         let user_identity = "ABC".to_string().try_into()?;
         let open_channel = Mining::OpenExtendedMiningChannel(OpenExtendedMiningChannel {
-            request_id: 0.into(),               // TODO
-            user_identity,                      // TODO
-            nominal_hash_rate: 5.4,             // TODO
-            max_target: u256_from_int(567_u64), // TODO
+            request_id: 0.into(),   // TODO
+            user_identity,          // TODO
+            nominal_hash_rate: 5.4, // TODO
+            // The SV1 downstreams mine with this difficulty
+            max_target: difficulty_to_target(downstream_sv1::new_difficulty()),
             min_extranonce_size: 8,
         });
         let sv2_frame: StdFrame = Message::Mining(open_channel).try_into()?;
@@ -243,42 +229,19 @@ impl Upstream {
     fn _is_contained_in_upstream_target(&self, _share: SubmitSharesExtended) -> bool {
         todo!()
     }
-
-    /// Creates the `SetupConnection` message to setup the connection with the SV2 Upstream role.
-    /// TODO: The Mining Device information is hard coded here, need to receive from Downstream
-    /// instead.
-    fn get_setup_connection_message(
-        min_version: u16,
-        max_version: u16,
-    ) -> ProxyResult<SetupConnection<'static>> {
-        let endpoint_host = "0.0.0.0".to_string().into_bytes().try_into()?;
-        let vendor = String::new().try_into()?;
-        let hardware_version = String::new().try_into()?;
-        let firmware = String::new().try_into()?;
-        let device_id = String::new().try_into()?;
-        let flags = 0b0111_0000_0000_0000_0000_0000_0000_0000;
-        Ok(SetupConnection {
-            protocol: Protocol::MiningProtocol,
-            min_version,
-            max_version,
-            flags,
-            endpoint_host,
-            endpoint_port: 50,
-            vendor,
-            hardware_version,
-            firmware,
-            device_id,
-        })
-    }
 }
 
 impl IsUpstream<Downstream, NullDownstreamMiningSelector> for Upstream {
     fn get_version(&self) -> u16 {
-        todo!()
+        self.mining_connection
+            .expect("Expected `Upstream`'s `mining_connection` to be `Some`, got `None`")
+            .version
     }
 
     fn get_flags(&self) -> u32 {
-        todo!()
+        self.mining_connection
+            .expect("Expected `Upstream`'s `mining_connection` to be `Some`, got `None`")
+            .setup_connection_success_flags
     }
 
     fn get_supported_protocols(&self) -> Vec<Protocol> {
@@ -1,6 +1,12 @@
-use super::{EitherFrame, StdFrame};
+use super::{EitherFrame, Message, StdFrame, Sv2MiningConnection};
 use crate::ProxyResult;
 use async_channel::{Receiver, Sender};
+use codec_sv2::Frame;
+use roles_logic_sv2::{
+    common_messages_sv2::{Protocol, SetupConnection},
+    errors::Error,
+    parsers::CommonMessages,
+};
 
 /// Handles the sending and receiving of messages to and from an SV2 Upstream role (most typically
 /// a SV2 Pool server).
@@ -28,4 +34,144 @@ impl UpstreamConnection {
             .expect("Error sending `EitherFrame` to the Upstream role");
         Ok(())
     }
+
+    /// Send a `SetupConnection` for the mining protocol to the SV2 Upstream role and wait for the
+    /// response. Return the negotiated parameters if the Upstream role accept the connection,
+    /// otherwise return `NoPairableUpstream` with the requested versions and the flags that
+    /// caused the error.
+    pub async fn setup(
+        &mut self,
+        flags: u32,
+        min_version: u16,
+        max_version: u16,
+        endpoint_host: String,
+        endpoint_port: u16,
+    ) -> ProxyResult<Sv2MiningConnection> {
+        // TODO: The Mining Device information is hard coded here, need to receive from
+        // Downstream instead.
+        let setup_connection = SetupConnection {
+            protocol: Protocol::MiningProtocol,
+            min_version,
+            max_version,
+            flags,
+            endpoint_host: endpoint_host.into_bytes().try_into()?,
+            endpoint_port,
+            vendor: String::new().try_into()?,
+            hardware_version: String::new().try_into()?,
+            firmware: String::new().try_into()?,
+            device_id: String::new().try_into()?,
+        };
+        let sv2_frame: StdFrame = Message::Common(setup_connection.into()).try_into()?;
+        self.send(sv2_frame).await?;
+
+        // Wait for the SV2 Upstream to respond with either a `SetupConnectionSuccess` or a
+        // `SetupConnectionError` inside a SV2 binary message frame
+        let mut incoming: StdFrame = self
+            .receiver
+            .recv()
+            .await
+            .expect("Error receiving `EitherFrame` from the Upstream role")
+            .try_into()?;
+        let message_type = incoming
+            .get_header()
+            .ok_or(Error::UnexpectedMessage)?
+            .msg_type();
+        let payload = incoming.payload();
+
+        let message: CommonMessages = (message_type, payload).try_into()?;
+        match message {
+            CommonMessages::SetupConnectionSuccess(m)
+                if m.used_version >= min_version && m.used_version <= max_version =>
+            {
+                Ok(Sv2MiningConnection {
+                    version: m.used_version,
+                    _setup_connection_flags: flags,
+                    setup_connection_success_flags: m.flags,
+                })
+            }
+            // The upstream selected a version that we did not propose
+            CommonMessages::SetupConnectionSuccess(_) => {
+                Err(Error::NoPairableUpstream((min_version, max_version, flags)).into())
+            }
+            CommonMessages::SetupConnectionError(m) => {
+                Err(Error::NoPairableUpstream((min_version, max_version, m.flags)).into())
+            }
+            _ => Err(Error::UnexpectedMessage.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roles_logic_sv2::common_messages_sv2::{SetupConnectionError, SetupConnectionSuccess};
+
+    // Answer the first `SetupConnection` with `used_version`, the connection is accepted only if
+    // every requested flag is in `supported_flags`
+    fn mock_pool(supported_flags: u32, used_version: u16) -> UpstreamConnection {
+        let (to_pool, from_translator) = async_channel::bounded(1);
+        let (to_translator, from_pool) = async_channel::bounded(1);
+        async_std::task::spawn(async move {
+            let frame: EitherFrame = from_translator.recv().await.unwrap();
+            let mut frame: StdFrame = frame.try_into().unwrap();
+            let message_type = frame.get_header().unwrap().msg_type();
+            let message: CommonMessages = (message_type, frame.payload()).try_into().unwrap();
+            let response = match message {
+                CommonMessages::SetupConnection(m) if m.flags & !supported_flags == 0 => {
+                    CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+                        used_version,
+                        flags: supported_flags,
+                    })
+                }
+                CommonMessages::SetupConnection(m) => {
+                    CommonMessages::SetupConnectionError(SetupConnectionError {
+                        flags: m.flags & !supported_flags,
+                        error_code: "unsupported-feature-flags".to_string().try_into().unwrap(),
+                    })
+                }
+                _ => panic!("Expected a `SetupConnection`"),
+            };
+            let frame: StdFrame = Message::Common(response).try_into().unwrap();
+            to_translator.send(frame.into()).await.unwrap();
+        });
+        UpstreamConnection {
+            receiver: from_pool,
+            sender: to_pool,
+        }
+    }
+
+    #[async_std::test]
+    async fn test_setup_accepted() {
+        let mut connection = mock_pool(0b0111, 2);
+        let mining_connection = connection
+            .setup(0b0110, 2, 2, "0.0.0.0".to_string(), 50)
+            .await
+            .unwrap();
+        assert_eq!(mining_connection.version, 2);
+        assert_eq!(mining_connection.setup_connection_success_flags, 0b0111);
+    }
+
+    #[async_std::test]
+    async fn test_setup_rejected_flags() {
+        let mut connection = mock_pool(0b0111, 2);
+        let res = connection
+            .setup(0b1001, 2, 2, "0.0.0.0".to_string(), 50)
+            .await;
+        assert!(matches!(
+            res,
+            Err(crate::error::Error::NoPairableUpstream((2, 2, 0b1000)))
+        ));
+    }
+
+    #[async_std::test]
+    async fn test_setup_rejected_version() {
+        let mut connection = mock_pool(0b0111, 3);
+        let res = connection
+            .setup(0b0110, 1, 2, "0.0.0.0".to_string(), 50)
+            .await;
+        assert!(matches!(
+            res,
+            Err(crate::error::Error::NoPairableUpstream((1, 2, 0b0110)))
+        ));
+    }
 }
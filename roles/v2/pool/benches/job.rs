@@ -0,0 +1,99 @@
+//! Time and allocations of the building of the job of a channel from a new extended job, run with
+//! `cargo bench --bench job`
+use binary_sv2::{
+    alloc_metrics::{measure, CountingAllocator},
+    Seq0255, U256,
+};
+use bitcoin::{
+    hash_types::BlockHash,
+    hashes::{sha256d::Hash, Hash as Hash_},
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use roles_logic_sv2::{mining_sv2::NewExtendedMiningJob, utils::merkle_root_from_path};
+use std::convert::TryInto;
+
+// The jobs of the pool, the module only depends on external crates
+#[allow(dead_code)]
+#[path = "../src/lib/mining_pool/job.rs"]
+mod job;
+use job::PartialJob;
+
+// Only count the allocations of the code run by `measure`
+#[global_allocator]
+static GLOBAL: CountingAllocator<std::alloc::System> = CountingAllocator(std::alloc::System);
+
+// Version 2 coinbase with a 12 bytes script_sig: push of the height (3 bytes) followed by 8 bytes
+// of extranonce, and one P2WPKH output
+fn coinbase_parts() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let mut prefix = vec![0x02, 0, 0, 0, 0x01];
+    prefix.extend([0; 32]);
+    prefix.extend([0xff; 4]);
+    prefix.extend([0x0c, 0x03, 0x01, 0x02, 0x03]);
+    let extranonce = vec![0; 8];
+    let mut suffix = vec![0xff; 4];
+    suffix.push(0x01);
+    suffix.extend(5_000_000_000_u64.to_le_bytes());
+    suffix.extend([0x16, 0x00, 0x14]);
+    suffix.extend([0xaa; 20]);
+    suffix.extend([0; 4]);
+    (prefix, extranonce, suffix)
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let (prefix, extranonce, suffix) = coinbase_parts();
+    let merkle_path: Vec<U256> = (0..12).map(|i| [i; 32].into()).collect();
+    let new_ext_job = NewExtendedMiningJob {
+        channel_id: 1,
+        job_id: 2,
+        future_job: false,
+        version: 0x2000_0000,
+        version_rolling_allowed: true,
+        merkle_path: Seq0255::new(merkle_path).unwrap(),
+        coinbase_tx_prefix: prefix.try_into().unwrap(),
+        coinbase_tx_suffix: suffix.try_into().unwrap(),
+    };
+    let partial = PartialJob {
+        target: [0xff; 32].into(),
+        extranonce,
+        total_shares_sum: 0,
+    };
+    let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+    let path = &new_ext_job.merkle_path.inner_as_ref()[..];
+
+    // The merkle root from the cloned prefix and suffix, as before the borrowing accessors
+    let (_, cloned) = measure(|| {
+        merkle_root_from_path(
+            &new_ext_job.coinbase_tx_prefix.to_vec()[..],
+            &new_ext_job.coinbase_tx_suffix.to_vec()[..],
+            &partial.extranonce[..],
+            path,
+        )
+    });
+    let (_, borrowed) = measure(|| {
+        merkle_root_from_path(
+            new_ext_job.coinbase_prefix_bytes(),
+            new_ext_job.coinbase_suffix_bytes(),
+            &partial.extranonce[..],
+            path,
+        )
+    });
+    let (_, job) =
+        measure(|| partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3));
+    println!(
+        "merkle root: {} bytes allocated from the cloned coinbase, {} from the borrowed one",
+        cloned.total_bytes, borrowed.total_bytes
+    );
+    println!(
+        "to_complete_standard_job: {} bytes allocated",
+        job.total_bytes
+    );
+
+    c.bench_function("to_complete_standard_job", |b| {
+        b.iter(|| {
+            partial.to_complete_standard_job(black_box(&new_ext_job), 0x1d00_ffff, prev_hash, 3)
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+use crate::Configuration;
+use codec_sv2::{
+    noise_sv2::formats::{EncodedEd25519PublicKey, EncodedEd25519SecretKey},
+    Responder,
+};
+use roles_logic_sv2::utils::Mutex;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Text of the config file. It contains the authority secret key so with the `zeroize` feature it
+/// is wiped when dropped, the `EncodedEd25519SecretKey` parsed from it already wipe itself.
+#[cfg(feature = "zeroize")]
+pub type ConfigText = noise_sv2::zeroize::Zeroizing<String>;
+#[cfg(not(feature = "zeroize"))]
+pub type ConfigText = String;
+
+pub fn read_config(path: &Path) -> std::io::Result<ConfigText> {
+    let text = std::fs::read_to_string(path)?;
+    #[cfg(feature = "zeroize")]
+    let text = noise_sv2::zeroize::Zeroizing::new(text);
+    Ok(text)
+}
+
+/// Authority keypair used to sign the noise certificates of the pool. Every new connection get a
+/// new `Responder` (and so a new certificate) from the keys that are current when the connection
+/// is accepted, so swapping the keys do not affect the already established connections.
+#[derive(Debug, Clone)]
+pub struct AuthorityKeys {
+    pub public_key: EncodedEd25519PublicKey,
+    pub secret_key: EncodedEd25519SecretKey,
+    pub cert_validity: Duration,
+}
+
+impl AuthorityKeys {
+    pub fn from_config(config: &Configuration) -> Self {
+        Self {
+            public_key: config.authority_public_key.clone(),
+            secret_key: config.authority_secret_key.clone(),
+            cert_validity: Duration::from_secs(config.cert_validity_sec),
+        }
+    }
+
+    pub fn responder(&self) -> Result<Responder, codec_sv2::noise_sv2::Error> {
+        Responder::from_authority_kp(
+            self.public_key.clone().into_inner().as_bytes(),
+            self.secret_key.clone().into_inner().as_bytes(),
+            self.cert_validity,
+        )
+    }
+
+    /// Replace `keys` with the keys of `config`. The old keys are kept if the new ones can not
+    /// build a `Responder`.
+    pub fn rotate(
+        keys: &Mutex<AuthorityKeys>,
+        config: &Configuration,
+    ) -> Result<(), codec_sv2::noise_sv2::Error> {
+        let new_keys = AuthorityKeys::from_config(config);
+        new_keys.responder()?;
+        keys.safe_lock(|k| *k = new_keys).unwrap();
+        Ok(())
+    }
+}
+
+/// Reload the authority keys and the cert validity from `config_path` every time the process
+/// receive a SIGHUP. If the new config can not be read or the new keys are not valid the old keys
+/// are kept.
+pub async fn rotate_on_sighup(keys: Arc<Mutex<AuthorityKeys>>, config_path: PathBuf) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            println!("Can not listen for SIGHUP, key rotation disabled: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        let config = match read_config(&config_path)
+            .map_err(|e| e.to_string())
+            .and_then(|c| Configuration::load(&c, std::env::vars()))
+        {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Failed to reload config file, keys not rotated: {}", e);
+                continue;
+            }
+        };
+        match AuthorityKeys::rotate(&keys, &config) {
+            Ok(()) => println!("Authority keys rotated"),
+            Err(e) => println!("Invalid authority keys, keys not rotated: {:?}", e),
+        }
+    }
+}
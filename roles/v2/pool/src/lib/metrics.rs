@@ -0,0 +1,22 @@
+//! Counters of the frames sent to and received from the downstreams, together with the codec
+//! counters of `binary_sv2::metrics`.
+use binary_sv2::metrics::{Counter, CounterSnapshot};
+
+pub static SENT: Counter = Counter::new();
+pub static RECEIVED: Counter = Counter::new();
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolMetrics {
+    pub sent: CounterSnapshot,
+    pub received: CounterSnapshot,
+    pub codec: binary_sv2::metrics::Metrics,
+}
+
+#[allow(dead_code)]
+pub fn snapshot() -> PoolMetrics {
+    PoolMetrics {
+        sent: SENT.snapshot(),
+        received: RECEIVED.snapshot(),
+        codec: binary_sv2::metrics::snapshot(),
+    }
+}
@@ -0,0 +1,224 @@
+//! Line based admin protocol served on a local socket, see `admin_address` in the config. Each
+//! received line is a command, the response is a list of lines of space separated `key=value`
+//! pairs terminated by an empty line:
+//! - `downstreams`: a line for each downstream
+//! - `channels <downstream id>`: a line for each channel of the downstream, with the lowest hash
+//!   submitted in the round as big endian hex
+//! - `jobs <downstream id>`: the current job and the future jobs of the downstream
+//! - `stats`: a single line with the counters of the pool
+//! - `retarget <downstream id> <target>`: send a `SetTarget` with the target, 64 hex chars big
+//!   endian, for every channel of the downstream, the line has the number of channels
+//! - `cancel <job id>`: cancel the future job on every downstream, the line has the number of
+//!   downstreams that had it
+//! - `pause <downstream id>`: stop sending jobs and prev hashes to the downstream, without
+//!   disconnecting it
+//! - `resume <downstream id>`: resume a paused downstream and send the jobs that it missed
+//!
+//! A command that fail return a single `error=<reason>` line.
+use super::{Downstream, Pool};
+use binary_sv2::U256;
+use roles_logic_sv2::utils::Mutex;
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task,
+};
+
+/// Answer the commands received on `listener` until the listener fail
+pub async fn serve(listener: TcpListener, pool: Arc<Mutex<Pool>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                task::spawn(handle_connection(stream, pool.clone()));
+            }
+            Err(e) => {
+                println!("ERROR: Admin socket closed: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, pool: Arc<Mutex<Pool>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let mut response = handle_command(&pool, &line).await;
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Execute `command` and return the response, every line is terminated by a new line
+pub async fn handle_command(pool: &Arc<Mutex<Pool>>, command: &str) -> String {
+    let mut args = command.split_whitespace();
+    let lines = match (args.next(), args.next().map(str::parse::<u32>), args.next()) {
+        (Some("downstreams"), None, None) => downstreams(pool).iter().map(describe).collect(),
+        (Some("channels"), Some(Ok(id)), None) => match downstream(pool, id) {
+            Some(d) => d.safe_lock(|d| channels(d)).unwrap(),
+            None => vec![format!("error=unknown-downstream id={}", id)],
+        },
+        (Some("jobs"), Some(Ok(id)), None) => match downstream(pool, id) {
+            Some(d) => d.safe_lock(|d| jobs(d)).unwrap(),
+            None => vec![format!("error=unknown-downstream id={}", id)],
+        },
+        (Some("stats"), None, None) => vec![stats(pool)],
+        (Some("retarget"), Some(Ok(id)), Some(target)) => {
+            match (downstream(pool, id), parse_target(target)) {
+                (Some(d), Some(target)) => match Downstream::retarget_all(d, target).await {
+                    Ok(channels) => vec![format!("retargeted={}", channels)],
+                    Err(()) => vec![format!("error=downstream-unreachable id={}", id)],
+                },
+                (None, _) => vec![format!("error=unknown-downstream id={}", id)],
+                (_, None) => vec![format!("error=invalid-target target={:?}", target)],
+            }
+        }
+        (Some("cancel"), Some(Ok(job_id)), None) => {
+            let cancelled = pool.safe_lock(|p| p.cancel_future_job(job_id)).unwrap();
+            vec![format!("cancelled={}", cancelled)]
+        }
+        (Some("pause"), Some(Ok(id)), None) => {
+            match pool.safe_lock(|p| p.pause_downstream(id)).unwrap() {
+                true => vec![format!("paused={}", id)],
+                false => vec![format!("error=unknown-downstream id={}", id)],
+            }
+        }
+        (Some("resume"), Some(Ok(id)), None) => {
+            match Pool::resume_downstream(pool.clone(), id).await {
+                true => vec![format!("resumed={}", id)],
+                false => vec![format!("error=unknown-downstream id={}", id)],
+            }
+        }
+        _ => vec![format!("error=unknown-command command={:?}", command)],
+    };
+    let mut response = String::new();
+    for line in lines {
+        response.push_str(&line);
+        response.push('\n');
+    }
+    response
+}
+
+// Parse 64 hex chars into a big endian U256
+fn parse_target(hex: &str) -> Option<U256<'static>> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut target = [0_u8; 32];
+    for (i, byte) in target.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(target.into())
+}
+
+// The downstreams are copied out of the pool so that the pool is not locked while the
+// downstreams are
+fn downstreams(pool: &Arc<Mutex<Pool>>) -> Vec<Arc<Mutex<Downstream>>> {
+    pool.safe_lock(|p| {
+        p.group_downstreams
+            .values()
+            .chain(p.hom_downstreams.values())
+            .cloned()
+            .collect()
+    })
+    .unwrap()
+}
+
+fn downstream(pool: &Arc<Mutex<Pool>>, id: u32) -> Option<Arc<Mutex<Downstream>>> {
+    pool.safe_lock(|p| p.get_downstream(id)).unwrap()
+}
+
+fn describe(downstream: &Arc<Mutex<Downstream>>) -> String {
+    downstream
+        .safe_lock(|d| {
+            format!(
+                "id={} header_only={} version_rolling={} channels={} paused={}",
+                d.id,
+                d.downstream_data.header_only,
+                d.downstream_data.version_rolling,
+                d.jobs.len(),
+                d.paused
+            )
+        })
+        .unwrap()
+}
+
+fn channels(downstream: &Downstream) -> Vec<String> {
+    let mut channel_ids: Vec<u32> = downstream.jobs.keys().copied().collect();
+    channel_ids.sort_unstable();
+    channel_ids
+        .into_iter()
+        .map(|channel_id| {
+            let mut line = format!(
+                "channel={} round_shares={} total_shares={}",
+                channel_id,
+                downstream.round_share_count(channel_id).unwrap_or(0),
+                downstream.total_share_count(channel_id).unwrap_or(0)
+            );
+            // Only once a share has been submitted in the round
+            if let Some(best_hash) = downstream.best_hash(channel_id) {
+                line.push_str(" best_hash=");
+                for byte in best_hash.to_vec() {
+                    line.push_str(&format!("{:02x}", byte));
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+fn jobs(downstream: &Downstream) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some((job, template_id)) = &downstream.last_valid_extended_job {
+        lines.push(format!(
+            "job={} template={} future=false",
+            job.job_id, template_id
+        ));
+    }
+    let mut future_job_ids = downstream.future_job_ids();
+    future_job_ids.sort_unstable();
+    for job_id in future_job_ids {
+        lines.push(format!(
+            "job={} template={} future=true",
+            job_id, downstream.future_jobs[&job_id].template_id
+        ));
+    }
+    lines
+}
+
+fn stats(pool: &Arc<Mutex<Pool>>) -> String {
+    let downstreams = downstreams(pool);
+    let (mut channels, mut future_jobs) = (0, 0);
+    for downstream in &downstreams {
+        downstream
+            .safe_lock(|d| {
+                channels += d.jobs.len();
+                future_jobs += d.future_jobs.len();
+            })
+            .unwrap();
+    }
+    let (last_new_work, desync_events) = pool
+        .safe_lock(|p| (p.last_new_work, p.desync_events))
+        .unwrap();
+    #[allow(unused_mut)]
+    let mut stats = format!(
+        "downstreams={} channels={} future_jobs={} last_new_work={} desync_events={}",
+        downstreams.len(),
+        channels,
+        future_jobs,
+        last_new_work,
+        desync_events
+    );
+    #[cfg(feature = "metrics")]
+    {
+        let metrics = crate::lib::metrics::snapshot();
+        stats.push_str(&format!(
+            " sent_frames={} received_frames={}",
+            metrics.sent.messages, metrics.received.messages
+        ));
+    }
+    stats
+}
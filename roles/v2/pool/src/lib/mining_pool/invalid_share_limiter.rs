@@ -0,0 +1,58 @@
+use super::system_clock;
+use crate::InvalidShareLimitConfig;
+use std::collections::HashMap;
+
+/// Count the invalid shares of the channels of a downstream, see `invalid_share_limit` in the
+/// config
+#[derive(Debug)]
+pub struct InvalidShareLimiter {
+    config: InvalidShareLimitConfig,
+    // channel_id -> (start of the window, invalid shares in the window)
+    windows: HashMap<u32, (u32, u32)>,
+    /// Return the current unix time in seconds, it is a field so that tests can control "now"
+    pub clock: fn() -> u32,
+}
+
+impl InvalidShareLimiter {
+    pub fn new(config: InvalidShareLimitConfig) -> Self {
+        Self {
+            config,
+            windows: HashMap::new(),
+            clock: system_clock,
+        }
+    }
+
+    /// Count an invalid share of `channel_id`, return true if the channel sent more than
+    /// `max_invalid_shares` invalid shares in the current window
+    pub fn on_invalid_share(&mut self, channel_id: u32) -> bool {
+        let now = (self.clock)();
+        let window = self.windows.entry(channel_id).or_insert((now, 0));
+        if now.saturating_sub(window.0) >= self.config.window_sec {
+            *window = (now, 0);
+        }
+        window.1 += 1;
+        window.1 > self.config.max_invalid_shares
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_share_limit() {
+        let mut limiter = InvalidShareLimiter::new(InvalidShareLimitConfig {
+            max_invalid_shares: 2,
+            window_sec: 10,
+        });
+        limiter.clock = || 100;
+        assert!(!limiter.on_invalid_share(1));
+        assert!(!limiter.on_invalid_share(1));
+        assert!(limiter.on_invalid_share(1));
+        // The limit is per channel
+        assert!(!limiter.on_invalid_share(2));
+        // and per window
+        limiter.clock = || 110;
+        assert!(!limiter.on_invalid_share(1));
+    }
+}
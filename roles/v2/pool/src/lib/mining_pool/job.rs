@@ -0,0 +1,386 @@
+//! Jobs of the channels of a downstream and the validation of the shares submitted for them.
+//! The module only depends on external crates so that the benches can include it.
+use binary_sv2::{B064K, U256};
+use bitcoin::{
+    blockdata::block::BlockHeader,
+    hash_types::BlockHash,
+    hashes::{sha256d::Hash, Hash as Hash_},
+    TxMerkleNode,
+};
+use roles_logic_sv2::{
+    errors::Error,
+    mining_sv2::{NewExtendedMiningJob, NewMiningJob},
+    template_distribution_sv2::SubmitSolution,
+    utils::merkle_root_from_path,
+};
+use std::convert::TryInto;
+
+#[derive(Debug, Clone)]
+pub(super) struct PartialJob {
+    pub(super) target: U256<'static>,
+    pub(super) extranonce: Vec<u8>,
+    // Valid shares of the channel since it has been opened
+    pub(super) total_shares_sum: u64,
+}
+
+impl PartialJob {
+    pub fn to_complete_standard_job(
+        &self,
+        new_ext_job: &NewExtendedMiningJob<'static>,
+        nbits: u32,
+        prev_hash: BlockHash,
+        template_id: u64,
+    ) -> CompleteJob {
+        let merkle_root: [u8; 32] = merkle_root_from_path(
+            new_ext_job.coinbase_prefix_bytes(),
+            new_ext_job.coinbase_suffix_bytes(),
+            &(self.extranonce[..]),
+            &(new_ext_job.merkle_path.inner_as_ref()[..]),
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        let merkle_root = Hash::from_inner(merkle_root);
+        let merkle_root = TxMerkleNode::from_hash(merkle_root);
+        CompleteJob {
+            target: self.target.clone(),
+            bitcoin_target: target_from_nbits(nbits),
+            nbits,
+            prev_hash,
+            version: new_ext_job.version,
+            new_shares_sum: 0,
+            total_shares_sum: self.total_shares_sum,
+            coinbase_tx_prefix: new_ext_job.coinbase_tx_prefix.to_vec(),
+            coinbase_tx_suffix: new_ext_job.coinbase_tx_suffix.to_vec(),
+            merkle_path: new_ext_job.merkle_path.to_vec(),
+            extranonce: self.extranonce.clone(),
+            merkle_root,
+            template_id,
+            best_hash: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct CompleteJob {
+    pub(super) template_id: u64,
+    pub(super) target: U256<'static>,
+    // Target of `nbits`
+    pub(super) bitcoin_target: U256<'static>,
+    pub(super) nbits: u32,
+    pub(super) prev_hash: BlockHash,
+    pub(super) version: u32,
+    // Valid shares of the current round, a round start with a new prev hash
+    pub(super) new_shares_sum: u64,
+    // Valid shares of the channel since it has been opened
+    pub(super) total_shares_sum: u64,
+    pub(super) coinbase_tx_suffix: Vec<u8>,
+    pub(super) coinbase_tx_prefix: Vec<u8>,
+    pub(super) extranonce: Vec<u8>,
+    #[allow(dead_code)]
+    pub(super) merkle_path: Vec<Vec<u8>>,
+    pub(super) merkle_root: TxMerkleNode,
+    // Lowest hash submitted for the current prev hash, in the little endian byte order of
+    // `BlockHash` so that it is stored as hashed
+    pub(super) best_hash: Option<[u8; 32]>,
+}
+
+#[derive(Debug)]
+pub enum VelideateTargetResult {
+    LessThanBitcoinTarget(BlockHash, u64, SubmitSolution<'static>),
+    LessThanDownstreamTarget(BlockHash, u64),
+    Invalid(BlockHash),
+    /// The share meet the channel target but it is refused by the `DownstreamSharePolicy`
+    RateLimited(BlockHash),
+    /// The ntime of the share is bigger than the max allowed ntime, it contains the share ntime
+    NtimeTooFarInFuture(u32),
+}
+
+/// Correctly spelled name of `VelideateTargetResult`, the old name is kept for compatibility
+#[allow(dead_code)]
+pub type ValidateTargetResult = VelideateTargetResult;
+
+impl std::fmt::Display for VelideateTargetResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::LessThanBitcoinTarget(hash, shares, _) => {
+                write!(f, "block found: {} (share #{})", hash, shares)
+            }
+            Self::LessThanDownstreamTarget(hash, shares) => {
+                write!(f, "valid share: {} (share #{})", hash, shares)
+            }
+            Self::Invalid(hash) => write!(f, "invalid: {}", hash),
+            Self::RateLimited(hash) => write!(f, "rate limited: {}", hash),
+            Self::NtimeTooFarInFuture(ntime) => write!(f, "ntime too far in future: {}", ntime),
+        }
+    }
+}
+
+/// Check that `coinbase` (prefix + extranonce + suffix) is a valid serialized transaction, a
+/// wrong extranonce offset or length make it unparsable. With the `check-coinbase` feature it is
+/// called on every coinbase built by the pool.
+#[cfg(any(feature = "check-coinbase", test))]
+pub fn check_coinbase(coinbase: &[u8]) -> Result<(), bitcoin::consensus::encode::Error> {
+    bitcoin::consensus::deserialize::<bitcoin::Transaction>(coinbase).map(|_| ())
+}
+
+/// Target encoded in the compact form `nbits`, as a big endian `U256`. Decoded as
+/// `BlockHeader::u256_from_compact_target` do: a negative mantissa is a zero target and the bytes
+/// shifted out of an overflowing target are dropped.
+pub fn target_from_nbits(nbits: u32) -> U256<'static> {
+    let mut target = [0_u8; 32];
+    let exponent = (nbits >> 24) as usize;
+    let (mantissa, shift) = if exponent <= 3 {
+        ((nbits & 0x00ff_ffff) >> (8 * (3 - exponent)), 0)
+    } else {
+        (nbits & 0x00ff_ffff, exponent - 3)
+    };
+    if mantissa <= 0x007f_ffff {
+        // Least significant byte first, `shift` is in bytes
+        for (i, byte) in mantissa.to_le_bytes()[..3].iter().enumerate() {
+            if let Some(index) = 31_usize.checked_sub(shift + i) {
+                target[index] = *byte;
+            }
+        }
+    }
+    target.into()
+}
+
+impl CompleteJob {
+    /// Job for a standard channel (header only downstream) that mine this job, the merkle root is
+    /// the one of the coinbase with the extranonce of the channel
+    pub fn to_new_mining_job(&self, channel_id: u32, job_id: u32) -> NewMiningJob<'static> {
+        NewMiningJob {
+            channel_id,
+            job_id,
+            future_job: false,
+            version: self.version,
+            merkle_root: self.merkle_root.as_hash().into_inner().into(),
+        }
+    }
+
+    pub fn get_coinbase(&self) -> B064K<'static> {
+        let mut coinbase = Vec::new();
+        coinbase.extend(self.coinbase_tx_prefix.clone());
+        coinbase.extend(self.extranonce.clone());
+        coinbase.extend(self.coinbase_tx_suffix.clone());
+        #[cfg(feature = "check-coinbase")]
+        if let Err(e) = check_coinbase(&coinbase) {
+            println!(
+                "Coinbase of template {} is not a valid transaction: {:?}",
+                self.template_id, e
+            );
+        }
+        coinbase.try_into().unwrap()
+    }
+    /// Header of the block mined by a share of this job, the extranonce suffix replace the end of
+    /// the extranonce of the job
+    fn header(
+        &self,
+        nonce: u32,
+        version: u32,
+        ntime: u32,
+        extranonce_suffix: Option<&[u8]>,
+    ) -> Result<BlockHeader, Error> {
+        let merkle_root = match extranonce_suffix {
+            None => self.merkle_root,
+            Some(suffix) => {
+                if suffix.len() > self.extranonce.len() {
+                    return Err(Error::InvalidExtranonceSuffixLen((
+                        self.extranonce.len(),
+                        suffix.len(),
+                    )));
+                }
+                let mid_point = self.extranonce.len() - suffix.len();
+                let extranonce = [&self.extranonce[0..mid_point], suffix].concat();
+                let merkle_root: [u8; 32] = merkle_root_from_path(
+                    &(self.coinbase_tx_prefix[..]),
+                    &(self.coinbase_tx_suffix[..]),
+                    &extranonce[..],
+                    &(self.merkle_path[..]),
+                )
+                .ok_or(Error::InvalidCoinbase)?
+                .try_into()
+                .unwrap();
+                let merkle_root = Hash::from_inner(merkle_root);
+                TxMerkleNode::from_hash(merkle_root)
+            }
+        };
+        // The version is an u32 in the share messages and an i32 in the header, both encoded as
+        // the same 4 little endian bytes
+        let version = i32::from_le_bytes(version.to_le_bytes());
+        Ok(BlockHeader {
+            version,
+            prev_blockhash: self.prev_hash,
+            merkle_root,
+            time: ntime,
+            bits: self.nbits,
+            nonce,
+        })
+    }
+
+    /// Hash of the block mined by a share of this job, the same hash checked by
+    /// `validate_target`. Used to verify a share out of band.
+    pub fn header_hash(
+        &self,
+        nonce: u32,
+        version: u32,
+        ntime: u32,
+        extranonce_suffix: Option<&[u8]>,
+    ) -> Result<BlockHash, Error> {
+        Ok(self
+            .header(nonce, version, ntime, extranonce_suffix)?
+            .block_hash())
+    }
+
+    pub fn validate_target(
+        &mut self,
+        nonce: u32,
+        version: u32,
+        ntime: u32,
+        extranonce_suffix: Option<&[u8]>,
+        max_ntime: Option<u32>,
+    ) -> VelideateTargetResult {
+        if max_ntime.map_or(false, |max| ntime > max) {
+            return VelideateTargetResult::NtimeTooFarInFuture(ntime);
+        }
+        if extranonce_suffix.is_some() {
+            assert!(self.extranonce.len() == 32);
+        }
+        let hash_ = self
+            .header_hash(nonce, version, ntime, extranonce_suffix)
+            .unwrap();
+
+        let hash = hash_.as_hash().into_inner();
+        // Both are little endian so the most significant bytes are compared first from the end
+        if self
+            .best_hash
+            .map_or(true, |best| hash.iter().rev().lt(best.iter().rev()))
+        {
+            self.best_hash = Some(hash);
+        }
+        // The targets are big endian
+        let mut be_hash = hash;
+        be_hash.reverse();
+        let be_hash = U256::from(&mut be_hash);
+        if be_hash <= self.bitcoin_target {
+            self.credit_share();
+            let solution = SubmitSolution {
+                template_id: self.template_id,
+                version,
+                header_timestamp: ntime,
+                header_nonce: nonce,
+                coinbase_tx: self.get_coinbase(),
+            };
+            VelideateTargetResult::LessThanBitcoinTarget(hash_, self.new_shares_sum, solution)
+        } else if be_hash <= self.target {
+            // The share is counted only if the `DownstreamSharePolicy` say so, see
+            // `Downstream::check_target`
+            VelideateTargetResult::LessThanDownstreamTarget(hash_, self.new_shares_sum)
+        } else {
+            VelideateTargetResult::Invalid(hash_)
+        }
+    }
+
+    /// Count a valid share and return the shares of the round
+    pub(super) fn credit_share(&mut self) -> u64 {
+        self.new_shares_sum += 1;
+        self.total_shares_sum += 1;
+        self.new_shares_sum
+    }
+
+    pub fn update_job(
+        &self,
+        new_ext_job: &NewExtendedMiningJob<'static>,
+        nbits: u32,
+        prev_hash: BlockHash,
+        template_id: u64,
+    ) -> Self {
+        let merkle_root: [u8; 32] = merkle_root_from_path(
+            &(self.coinbase_tx_prefix[..]),
+            &(self.coinbase_tx_suffix[..]),
+            &(self.extranonce[..]),
+            &(new_ext_job.merkle_path.inner_as_ref()[..]),
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        let merkle_root = Hash::from_inner(merkle_root);
+        let merkle_root = TxMerkleNode::from_hash(merkle_root);
+        // A new template on the same prev hash do not start a new round
+        let same_round = prev_hash == self.prev_hash;
+        Self {
+            target: self.target.clone(),
+            bitcoin_target: target_from_nbits(nbits),
+            nbits,
+            prev_hash,
+            version: new_ext_job.version,
+            new_shares_sum: if same_round { self.new_shares_sum } else { 0 },
+            total_shares_sum: self.total_shares_sum,
+            coinbase_tx_prefix: new_ext_job.coinbase_tx_prefix.to_vec(),
+            coinbase_tx_suffix: new_ext_job.coinbase_tx_suffix.to_vec(),
+            merkle_path: new_ext_job.merkle_path.to_vec(),
+            extranonce: self.extranonce.clone(),
+            merkle_root,
+            template_id,
+            best_hash: if same_round { self.best_hash } else { None },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Job {
+    Partial(PartialJob),
+    Complete(CompleteJob),
+}
+
+impl Job {
+    pub fn new(target: U256<'static>, extranonce: Vec<u8>) -> Self {
+        Self::Partial(PartialJob {
+            target,
+            extranonce,
+            total_shares_sum: 0,
+        })
+    }
+    pub fn update_job(
+        &mut self,
+        new_ext_job: &NewExtendedMiningJob<'static>,
+        nbits: u32,
+        prev_hash: BlockHash,
+        template_id: u64,
+    ) {
+        match self {
+            Job::Partial(p) => {
+                *self = Self::Complete(p.to_complete_standard_job(
+                    new_ext_job,
+                    nbits,
+                    prev_hash,
+                    template_id,
+                ));
+            }
+            Job::Complete(c) => {
+                *self = Self::Complete(c.update_job(new_ext_job, nbits, prev_hash, template_id));
+            }
+        }
+    }
+
+    pub fn make_partial(&mut self) {
+        match self {
+            Self::Partial(_) => (),
+            Self::Complete(c) => {
+                *self = Self::Partial(PartialJob {
+                    target: c.target.clone(),
+                    extranonce: c.extranonce.clone(),
+                    total_shares_sum: c.total_shares_sum,
+                });
+            }
+        }
+    }
+
+    pub fn set_target(&mut self, target: U256<'static>) {
+        match self {
+            Self::Partial(p) => p.target = target,
+            Self::Complete(c) => c.target = target,
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use crate::lib::mining_pool::{Downstream, VelideateTargetResult};
+use crate::lib::mining_pool::{Downstream, VelideateTargetResult, EXTENDED_EXTRANONCE_SIZE};
 use binary_sv2::U256;
 use bitcoin::util::uint::Uint256;
 use roles_logic_sv2::{
+    common_messages_sv2::Protocol,
     errors::Error,
     handlers::mining::{ParseDownstreamMiningMessages, SendTo, SupportedChannelTypes},
     mining_sv2::*,
@@ -36,6 +37,24 @@ pub fn u256_to_uint_256(v: U256<'static>) -> Uint256 {
     Uint256([d, c, b, a])
 }
 
+pub fn uint_256_to_u256(v: Uint256) -> U256<'static> {
+    let [d, c, b, a] = v.0;
+    let mut bs = [0_u8; 32];
+    for (chunk, word) in bs.chunks_exact_mut(8).zip([a, b, c, d]) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    bs.into()
+}
+
+// Response to an open channel request of a downstream that has `max_channels_per_downstream`
+// channels
+fn too_many_channels(request_id: u32) -> SendTo<()> {
+    SendTo::Respond(Mining::OpenMiningChannelError(OpenMiningChannelError {
+        request_id,
+        error_code: "too-many-channels".to_string().try_into().unwrap(),
+    }))
+}
+
 impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting> for Downstream {
     fn get_channel_type(&self) -> SupportedChannelTypes {
         SupportedChannelTypes::GroupAndExtended
@@ -45,13 +64,22 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         false
     }
 
+    fn is_message_type_allowed(&self, message_type: u8) -> bool {
+        self.allowed_message_types.as_ref().map_or(true, |a| {
+            a.is_allowed(Protocol::MiningProtocol, message_type)
+        })
+    }
+
     fn handle_open_standard_mining_channel(
         &mut self,
         incoming: OpenStandardMiningChannel,
         _m: Option<Arc<Mutex<()>>>,
     ) -> Result<SendTo<()>, Error> {
         let request_id = incoming.get_request_id_as_u32();
-        let target = hash_rate_to_target(incoming.nominal_hash_rate);
+        if self.channel_limit_reached() {
+            return Ok(too_many_channels(request_id));
+        }
+        let target = self.clamp_target(hash_rate_to_target(incoming.nominal_hash_rate));
         let extranonce_prefix = self
             .extranonces
             .safe_lock(|e| e.next_standard().unwrap().into_b032())
@@ -59,8 +87,10 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         let message = match (self.downstream_data.header_only, self.id) {
             (false, group_channel_id) => {
                 let channel_id = self.channel_ids.next();
+                self.user_identities
+                    .insert(channel_id, incoming.user_identity.clone().into_static());
                 let mut partial_job = crate::lib::mining_pool::Job::new(
-                    u256_to_uint_256(target.clone()),
+                    target.clone(),
                     extranonce_prefix.clone().to_vec(),
                 );
                 match (
@@ -98,8 +128,10 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                 }
             }
             (true, channel_id) => {
+                self.user_identities
+                    .insert(channel_id, incoming.user_identity.clone().into_static());
                 let mut partial_job = crate::lib::mining_pool::Job::new(
-                    u256_to_uint_256(target.clone()),
+                    target.clone(),
                     extranonce_prefix.clone().to_vec(),
                 );
                 match (
@@ -153,7 +185,10 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
             todo!()
         };
         let request_id = incoming.get_request_id_as_u32();
-        let target = hash_rate_to_target(incoming.nominal_hash_rate);
+        if self.channel_limit_reached() {
+            return Ok(too_many_channels(request_id));
+        }
+        let target = self.clamp_target(hash_rate_to_target(incoming.nominal_hash_rate));
         let extended = self
             .extranonces
             .safe_lock(|e| {
@@ -162,14 +197,13 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                     .into_b032()
             })
             .unwrap();
+        let extended = extended.to_vec();
         let channel_id = self.channel_ids.next();
-        let mut partial_job = crate::lib::mining_pool::Job::new(
-            u256_to_uint_256(target.clone()),
-            extended.clone().to_vec(),
-        );
-        let mut extended = extended.to_vec();
-        extended.resize(16, 0);
-        self.prefixes.insert(channel_id, extended.clone());
+        // Inserted first so that a refused channel leave no state behind
+        self.insert_prefix(channel_id, extended.clone())?;
+        self.user_identities
+            .insert(channel_id, incoming.user_identity.clone().into_static());
+        let mut partial_job = crate::lib::mining_pool::Job::new(target.clone(), extended.clone());
         match (
             &self.last_valid_extended_job,
             &self.last_prev_hash,
@@ -200,7 +234,7 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
             request_id,
             target,
             channel_id,
-            extranonce_size: 16,
+            extranonce_size: EXTENDED_EXTRANONCE_SIZE,
             extranonce_prefix: extended.try_into().unwrap(),
         };
         Ok(SendTo::Respond(Mining::OpenExtendedMiningChannelSuccess(
@@ -208,35 +242,46 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         )))
     }
 
-    fn handle_update_channel(&mut self, _: UpdateChannel) -> Result<SendTo<()>, Error> {
-        todo!()
+    fn handle_update_channel(&mut self, m: UpdateChannel) -> Result<SendTo<()>, Error> {
+        let target = hash_rate_to_target(m.nominal_hash_rate);
+        let maximum_target = m.maximum_target.into_static();
+        let target = target.min(maximum_target);
+        match self.set_channel_target(m.channel_id, target) {
+            Some(target) => Ok(SendTo::Respond(Mining::SetTarget(SetTarget {
+                channel_id: m.channel_id,
+                maximum_target: target,
+            }))),
+            None => Ok(SendTo::Respond(Mining::UpdateChannelError(
+                UpdateChannelError::new(m.channel_id, UpdateChannelErrorCode::InvalidChannelId),
+            ))),
+        }
     }
 
     fn handle_submit_shares_standard(
         &mut self,
         m: SubmitSharesStandard,
     ) -> Result<SendTo<()>, Error> {
-        match self.check_target(m.channel_id, m.nonce, m.version, m.ntime, None) {
+        match self.check_target(m.channel_id, m.job_id, m.nonce, m.version, m.ntime, None) {
             Ok(VelideateTargetResult::LessThanBitcoinTarget(_, new_shares_sum, solution)) => {
                 // That unwrap means lose a block!!! TODO
-                self.solution_sender.try_send(solution).unwrap();
-                Ok(SendTo::Respond(Mining::SubmitSharesSuccess(
-                    SubmitSharesSuccess {
-                        channel_id: m.channel_id,
-                        last_sequence_number: m.sequence_number,
-                        new_submits_accepted_count: 1,
-                        new_shares_sum,
-                    },
-                )))
+                self.solution_sender.send(solution).unwrap();
+                let ack = SubmitSharesSuccess {
+                    channel_id: m.channel_id,
+                    last_sequence_number: m.sequence_number,
+                    new_submits_accepted_count: 1,
+                    new_shares_sum,
+                };
+                Ok(self.ack_share(ack, true))
             }
-            Ok(VelideateTargetResult::LessThanDownstreamTarget(_, new_shares_sum)) => Ok(
-                SendTo::Respond(Mining::SubmitSharesSuccess(SubmitSharesSuccess {
+            Ok(VelideateTargetResult::LessThanDownstreamTarget(_, new_shares_sum)) => {
+                let ack = SubmitSharesSuccess {
                     channel_id: m.channel_id,
                     last_sequence_number: m.sequence_number,
                     new_submits_accepted_count: 1,
                     new_shares_sum,
-                })),
-            ),
+                };
+                Ok(self.ack_share(ack, false))
+            }
             Ok(VelideateTargetResult::Invalid(_)) => Ok(SendTo::Respond(
                 Mining::SubmitSharesError(SubmitSharesError {
                     channel_id: m.channel_id,
@@ -244,7 +289,27 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                     error_code: "difficulty-too-low".to_string().try_into().unwrap(),
                 }),
             )),
-            Err(()) => Ok(SendTo::None(None)),
+            Ok(VelideateTargetResult::RateLimited(_)) => Ok(SendTo::Respond(
+                Mining::SubmitSharesError(SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: "too-many-shares".to_string().try_into().unwrap(),
+                }),
+            )),
+            Ok(VelideateTargetResult::NtimeTooFarInFuture(_)) => Ok(SendTo::Respond(
+                Mining::SubmitSharesError(SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: "invalid-timestamp".to_string().try_into().unwrap(),
+                }),
+            )),
+            Err(e) => Ok(SendTo::Respond(Mining::SubmitSharesError(
+                SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: e.error_code().to_string().try_into().unwrap(),
+                },
+            ))),
         }
     }
 
@@ -254,6 +319,7 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
     ) -> Result<SendTo<()>, Error> {
         match self.check_target(
             m.channel_id,
+            m.job_id,
             m.nonce,
             m.version,
             m.ntime,
@@ -261,24 +327,24 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         ) {
             Ok(VelideateTargetResult::LessThanBitcoinTarget(_, new_shares_sum, solution)) => {
                 // That unwrap means lose a block!!! TODO
-                self.solution_sender.try_send(solution).unwrap();
-                Ok(SendTo::Respond(Mining::SubmitSharesSuccess(
-                    SubmitSharesSuccess {
-                        channel_id: m.channel_id,
-                        last_sequence_number: m.sequence_number,
-                        new_submits_accepted_count: 1,
-                        new_shares_sum,
-                    },
-                )))
+                self.solution_sender.send(solution).unwrap();
+                let ack = SubmitSharesSuccess {
+                    channel_id: m.channel_id,
+                    last_sequence_number: m.sequence_number,
+                    new_submits_accepted_count: 1,
+                    new_shares_sum,
+                };
+                Ok(self.ack_share(ack, true))
             }
-            Ok(VelideateTargetResult::LessThanDownstreamTarget(_, new_shares_sum)) => Ok(
-                SendTo::Respond(Mining::SubmitSharesSuccess(SubmitSharesSuccess {
+            Ok(VelideateTargetResult::LessThanDownstreamTarget(_, new_shares_sum)) => {
+                let ack = SubmitSharesSuccess {
                     channel_id: m.channel_id,
                     last_sequence_number: m.sequence_number,
                     new_submits_accepted_count: 1,
                     new_shares_sum,
-                })),
-            ),
+                };
+                Ok(self.ack_share(ack, false))
+            }
             Ok(VelideateTargetResult::Invalid(_)) => Ok(SendTo::Respond(
                 Mining::SubmitSharesError(SubmitSharesError {
                     channel_id: m.channel_id,
@@ -286,7 +352,27 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                     error_code: "difficulty-too-low".to_string().try_into().unwrap(),
                 }),
             )),
-            Err(()) => Ok(SendTo::None(None)),
+            Ok(VelideateTargetResult::RateLimited(_)) => Ok(SendTo::Respond(
+                Mining::SubmitSharesError(SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: "too-many-shares".to_string().try_into().unwrap(),
+                }),
+            )),
+            Ok(VelideateTargetResult::NtimeTooFarInFuture(_)) => Ok(SendTo::Respond(
+                Mining::SubmitSharesError(SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: "invalid-timestamp".to_string().try_into().unwrap(),
+                }),
+            )),
+            Err(e) => Ok(SendTo::Respond(Mining::SubmitSharesError(
+                SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: e.error_code().to_string().try_into().unwrap(),
+                },
+            ))),
         }
     }
 
@@ -1,30 +1,51 @@
 use codec_sv2::{HandshakeRole, Responder};
 use network_helpers::noise_connection_tokio::Connection;
-use tokio::{net::TcpListener, task};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task,
+};
 
-use crate::{Configuration, EitherFrame, StdFrame};
+use crate::{
+    lib::authority::AuthorityKeys, Configuration, DownstreamSharePolicy, EitherFrame,
+    InvalidShareLimitConfig, ShareBatchConfig, StdFrame,
+};
 use async_channel::{Receiver, Sender};
-use binary_sv2::{B064K, U256};
+use binary_sv2::{Str0255, U256};
 use bitcoin::{
-    blockdata::block::BlockHeader,
     hash_types::BlockHash,
     hashes::{sha256d::Hash, Hash as Hash_},
     util::uint::Uint256,
-    TxMerkleNode,
 };
-use codec_sv2::Frame;
+use codec_sv2::{Frame, FrameConversionError};
+use const_sv2::{
+    CHANNEL_BIT_MINING_SET_NEW_PREV_HASH, CHANNEL_BIT_NEW_EXTENDED_MINING_JOB,
+    MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH, MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB,
+};
 use roles_logic_sv2::{
     common_properties::{CommonDownstreamData, IsDownstream, IsMiningDownstream},
     errors::Error,
-    handlers::mining::{ParseDownstreamMiningMessages, SendTo},
-    job_creator::JobsCreators,
-    mining_sv2::{ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash as NewPrevHash},
+    handlers::{
+        mining::{ParseDownstreamMiningMessages, SendTo},
+        MessageTypeAllowlist,
+    },
+    job_creator::{CoinbaseOverrides, JobsCreators, TemplateWitness},
+    mining_sv2::{
+        ExtendedExtranonce, NewExtendedMiningJob, SetNewPrevHash as NewPrevHash, SetTarget,
+        SubmitSharesSuccess,
+    },
     parsers::{Mining, PoolMessages},
     routing_logic::MiningRoutingLogic,
-    template_distribution_sv2::{NewTemplate, SetNewPrevHash, SubmitSolution},
-    utils::{merkle_root_from_path, Id, Mutex},
+    template_distribution_sv2::{NewTemplate, SetNewPrevHash},
+    utils::{validate_nbits, Id, Mutex},
 };
-use std::{collections::HashMap, convert::TryInto, sync::Arc};
+use std::{collections::HashMap, convert::TryInto, future::Future, sync::Arc, time::Duration};
+
+/// Length of the extranonce of the coinbase
+const EXTRANONCE_LEN: usize = 32;
+
+/// Bytes of the extranonce rolled by the downstream of an extended channel, the other bytes are the
+/// extranonce prefix of the channel
+pub const EXTENDED_EXTRANONCE_SIZE: u16 = 16;
 
 pub fn u256_to_block_hash(v: U256<'static>) -> BlockHash {
     let hash: [u8; 32] = v.to_vec().try_into().unwrap();
@@ -32,219 +53,184 @@ pub fn u256_to_block_hash(v: U256<'static>) -> BlockHash {
     BlockHash::from_hash(hash)
 }
 
+/// Pass the frames received from `receiver` to `handle` until the connection is closed. After the
+/// noise handshake only `Sv2Frame`s are expected: an handshake frame stop the loop and the
+/// conversion error is returned so that the caller can close the connection.
+async fn receive_frames<F, Fut>(
+    receiver: Receiver<EitherFrame>,
+    mut handle: F,
+) -> Result<(), FrameConversionError>
+where
+    F: FnMut(StdFrame) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    while let Ok(received) = receiver.recv().await {
+        let std_frame: StdFrame = received.try_into()?;
+        handle(std_frame).await;
+    }
+    Ok(())
+}
+
 pub mod setup_connection;
 use setup_connection::SetupConnectionHandler;
 
 pub mod message_handler;
 
-#[derive(Debug, Clone)]
-struct PartialJob {
-    target: Uint256,
-    extranonce: Vec<u8>,
+pub mod solution_sender;
+use solution_sender::SolutionSender;
+
+pub mod share_logger;
+use share_logger::{PrintShareLogger, ShareLogger, ShareRecord};
+
+pub mod share_batch;
+use share_batch::ShareBatch;
+
+pub mod share_policy;
+use share_policy::{ShareAction, SharePolicy};
+
+pub mod invalid_share_limiter;
+use invalid_share_limiter::InvalidShareLimiter;
+
+pub mod reconnect;
+use reconnect::ParkedDownstreams;
+
+#[cfg(feature = "admin")]
+pub mod admin;
+
+#[cfg(feature = "replay-protection")]
+pub mod replay_guard;
+#[cfg(feature = "replay-protection")]
+use replay_guard::{ReplayGuard, MESSAGE_TYPE_SEQUENCED};
+
+pub mod job;
+use job::Job;
+pub use job::{ValidateTargetResult, VelideateTargetResult};
+
+/// Shares with an ntime bigger than `clock() + max_drift` are rejected. The clock return the
+/// current unix time in seconds, it is a parameter so that tests can control "now".
+#[derive(Debug, Clone, Copy)]
+pub struct NtimeLimit {
+    pub max_drift: u32,
+    pub clock: fn() -> u32,
 }
 
-impl PartialJob {
-    pub fn to_complete_standard_job(
-        &self,
-        new_ext_job: &NewExtendedMiningJob<'static>,
-        nbits: u32,
-        prev_hash: BlockHash,
-        template_id: u64,
-    ) -> CompleteJob {
-        let merkle_root: [u8; 32] = merkle_root_from_path(
-            &(new_ext_job.coinbase_tx_prefix.to_vec()[..]),
-            &(new_ext_job.coinbase_tx_suffix.to_vec()[..]),
-            &(self.extranonce[..]),
-            &(new_ext_job.merkle_path.inner_as_ref()[..]),
-        )
-        .unwrap()
-        .try_into()
-        .unwrap();
-        let merkle_root = Hash::from_inner(merkle_root);
-        let merkle_root = TxMerkleNode::from_hash(merkle_root);
-        CompleteJob {
-            target: self.target,
-            nbits,
-            prev_hash,
-            new_shares_sum: 0,
-            coinbase_tx_prefix: new_ext_job.coinbase_tx_prefix.to_vec(),
-            coinbase_tx_suffix: new_ext_job.coinbase_tx_suffix.to_vec(),
-            merkle_path: new_ext_job.merkle_path.to_vec(),
-            extranonce: self.extranonce.clone(),
-            merkle_root,
-            template_id,
+impl NtimeLimit {
+    pub fn new(max_drift: u32) -> Self {
+        Self {
+            max_drift,
+            clock: system_clock,
         }
     }
-}
-#[derive(Debug, Clone)]
-struct CompleteJob {
-    template_id: u64,
-    target: Uint256,
-    nbits: u32,
-    prev_hash: BlockHash,
-    new_shares_sum: u64,
-    coinbase_tx_suffix: Vec<u8>,
-    coinbase_tx_prefix: Vec<u8>,
-    extranonce: Vec<u8>,
-    #[allow(dead_code)]
-    merkle_path: Vec<Vec<u8>>,
-    merkle_root: TxMerkleNode,
+
+    pub fn max_ntime(&self) -> u32 {
+        (self.clock)().saturating_add(self.max_drift)
+    }
 }
 
-#[derive(Debug)]
-pub enum VelideateTargetResult {
-    LessThanBitcoinTarget(BlockHash, u64, SubmitSolution<'static>),
-    LessThanDownstreamTarget(BlockHash, u64),
-    Invalid(BlockHash),
+pub fn system_clock() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
 }
 
-impl CompleteJob {
-    pub fn get_coinbase(&self) -> B064K<'static> {
-        let mut coinbase = Vec::new();
-        coinbase.extend(self.coinbase_tx_prefix.clone());
-        coinbase.extend(self.extranonce.clone());
-        coinbase.extend(self.coinbase_tx_suffix.clone());
-        coinbase.try_into().unwrap()
+/// Easiest target that a channel with difficulty `difficulty` can have, the difficulty is relative
+/// to the bitcoin difficulty 1 target. Return None for difficulty 0.
+pub fn max_target_from_difficulty(difficulty: u64) -> Option<Uint256> {
+    if difficulty == 0 {
+        return None;
     }
-    pub fn validate_target(
-        &mut self,
-        nonce: u32,
-        version: u32,
-        ntime: u32,
-        extranonce_suffix: Option<&[u8]>,
-    ) -> VelideateTargetResult {
-        let merkle_root = match extranonce_suffix {
-            None => self.merkle_root,
-            Some(suffix) => {
-                let mid_point = self.extranonce.len() - suffix.len();
-                let extranonce = [&self.extranonce[0..mid_point], suffix].concat();
-                assert!(self.extranonce.len() == 32);
-                let merkle_root: [u8; 32] = merkle_root_from_path(
-                    &(self.coinbase_tx_prefix[..]),
-                    &(self.coinbase_tx_suffix[..]),
-                    &extranonce[..],
-                    &(self.merkle_path[..]),
-                )
-                .unwrap()
-                .try_into()
-                .unwrap();
-                let merkle_root = Hash::from_inner(merkle_root);
-                TxMerkleNode::from_hash(merkle_root)
-            }
-        };
-        // TODO  how should version be transoformed from u32 into i32???
-        let version = version as i32;
-        let header = BlockHeader {
-            version,
-            prev_blockhash: self.prev_hash,
-            merkle_root,
-            time: ntime,
-            bits: self.nbits,
-            nonce,
-        };
+    let difficulty_1_target = Uint256([0, 0, 0, 0x0000_0000_ffff_0000]);
+    Some(difficulty_1_target / Uint256::from_u64(difficulty)?)
+}
 
-        let bitcoin_target = header.target();
+/// Difficulty of `target` relative to the bitcoin difficulty 1 target
+pub fn difficulty_from_target(target: &U256<'static>) -> f64 {
+    let target = target
+        .inner_as_ref()
+        .iter()
+        .fold(0.0, |acc, byte| acc * 256.0 + *byte as f64);
+    let difficulty_1_target = 0xffff_u64 as f64 * 2_f64.powi(208);
+    difficulty_1_target / target
+}
 
-        let hash_ = header.block_hash();
-        let mut hash = hash_.as_hash().into_inner();
-        hash.reverse();
-        let hash = Uint256::from_be_bytes(hash);
-        if hash <= bitcoin_target {
-            self.new_shares_sum += 1;
-            let solution = SubmitSolution {
-                template_id: self.template_id,
-                version: version as u32,
-                header_timestamp: ntime,
-                header_nonce: nonce,
-                coinbase_tx: self.get_coinbase(),
-            };
-            VelideateTargetResult::LessThanBitcoinTarget(hash_, self.new_shares_sum, solution)
-        } else if hash <= self.target {
-            self.new_shares_sum += 1;
-            VelideateTargetResult::LessThanDownstreamTarget(hash_, self.new_shares_sum)
-        } else {
-            VelideateTargetResult::Invalid(hash_)
-        }
-    }
+/// Why a share could not be validated by `Downstream::check_target`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckTargetError {
+    /// The channel is open but the pool never sent a job for it
+    NoJobForChannel,
+    /// The job of the channel has been invalidated (a block has been found) and the pool is
+    /// waiting for a new prev hash
+    JobNotComplete,
+    /// The channel has never been opened
+    UnknownChannel,
+}
 
-    pub fn update_job(
-        &self,
-        new_ext_job: &NewExtendedMiningJob<'static>,
-        nbits: u32,
-        prev_hash: BlockHash,
-        template_id: u64,
-    ) -> Self {
-        let merkle_root: [u8; 32] = merkle_root_from_path(
-            &(self.coinbase_tx_prefix[..]),
-            &(self.coinbase_tx_suffix[..]),
-            &(self.extranonce[..]),
-            &(new_ext_job.merkle_path.inner_as_ref()[..]),
-        )
-        .unwrap()
-        .try_into()
-        .unwrap();
-        let merkle_root = Hash::from_inner(merkle_root);
-        let merkle_root = TxMerkleNode::from_hash(merkle_root);
-        Self {
-            target: self.target,
-            nbits,
-            prev_hash,
-            new_shares_sum: 0,
-            coinbase_tx_prefix: new_ext_job.coinbase_tx_prefix.to_vec(),
-            coinbase_tx_suffix: new_ext_job.coinbase_tx_suffix.to_vec(),
-            merkle_path: new_ext_job.merkle_path.to_vec(),
-            extranonce: self.extranonce.clone(),
-            merkle_root,
-            template_id,
+impl CheckTargetError {
+    /// Error code of the `SubmitSharesError` sent to the downstream
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            CheckTargetError::NoJobForChannel => "invalid-job-id",
+            CheckTargetError::JobNotComplete => "stale-share",
+            CheckTargetError::UnknownChannel => "invalid-channel-id",
         }
     }
 }
 
+/// Future job of a downstream. The jobs created from a template differ only by the fields of the
+/// group channel, so the job is shared with an `Arc` by every downstream that received it (the
+/// merkle path and the coinbase are not copied for each downstream) and the channel fields are
+/// stored aside.
 #[derive(Debug, Clone)]
-enum Job {
-    Partial(PartialJob),
-    Complete(CompleteJob),
+struct FutureJob {
+    // The channel fields of the shared job are the ones of the first downstream that received it
+    shared: Arc<NewExtendedMiningJob<'static>>,
+    channel_id: u32,
+    job_id: u32,
+    version_rolling_allowed: bool,
+    template_id: u64,
 }
 
-impl Job {
-    pub fn new(target: Uint256, extranonce: Vec<u8>) -> Self {
-        Self::Partial(PartialJob { target, extranonce })
-    }
-    pub fn update_job(
-        &mut self,
-        new_ext_job: &NewExtendedMiningJob<'static>,
-        nbits: u32,
-        prev_hash: BlockHash,
+impl FutureJob {
+    /// Take the shared part of `job` from `shared` if it has the same template fields, otherwise
+    /// `shared` is replaced by a copy of `job` so that the next jobs of the template can share it
+    fn new(
+        job: &NewExtendedMiningJob<'static>,
         template_id: u64,
-    ) {
-        match self {
-            Job::Partial(p) => {
-                *self = Self::Complete(p.to_complete_standard_job(
-                    new_ext_job,
-                    nbits,
-                    prev_hash,
-                    template_id,
-                ));
-            }
-            Job::Complete(c) => {
-                *self = Self::Complete(c.update_job(new_ext_job, nbits, prev_hash, template_id));
-            }
+        shared: &mut Option<Arc<NewExtendedMiningJob<'static>>>,
+    ) -> Self {
+        let shared = match shared {
+            Some(s) if same_template_fields(s, job) => s.clone(),
+            _ => shared.insert(Arc::new(job.clone())).clone(),
+        };
+        Self {
+            shared,
+            channel_id: job.channel_id,
+            job_id: job.job_id,
+            version_rolling_allowed: job.version_rolling_allowed,
+            template_id,
         }
     }
 
-    pub fn make_partial(&mut self) {
-        match self {
-            Self::Partial(_) => (),
-            Self::Complete(c) => {
-                *self = Self::Partial(PartialJob {
-                    target: c.target,
-                    extranonce: c.extranonce.clone(),
-                });
-            }
+    /// The job as it has been sent to the downstream
+    fn to_job(&self) -> NewExtendedMiningJob<'static> {
+        NewExtendedMiningJob {
+            channel_id: self.channel_id,
+            job_id: self.job_id,
+            version_rolling_allowed: self.version_rolling_allowed,
+            ..(*self.shared).clone()
         }
     }
 }
 
+fn same_template_fields(a: &NewExtendedMiningJob, b: &NewExtendedMiningJob) -> bool {
+    a.future_job == b.future_job
+        && a.version == b.version
+        && a.merkle_path.inner_as_ref() == b.merkle_path.inner_as_ref()
+        && a.coinbase_tx_prefix.inner_as_ref() == b.coinbase_tx_prefix.inner_as_ref()
+        && a.coinbase_tx_suffix.inner_as_ref() == b.coinbase_tx_suffix.inner_as_ref()
+}
+
 #[derive(Debug)]
 pub struct ExtendedJob {
     #[allow(dead_code)]
@@ -253,6 +239,23 @@ pub struct ExtendedJob {
     nbits: u32,
 }
 
+/// Owned copy of the job state of a [`Downstream`], used to resume serving the downstream from
+/// another pool instance (eg a hot standby) with [`Downstream::restore`]
+#[derive(Debug, Clone)]
+pub struct DownstreamSnapshot {
+    id: u32,
+    downstream_data: CommonDownstreamData,
+    requested_data: CommonDownstreamData,
+    channel_ids: Id,
+    jobs: HashMap<u32, Job>,
+    future_jobs: HashMap<u32, (NewExtendedMiningJob<'static>, u64)>,
+    prefixes: HashMap<u32, Vec<u8>>,
+    last_prev_hash: Option<BlockHash>,
+    last_nbits: Option<u32>,
+    last_valid_extended_job: Option<(NewExtendedMiningJob<'static>, u64)>,
+    user_identities: HashMap<u32, Str0255<'static>>,
+}
+
 #[derive(Debug)]
 pub struct Downstream {
     // Either group or channel id
@@ -264,15 +267,43 @@ pub struct Downstream {
     extranonces: Arc<Mutex<ExtendedExtranonce>>,
     // channel_id -> Job
     jobs: HashMap<u32, Job>,
-    // extended_job_id -> (FutureJob,template_id)
-    future_jobs: HashMap<u32, (NewExtendedMiningJob<'static>, u64)>,
+    // extended_job_id -> FutureJob
+    future_jobs: HashMap<u32, FutureJob>,
     // channel_id -> Prefixes VALID ONLY FOR EXTENDED CHANNELS
     prefixes: HashMap<u32, Vec<u8>>,
     last_prev_hash: Option<BlockHash>,
     last_nbits: Option<u32>,
     // (job,template_id)
     last_valid_extended_job: Option<(NewExtendedMiningJob<'static>, u64)>,
-    solution_sender: Sender<SubmitSolution<'static>>,
+    solution_sender: SolutionSender,
+    ntime_limit: Option<NtimeLimit>,
+    // Targets easier than this are clamped to it
+    max_target: Option<U256<'static>>,
+    // channel_id -> user_identity of the open channel message
+    user_identities: HashMap<u32, Str0255<'static>>,
+    share_logger: Arc<dyn ShareLogger>,
+    // Buffered valid shares, None if the shares are acked one by one
+    share_batch: Option<ShareBatch>,
+    share_policy: SharePolicy,
+    // Open channel requests are refused when the downstream has this many channels
+    max_channels: Option<usize>,
+    // None if the invalid shares are not limited
+    invalid_shares: Option<InvalidShareLimiter>,
+    // While paused the jobs and prev hashes update the state but are not sent, see
+    // `Pool::pause_downstream`
+    paused: bool,
+    // Last prev hash received while paused, sent on resume
+    missed_prev_hash: Option<NewPrevHash<'static>>,
+    // None if every message type is allowed, see `allowed_message_types` in the config
+    allowed_message_types: Option<MessageTypeAllowlist>,
+    // Sent in the setup connection, a downstream with a token is parked on disconnection, see
+    // `Pool::park_downstream`
+    reconnect_token: Option<String>,
+    // Data of the flags of the setup connection, `downstream_data` is built from the flags of the
+    // answer. A reconnect with the token is refused if the flags are not the same.
+    requested_data: CommonDownstreamData,
+    #[cfg(feature = "replay-protection")]
+    replay_guard: ReplayGuard,
 }
 
 /// Accept downstream connection
@@ -286,37 +317,250 @@ pub struct Pool {
     job_creators: Arc<Mutex<JobsCreators>>,
     last_new_prev_hash: Option<SetNewPrevHash<'static>>,
     extranonces: Arc<Mutex<ExtendedExtranonce>>,
-    solution_sender: Sender<SubmitSolution<'static>>,
+    solution_sender: SolutionSender,
     new_template_processed: bool,
+    ntime_limit: Option<NtimeLimit>,
+    /// Easiest target that can be set on a channel, see `min_channel_difficulty` in the config
+    max_target: Option<U256<'static>>,
+    /// (min, max) protocol versions accepted from the downstreams
+    protocol_versions: (u16, u16),
+    share_logger: Arc<dyn ShareLogger>,
+    /// If present the valid shares are acked and logged in batches
+    share_batch: Option<ShareBatchConfig>,
+    downstream_share_policy: DownstreamSharePolicy,
+    /// Max number of channels that a downstream can open
+    max_channels_per_downstream: Option<usize>,
+    /// If present the downstreams that submit too many invalid shares are disconnected
+    invalid_share_limit: Option<InvalidShareLimitConfig>,
+    /// If present the downstreams can only send these message types
+    allowed_message_types: Option<MessageTypeAllowlist>,
+    /// Unix time of the last template or prev hash, the jobs are refreshed only if nothing
+    /// arrived in the last `job_refresh_interval_sec`
+    last_new_work: u32,
+    /// Ask the template receiver to reconnect to the template provider, see `Pool::on_desync`
+    resync_sender: Sender<()>,
+    /// Prev hashes received for a template that the pool never received
+    desync_events: u64,
+    /// If present the downstreams that disconnect with a reconnect token are kept for
+    /// `reconnect_grace_sec`, see `reconnect`
+    parked: Option<ParkedDownstreams>,
 }
 
 impl Downstream {
+    /// Ids of the future jobs sent to the downstream and not yet activated by a prev hash
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn future_job_ids(&self) -> Vec<u32> {
+        self.future_jobs.keys().copied().collect()
+    }
+
+    /// Forget a future job, when the prev hash for its template arrive the job is not activated.
+    /// Return the template id of the cancelled job, None if there is no such future job.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn cancel_future_job(&mut self, job_id: u32) -> Option<u64> {
+        self.future_jobs.remove(&job_id).map(|job| job.template_id)
+    }
+
+    /// Check that `prefix` can be the extranonce prefix of an extended channel: together with the
+    /// `EXTENDED_EXTRANONCE_SIZE` bytes rolled by the downstream it must fill the extranonce,
+    /// otherwise the extranonces rebuilt to validate the shares of the channel would be wrong.
+    pub fn check_prefix_len(prefix: &[u8]) -> Result<(), Error> {
+        let expected = EXTRANONCE_LEN - EXTENDED_EXTRANONCE_SIZE as usize;
+        if prefix.len() != expected {
+            return Err(Error::InvalidExtranoncePrefixLen((expected, prefix.len())));
+        }
+        Ok(())
+    }
+
+    /// Store the extranonce prefix of the extended channel `channel_id`, see `check_prefix_len`
+    pub fn insert_prefix(&mut self, channel_id: u32, prefix: Vec<u8>) -> Result<(), Error> {
+        Self::check_prefix_len(&prefix)?;
+        self.prefixes.insert(channel_id, prefix);
+        Ok(())
+    }
+
+    /// Lowest hash submitted on `channel_id` since the last prev hash, as a big endian `U256`
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn best_hash(&self, channel_id: u32) -> Option<U256<'static>> {
+        match self.jobs.get(&channel_id) {
+            Some(Job::Complete(job)) => job.best_hash.map(|mut hash| {
+                hash.reverse();
+                hash.into()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Valid shares submitted on `channel_id` in the current round, the count is reset when a new
+    /// prev hash start a new round (a new template on the same prev hash do not reset it). None
+    /// if the downstream do not have such a channel.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn round_share_count(&self, channel_id: u32) -> Option<u64> {
+        match self.jobs.get(&channel_id)? {
+            Job::Complete(job) => Some(job.new_shares_sum),
+            // The job become partial when a block is found and wait for the next prev hash
+            Job::Partial(_) => Some(0),
+        }
+    }
+
+    /// Valid shares submitted on `channel_id` since the channel has been opened
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn total_share_count(&self, channel_id: u32) -> Option<u64> {
+        match self.jobs.get(&channel_id)? {
+            Job::Complete(job) => Some(job.total_shares_sum),
+            Job::Partial(job) => Some(job.total_shares_sum),
+        }
+    }
+
+    /// Validate a share, every valid share is logged with the `ShareLogger` of the pool. The
+    /// shares that do not find a block are counted according to the `DownstreamSharePolicy`.
     pub fn check_target(
         &mut self,
         channel_id: u32,
+        job_id: u32,
         nonce: u32,
         version: u32,
         ntime: u32,
         extranonce_suffix: Option<&[u8]>,
-    ) -> Result<VelideateTargetResult, ()> {
+    ) -> Result<VelideateTargetResult, CheckTargetError> {
         let id = channel_id;
+        let max_ntime = self.ntime_limit.map(|limit| limit.max_ntime());
         match self.jobs.get_mut(&id) {
             Some(Job::Complete(job)) => {
-                let res = job.validate_target(nonce, version, ntime, extranonce_suffix);
+                let res = match job.validate_target(
+                    nonce,
+                    version,
+                    ntime,
+                    extranonce_suffix,
+                    max_ntime,
+                ) {
+                    VelideateTargetResult::LessThanDownstreamTarget(hash, new_shares_sum) => {
+                        match self.share_policy.on_share(channel_id) {
+                            ShareAction::Credit => VelideateTargetResult::LessThanDownstreamTarget(
+                                hash,
+                                job.credit_share(),
+                            ),
+                            ShareAction::Forward => {
+                                VelideateTargetResult::LessThanDownstreamTarget(
+                                    hash,
+                                    new_shares_sum,
+                                )
+                            }
+                            ShareAction::RateLimited => VelideateTargetResult::RateLimited(hash),
+                        }
+                    }
+                    res => res,
+                };
+                let record = ShareRecord {
+                    channel_id,
+                    user_identity: self.user_identities.get(&channel_id).cloned(),
+                    job_id,
+                    nonce,
+                    ntime,
+                    version,
+                    target_difficulty: difficulty_from_target(&job.target),
+                    timestamp: system_clock() as u64,
+                };
                 match res {
                     VelideateTargetResult::LessThanBitcoinTarget(_, _, _) => {
+                        println!("Channel {}: {}", channel_id, res);
+                        self.log_share(record);
                         self.jobs.get_mut(&id).as_mut().unwrap().make_partial();
                     }
-                    VelideateTargetResult::LessThanDownstreamTarget(_, _) => (),
-                    VelideateTargetResult::Invalid(_) => (),
+                    VelideateTargetResult::LessThanDownstreamTarget(_, _) => self.log_share(record),
+                    VelideateTargetResult::Invalid(_) => self.on_invalid_share(channel_id),
+                    VelideateTargetResult::RateLimited(_) => (),
+                    VelideateTargetResult::NtimeTooFarInFuture(_) => (),
                 };
                 Ok(res)
             }
-            Some(Job::Partial(_)) => Err(()),
-            None => Err(()),
+            Some(Job::Partial(_)) if self.last_valid_extended_job.is_none() => {
+                Err(CheckTargetError::NoJobForChannel)
+            }
+            Some(Job::Partial(_)) => Err(CheckTargetError::JobNotComplete),
+            None => Err(CheckTargetError::UnknownChannel),
+        }
+    }
+
+    /// Count an invalid share of `channel_id`, past the `invalid_share_limit` of the config the
+    /// downstream is disconnected as misbehaving. Only the incoming messages are closed here so
+    /// that the error for the share is still sent, the receiver task then close the connection.
+    fn on_invalid_share(&mut self, channel_id: u32) {
+        let exceeded = self
+            .invalid_shares
+            .as_mut()
+            .map_or(false, |limiter| limiter.on_invalid_share(channel_id));
+        if exceeded && !self.receiver.is_closed() {
+            println!(
+                "Closing connection with downstream {}: too many invalid shares on channel {}",
+                self.id, channel_id
+            );
+            self.receiver.close();
+        }
+    }
+
+    /// True if the downstream can not open more channels, see `max_channels_per_downstream` in
+    /// the config
+    pub fn channel_limit_reached(&self) -> bool {
+        self.max_channels
+            .map_or(false, |max| self.jobs.len() >= max)
+    }
+
+    fn log_share(&mut self, record: ShareRecord) {
+        match self.share_batch.as_mut() {
+            Some(batch) => batch.push_record(record),
+            None => self.share_logger.log_share(record),
+        }
+    }
+
+    /// Ack a valid share. If the shares are batched the ack is buffered and the aggregate acks
+    /// are returned only when the batch is full or when the share find a block.
+    pub fn ack_share(&mut self, ack: SubmitSharesSuccess, block_found: bool) -> SendTo<()> {
+        let full = match self.share_batch.as_mut() {
+            Some(batch) => batch.push_ack(ack),
+            None => return SendTo::Respond(Mining::SubmitSharesSuccess(ack)),
+        };
+        if full || block_found {
+            let acks = self
+                .flush_shares()
+                .into_iter()
+                .map(|ack| SendTo::Respond(Mining::SubmitSharesSuccess(ack)))
+                .collect();
+            SendTo::Multiple(acks)
+        } else {
+            SendTo::None(None)
+        }
+    }
+
+    /// Log the buffered shares and return an aggregate ack for every channel that has buffered
+    /// shares
+    pub fn flush_shares(&mut self) -> Vec<SubmitSharesSuccess> {
+        match self.share_batch.as_mut() {
+            Some(batch) => batch.flush(self.share_logger.as_ref()),
+            None => vec![],
         }
     }
 
+    /// Flush the buffered shares every `interval` until the downstream is disconnected
+    fn spawn_share_flusher(self_: Arc<Mutex<Self>>, interval: Duration) {
+        task::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let acks = self_.safe_lock(|d| d.flush_shares()).unwrap();
+                for ack in acks {
+                    if Self::send(self_.clone(), Mining::SubmitSharesSuccess(ack))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                if self_.safe_lock(|d| d.sender.is_closed()).unwrap() {
+                    return;
+                }
+            }
+        });
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         mut receiver: Receiver<EitherFrame>,
@@ -326,14 +570,39 @@ impl Downstream {
         job_creators: Arc<Mutex<JobsCreators>>,
         extranonces: Arc<Mutex<ExtendedExtranonce>>,
         last_new_prev_hash: Option<SetNewPrevHash<'static>>,
-        solution_sender: Sender<SubmitSolution<'static>>,
+        solution_sender: SolutionSender,
         pool: Arc<Mutex<Pool>>,
-    ) -> Arc<Mutex<Self>> {
-        let setup_connection = Arc::new(Mutex::new(SetupConnectionHandler::new()));
+    ) -> Result<Arc<Mutex<Self>>, Error> {
+        let (min_version, max_version) = pool.safe_lock(|p| p.protocol_versions).unwrap();
+        let setup_connection = Arc::new(Mutex::new(SetupConnectionHandler::new(
+            min_version,
+            max_version,
+        )));
         let downstream_data =
-            SetupConnectionHandler::setup(setup_connection, &mut receiver, &mut sender)
-                .await
-                .unwrap();
+            SetupConnectionHandler::setup(setup_connection.clone(), &mut receiver, &mut sender)
+                .await?;
+        let (reconnect_token, requested_data) = setup_connection
+            .safe_lock(|s| (s.reconnect_token.clone(), s.requested_data))
+            .unwrap();
+        // Always set by a successful setup
+        let requested_data = requested_data.unwrap_or(downstream_data);
+        if let Some(token) = &reconnect_token {
+            let parked_data = pool.safe_lock(|p| p.parked_requested_data(token)).unwrap();
+            if matches!(parked_data, Some(parked_data) if parked_data != requested_data) {
+                println!(
+                    "Refusing the reconnect with token {}: the setup connection flags changed",
+                    token
+                );
+                receiver.close();
+                sender.close();
+                return Err(Error::ReconnectDataMismatch);
+            }
+            let parked = pool.safe_lock(|p| p.reclaim_parked(token)).unwrap();
+            if let Some(parked) = parked {
+                Self::reconnect(parked.clone(), receiver, sender, pool).await;
+                return Ok(parked);
+            }
+        }
         let id = match downstream_data.header_only {
             false => group_ids.safe_lock(|id| id.next()).unwrap(),
             true => {
@@ -353,7 +622,7 @@ impl Downstream {
 
         for job in &extended_jobs {
             if job.0.future_job {
-                future_jobs.insert(job.0.job_id, (job.0.clone(), job.1));
+                future_jobs.insert(job.0.job_id, FutureJob::new(&job.0, job.1, &mut None));
             } else {
                 last_valid_extended_job = Some((job.0.clone(), job.1));
             }
@@ -372,6 +641,30 @@ impl Downstream {
             }
         }
 
+        let (
+            ntime_limit,
+            max_target,
+            share_logger,
+            share_batch,
+            share_policy,
+            max_channels,
+            invalid_share_limit,
+            allowed_message_types,
+        ) = pool
+            .safe_lock(|p| {
+                (
+                    p.ntime_limit,
+                    p.max_target.clone(),
+                    p.share_logger.clone(),
+                    p.share_batch,
+                    p.downstream_share_policy,
+                    p.max_channels_per_downstream,
+                    p.invalid_share_limit,
+                    p.allowed_message_types.clone(),
+                )
+            })
+            .unwrap();
+
         let self_ = Arc::new(Mutex::new(Downstream {
             id,
             receiver,
@@ -386,6 +679,21 @@ impl Downstream {
             last_valid_extended_job,
             solution_sender,
             prefixes: HashMap::new(),
+            ntime_limit,
+            max_target,
+            user_identities: HashMap::new(),
+            share_logger,
+            share_batch: share_batch.map(|c| ShareBatch::new(c.max_shares)),
+            share_policy: SharePolicy::new(share_policy),
+            max_channels,
+            invalid_shares: invalid_share_limit.map(InvalidShareLimiter::new),
+            paused: false,
+            missed_prev_hash: None,
+            allowed_message_types,
+            reconnect_token,
+            requested_data,
+            #[cfg(feature = "replay-protection")]
+            replay_guard: ReplayGuard::default(),
         }));
 
         for job in extended_jobs {
@@ -408,51 +716,203 @@ impl Downstream {
                 min_ntime: 0,
                 nbits: new_prev_hash.n_bits,
             };
-            self_
-                .safe_lock(|d| d.on_new_prev_hash_sync(message.clone()))
+            match self_
+                .safe_lock(|d| d.on_new_prev_hash_sync(&message))
                 .unwrap()
-                .unwrap();
-            Downstream::send(self_.clone(), Mining::SetNewPrevHash(message))
-                .await
-                .unwrap();
+            {
+                Ok(_) => Downstream::send(self_.clone(), Mining::SetNewPrevHash(message))
+                    .await
+                    .unwrap(),
+                Err(e) => println!("Prev hash not sent to downstream {}: {}", id, e),
+            }
         };
 
-        let cloned = self_.clone();
+        if let Some(config) = share_batch {
+            Self::spawn_share_flusher(self_.clone(), config.flush_interval());
+        }
+        Self::spawn_receiver(self_.clone(), pool);
+        Ok(self_)
+    }
+
+    /// Copy the job state of the downstream, see [`DownstreamSnapshot`]
+    #[allow(dead_code)]
+    pub fn snapshot(&self) -> DownstreamSnapshot {
+        let as_static =
+            |(job, template_id): &(NewExtendedMiningJob, u64)| (job.as_static(), *template_id);
+        DownstreamSnapshot {
+            id: self.id,
+            downstream_data: self.downstream_data,
+            requested_data: self.requested_data,
+            channel_ids: self.channel_ids.clone(),
+            jobs: self.jobs.clone(),
+            future_jobs: self
+                .future_jobs
+                .iter()
+                .map(|(job_id, job)| (*job_id, (job.to_job(), job.template_id)))
+                .collect(),
+            prefixes: self.prefixes.clone(),
+            last_prev_hash: self.last_prev_hash,
+            last_nbits: self.last_nbits,
+            last_valid_extended_job: self.last_valid_extended_job.as_ref().map(as_static),
+            user_identities: self.user_identities.clone(),
+        }
+    }
+
+    /// Build a downstream from a snapshot taken with [`Downstream::snapshot`], the setup
+    /// connection is not done again as the downstream is already set up. The downstream is not
+    /// added to the pool.
+    #[allow(dead_code)]
+    pub fn restore(
+        snapshot: DownstreamSnapshot,
+        receiver: Receiver<EitherFrame>,
+        sender: Sender<EitherFrame>,
+        extranonces: Arc<Mutex<ExtendedExtranonce>>,
+        solution_sender: SolutionSender,
+        pool: Arc<Mutex<Pool>>,
+    ) -> Arc<Mutex<Self>> {
+        let (
+            ntime_limit,
+            max_target,
+            share_logger,
+            share_batch,
+            share_policy,
+            max_channels,
+            invalid_share_limit,
+            allowed_message_types,
+        ) = pool
+            .safe_lock(|p| {
+                (
+                    p.ntime_limit,
+                    p.max_target.clone(),
+                    p.share_logger.clone(),
+                    p.share_batch,
+                    p.downstream_share_policy,
+                    p.max_channels_per_downstream,
+                    p.invalid_share_limit,
+                    p.allowed_message_types.clone(),
+                )
+            })
+            .unwrap();
+        let mut shared = None;
+        let self_ = Arc::new(Mutex::new(Downstream {
+            id: snapshot.id,
+            receiver,
+            sender,
+            downstream_data: snapshot.downstream_data,
+            channel_ids: snapshot.channel_ids,
+            extranonces,
+            jobs: snapshot.jobs,
+            future_jobs: snapshot
+                .future_jobs
+                .iter()
+                .map(|(job_id, (job, template_id))| {
+                    (*job_id, FutureJob::new(job, *template_id, &mut shared))
+                })
+                .collect(),
+            prefixes: snapshot.prefixes,
+            last_prev_hash: snapshot.last_prev_hash,
+            last_nbits: snapshot.last_nbits,
+            last_valid_extended_job: snapshot.last_valid_extended_job,
+            solution_sender,
+            ntime_limit,
+            max_target,
+            user_identities: snapshot.user_identities,
+            share_logger,
+            share_batch: share_batch.map(|c| ShareBatch::new(c.max_shares)),
+            share_policy: SharePolicy::new(share_policy),
+            max_channels,
+            invalid_shares: invalid_share_limit.map(InvalidShareLimiter::new),
+            paused: false,
+            missed_prev_hash: None,
+            allowed_message_types,
+            reconnect_token: None,
+            requested_data: snapshot.requested_data,
+            #[cfg(feature = "replay-protection")]
+            replay_guard: ReplayGuard::default(),
+        }));
+        if let Some(config) = share_batch {
+            Self::spawn_share_flusher(self_.clone(), config.flush_interval());
+        }
+        Self::spawn_receiver(self_.clone(), pool);
+        self_
+    }
+
+    /// Serve a downstream reclaimed with `Pool::reclaim_parked` on its new connection. The jobs
+    /// that it missed while disconnected are sent like on `resume`.
+    async fn reconnect(
+        self_: Arc<Mutex<Self>>,
+        receiver: Receiver<EitherFrame>,
+        sender: Sender<EitherFrame>,
+        pool: Arc<Mutex<Pool>>,
+    ) {
+        let id = self_
+            .safe_lock(|d| {
+                d.receiver = receiver;
+                d.sender = sender;
+                d.id
+            })
+            .unwrap();
+        println!("Downstream {} reconnected, its state is restored", id);
+        // The flusher of the old connection stopped when the sender has been closed
+        if let Some(config) = pool.safe_lock(|p| p.share_batch).unwrap() {
+            Self::spawn_share_flusher(self_.clone(), config.flush_interval());
+        }
+        Self::spawn_receiver(self_.clone(), pool);
+        // If the new connection is already gone the downstream is removed by the receiver task
+        if Self::resume(self_).await.is_err() {
+            println!("Failed to send the missed jobs to downstream {}", id);
+        }
+    }
+
+    /// Handle the messages received from the downstream until the connection is closed, then
+    /// remove the downstream from the pool
+    fn spawn_receiver(self_: Arc<Mutex<Self>>, pool: Arc<Mutex<Pool>>) {
+        let (id, downstream_data, receiver) = self_
+            .safe_lock(|d| (d.id, d.downstream_data, d.receiver.clone()))
+            .unwrap();
+        let cloned = self_;
 
         task::spawn(async move {
-            loop {
-                let receiver = cloned.safe_lock(|d| d.receiver.clone()).unwrap();
-                match receiver.recv().await {
-                    Ok(received) => {
-                        let received: Result<StdFrame, _> = received.try_into();
-                        match received {
-                            Ok(std_frame) => Downstream::next(cloned.clone(), std_frame).await,
-                            _ => todo!(),
-                        }
-                    }
-                    _ => {
-                        match downstream_data.header_only {
-                            false => {
-                                pool.safe_lock(|p| p.group_downstreams.remove(&id).unwrap())
-                                    .unwrap();
-                            }
-                            true => {
-                                //_hom_ids.safe_lock(|id| id.next()).unwrap();
-                                panic!("Downstream standard channel not supported");
-                            }
-                        };
-                        break;
-                    }
-                }
-                //let incoming: StdFrame = receiver.recv().await.expect("DICOLCALALCLA").try_into().unwrap();
+            let received = receive_frames(receiver, |std_frame| {
+                #[cfg(feature = "metrics")]
+                crate::lib::metrics::RECEIVED.record(std_frame.encoded_length());
+                Downstream::next(cloned.clone(), std_frame)
+            })
+            .await;
+            if let Err(e) = &received {
+                println!("Closing connection with downstream {}: {}", id, e);
+            }
+            // The receiver could have been closed by the pool (eg for a misbehaving downstream)
+            // so the sender is always closed to drop the connection
+            cloned
+                .safe_lock(|d| {
+                    d.receiver.close();
+                    d.sender.close();
+                })
+                .unwrap();
+            // A downstream that sent an invalid frame is not allowed to reclaim its state
+            if received.is_ok() && Pool::park_downstream(&pool, &cloned) {
+                return;
             }
+            // The downstream could have been already removed (eg by a failed broadcast) so a
+            // missing key is not an error here
+            pool.safe_lock(|p| match downstream_data.header_only {
+                false => p.group_downstreams.remove(&id),
+                true => p.hom_downstreams.remove(&id),
+            })
+            .unwrap();
         });
-        self_
     }
 
     pub async fn next(self_mutex: Arc<Mutex<Self>>, mut incoming: StdFrame) {
         let message_type = incoming.get_header().unwrap().msg_type();
         let payload = incoming.payload();
+        #[cfg(feature = "replay-protection")]
+        let (message_type, payload) = match Self::open_sequenced(&self_mutex, message_type, payload)
+        {
+            Some(opened) => opened,
+            None => return,
+        };
         let next_message_to_send = ParseDownstreamMiningMessages::handle_message_mining(
             self_mutex.clone(),
             message_type,
@@ -463,98 +923,355 @@ impl Downstream {
             Ok(SendTo::Respond(message)) => {
                 Self::send(self_mutex, message).await.unwrap();
             }
+            Ok(SendTo::Multiple(messages)) => {
+                for message in messages {
+                    if let SendTo::Respond(message) = message {
+                        Self::send(self_mutex.clone(), message).await.unwrap();
+                    }
+                }
+            }
             Ok(SendTo::None(_)) => (),
             Ok(_) => panic!(),
+            Err(Error::WrongMessageType(message_type)) => {
+                let id = self_mutex.safe_lock(|d| d.id).unwrap();
+                println!(
+                    "WARNING: Downstream {} sent a message of type {:#04x} that is not allowed \
+                     or unknown, dropping it",
+                    id, message_type
+                );
+            }
+            // The pool can not serve the channel, the receiver task then close the connection
+            Err(Error::InvalidExtranoncePrefixLen((expected, actual))) => {
+                self_mutex
+                    .safe_lock(|d| {
+                        println!(
+                            "Closing connection with downstream {}: extranonce prefix of {} \
+                             bytes, expected {}",
+                            d.id, actual, expected
+                        );
+                        d.receiver.close();
+                    })
+                    .unwrap();
+            }
             Err(Error::UnexpectedMessage) => todo!(),
             Err(_) => todo!(),
         }
     }
 
+    /// Unwrap a sequenced frame, see `replay_guard`. Return None if the frame is malformed or if
+    /// its sequence number is not bigger than the last one received from the downstream, the
+    /// frame is then dropped. The frames that are not sequenced are returned as they are.
+    #[cfg(feature = "replay-protection")]
+    fn open_sequenced<'a>(
+        self_: &Arc<Mutex<Self>>,
+        message_type: u8,
+        payload: &'a mut [u8],
+    ) -> Option<(u8, &'a mut [u8])> {
+        if message_type != MESSAGE_TYPE_SEQUENCED {
+            return Some((message_type, payload));
+        }
+        let id = self_.safe_lock(|d| d.id).unwrap();
+        let (sequence_number, message_type, payload) = match replay_guard::open(payload) {
+            Some(opened) => opened,
+            None => {
+                println!(
+                    "WARNING: Downstream {} sent a malformed sequenced frame",
+                    id
+                );
+                return None;
+            }
+        };
+        let (last, accepted) = self_
+            .safe_lock(|d| {
+                let last = d.replay_guard.last_sequence_number();
+                (last, d.replay_guard.check(sequence_number))
+            })
+            .unwrap();
+        if !accepted {
+            println!(
+                "WARNING: Downstream {} sent the sequence number {} after {:?}, frame dropped",
+                id, sequence_number, last
+            );
+            return None;
+        }
+        Some((message_type, payload))
+    }
+
     pub async fn send(
         self_mutex: Arc<Mutex<Self>>,
         message: roles_logic_sv2::parsers::Mining<'static>,
     ) -> Result<(), ()> {
-        let sv2_frame: StdFrame = PoolMessages::Mining(message).try_into().unwrap();
+        let sv2_frame: StdFrame = PoolMessages::Mining(message)
+            .into_frame()
+            .map_err(|e| println!("Can not build frame for downstream: {}", e))?;
         let sender = self_mutex.safe_lock(|self_| self_.sender.clone()).unwrap();
+        #[cfg(feature = "metrics")]
+        crate::lib::metrics::SENT.record(sv2_frame.encoded_length());
         sender.send(sv2_frame.into()).await.map_err(|_| ())?;
         Ok(())
     }
 
-    pub fn on_new_prev_hash_sync(&mut self, message: NewPrevHash<'static>) -> Result<StdFrame, ()> {
+    /// Return `target` or the easiest target allowed by the pool if `target` is easier
+    pub fn clamp_target(&self, target: U256<'static>) -> U256<'static> {
+        match &self.max_target {
+            Some(max_target) if &target > max_target => max_target.clone(),
+            _ => target,
+        }
+    }
+
+    /// Update the target used to validate the shares of `channel_id`, the target is clamped with
+    /// [`Downstream::clamp_target`]. Return the applied target, None if the downstream do not
+    /// have such a channel.
+    pub fn set_channel_target(
+        &mut self,
+        channel_id: u32,
+        target: U256<'static>,
+    ) -> Option<U256<'static>> {
+        let target = self.clamp_target(target);
+        let job = self.jobs.get_mut(&channel_id)?;
+        job.set_target(target.clone());
+        Some(target)
+    }
+
+    /// Update the target of `channel_id` and send a `SetTarget` for it to the downstream
+    #[cfg(feature = "admin")]
+    pub async fn send_set_target(
+        self_mutex: Arc<Mutex<Self>>,
+        channel_id: u32,
+        target: U256<'static>,
+    ) -> Result<(), ()> {
+        let target = self_mutex
+            .safe_lock(|d| d.set_channel_target(channel_id, target))
+            .unwrap()
+            .ok_or(())?;
+        let message = Mining::SetTarget(SetTarget {
+            channel_id,
+            maximum_target: target,
+        });
+        Self::send(self_mutex, message).await
+    }
+
+    /// Send a `SetTarget` with `target` for every channel of the downstream, used by the
+    /// operator to change the difficulty of a whole proxy at once. Return the number of channels.
+    #[cfg(feature = "admin")]
+    pub async fn retarget_all(
+        self_mutex: Arc<Mutex<Self>>,
+        target: U256<'static>,
+    ) -> Result<usize, ()> {
+        let mut channel_ids: Vec<u32> = self_mutex
+            .safe_lock(|d| d.jobs.keys().copied().collect())
+            .unwrap();
+        channel_ids.sort_unstable();
+        for channel_id in &channel_ids {
+            Self::send_set_target(self_mutex.clone(), *channel_id, target.clone()).await?;
+        }
+        Ok(channel_ids.len())
+    }
+
+    /// Activate the job of `message`. A prev hash with a malformed nbits is refused before
+    /// touching the jobs, so the downstream keep mining on the old prev hash.
+    pub fn on_new_prev_hash_sync(
+        &mut self,
+        message: &NewPrevHash<'static>,
+    ) -> Result<StdFrame, Error> {
+        validate_nbits(message.nbits)?;
         let prev_hash = message.prev_hash.clone();
 
         if let Some(future_job) = self.future_jobs.remove(&message.job_id) {
+            let (new_job, template_id) = (future_job.to_job(), future_job.template_id);
             for job in self.jobs.values_mut() {
                 job.update_job(
-                    &future_job.0,
+                    &new_job,
                     message.nbits,
                     u256_to_block_hash(prev_hash.clone()),
-                    future_job.1,
+                    template_id,
                 );
             }
+            self.last_valid_extended_job = Some((new_job, template_id));
         }
 
         self.last_nbits = Some(message.nbits);
         self.last_prev_hash = Some(u256_to_block_hash(prev_hash));
         self.future_jobs = HashMap::new();
 
-        let sv2_frame: StdFrame = PoolMessages::Mining(Mining::SetNewPrevHash(message))
-            .try_into()
-            .unwrap();
-        Ok(sv2_frame)
+        StdFrame::from_message_ref(
+            message,
+            MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH,
+            0,
+            CHANNEL_BIT_MINING_SET_NEW_PREV_HASH,
+        )
+        .ok_or(Error::BadPayloadSize)
     }
 
     pub async fn on_new_prev_hash(
         self_: Arc<Mutex<Self>>,
         message: NewPrevHash<'static>,
     ) -> Result<(), ()> {
-        let sv2_frame = self_
-            .safe_lock(|s| s.on_new_prev_hash_sync(message))
-            .unwrap()?;
+        let sv2_frame = match self_
+            .safe_lock(|s| match s.on_new_prev_hash_sync(&message) {
+                // The state is updated and the prev hash is sent on resume
+                Ok(_) if s.paused => {
+                    s.missed_prev_hash = Some(message);
+                    Ok(None)
+                }
+                res => res.map(Some),
+            })
+            .unwrap()
+        {
+            Ok(Some(sv2_frame)) => sv2_frame,
+            Ok(None) => return Ok(()),
+            // Not a connection error, the downstream is kept
+            Err(e) => {
+                println!("Prev hash skipped: {}", e);
+                return Ok(());
+            }
+        };
         let sender = self_.safe_lock(|self_| self_.sender.clone()).unwrap();
 
+        #[cfg(feature = "metrics")]
+        crate::lib::metrics::SENT.record(sv2_frame.encoded_length());
         sender.send(sv2_frame.into()).await.map_err(|_| ())?;
 
         Ok(())
     }
 
-    pub async fn on_new_extended_job(
-        self_: Arc<Mutex<Self>>,
+    /// Update the jobs of the downstream with `message` and return the frame to send, None if the
+    /// downstream is paused. A future job share the template fields with `shared`, see
+    /// `FutureJob::new`. A header only downstream get an active job as the `NewMiningJob` of its
+    /// standard channel, see `CompleteJob::to_new_mining_job`.
+    fn on_new_extended_job_sync(
+        &mut self,
         message: NewExtendedMiningJob<'static>,
-        _merkle_path: Vec<Vec<u8>>,
         template_id: u64,
-    ) -> Result<(), ()> {
+        shared: &mut Option<Arc<NewExtendedMiningJob<'static>>>,
+    ) -> Result<Option<StdFrame>, ()> {
+        let job_id = message.job_id;
+        let standard = self.downstream_data.header_only && !message.future_job;
+        // The frame is encoded from a reference so that the job can be kept without cloning it
+        let sv2_frame = match self.paused || standard {
+            true => None,
+            false => Some(
+                StdFrame::from_message_ref(
+                    &message,
+                    MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB,
+                    0,
+                    CHANNEL_BIT_NEW_EXTENDED_MINING_JOB,
+                )
+                .ok_or_else(|| {
+                    println!(
+                        "Can not build frame for new extended job: {}",
+                        Error::BadPayloadSize
+                    )
+                })?,
+            ),
+        };
         if !message.future_job {
-            self_
-                .safe_lock(|s| {
-                    for job in s.jobs.values_mut() {
-                        job.update_job(
-                            &message,
-                            s.last_nbits.unwrap(),
-                            *s.last_prev_hash.as_ref().unwrap(),
-                            template_id,
-                        );
-                    }
-                })
-                .unwrap();
+            for job in self.jobs.values_mut() {
+                job.update_job(
+                    &message,
+                    self.last_nbits.unwrap(),
+                    *self.last_prev_hash.as_ref().unwrap(),
+                    template_id,
+                );
+            }
+            self.last_valid_extended_job = Some((message, template_id));
         } else {
-            self_
-                .safe_lock(|s| {
-                    s.future_jobs
-                        .insert(message.job_id, (message.clone(), template_id))
-                })
-                .unwrap();
+            self.future_jobs.insert(
+                message.job_id,
+                FutureJob::new(&message, template_id, shared),
+            );
+        }
+        if standard && !self.paused {
+            // The job is complete only once the channel has been opened
+            if let Some(Job::Complete(job)) = self.jobs.get(&self.id) {
+                let job = job.to_new_mining_job(self.id, job_id);
+                let sv2_frame = PoolMessages::Mining(Mining::NewMiningJob(job))
+                    .into_frame()
+                    .map_err(|e| println!("Can not build frame for new mining job: {}", e))?;
+                return Ok(Some(sv2_frame));
+            }
         }
+        Ok(sv2_frame)
+    }
 
-        let sv2_frame: StdFrame = PoolMessages::Mining(Mining::NewExtendedMiningJob(message))
-            .try_into()
-            .unwrap();
+    pub async fn on_new_extended_job(
+        self_: Arc<Mutex<Self>>,
+        message: NewExtendedMiningJob<'static>,
+        _merkle_path: Vec<Vec<u8>>,
+        template_id: u64,
+    ) -> Result<(), ()> {
+        let sv2_frame = match self_
+            .safe_lock(|s| s.on_new_extended_job_sync(message, template_id, &mut None))
+            .unwrap()?
+        {
+            Some(sv2_frame) => sv2_frame,
+            None => return Ok(()),
+        };
 
         let sender = self_.safe_lock(|self_| self_.sender.clone()).unwrap();
+        #[cfg(feature = "metrics")]
+        crate::lib::metrics::SENT.record(sv2_frame.encoded_length());
         sender.send(sv2_frame.into()).await.map_err(|_| ())?;
 
         Ok(())
     }
+
+    /// Send again the job that the downstream is mining on, as a non future job. It does
+    /// nothing if the downstream has not an active job yet.
+    pub async fn refresh_job(self_: Arc<Mutex<Self>>) -> Result<(), ()> {
+        let job = self_
+            .safe_lock(|s| match s.paused {
+                true => None,
+                false => s.last_valid_extended_job.as_ref().map(|job| job.0.clone()),
+            })
+            .unwrap();
+        match job {
+            Some(mut job) => {
+                job.future_job = false;
+                Self::send(self_, Mining::NewExtendedMiningJob(job)).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Send the jobs that the downstream missed while paused: the current job, activated by the
+    /// last prev hash if a prev hash has been skipped, and the future jobs not yet activated
+    pub async fn resume(self_: Arc<Mutex<Self>>) -> Result<(), ()> {
+        let (job, missed_prev_hash, future_jobs) = self_
+            .safe_lock(|s| {
+                s.paused = false;
+                let mut future_jobs: Vec<NewExtendedMiningJob<'static>> =
+                    s.future_jobs.values().map(FutureJob::to_job).collect();
+                future_jobs.sort_by_key(|job| job.job_id);
+                (
+                    s.last_valid_extended_job.as_ref().map(|job| job.0.clone()),
+                    s.missed_prev_hash.take(),
+                    future_jobs,
+                )
+            })
+            .unwrap();
+        match (job, missed_prev_hash) {
+            (Some(mut job), Some(prev_hash)) => {
+                let job_id = job.job_id;
+                job.future_job = true;
+                Self::send(self_.clone(), Mining::NewExtendedMiningJob(job)).await?;
+                let prev_hash = NewPrevHash {
+                    job_id,
+                    ..prev_hash
+                };
+                Self::send(self_.clone(), Mining::SetNewPrevHash(prev_hash)).await?;
+            }
+            (Some(mut job), None) => {
+                job.future_job = false;
+                Self::send(self_.clone(), Mining::NewExtendedMiningJob(job)).await?;
+            }
+            (None, _) => (),
+        }
+        for job in future_jobs {
+            Self::send(self_.clone(), Mining::NewExtendedMiningJob(job)).await?;
+        }
+        Ok(())
+    }
 }
 impl IsDownstream for Downstream {
     fn get_downstream_mining_data(&self) -> CommonDownstreamData {
@@ -565,52 +1282,341 @@ impl IsDownstream for Downstream {
 impl IsMiningDownstream for Downstream {}
 
 impl Pool {
-    async fn accept_incoming_connection(self_: Arc<Mutex<Pool>>, config: Configuration) {
-        let listner = TcpListener::bind(&config.listen_address).await.unwrap();
-        while let Ok((stream, _)) = listner.accept().await {
-            let solution_sender = self_.safe_lock(|p| p.solution_sender.clone()).unwrap();
-            let responder = Responder::from_authority_kp(
-                config.authority_public_key.clone().into_inner().as_bytes(),
-                config.authority_secret_key.clone().into_inner().as_bytes(),
-                std::time::Duration::from_secs(config.cert_validity_sec),
-            )
+    /// Cancel the future job `job_id` on every downstream, used when the template of the job is
+    /// invalidated before its prev hash arrive. Return the number of downstreams that had the
+    /// job.
+    ///
+    /// Each downstream is locked while the pool is locked by the caller, the same order of
+    /// `remove_downstream`: a downstream must never lock the pool.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn cancel_future_job(&self, job_id: u32) -> usize {
+        self.group_downstreams
+            .values()
+            .chain(self.hom_downstreams.values())
+            .filter(|downstream| {
+                downstream
+                    .safe_lock(|d| d.cancel_future_job(job_id).is_some())
+                    .unwrap()
+            })
+            .count()
+    }
+
+    fn get_downstream(&self, channel_id: u32) -> Option<Arc<Mutex<Downstream>>> {
+        self.group_downstreams
+            .get(&channel_id)
+            .or_else(|| self.hom_downstreams.get(&channel_id))
+            .cloned()
+    }
+
+    /// Stop sending new jobs and prev hashes to the downstream `channel_id`, without
+    /// disconnecting it. The jobs still update the state of the downstream so that they can be
+    /// sent on `resume_downstream`. Return false if there is no such downstream.
+    #[cfg(feature = "admin")]
+    pub fn pause_downstream(&self, channel_id: u32) -> bool {
+        match self.get_downstream(channel_id) {
+            Some(downstream) => {
+                downstream.safe_lock(|d| d.paused = true).unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resume a downstream paused with `pause_downstream` and send the jobs that it missed.
+    /// Return false if there is no such downstream.
+    #[cfg(feature = "admin")]
+    pub async fn resume_downstream(self_: Arc<Mutex<Self>>, channel_id: u32) -> bool {
+        let downstream = match self_.safe_lock(|p| p.get_downstream(channel_id)).unwrap() {
+            Some(downstream) => downstream,
+            None => return false,
+        };
+        if Downstream::resume(downstream.clone()).await.is_err() {
+            println!(
+                "Failed to send the current job to downstream {}, removing it",
+                channel_id
+            );
+            self_
+                .safe_lock(|p| p.remove_downstream(&downstream))
+                .unwrap();
+        }
+        true
+    }
+
+    /// Remove a downstream from the pool, used when the downstream can not be reached anymore.
+    /// If the downstream has been already removed it does nothing.
+    fn remove_downstream(&mut self, downstream: &Arc<Mutex<Downstream>>) {
+        let (header_only, id) = downstream
+            .safe_lock(|d| (d.downstream_data.header_only, d.id))
             .unwrap();
-            let last_new_prev_hash = self_.safe_lock(|x| x.last_new_prev_hash.clone()).unwrap();
-            let (receiver, sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
-                Connection::new(stream, HandshakeRole::Responder(responder)).await;
-            let group_ids = self_.safe_lock(|s| s.group_ids.clone()).unwrap();
-            let hom_ids = self_.safe_lock(|s| s.hom_ids.clone()).unwrap();
-            let job_creators = self_.safe_lock(|s| s.job_creators.clone()).unwrap();
-            let extranonces = self_.safe_lock(|s| s.extranonces.clone()).unwrap();
-            let downstream = Downstream::new(
-                receiver,
-                sender,
-                group_ids,
-                hom_ids,
-                job_creators,
-                extranonces,
-                last_new_prev_hash,
-                solution_sender,
+        match header_only {
+            false => self.group_downstreams.remove(&id),
+            true => self.hom_downstreams.remove(&id),
+        };
+    }
+
+    /// Keep a downstream that disconnected with a reconnect token in the pool, paused, so that it
+    /// can reclaim its channels and jobs if it reconnects within `reconnect_grace_sec`. Return
+    /// false if the downstream has no token or if the reconnections are not enabled.
+    fn park_downstream(self_: &Arc<Mutex<Self>>, downstream: &Arc<Mutex<Downstream>>) -> bool {
+        let (id, token) = downstream
+            .safe_lock(|d| (d.id, d.reconnect_token.clone()))
+            .unwrap();
+        let token = match token {
+            Some(token) => token,
+            None => return false,
+        };
+        let grace_sec = match self_
+            .safe_lock(|p| p.parked.as_ref().map(|parked| parked.grace_sec()))
+            .unwrap()
+        {
+            Some(grace_sec) => grace_sec,
+            None => return false,
+        };
+        // Paused before being parked so that the jobs are not sent on the closed connection
+        downstream.safe_lock(|d| d.paused = true).unwrap();
+        self_
+            .safe_lock(|p| {
+                // A downstream parked with the same token will never be reclaimed
+                if let Some(replaced) = p.parked.as_mut().unwrap().park(token, id) {
+                    if let Some(replaced) = p.get_downstream(replaced) {
+                        p.remove_downstream(&replaced);
+                    }
+                }
+            })
+            .unwrap();
+        println!(
+            "Downstream {} disconnected, its state is kept for {}s",
+            id, grace_sec
+        );
+        let pool = self_.clone();
+        task::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(grace_sec as u64 + 1)).await;
+            pool.safe_lock(|p| p.remove_expired_parked()).unwrap();
+        });
+        true
+    }
+
+    /// Data of the setup connection flags of the downstream parked with `token`
+    fn parked_requested_data(&self, token: &str) -> Option<CommonDownstreamData> {
+        let id = self.parked.as_ref()?.get(token)?;
+        self.get_downstream(id)?
+            .safe_lock(|d| d.requested_data)
+            .ok()
+    }
+
+    /// The parked downstream that disconnected with `token`, see `park_downstream`. None if there
+    /// is no such downstream or if its grace window expired.
+    fn reclaim_parked(&mut self, token: &str) -> Option<Arc<Mutex<Downstream>>> {
+        let id = self.parked.as_mut()?.reclaim(token)?;
+        self.get_downstream(id)
+    }
+
+    /// Remove the parked downstreams whose grace window expired
+    fn remove_expired_parked(&mut self) {
+        let expired = match self.parked.as_mut() {
+            Some(parked) => parked.expired(),
+            None => return,
+        };
+        for id in expired {
+            if let Some(downstream) = self.get_downstream(id) {
+                println!("Downstream {} did not reconnect in time, removing it", id);
+                self.remove_downstream(&downstream);
+            }
+        }
+    }
+
+    /// Refresh the job of every downstream if no template or prev hash arrived in the last
+    /// `interval` seconds
+    async fn refresh_jobs(self_: Arc<Mutex<Self>>, now: u32, interval: u32) {
+        let due = self_
+            .safe_lock(|p| now.saturating_sub(p.last_new_work) >= interval)
+            .unwrap();
+        if !due {
+            return;
+        }
+        let group_downstreams: Vec<Arc<Mutex<Downstream>>> = self_
+            .safe_lock(|s| s.group_downstreams.iter().map(|d| d.1.clone()).collect())
+            .unwrap();
+        for downstream in group_downstreams {
+            if Downstream::refresh_job(downstream.clone()).await.is_err() {
+                let id = downstream.safe_lock(|d| d.id).unwrap();
+                println!(
+                    "Failed to refresh the job of downstream {}, removing it",
+                    id
+                );
+                self_
+                    .safe_lock(|p| p.remove_downstream(&downstream))
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Call `refresh_jobs` every `interval` seconds
+    fn spawn_job_refresher(self_: Arc<Mutex<Self>>, interval: u32) {
+        task::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(interval as u64)).await;
+                Self::refresh_jobs(self_.clone(), system_clock(), interval).await;
+            }
+        });
+    }
+
+    async fn accept_incoming_connection(
+        self_: Arc<Mutex<Pool>>,
+        config: Configuration,
+        authority_keys: Arc<Mutex<AuthorityKeys>>,
+    ) {
+        let listner = TcpListener::bind(&config.listen_address).await.unwrap();
+        Self::accept_connections(
+            self_,
+            listner,
+            config.max_buffered_bytes,
+            Duration::from_secs(config.setup_timeout_sec),
+            authority_keys,
+        )
+        .await
+    }
+
+    /// Set up each connection accepted by `listener` in its own task, so that a downstream that
+    /// stalls the noise handshake or the setup connection does not delay the next ones
+    async fn accept_connections(
+        self_: Arc<Mutex<Pool>>,
+        listener: TcpListener,
+        max_buffered_bytes: Option<usize>,
+        setup_timeout: Duration,
+        authority_keys: Arc<Mutex<AuthorityKeys>>,
+    ) {
+        while let Ok((stream, _)) = listener.accept().await {
+            // The keys are read for each connection so that a rotation is picked up by the next
+            // connection
+            let responder = match authority_keys.safe_lock(|k| k.responder()) {
+                Ok(Ok(responder)) => responder,
+                Ok(Err(e)) => {
+                    println!(
+                        "ERROR: can not build the noise responder, connection dropped: {:?}",
+                        e
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    println!(
+                        "ERROR: authority keys lock poisoned, connection dropped: {:?}",
+                        e
+                    );
+                    continue;
+                }
+            };
+            task::spawn(Self::on_new_connection(
                 self_.clone(),
-            )
-            .await;
+                stream,
+                responder,
+                max_buffered_bytes,
+                setup_timeout,
+            ));
+        }
+    }
 
-            let (is_header_only, channel_id) = downstream
-                .safe_lock(|d| (d.downstream_data.header_only, d.id))
-                .unwrap();
+    /// Do the noise handshake and the setup connection of a new downstream and add it to the
+    /// pool. A downstream that do not complete both within `setup_timeout` is dropped so that it
+    /// can not hold the connection forever.
+    async fn on_new_connection(
+        self_: Arc<Mutex<Pool>>,
+        stream: TcpStream,
+        responder: Responder,
+        max_buffered_bytes: Option<usize>,
+        setup_timeout: Duration,
+    ) {
+        let setup = async {
+            let role = HandshakeRole::Responder(responder);
+            let (receiver, sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
+                match max_buffered_bytes {
+                    Some(max) => Connection::with_max_buffered_bytes(stream, role, max).await,
+                    None => Connection::new(stream, role).await,
+                };
+            Self::on_new_downstream(self_, receiver, sender).await
+        };
+        match tokio::time::timeout(setup_timeout, setup).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => println!("Downstream connection refused: {}", e),
+            Err(_) => println!(
+                "Downstream did not complete the setup in {:?}, connection dropped",
+                setup_timeout
+            ),
+        }
+    }
 
+    /// Do the setup connection of a downstream connected with `receiver` and `sender` and add it
+    /// to the pool
+    async fn on_new_downstream(
+        self_: Arc<Mutex<Pool>>,
+        receiver: Receiver<EitherFrame>,
+        sender: Sender<EitherFrame>,
+    ) -> Result<Arc<Mutex<Downstream>>, Error> {
+        // Read after the handshake so that the downstream start from the current state
+        let (solution_sender, last_new_prev_hash, group_ids, hom_ids, job_creators, extranonces) =
             self_
-                .safe_lock(|p| {
-                    if is_header_only {
-                        p.hom_downstreams.insert(channel_id, downstream);
-                    } else {
-                        p.group_downstreams.insert(channel_id, downstream);
-                    }
+                .safe_lock(|s| {
+                    (
+                        s.solution_sender.clone(),
+                        s.last_new_prev_hash.clone(),
+                        s.group_ids.clone(),
+                        s.hom_ids.clone(),
+                        s.job_creators.clone(),
+                        s.extranonces.clone(),
+                    )
                 })
                 .unwrap();
+        let downstream = Downstream::new(
+            receiver,
+            sender,
+            group_ids,
+            hom_ids,
+            job_creators,
+            extranonces,
+            last_new_prev_hash,
+            solution_sender,
+            self_.clone(),
+        )
+        .await?;
+
+        let (is_header_only, channel_id) = downstream
+            .safe_lock(|d| (d.downstream_data.header_only, d.id))
+            .unwrap();
+
+        self_
+            .safe_lock(|p| {
+                if is_header_only {
+                    p.hom_downstreams.insert(channel_id, downstream.clone());
+                } else {
+                    p.group_downstreams.insert(channel_id, downstream.clone());
+                }
+            })
+            .unwrap();
+        Ok(downstream)
+    }
+
+    /// A prev hash referenced a template that the pool never received, the pool and the template
+    /// provider are out of step. The prev hash is dropped, so that the jobs are not activated on
+    /// a wrong state, and the template receiver is asked to reconnect: on a new connection the
+    /// template provider send again its current template and prev hash.
+    fn on_desync(&mut self, template_id: u64) {
+        self.desync_events += 1;
+        println!(
+            "WARNING: Prev hash for unknown template {}, resyncing with the template provider \
+             (desync #{})",
+            template_id, self.desync_events
+        );
+        // If the channel is full a resync is already pending
+        if self.resync_sender.try_send(()).is_err() && self.resync_sender.is_closed() {
+            println!("ERROR: Template receiver stopped, can not resync");
         }
     }
 
+    #[allow(dead_code)]
+    pub fn desync_events(&self) -> u64 {
+        self.desync_events
+    }
+
     async fn on_new_prev_hash(self_: Arc<Mutex<Self>>, rx: Receiver<SetNewPrevHash<'static>>) {
         while let Ok(new_prev_hash) = rx.recv().await {
             while !self_.safe_lock(|s| s.new_template_processed).unwrap() {
@@ -619,6 +1625,21 @@ impl Pool {
             self_
                 .safe_lock(|s| s.new_template_processed = false)
                 .unwrap();
+            let known_template = self_
+                .safe_lock(|s| {
+                    s.job_creators
+                        .safe_lock(|jc| jc.has_template(new_prev_hash.template_id))
+                        .unwrap()
+                })
+                .unwrap();
+            // `new_template_processed` stay false so the next prev hash wait for a template of
+            // the new connection
+            if !known_template {
+                self_
+                    .safe_lock(|s| s.on_desync(new_prev_hash.template_id))
+                    .unwrap();
+                continue;
+            }
             self_
                 .safe_lock(|s| {
                     s.job_creators
@@ -627,7 +1648,10 @@ impl Pool {
                 })
                 .unwrap();
             self_
-                .safe_lock(|s| s.last_new_prev_hash = Some(new_prev_hash.clone()))
+                .safe_lock(|s| {
+                    s.last_new_prev_hash = Some(new_prev_hash.clone());
+                    s.last_new_work = system_clock();
+                })
                 .unwrap();
             let hom_downstreams: Vec<Arc<Mutex<Downstream>>> = self_
                 .safe_lock(|s| s.hom_downstreams.iter().map(|d| d.1.clone()).collect())
@@ -653,72 +1677,189 @@ impl Pool {
                     min_ntime: 0,
                     nbits: new_prev_hash.n_bits,
                 };
-                Downstream::on_new_prev_hash(downstream.clone(), message)
+                if Downstream::on_new_prev_hash(downstream.clone(), message)
                     .await
-                    .unwrap();
+                    .is_err()
+                {
+                    println!(
+                        "Failed to send new prev hash to downstream {}, removing it",
+                        channel_id
+                    );
+                    self_
+                        .safe_lock(|p| p.remove_downstream(&downstream))
+                        .unwrap();
+                }
             }
         }
     }
 
-    async fn on_new_template(self_: Arc<Mutex<Self>>, rx: Receiver<NewTemplate<'_>>) {
-        while let Ok(mut new_template) = rx.recv().await {
+    /// Create the jobs of each template received on `rx`, the witness data of a template, if
+    /// any, is used for the witness commitment of its coinbase
+    async fn on_new_template(
+        self_: Arc<Mutex<Self>>,
+        rx: Receiver<(NewTemplate<'_>, Option<TemplateWitness>)>,
+    ) {
+        while let Ok((mut new_template, witness)) = rx.recv().await {
             let job_creators = self_.safe_lock(|s| s.job_creators.clone()).unwrap();
             let mut new_jobs = job_creators
-                .safe_lock(|j| j.on_new_template(&mut new_template).unwrap())
+                .safe_lock(|j| {
+                    if let Some(witness) = witness {
+                        j.set_template_witness(new_template.template_id, witness);
+                    }
+                    j.on_new_template(&mut new_template).unwrap()
+                })
                 .unwrap();
             let group_downstreams: Vec<Arc<Mutex<Downstream>>> = self_
                 .safe_lock(|s| s.group_downstreams.iter().map(|d| d.1.clone()).collect())
                 .unwrap();
             // TODO add standard channel downstream
-            for downstream in group_downstreams {
-                let channel_id = downstream.safe_lock(|x| x.id).unwrap();
-                let extended_job = new_jobs.remove(&channel_id).unwrap();
-                Downstream::on_new_extended_job(
-                    downstream,
-                    extended_job,
-                    new_template.merkle_path.to_vec(),
-                    new_template.template_id,
-                )
-                .await
-                .unwrap();
-            }
+            let jobs = group_downstreams
+                .into_iter()
+                .map(|downstream| {
+                    let channel_id = downstream.safe_lock(|x| x.id).unwrap();
+                    (downstream, new_jobs.remove(&channel_id).unwrap())
+                })
+                .collect();
+            Self::send_job_batch(self_.clone(), jobs, new_template.template_id).await;
             self_
-                .safe_lock(|s| s.new_template_processed = true)
+                .safe_lock(|s| {
+                    s.new_template_processed = true;
+                    s.last_new_work = system_clock();
+                })
                 .unwrap();
         }
     }
 
+    /// Send each job of `jobs` to its downstream. The state of every downstream is updated and
+    /// every frame is built before sending the first one, so that a downstream that disconnects
+    /// in the middle of the batch does not leave the others without the new job. The downstreams
+    /// that can not be reached are removed and the batch continues with the next one.
+    async fn send_job_batch(
+        self_: Arc<Mutex<Self>>,
+        jobs: Vec<(Arc<Mutex<Downstream>>, NewExtendedMiningJob<'static>)>,
+        template_id: u64,
+    ) {
+        let mut frames = Vec::with_capacity(jobs.len());
+        // The future jobs of the batch are all created from the same template
+        let mut shared = None;
+        for (downstream, job) in jobs {
+            let (channel_id, sender, sv2_frame) = downstream
+                .safe_lock(|d| {
+                    let sv2_frame = d.on_new_extended_job_sync(job, template_id, &mut shared);
+                    (d.id, d.sender.clone(), sv2_frame)
+                })
+                .unwrap();
+            match sv2_frame {
+                Ok(Some(sv2_frame)) => frames.push((downstream, channel_id, sender, sv2_frame)),
+                // Paused, or header only without an open channel
+                Ok(None) => (),
+                Err(_) => {
+                    println!(
+                        "Failed to send new extended job to downstream {}, removing it",
+                        channel_id
+                    );
+                    self_
+                        .safe_lock(|p| p.remove_downstream(&downstream))
+                        .unwrap();
+                }
+            }
+        }
+        for (downstream, channel_id, sender, sv2_frame) in frames {
+            #[cfg(feature = "metrics")]
+            crate::lib::metrics::SENT.record(sv2_frame.encoded_length());
+            if sender.send(sv2_frame.into()).await.is_err() {
+                println!(
+                    "Failed to send new extended job to downstream {}, removing it",
+                    channel_id
+                );
+                self_
+                    .safe_lock(|p| p.remove_downstream(&downstream))
+                    .unwrap();
+            }
+        }
+    }
+
     pub async fn start(
         config: Configuration,
-        new_template_rx: Receiver<NewTemplate<'static>>,
+        new_template_rx: Receiver<(NewTemplate<'static>, Option<TemplateWitness>)>,
         new_prev_hash_rx: Receiver<SetNewPrevHash<'static>>,
-        solution_sender: Sender<SubmitSolution<'static>>,
+        solution_sender: SolutionSender,
+        resync_sender: Sender<()>,
+        authority_keys: Arc<Mutex<AuthorityKeys>>,
     ) {
         //let group_id_generator = Arc::new(Mutex::new(Id::new()));
         let range_0 = std::ops::Range { start: 0, end: 0 };
         let range_1 = std::ops::Range { start: 0, end: 16 };
         let range_2 = std::ops::Range { start: 16, end: 32 };
+        let mut job_creators =
+            JobsCreators::new(crate::BLOCK_REWARD, crate::new_pub_key()).unwrap();
+        job_creators.set_coinbase_overrides(CoinbaseOverrides {
+            locktime: config.coinbase_locktime,
+            input_sequence: config.coinbase_input_sequence,
+        });
         let pool = Arc::new(Mutex::new(Pool {
             group_downstreams: HashMap::new(),
             hom_downstreams: HashMap::new(),
             hom_ids: Arc::new(Mutex::new(Id::new())),
             group_ids: Arc::new(Mutex::new(Id::new())),
-            job_creators: Arc::new(Mutex::new(
-                JobsCreators::new(crate::BLOCK_REWARD, crate::new_pub_key()).unwrap(),
-            )),
+            job_creators: Arc::new(Mutex::new(job_creators)),
             last_new_prev_hash: None,
             extranonces: Arc::new(Mutex::new(ExtendedExtranonce::new(
                 range_0, range_1, range_2,
             ))),
             solution_sender,
             new_template_processed: false,
+            ntime_limit: config.max_ntime_drift.map(NtimeLimit::new),
+            max_target: config
+                .min_channel_difficulty
+                .and_then(max_target_from_difficulty)
+                .map(message_handler::uint_256_to_u256),
+            protocol_versions: (config.min_protocol_version, config.max_protocol_version),
+            share_logger: Arc::new(PrintShareLogger::default()),
+            share_batch: config.share_batch,
+            downstream_share_policy: config.downstream_share_policy,
+            max_channels_per_downstream: config.max_channels_per_downstream,
+            invalid_share_limit: config.invalid_share_limit,
+            // Protocols are checked by `Configuration::validate`
+            allowed_message_types: config.allowed_message_types.as_ref().map(|allowed| {
+                MessageTypeAllowlist::new(
+                    allowed
+                        .iter()
+                        .map(|(protocol, message_type)| {
+                            ((*protocol).try_into().unwrap(), *message_type)
+                        })
+                        .collect(),
+                )
+            }),
+            last_new_work: system_clock(),
+            resync_sender,
+            desync_events: 0,
+            parked: config.reconnect_grace_sec.map(ParkedDownstreams::new),
         }));
 
+        if let Some(interval) = config.job_refresh_interval_sec {
+            Self::spawn_job_refresher(pool.clone(), interval);
+        }
+
+        #[cfg(feature = "admin")]
+        if let Some(address) = &config.admin_address {
+            match TcpListener::bind(address).await {
+                Ok(listener) => {
+                    task::spawn(admin::serve(listener, pool.clone()));
+                }
+                Err(e) => println!("ERROR: Can not bind the admin socket {}: {}", address, e),
+            }
+        }
+
         let cloned = pool.clone();
         let cloned2 = pool.clone();
         let cloned3 = pool.clone();
 
-        task::spawn(Self::accept_incoming_connection(cloned, config));
+        task::spawn(Self::accept_incoming_connection(
+            cloned,
+            config,
+            authority_keys,
+        ));
 
         task::spawn(async {
             Self::on_new_prev_hash(cloned2, new_prev_hash_rx).await;
@@ -730,3 +1871,2213 @@ impl Pool {
         .await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        job::{check_coinbase, target_from_nbits, CompleteJob, PartialJob},
+        *,
+    };
+    use binary_sv2::Seq0255;
+    use bitcoin::blockdata::block::BlockHeader;
+    use roles_logic_sv2::{
+        mining_sv2::NewMiningJob, template_distribution_sv2::SubmitSolution,
+        utils::merkle_root_from_path,
+    };
+
+    // Version 2 coinbase with a 12 bytes script_sig: push of the height (3 bytes) followed by 8
+    // bytes of extranonce
+    fn coinbase_parts() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let mut prefix = vec![0x02, 0, 0, 0, 0x01];
+        prefix.extend([0; 32]);
+        prefix.extend([0xff; 4]);
+        prefix.extend([0x0c, 0x03, 0x01, 0x02, 0x03]);
+        let extranonce = vec![0; 8];
+        let mut suffix = vec![0xff; 4];
+        // One P2WPKH output of 50 btc
+        suffix.push(0x01);
+        suffix.extend(5_000_000_000_u64.to_le_bytes());
+        suffix.extend([0x16, 0x00, 0x14]);
+        suffix.extend([0xaa; 20]);
+        suffix.extend([0; 4]);
+        (prefix, extranonce, suffix)
+    }
+
+    #[test]
+    fn test_check_coinbase() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let coinbase = [&prefix[..], &extranonce[..], &suffix[..]].concat();
+        assert!(check_coinbase(&coinbase).is_ok());
+    }
+
+    #[test]
+    fn test_to_new_mining_job() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let partial = PartialJob {
+            target: to_u256(Uint256::from_u64(1).unwrap()),
+            extranonce: extranonce.clone(),
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let complete = partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3);
+
+        let job = complete.to_new_mining_job(7, 8);
+        let merkle_root =
+            merkle_root_from_path(&prefix, &suffix, &extranonce, &Vec::<Vec<u8>>::new()).unwrap();
+        assert_eq!(job.channel_id, 7);
+        assert_eq!(job.job_id, 8);
+        assert!(!job.future_job);
+        assert_eq!(job.version, 0x2000_0000);
+        assert_eq!(job.merkle_root.to_vec(), merkle_root);
+    }
+
+    #[test]
+    fn test_check_coinbase_misaligned_extranonce() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        // The extranonce is one byte shorter than the space reserved in the script_sig
+        let coinbase = [&prefix[..], &extranonce[1..], &suffix[..]].concat();
+        assert!(check_coinbase(&coinbase).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_frame_after_handshake() {
+        use crate::lib::test_utils::{in_memory, load, Direction};
+        let setup_session = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/setup_session.txt");
+        let setup_connection = load(setup_session)
+            .unwrap()
+            .into_iter()
+            .find(|(direction, _)| *direction == Direction::ToPool)
+            .map(|(_, frame)| frame)
+            .unwrap();
+        let ((receiver, _), (_, sender)) = in_memory();
+        let sv2_frame = StdFrame::from_bytes(setup_connection).unwrap();
+        sender.send(sv2_frame.into()).await.unwrap();
+        let handshake_frame = codec_sv2::HandShakeFrame::from_bytes(vec![1, 0, 0].into()).unwrap();
+        sender.send(handshake_frame.into()).await.unwrap();
+
+        let mut handled = 0;
+        let received = receive_frames(receiver, |_| {
+            handled += 1;
+            async {}
+        })
+        .await;
+        assert_eq!(handled, 1);
+        assert_eq!(
+            received,
+            Err(FrameConversionError::ExpectedSv2Frame { len: 3 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stalled_client_does_not_block_others() {
+        use crate::lib::test_utils::{frame_to_bytes, load, Direction};
+        use codec_sv2::Initiator;
+        let config =
+            Configuration::load(include_str!("../../../pool-config.toml"), vec![]).unwrap();
+        let authority_public_key = config.authority_public_key.clone().into_inner().to_bytes();
+        let authority_keys = Arc::new(Mutex::new(AuthorityKeys::from_config(&config)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let pool = Arc::new(Mutex::new(test_pool()));
+        task::spawn(Pool::accept_connections(
+            pool.clone(),
+            listener,
+            None,
+            Duration::from_secs(60),
+            authority_keys,
+        ));
+
+        // Connects and never starts the noise handshake
+        let _stalled = TcpStream::connect(address).await.unwrap();
+
+        let setup_session = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/setup_session.txt");
+        let recording = load(setup_session).unwrap();
+        let frame = |direction| {
+            recording
+                .iter()
+                .find(|(d, _)| *d == direction)
+                .map(|(_, frame)| frame.clone())
+                .unwrap()
+        };
+        let stream = TcpStream::connect(address).await.unwrap();
+        let initiator = Initiator::from_raw_k(authority_public_key).unwrap();
+        // Well before the setup timeout of the stalled client
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let (receiver, sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
+                Connection::new(stream, HandshakeRole::Initiator(initiator)).await;
+            let setup_connection = StdFrame::from_bytes(frame(Direction::ToPool)).unwrap();
+            sender.send(setup_connection.into()).await.unwrap();
+            let success = frame_to_bytes(receiver.recv().await.unwrap()).unwrap();
+            assert_eq!(success, frame(Direction::FromPool));
+            // The downstream is added once the setup completes
+            while pool.safe_lock(|p| p.group_downstreams.len()).unwrap() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_setup_timeout() {
+        let config =
+            Configuration::load(include_str!("../../../pool-config.toml"), vec![]).unwrap();
+        let authority_keys = Arc::new(Mutex::new(AuthorityKeys::from_config(&config)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        task::spawn(Pool::accept_connections(
+            Arc::new(Mutex::new(test_pool())),
+            listener,
+            None,
+            Duration::from_millis(100),
+            authority_keys,
+        ));
+
+        // The pool drop the connection of a client that never starts the noise handshake
+        let mut stalled = TcpStream::connect(address).await.unwrap();
+        let mut buffer = [0; 1];
+        let read = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::io::AsyncReadExt::read(&mut stalled, &mut buffer),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+    }
+
+    #[tokio::test]
+    async fn test_keep_accepting_on_responder_error() {
+        use codec_sv2::Initiator;
+        let config =
+            Configuration::load(include_str!("../../../pool-config.toml"), vec![]).unwrap();
+        let authority_public_key = config.authority_public_key.clone().into_inner().to_bytes();
+        let authority_keys = Arc::new(Mutex::new(AuthorityKeys::from_config(&config)));
+        // The certificate can not be valid past the u32 unix time so no responder can be built
+        authority_keys
+            .safe_lock(|k| k.cert_validity = Duration::from_secs(u32::MAX as u64))
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        task::spawn(Pool::accept_connections(
+            Arc::new(Mutex::new(test_pool())),
+            listener,
+            None,
+            Duration::from_secs(60),
+            authority_keys.clone(),
+        ));
+
+        // The connection is dropped
+        let mut refused = TcpStream::connect(address).await.unwrap();
+        let mut buffer = [0; 1];
+        let read = tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::io::AsyncReadExt::read(&mut refused, &mut buffer),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+
+        // And the pool keep accepting, the next connection complete the handshake once the keys
+        // are fixed
+        authority_keys
+            .safe_lock(|k| k.cert_validity = Duration::from_secs(config.cert_validity_sec))
+            .unwrap();
+        let stream = TcpStream::connect(address).await.unwrap();
+        let initiator = Initiator::from_raw_k(authority_public_key).unwrap();
+        let (_receiver, _sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
+            tokio::time::timeout(
+                Duration::from_secs(5),
+                Connection::new(stream, HandshakeRole::Initiator(initiator)),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotate_authority_keys() {
+        use crate::lib::test_utils::{frame_to_bytes, load, Direction};
+        use codec_sv2::Initiator;
+        let config =
+            Configuration::load(include_str!("../../../pool-config.toml"), vec![]).unwrap();
+        let old_public_key = config.authority_public_key.clone().into_inner().to_bytes();
+        let authority_keys = Arc::new(Mutex::new(AuthorityKeys::from_config(&config)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let pool = Arc::new(Mutex::new(test_pool()));
+        task::spawn(Pool::accept_connections(
+            pool.clone(),
+            listener,
+            None,
+            Duration::from_secs(60),
+            authority_keys.clone(),
+        ));
+
+        let setup_session = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/setup_session.txt");
+        let recording = load(setup_session).unwrap();
+        let frame = |direction| {
+            recording
+                .iter()
+                .find(|(d, _)| *d == direction)
+                .map(|(_, frame)| frame.clone())
+                .unwrap()
+        };
+        let connect = |public_key| async move {
+            let stream = TcpStream::connect(address).await.unwrap();
+            let initiator = Initiator::from_raw_k(public_key).unwrap();
+            let connection: (Receiver<EitherFrame>, Sender<EitherFrame>) = tokio::time::timeout(
+                Duration::from_secs(5),
+                Connection::new(stream, HandshakeRole::Initiator(initiator)),
+            )
+            .await
+            .unwrap();
+            connection
+        };
+        let (old_receiver, old_sender) = connect(old_public_key).await;
+
+        // Rotate to another keypair as the SIGHUP handler do
+        let new_config = Configuration::load(
+            include_str!("../../../pool-config.toml"),
+            vec![
+                (
+                    "POOL_AUTHORITY_PUBLIC_KEY".to_string(),
+                    "NxgQad5km17BDjoi7t75FrvZJQzKv7RMrdxnzwyL7XharAcwX".to_string(),
+                ),
+                (
+                    "POOL_AUTHORITY_SECRET_KEY".to_string(),
+                    "23ze8iuaFibVAYvTvfpzUBNdizmiH14ad5tULYyBZafAkP3Xj4".to_string(),
+                ),
+            ],
+        )
+        .unwrap();
+        let new_public_key = new_config
+            .authority_public_key
+            .clone()
+            .into_inner()
+            .to_bytes();
+        assert_ne!(new_public_key, old_public_key);
+        AuthorityKeys::rotate(&authority_keys, &new_config).unwrap();
+
+        // A new connection handshakes with the new authority key
+        let (new_receiver, new_sender) = connect(new_public_key).await;
+
+        // Both connections complete the setup, the one opened before the rotation is still up
+        for (receiver, sender) in [(old_receiver, old_sender), (new_receiver, new_sender)] {
+            let setup_connection = StdFrame::from_bytes(frame(Direction::ToPool)).unwrap();
+            sender.send(setup_connection.into()).await.unwrap();
+            let success = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(frame_to_bytes(success).unwrap(), frame(Direction::FromPool));
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while pool.safe_lock(|p| p.group_downstreams.len()).unwrap() < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_translator_target_in_pool() {
+        use roles_logic_sv2::utils::difficulty_to_target;
+        // The max target that the translator compute for a SV1 difficulty as the pool read it
+        let target = message_handler::u256_to_uint_256(difficulty_to_target(1.0));
+        assert_eq!(target, BlockHeader::u256_from_compact_target(0x1d00_ffff));
+        let harder = message_handler::u256_to_uint_256(difficulty_to_target(1024.0));
+        assert!(harder < target);
+        assert_eq!(
+            message_handler::uint_256_to_u256(target).to_vec(),
+            difficulty_to_target(1.0).to_vec()
+        );
+    }
+
+    fn test_pool() -> Pool {
+        let (solution_sender, solution_receiver) = async_channel::bounded(1);
+        Pool {
+            group_downstreams: HashMap::new(),
+            hom_downstreams: HashMap::new(),
+            hom_ids: Arc::new(Mutex::new(Id::new())),
+            group_ids: Arc::new(Mutex::new(Id::new())),
+            job_creators: Arc::new(Mutex::new(
+                JobsCreators::new(crate::BLOCK_REWARD, crate::new_pub_key()).unwrap(),
+            )),
+            last_new_prev_hash: None,
+            extranonces: Arc::new(Mutex::new(ExtendedExtranonce::new(0..0, 0..16, 16..32))),
+            solution_sender: SolutionSender::new(
+                solution_sender,
+                solution_receiver,
+                crate::SolutionChannelPolicy::Block,
+            ),
+            new_template_processed: false,
+            ntime_limit: None,
+            max_target: None,
+            protocol_versions: (2, 2),
+            share_logger: Arc::new(PrintShareLogger::default()),
+            share_batch: None,
+            downstream_share_policy: DownstreamSharePolicy::Count,
+            max_channels_per_downstream: None,
+            invalid_share_limit: None,
+            allowed_message_types: None,
+            last_new_work: 0,
+            resync_sender: async_channel::bounded(1).0,
+            desync_events: 0,
+            parked: None,
+        }
+    }
+
+    fn test_downstream(
+        pool: &Pool,
+        id: u32,
+        receiver: Receiver<EitherFrame>,
+        sender: Sender<EitherFrame>,
+    ) -> Downstream {
+        let downstream_data = CommonDownstreamData {
+            header_only: false,
+            work_selection: false,
+            version_rolling: true,
+        };
+        Downstream {
+            id,
+            receiver,
+            sender,
+            downstream_data,
+            channel_ids: Id::new(),
+            extranonces: pool.extranonces.clone(),
+            jobs: HashMap::new(),
+            future_jobs: HashMap::new(),
+            prefixes: HashMap::new(),
+            last_prev_hash: None,
+            last_nbits: None,
+            last_valid_extended_job: None,
+            solution_sender: pool.solution_sender.clone(),
+            ntime_limit: None,
+            max_target: None,
+            user_identities: HashMap::new(),
+            share_logger: pool.share_logger.clone(),
+            share_batch: None,
+            share_policy: SharePolicy::new(DownstreamSharePolicy::Count),
+            max_channels: pool.max_channels_per_downstream,
+            invalid_shares: pool.invalid_share_limit.map(InvalidShareLimiter::new),
+            paused: false,
+            missed_prev_hash: None,
+            allowed_message_types: pool.allowed_message_types.clone(),
+            reconnect_token: None,
+            requested_data: downstream_data,
+            #[cfg(feature = "replay-protection")]
+            replay_guard: ReplayGuard::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_header_only() {
+        use crate::lib::test_utils::in_memory;
+        let mut pool = test_pool();
+        // The ids of the header only and of the group downstreams are allocated separately
+        let ((receiver, sender), _remote) = in_memory();
+        let group = test_downstream(&pool, 1, receiver, sender);
+        pool.group_downstreams
+            .insert(1, Arc::new(Mutex::new(group)));
+        let ((receiver, sender), (_from_pool, to_pool)) = in_memory();
+        let mut header_only = test_downstream(&pool, 1, receiver, sender);
+        header_only.downstream_data.header_only = true;
+        let header_only = Arc::new(Mutex::new(header_only));
+        pool.hom_downstreams.insert(1, header_only.clone());
+        let pool = Arc::new(Mutex::new(pool));
+        Downstream::spawn_receiver(header_only.clone(), pool.clone());
+
+        to_pool.close();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while pool.safe_lock(|p| !p.hom_downstreams.is_empty()).unwrap() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(pool
+            .safe_lock(|p| p.group_downstreams.contains_key(&1))
+            .unwrap());
+        // Removing a downstream that is already gone is not an error
+        pool.safe_lock(|p| p.remove_downstream(&header_only))
+            .unwrap();
+        assert_eq!(pool.safe_lock(|p| p.group_downstreams.len()).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_jobs() {
+        use crate::lib::test_utils::{frame_to_bytes, in_memory};
+        let (prefix, _, suffix) = coinbase_parts();
+        let job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: true,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let ((receiver, sender), (from_pool, _to_pool)) = in_memory();
+        let mut pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.last_valid_extended_job = Some((job.clone(), 3));
+        pool.group_downstreams
+            .insert(1, Arc::new(Mutex::new(downstream)));
+        pool.last_new_work = 100;
+        let pool = Arc::new(Mutex::new(pool));
+
+        // A template arrived 10 seconds ago
+        Pool::refresh_jobs(pool.clone(), 110, 30).await;
+        assert!(from_pool.is_empty());
+
+        Pool::refresh_jobs(pool.clone(), 130, 30).await;
+        let refresh = frame_to_bytes(from_pool.recv().await.unwrap()).unwrap();
+        let expected: StdFrame =
+            PoolMessages::Mining(Mining::NewExtendedMiningJob(NewExtendedMiningJob {
+                future_job: false,
+                ..job
+            }))
+            .into_frame()
+            .unwrap();
+        assert_eq!(Some(refresh), frame_to_bytes(expected.into()));
+    }
+
+    #[test]
+    fn test_validate_target_result_display() {
+        let hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let solution = SubmitSolution {
+            template_id: 1,
+            version: 0x2000_0000,
+            header_timestamp: 0,
+            header_nonce: 0,
+            coinbase_tx: vec![0; 10].try_into().unwrap(),
+        };
+        let results: [(ValidateTargetResult, String); 5] = [
+            (
+                VelideateTargetResult::LessThanBitcoinTarget(hash, 3, solution),
+                format!("block found: {} (share #3)", hash),
+            ),
+            (
+                VelideateTargetResult::LessThanDownstreamTarget(hash, 2),
+                format!("valid share: {} (share #2)", hash),
+            ),
+            (
+                VelideateTargetResult::Invalid(hash),
+                format!("invalid: {}", hash),
+            ),
+            (
+                VelideateTargetResult::RateLimited(hash),
+                format!("rate limited: {}", hash),
+            ),
+            (
+                VelideateTargetResult::NtimeTooFarInFuture(1000),
+                "ntime too far in future: 1000".to_string(),
+            ),
+        ];
+        for (result, expected) in results {
+            assert_eq!(result.to_string(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_future_jobs() {
+        let (prefix, _, suffix) = coinbase_parts();
+        let job = |channel_id| NewExtendedMiningJob {
+            channel_id,
+            job_id: channel_id + 10,
+            future_job: true,
+            version: 0x2000_0000,
+            version_rolling_allowed: channel_id % 2 == 0,
+            merkle_path: Seq0255::new(vec![[7; 32].into(); 12]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let pool = test_pool();
+        let mut jobs = Vec::new();
+        let mut remotes = Vec::new();
+        for id in 1..=1000 {
+            let ((receiver, sender), remote) = crate::lib::test_utils::in_memory();
+            let downstream = Arc::new(Mutex::new(test_downstream(&pool, id, receiver, sender)));
+            jobs.push((downstream, job(id)));
+            remotes.push(remote);
+        }
+        let downstreams: Vec<Arc<Mutex<Downstream>>> = jobs.iter().map(|j| j.0.clone()).collect();
+        let pool = Arc::new(Mutex::new(pool));
+
+        Pool::send_job_batch(pool, jobs, 3).await;
+        let shared = downstreams[0]
+            .safe_lock(|d| d.future_jobs[&11].shared.clone())
+            .unwrap();
+        // A single copy of the template fields for all the downstreams
+        assert_eq!(Arc::strong_count(&shared), 1001);
+        for (downstream, id) in downstreams.iter().zip(1..) {
+            let (future_job, template_id) = downstream
+                .safe_lock(|d| {
+                    let job = &d.future_jobs[&(id + 10)];
+                    (job.to_job(), job.template_id)
+                })
+                .unwrap();
+            let expected = job(id);
+            assert_eq!(template_id, 3);
+            assert_eq!(future_job.channel_id, id);
+            assert_eq!(future_job.job_id, id + 10);
+            assert_eq!(future_job.version_rolling_allowed, id % 2 == 0);
+            assert!(same_template_fields(&future_job, &expected));
+        }
+    }
+
+    #[test]
+    fn test_min_channel_difficulty() {
+        use roles_logic_sv2::mining_sv2::UpdateChannel;
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        let floor = max_target_from_difficulty(1).unwrap();
+        assert_eq!(floor, Uint256([0, 0, 0, 0x0000_0000_ffff_0000]));
+        assert_eq!(max_target_from_difficulty(0), None);
+        downstream.max_target = Some(to_u256(floor));
+        downstream
+            .jobs
+            .insert(1, Job::new(to_u256(Uint256::from_u64(1).unwrap()), vec![]));
+        let update = |downstream: &mut Downstream, channel_id, maximum_target: [u8; 32]| {
+            let message = UpdateChannel {
+                channel_id,
+                nominal_hash_rate: 1.0,
+                maximum_target: maximum_target.into(),
+            };
+            downstream.handle_update_channel(message).unwrap()
+        };
+        let applied_target = |downstream: &Downstream| match &downstream.jobs[&1] {
+            Job::Partial(job) => job.target.clone(),
+            Job::Complete(job) => job.target.clone(),
+        };
+
+        // An extremely easy target is clamped to the floor
+        match update(&mut downstream, 1, [0xff; 32]) {
+            SendTo::Respond(Mining::SetTarget(m)) => {
+                assert_eq!(m.channel_id, 1);
+                assert_eq!(
+                    m.maximum_target.to_vec(),
+                    message_handler::uint_256_to_u256(floor).to_vec()
+                );
+            }
+            _ => panic!("expected a SetTarget"),
+        }
+        assert_eq!(applied_target(&downstream), to_u256(floor));
+
+        // A target harder than the floor is kept
+        let hard = Uint256([0, 0, 0, 0x0000_0000_0000_ffff]);
+        let hard_bytes: [u8; 32] = message_handler::uint_256_to_u256(hard)
+            .to_vec()
+            .try_into()
+            .unwrap();
+        match update(&mut downstream, 1, hard_bytes) {
+            SendTo::Respond(Mining::SetTarget(m)) => {
+                assert_eq!(m.maximum_target.to_vec(), hard_bytes.to_vec());
+            }
+            _ => panic!("expected a SetTarget"),
+        }
+        assert_eq!(applied_target(&downstream), to_u256(hard));
+
+        assert!(matches!(
+            update(&mut downstream, 9, [0xff; 32]),
+            SendTo::Respond(Mining::UpdateChannelError(_))
+        ));
+    }
+
+    #[test]
+    fn test_message_type_not_allowed() {
+        use const_sv2::{
+            MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL, MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+            MESSAGE_TYPE_UPDATE_CHANNEL,
+        };
+        use roles_logic_sv2::{common_messages_sv2::Protocol, mining_sv2::UpdateChannel};
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let mut pool = test_pool();
+        pool.allowed_message_types = Some(MessageTypeAllowlist::new(vec![
+            (
+                Protocol::MiningProtocol,
+                MESSAGE_TYPE_OPEN_EXTENDED_MINING_CHANNEL,
+            ),
+            (
+                Protocol::MiningProtocol,
+                MESSAGE_TYPE_SUBMIT_SHARES_EXTENDED,
+            ),
+        ]));
+        let downstream = Arc::new(Mutex::new(test_downstream(&pool, 1, receiver, sender)));
+
+        // A valid mining message that is not in the allowlist
+        let message = UpdateChannel {
+            channel_id: 1,
+            nominal_hash_rate: 10.0,
+            maximum_target: [0xff; 32].into(),
+        };
+        let mut frame: StdFrame = PoolMessages::Mining(Mining::UpdateChannel(message))
+            .into_frame()
+            .unwrap();
+        let res = ParseDownstreamMiningMessages::handle_message_mining(
+            downstream,
+            MESSAGE_TYPE_UPDATE_CHANNEL,
+            frame.payload(),
+            MiningRoutingLogic::None,
+        );
+        assert!(matches!(
+            res,
+            Err(Error::WrongMessageType(MESSAGE_TYPE_UPDATE_CHANNEL))
+        ));
+    }
+
+    #[test]
+    fn test_max_channels_per_downstream() {
+        use roles_logic_sv2::mining_sv2::OpenStandardMiningChannel;
+        let ((receiver, sender), _) = crate::lib::test_utils::in_memory();
+        let mut pool = test_pool();
+        pool.max_channels_per_downstream = Some(2);
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        let open = |request_id: u32| OpenStandardMiningChannel {
+            request_id: request_id.into(),
+            user_identity: "user".to_string().try_into().unwrap(),
+            nominal_hash_rate: 1.0,
+            max_target: [0xff; 32].into(),
+        };
+        for request_id in 0..2 {
+            let response = downstream
+                .handle_open_standard_mining_channel(open(request_id), None)
+                .unwrap();
+            assert!(matches!(
+                response,
+                SendTo::Respond(Mining::OpenStandardMiningChannelSuccess(_))
+            ));
+        }
+        match downstream
+            .handle_open_standard_mining_channel(open(2), None)
+            .unwrap()
+        {
+            SendTo::Respond(Mining::OpenMiningChannelError(e)) => {
+                assert_eq!(e.request_id, 2);
+                assert_eq!(e.error_code.to_vec(), b"too-many-channels".to_vec());
+            }
+            _ => panic!("expected an OpenMiningChannelError"),
+        }
+    }
+
+    #[test]
+    fn test_header_hash() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let partial = PartialJob {
+            target: to_u256(Uint256::from_u64(1).unwrap()),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let mut job = partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3);
+
+        // Computed out of band from the serialized header
+        let hash = job
+            .header_hash(42, 0x2000_0000, 1_700_000_000, Some(&[1, 2, 3, 4]))
+            .unwrap();
+        assert_eq!(
+            hash.to_string(),
+            "e766b4fca0e1ebd54d52a40e2e5cd92945b7b84c254799483a0c2269e57c2613"
+        );
+
+        let hash = job
+            .header_hash(42, 0x2000_0000, 1_700_000_000, None)
+            .unwrap();
+        match job.validate_target(42, 0x2000_0000, 1_700_000_000, None, None) {
+            VelideateTargetResult::Invalid(validated) => assert_eq!(validated, hash),
+            res => panic!("expected an invalid share, got {}", res),
+        }
+        assert!(matches!(
+            job.header_hash(42, 0x2000_0000, 1_700_000_000, Some(&[0; 9])),
+            Err(Error::InvalidExtranonceSuffixLen((8, 9)))
+        ));
+    }
+
+    fn to_u256(v: Uint256) -> U256<'static> {
+        message_handler::uint_256_to_u256(v)
+    }
+
+    // Value of a hash, the bytes of a `BlockHash` are little endian
+    fn hash_to_uint_256(mut hash: [u8; 32]) -> Uint256 {
+        hash.reverse();
+        Uint256::from_be_bytes(hash)
+    }
+
+    #[test]
+    fn test_target_from_nbits() {
+        for nbits in [
+            0x1d00_ffff,
+            0x207f_ffff,
+            0x1703_a30c,
+            0x0300_ffff,
+            0x0201_2345,
+            0x0112_3456,
+            0x0000_0000,
+            0x2100_0001,
+            0x2200_ffff,
+            // Negative
+            0x1d80_ffff,
+            0x0480_0000,
+            0x0380_0000,
+            // The sign bit is shifted out
+            0x0280_1234,
+        ] {
+            assert_eq!(
+                target_from_nbits(nbits),
+                to_u256(BlockHeader::u256_from_compact_target(nbits)),
+                "nbits {:x}",
+                nbits
+            );
+        }
+    }
+
+    #[test]
+    fn test_difficulty_from_target() {
+        let difficulty_1_target = to_u256(Uint256([0, 0, 0, 0x0000_0000_ffff_0000]));
+        assert_eq!(difficulty_from_target(&difficulty_1_target), 1.0);
+        let target = to_u256(max_target_from_difficulty(1024).unwrap());
+        assert_eq!(difficulty_from_target(&target), 1024.0);
+    }
+
+    #[test]
+    fn test_validate_target_on_hash_boundary() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let partial = PartialJob {
+            target: to_u256(Uint256::from_u64(1).unwrap()),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let mut job = partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3);
+        let share_hash = |job: &CompleteJob, nonce| {
+            let hash = job.header_hash(nonce, 0x2000_0000, 1_700_000_000, None);
+            hash_to_uint_256(hash.unwrap().as_hash().into_inner())
+        };
+
+        for nonce in 0..10 {
+            let hash = share_hash(&job, nonce);
+            // A share whose hash is equal to the target is valid
+            job.target = to_u256(hash);
+            assert!(matches!(
+                job.validate_target(nonce, 0x2000_0000, 1_700_000_000, None, None),
+                VelideateTargetResult::LessThanDownstreamTarget(_, _)
+            ));
+            job.target = to_u256(hash - Uint256::from_u64(1).unwrap());
+            assert!(matches!(
+                job.validate_target(nonce, 0x2000_0000, 1_700_000_000, None, None),
+                VelideateTargetResult::Invalid(_)
+            ));
+        }
+        let best = (0..10).map(|nonce| share_hash(&job, nonce)).min().unwrap();
+        assert_eq!(job.best_hash.map(hash_to_uint_256), Some(best));
+    }
+
+    #[test]
+    fn test_known_shares() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        // Downstream target 0xc000..00
+        let partial = PartialJob {
+            target: to_u256(Uint256([0, 0, 0, 0xc000_0000_0000_0000])),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        // Bitcoin target 0x7fffff00..00
+        let mut job = partial.to_complete_standard_job(&new_ext_job, 0x207f_ffff, prev_hash, 3);
+
+        // Hashes computed out of band from the serialized headers
+        let shares = [
+            (
+                0,
+                "8901aee67366b81a3015c004f8b8ea1400d18c3042460b45053efe31df2d4eae",
+                "share",
+            ),
+            (
+                1,
+                "3bf0af18a5951953867f2d8fb2e294bcdf80dd4a360e4edb4b2ba884c82f9ff9",
+                "block",
+            ),
+            (
+                3,
+                "09f8276766b471a2d3de18eca2c93a28aba12e86705b0b73869d6206b7cb8207",
+                "block",
+            ),
+            (
+                6,
+                "af8565cd3fd847727613fa2e0de9d8cff452905576b2f0b1a8494a5cfeb969c7",
+                "share",
+            ),
+            (
+                10,
+                "dd2a71e3d8d800c7bb9d652f3f22491e8983abb05ad3517274006d1822d35a24",
+                "invalid",
+            ),
+        ];
+        for (nonce, expected_hash, expected) in shares {
+            let (hash, decision) =
+                match job.validate_target(nonce, 0x2000_0000, 1_700_000_000, None, None) {
+                    VelideateTargetResult::LessThanBitcoinTarget(hash, _, _) => (hash, "block"),
+                    VelideateTargetResult::LessThanDownstreamTarget(hash, _) => (hash, "share"),
+                    VelideateTargetResult::Invalid(hash) => (hash, "invalid"),
+                    res => panic!("unexpected result {}", res),
+                };
+            assert_eq!(hash.to_string(), expected_hash);
+            assert_eq!(decision, expected, "nonce {}", nonce);
+        }
+        let best = job
+            .best_hash
+            .map(|hash| BlockHash::from_hash(Hash::from_inner(hash)));
+        assert_eq!(
+            best.unwrap().to_string(),
+            "09f8276766b471a2d3de18eca2c93a28aba12e86705b0b73869d6206b7cb8207"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_best_hash_tracks_minimum() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let partial = PartialJob {
+            target: to_u256(Uint256([0, 0, 0, 0xc000_0000_0000_0000])),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let job = partial.to_complete_standard_job(&new_ext_job, 0x207f_ffff, prev_hash, 3);
+
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let mut pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.jobs.insert(1, Job::Complete(job));
+        let downstream = Arc::new(Mutex::new(downstream));
+        pool.group_downstreams.insert(1, downstream.clone());
+        #[cfg(feature = "admin")]
+        let pool = Arc::new(Mutex::new(pool));
+        // As big endian hex, the order of the `BlockHash` strings
+        let best_hash = || {
+            downstream
+                .safe_lock(|d| d.best_hash(1))
+                .unwrap()
+                .map(|hash| {
+                    hash.to_vec()
+                        .iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect::<String>()
+                })
+        };
+        assert_eq!(best_hash(), None);
+
+        // Shares of decreasing hash, the hashes are the ones of `test_known_shares`
+        let shares = [
+            (
+                10,
+                "dd2a71e3d8d800c7bb9d652f3f22491e8983abb05ad3517274006d1822d35a24",
+            ),
+            (
+                6,
+                "af8565cd3fd847727613fa2e0de9d8cff452905576b2f0b1a8494a5cfeb969c7",
+            ),
+            (
+                0,
+                "8901aee67366b81a3015c004f8b8ea1400d18c3042460b45053efe31df2d4eae",
+            ),
+            (
+                1,
+                "3bf0af18a5951953867f2d8fb2e294bcdf80dd4a360e4edb4b2ba884c82f9ff9",
+            ),
+            (
+                3,
+                "09f8276766b471a2d3de18eca2c93a28aba12e86705b0b73869d6206b7cb8207",
+            ),
+        ];
+        let submit = |nonce| {
+            downstream
+                .safe_lock(|d| d.check_target(1, 2, nonce, 0x2000_0000, 1_700_000_000, None))
+                .unwrap()
+                .unwrap();
+        };
+        for (nonce, hash) in shares {
+            submit(nonce);
+            assert_eq!(best_hash().unwrap(), hash);
+        }
+        // A higher hash do not change the best one
+        submit(8);
+        let best = "09f8276766b471a2d3de18eca2c93a28aba12e86705b0b73869d6206b7cb8207";
+        assert_eq!(best_hash().unwrap(), best);
+
+        #[cfg(feature = "admin")]
+        assert!(admin::handle_command(&pool, "channels 1")
+            .await
+            .contains(&format!("best_hash={}", best)));
+
+        // A new prev hash start a new round
+        let new_prev_hash = BlockHash::from_hash(Hash::from_inner([1; 32]));
+        downstream
+            .safe_lock(|d| {
+                d.jobs
+                    .get_mut(&1)
+                    .unwrap()
+                    .update_job(&new_ext_job, 0x207f_ffff, new_prev_hash, 4)
+            })
+            .unwrap();
+        assert_eq!(best_hash(), None);
+    }
+
+    // Keep the logged shares so that the tests can check them
+    #[derive(Debug, Default)]
+    struct RecordingShareLogger {
+        shares: Mutex<Vec<ShareRecord>>,
+    }
+
+    impl ShareLogger for RecordingShareLogger {
+        fn log_share(&self, share: ShareRecord) {
+            self.shares.safe_lock(|s| s.push(share)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_share_burst_in_one_flush() {
+        use roles_logic_sv2::mining_sv2::SubmitSharesStandard;
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        // Every hash meet this target and no hash meet the bitcoin target
+        let partial = PartialJob {
+            target: to_u256(Uint256([u64::MAX; 4])),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let job = partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3);
+
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let pool = test_pool();
+        let logger = Arc::new(RecordingShareLogger::default());
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.share_logger = logger.clone();
+        downstream.share_batch = Some(ShareBatch::new(5));
+        downstream.jobs.insert(1, Job::Complete(job));
+        let share = |sequence_number| SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number,
+            job_id: 2,
+            nonce: sequence_number,
+            ntime: 1_700_000_000,
+            version: 0x2000_0000,
+        };
+
+        // The shares are buffered until the batch is full
+        for sequence_number in 0..4 {
+            let res = downstream.handle_submit_shares_standard(share(sequence_number));
+            assert!(matches!(res, Ok(SendTo::None(None))));
+        }
+        assert!(logger.shares.safe_lock(|s| s.is_empty()).unwrap());
+
+        // Then they are all acked and logged in a single flush
+        match downstream.handle_submit_shares_standard(share(4)) {
+            Ok(SendTo::Multiple(acks)) => match &acks[..] {
+                [SendTo::Respond(Mining::SubmitSharesSuccess(ack))] => {
+                    assert_eq!(ack.channel_id, 1);
+                    assert_eq!(ack.last_sequence_number, 4);
+                    assert_eq!(ack.new_submits_accepted_count, 5);
+                    assert_eq!(ack.new_shares_sum, 5);
+                }
+                _ => panic!("expected one aggregate ack"),
+            },
+            _ => panic!("expected the flushed acks"),
+        }
+        let nonces = logger
+            .shares
+            .safe_lock(|s| s.iter().map(|r| r.nonce).collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(nonces, vec![0, 1, 2, 3, 4]);
+        assert!(downstream.flush_shares().is_empty());
+    }
+
+    #[test]
+    fn test_check_target_errors() {
+        let (prefix, _, suffix) = coinbase_parts();
+        let job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.jobs.insert(
+            1,
+            Job::new(to_u256(Uint256::from_u64(1).unwrap()), vec![0; 32]),
+        );
+        let check = |downstream: &mut Downstream, channel_id, expected: CheckTargetError| {
+            let res = downstream.check_target(channel_id, 2, 0, 0x2000_0000, 1_700_000_000, None);
+            assert_eq!(res.err(), Some(expected));
+            let share = SubmitSharesStandard {
+                channel_id,
+                sequence_number: 7,
+                job_id: 2,
+                nonce: 0,
+                ntime: 1_700_000_000,
+                version: 0x2000_0000,
+            };
+            match downstream.handle_submit_shares_standard(share) {
+                Ok(SendTo::Respond(Mining::SubmitSharesError(e))) => {
+                    assert_eq!(e.channel_id, channel_id);
+                    assert_eq!(e.sequence_number, 7);
+                    assert_eq!(e.error_code.to_vec(), expected.error_code().as_bytes());
+                }
+                _ => panic!("expected a SubmitSharesError"),
+            }
+        };
+
+        // The channel is open but no job has been sent yet
+        check(&mut downstream, 1, CheckTargetError::NoJobForChannel);
+        // The job has been sent and invalidated by a block, the pool wait for a prev hash
+        downstream.last_valid_extended_job = Some((job, 3));
+        check(&mut downstream, 1, CheckTargetError::JobNotComplete);
+        check(&mut downstream, 9, CheckTargetError::UnknownChannel);
+        let codes = [
+            CheckTargetError::NoJobForChannel.error_code(),
+            CheckTargetError::JobNotComplete.error_code(),
+            CheckTargetError::UnknownChannel.error_code(),
+        ];
+        assert_eq!(
+            codes,
+            ["invalid-job-id", "stale-share", "invalid-channel-id"]
+        );
+    }
+
+    #[test]
+    fn test_ntime_limit() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        // Every hash meet this target so only the ntime can make a share invalid
+        let partial = PartialJob {
+            target: to_u256(Uint256([u64::MAX; 4])),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let job = partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3);
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.jobs.insert(1, Job::Complete(job));
+        let now = 1_700_000_000;
+        downstream.ntime_limit = Some(NtimeLimit {
+            max_drift: 7200,
+            clock: || 1_700_000_000,
+        });
+
+        // Within the drift
+        for (nonce, ntime) in [(0, now), (1, now + 7200)] {
+            let res = downstream.check_target(1, 2, nonce, 0x2000_0000, ntime, None);
+            assert!(matches!(
+                res,
+                Ok(VelideateTargetResult::LessThanDownstreamTarget(_, _))
+            ));
+        }
+        // In the future
+        for (nonce, ntime) in [(2, now + 7201), (3, u32::MAX)] {
+            let res = downstream.check_target(1, 2, nonce, 0x2000_0000, ntime, None);
+            assert!(matches!(
+                res,
+                Ok(VelideateTargetResult::NtimeTooFarInFuture(t)) if t == ntime
+            ));
+        }
+        let share = SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 4,
+            job_id: 2,
+            nonce: 4,
+            ntime: now + 7201,
+            version: 0x2000_0000,
+        };
+        match downstream.handle_submit_shares_standard(share) {
+            Ok(SendTo::Respond(Mining::SubmitSharesError(e))) => {
+                assert_eq!(e.error_code.to_vec(), b"invalid-timestamp".to_vec());
+            }
+            _ => panic!("expected a SubmitSharesError"),
+        }
+        // Without limit any ntime is accepted
+        downstream.ntime_limit = None;
+        let res = downstream.check_target(1, 2, 5, 0x2000_0000, u32::MAX, None);
+        assert!(matches!(
+            res,
+            Ok(VelideateTargetResult::LessThanDownstreamTarget(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_share_limit() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        // No hash meet this target so every share is invalid
+        let partial = PartialJob {
+            target: to_u256(Uint256::from_u64(1).unwrap()),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let job = partial.to_complete_standard_job(&new_ext_job, 0x1d00_ffff, prev_hash, 3);
+
+        // The other end is kept alive, dropping it would close the channels
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let mut pool = test_pool();
+        pool.invalid_share_limit = Some(InvalidShareLimitConfig {
+            max_invalid_shares: 3,
+            window_sec: 60,
+        });
+        let mut downstream = test_downstream(&pool, 1, receiver.clone(), sender);
+        downstream.invalid_shares.as_mut().unwrap().clock = || 100;
+        downstream.jobs.insert(1, Job::Complete(job));
+
+        for nonce in 0..3 {
+            let res = downstream.check_target(1, 2, nonce, 0x2000_0000, 0, None);
+            assert!(matches!(res, Ok(VelideateTargetResult::Invalid(_))));
+            assert!(!receiver.is_closed());
+        }
+        let res = downstream.check_target(1, 2, 3, 0x2000_0000, 0, None);
+        assert!(matches!(res, Ok(VelideateTargetResult::Invalid(_))));
+        assert!(receiver.is_closed());
+    }
+
+    #[cfg(feature = "admin")]
+    #[tokio::test]
+    async fn test_pause_resume_downstream() {
+        use crate::lib::test_utils::{frame_to_bytes, in_memory};
+        let (prefix, _, suffix) = coinbase_parts();
+        let job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: true,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let ((receiver, sender), (from_pool, _to_pool)) = in_memory();
+        let mut pool = test_pool();
+        let downstream = Arc::new(Mutex::new(test_downstream(&pool, 1, receiver, sender)));
+        pool.group_downstreams.insert(1, downstream.clone());
+        let pool = Arc::new(Mutex::new(pool));
+
+        let response = admin::handle_command(&pool, "pause 7").await;
+        assert_eq!(response, "error=unknown-downstream id=7\n");
+        let response = admin::handle_command(&pool, "pause 1").await;
+        assert_eq!(response, "paused=1\n");
+        Downstream::on_new_extended_job(downstream.clone(), job.clone(), vec![], 3)
+            .await
+            .unwrap();
+        assert!(from_pool.is_empty());
+        // The job is kept to be sent on resume
+        assert_eq!(
+            downstream
+                .safe_lock(|d| d.future_jobs.keys().copied().collect::<Vec<_>>())
+                .unwrap(),
+            vec![2]
+        );
+
+        let response = admin::handle_command(&pool, "resume 1").await;
+        assert_eq!(response, "resumed=1\n");
+        let delivered = frame_to_bytes(from_pool.recv().await.unwrap()).unwrap();
+        let expected: StdFrame = PoolMessages::Mining(Mining::NewExtendedMiningJob(job))
+            .into_frame()
+            .unwrap();
+        assert_eq!(Some(delivered), frame_to_bytes(expected.into()));
+        assert!(from_pool.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_header_only_get_new_mining_job() {
+        use crate::lib::test_utils::{frame_to_bytes, in_memory};
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let ((receiver, sender), (from_pool, _to_pool)) = in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.downstream_data.header_only = true;
+        downstream.last_prev_hash = Some(BlockHash::from_hash(Hash::from_inner([0; 32])));
+        downstream.last_nbits = Some(0x1d00_ffff);
+        let downstream = Arc::new(Mutex::new(downstream));
+
+        // No channel yet, nothing to mine
+        Downstream::on_new_extended_job(downstream.clone(), job.clone(), vec![], 3)
+            .await
+            .unwrap();
+        assert!(from_pool.is_empty());
+
+        // The standard channel of the downstream get the job with its own merkle root
+        downstream
+            .safe_lock(|d| {
+                let channel = Job::new(to_u256(Uint256::from_u64(1).unwrap()), extranonce.clone());
+                d.jobs.insert(1, channel);
+            })
+            .unwrap();
+        Downstream::on_new_extended_job(downstream.clone(), job, vec![], 3)
+            .await
+            .unwrap();
+        let delivered = frame_to_bytes(from_pool.recv().await.unwrap()).unwrap();
+        let merkle_root: [u8; 32] =
+            merkle_root_from_path(&prefix, &suffix, &extranonce, &Vec::<Vec<u8>>::new())
+                .unwrap()
+                .try_into()
+                .unwrap();
+        let expected: StdFrame = PoolMessages::Mining(Mining::NewMiningJob(NewMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            merkle_root: merkle_root.into(),
+        }))
+        .into_frame()
+        .unwrap();
+        assert_eq!(Some(delivered), frame_to_bytes(expected.into()));
+        assert!(from_pool.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_job_batch_failed_downstream() {
+        use crate::lib::test_utils::{frame_to_bytes, in_memory};
+        let (prefix, _, suffix) = coinbase_parts();
+        let job = |channel_id| NewExtendedMiningJob {
+            channel_id,
+            job_id: 2,
+            future_job: true,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let mut pool = test_pool();
+        let mut jobs = Vec::new();
+        let mut remotes = Vec::new();
+        for id in 1..=3 {
+            let ((receiver, sender), remote) = in_memory();
+            let downstream = Arc::new(Mutex::new(test_downstream(&pool, id, receiver, sender)));
+            pool.group_downstreams.insert(id, downstream.clone());
+            jobs.push((downstream, job(id)));
+            remotes.push(remote);
+        }
+        // The second downstream of the batch disconnected
+        remotes[1].0.close();
+        let pool = Arc::new(Mutex::new(pool));
+
+        Pool::send_job_batch(pool.clone(), jobs, 3).await;
+        for id in [1, 3] {
+            let (from_pool, _) = &remotes[id as usize - 1];
+            let delivered = frame_to_bytes(from_pool.recv().await.unwrap()).unwrap();
+            let expected: StdFrame = PoolMessages::Mining(Mining::NewExtendedMiningJob(job(id)))
+                .into_frame()
+                .unwrap();
+            assert_eq!(Some(delivered), frame_to_bytes(expected.into()));
+        }
+        let mut ids: Vec<u32> = pool
+            .safe_lock(|p| p.group_downstreams.keys().copied().collect())
+            .unwrap();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_insert_prefix_wrong_len() {
+        use crate::lib::test_utils::in_memory;
+        let ((receiver, sender), _remote) = in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        assert!(matches!(
+            downstream.insert_prefix(1, vec![0; 15]),
+            Err(Error::InvalidExtranoncePrefixLen((16, 15)))
+        ));
+        assert!(downstream.prefixes.is_empty());
+        downstream.insert_prefix(1, vec![0; 16]).unwrap();
+        assert_eq!(downstream.prefixes[&1], vec![0; 16]);
+    }
+
+    #[test]
+    fn test_open_extended_channel_wrong_prefix_len() {
+        use roles_logic_sv2::mining_sv2::OpenExtendedMiningChannel;
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        // Prefixes of 8 bytes, the downstream would need to roll 24 bytes
+        downstream.extranonces = Arc::new(Mutex::new(ExtendedExtranonce::new(0..0, 0..8, 8..32)));
+        let open = OpenExtendedMiningChannel {
+            request_id: 1_u32.into(),
+            user_identity: "user".to_string().try_into().unwrap(),
+            nominal_hash_rate: 1.0,
+            max_target: [0xff; 32].into(),
+            min_extranonce_size: 8,
+        };
+        assert!(matches!(
+            downstream.handle_open_extended_mining_channel(open),
+            Err(Error::InvalidExtranoncePrefixLen((16, 8)))
+        ));
+        // Nothing is allocated for the refused channel
+        assert!(downstream.prefixes.is_empty());
+        assert!(downstream.user_identities.is_empty());
+        assert!(downstream.jobs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_prefix_len_closes_connection() {
+        use crate::lib::test_utils::in_memory;
+        use roles_logic_sv2::mining_sv2::OpenExtendedMiningChannel;
+        let ((receiver, sender), (from_pool, to_pool)) = in_memory();
+        let mut pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.extranonces = Arc::new(Mutex::new(ExtendedExtranonce::new(0..0, 0..8, 8..32)));
+        let downstream = Arc::new(Mutex::new(downstream));
+        pool.group_downstreams.insert(1, downstream.clone());
+        let pool = Arc::new(Mutex::new(pool));
+        Downstream::spawn_receiver(downstream, pool.clone());
+
+        let open: StdFrame = PoolMessages::Mining(Mining::OpenExtendedMiningChannel(
+            OpenExtendedMiningChannel {
+                request_id: 1_u32.into(),
+                user_identity: "user".to_string().try_into().unwrap(),
+                nominal_hash_rate: 1.0,
+                max_target: [0xff; 32].into(),
+                min_extranonce_size: 8,
+            },
+        ))
+        .into_frame()
+        .unwrap();
+        to_pool.send(open.into()).await.unwrap();
+
+        // The pool does not panic, it closes the connection and removes the downstream
+        assert!(from_pool.recv().await.is_err());
+        while pool
+            .safe_lock(|p| p.group_downstreams.contains_key(&1))
+            .unwrap()
+        {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    #[tokio::test]
+    async fn test_admin_stats() {
+        use crate::lib::test_utils::in_memory;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        let ((receiver, sender), _remote) = in_memory();
+        let mut pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream
+            .jobs
+            .insert(3, Job::new(to_u256(Uint256::from_u64(1).unwrap()), vec![]));
+        pool.group_downstreams
+            .insert(1, Arc::new(Mutex::new(downstream)));
+        let pool = Arc::new(Mutex::new(pool));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        task::spawn(admin::serve(listener, pool));
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"stats\nchannels 1\n").await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+
+        let stats = lines.next_line().await.unwrap().unwrap();
+        let stats: HashMap<&str, u64> = stats
+            .split(' ')
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap();
+                (key, value.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(stats["downstreams"], 1);
+        assert_eq!(stats["channels"], 1);
+        assert_eq!(stats["future_jobs"], 0);
+        // The response is terminated by an empty line
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "channel=3 round_shares=0 total_shares=0"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_share_counters_round_reset() {
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = |job_id, future_job| NewExtendedMiningJob {
+            channel_id: 1,
+            job_id,
+            future_job,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let partial = PartialJob {
+            target: to_u256(Uint256([0, 0, 0, 0xc000_0000_0000_0000])),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let job =
+            partial.to_complete_standard_job(&new_ext_job(2, false), 0x207f_ffff, prev_hash, 3);
+
+        let ((receiver, sender), _remote) = crate::lib::test_utils::in_memory();
+        let mut pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.jobs.insert(1, Job::Complete(job));
+        let downstream = Arc::new(Mutex::new(downstream));
+        pool.group_downstreams.insert(1, downstream.clone());
+        #[cfg(feature = "admin")]
+        let pool = Arc::new(Mutex::new(pool));
+        let counters = || {
+            downstream
+                .safe_lock(|d| (d.round_share_count(1), d.total_share_count(1)))
+                .unwrap()
+        };
+
+        // Shares that do not find a block, see `test_known_shares`
+        for nonce in [0, 6, 8] {
+            downstream
+                .safe_lock(|d| d.check_target(1, 2, nonce, 0x2000_0000, 1_700_000_000, None))
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(counters(), (Some(3), Some(3)));
+        #[cfg(feature = "admin")]
+        assert!(admin::handle_command(&pool, "channels 1")
+            .await
+            .starts_with("channel=1 round_shares=3 total_shares=3"));
+
+        // A new template on the same prev hash do not reset the round
+        downstream
+            .safe_lock(|d| {
+                d.jobs.get_mut(&1).unwrap().update_job(
+                    &new_ext_job(3, false),
+                    0x207f_ffff,
+                    prev_hash,
+                    4,
+                )
+            })
+            .unwrap();
+        assert_eq!(counters(), (Some(3), Some(3)));
+
+        // A new prev hash start a new round, the total is kept
+        downstream
+            .safe_lock(|d| {
+                let mut shared = None;
+                let job = FutureJob::new(&new_ext_job(4, true), 5, &mut shared);
+                d.future_jobs.insert(4, job);
+                d.on_new_prev_hash_sync(&NewPrevHash {
+                    channel_id: 1,
+                    job_id: 4,
+                    prev_hash: [1; 32].into(),
+                    min_ntime: 0,
+                    nbits: 0x207f_ffff,
+                })
+                .unwrap();
+            })
+            .unwrap();
+        assert_eq!(counters(), (Some(0), Some(3)));
+        let unknown = downstream
+            .safe_lock(|d| (d.round_share_count(9), d.total_share_count(9)))
+            .unwrap();
+        assert_eq!(unknown, (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_future_job() {
+        use crate::lib::test_utils::in_memory;
+        let (prefix, _, suffix) = coinbase_parts();
+        let future_job = |channel_id, job_id| NewExtendedMiningJob {
+            channel_id,
+            job_id,
+            future_job: true,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let mut pool = test_pool();
+        let mut remotes = vec![];
+        for id in 1..3 {
+            let ((receiver, sender), remote) = in_memory();
+            remotes.push(remote);
+            let mut downstream = test_downstream(&pool, id, receiver, sender);
+            let mut shared = None;
+            for (job_id, template_id) in [(5, 10), (6, 11)] {
+                let job = FutureJob::new(&future_job(id, job_id), template_id, &mut shared);
+                downstream.future_jobs.insert(job_id, job);
+            }
+            pool.group_downstreams
+                .insert(id, Arc::new(Mutex::new(downstream)));
+        }
+        let pool = Arc::new(Mutex::new(pool));
+
+        let cancel = |job_id| pool.safe_lock(|p| p.cancel_future_job(job_id)).unwrap();
+        assert_eq!(cancel(5), 2);
+        assert_eq!(cancel(5), 0);
+        #[cfg(feature = "admin")]
+        assert_eq!(
+            admin::handle_command(&pool, "cancel 5").await,
+            "cancelled=0\n"
+        );
+        for id in 1..3 {
+            let downstream = pool.safe_lock(|p| p.get_downstream(id)).unwrap().unwrap();
+            downstream
+                .safe_lock(|d| {
+                    assert_eq!(d.future_job_ids(), vec![6]);
+                    assert_eq!(d.cancel_future_job(6), Some(11));
+                    assert_eq!(d.cancel_future_job(6), None);
+                    assert!(d.future_job_ids().is_empty());
+                })
+                .unwrap();
+        }
+    }
+
+    #[cfg(feature = "admin")]
+    #[tokio::test]
+    async fn test_retarget_all() {
+        use crate::lib::test_utils::{frame_to_bytes, in_memory};
+        let ((receiver, sender), (from_pool, _to_pool)) = in_memory();
+        let mut pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        for channel_id in [2, 3] {
+            downstream.jobs.insert(
+                channel_id,
+                Job::new(to_u256(Uint256::from_u64(1).unwrap()), vec![]),
+            );
+        }
+        pool.group_downstreams
+            .insert(1, Arc::new(Mutex::new(downstream)));
+        let pool = Arc::new(Mutex::new(pool));
+
+        let hex = "00000000ffff0000000000000000000000000000000000000000000000000000";
+        let response = admin::handle_command(&pool, &format!("retarget 1 {}", hex)).await;
+        assert_eq!(response, "retargeted=2\n");
+
+        let target = Uint256([0, 0, 0, 0x0000_0000_ffff_0000]);
+        for channel_id in [2, 3] {
+            let set_target = frame_to_bytes(from_pool.recv().await.unwrap()).unwrap();
+            let expected: StdFrame = PoolMessages::Mining(Mining::SetTarget(SetTarget {
+                channel_id,
+                maximum_target: message_handler::uint_256_to_u256(target),
+            }))
+            .into_frame()
+            .unwrap();
+            assert_eq!(Some(set_target), frame_to_bytes(expected.into()));
+        }
+        assert!(from_pool.is_empty());
+        let downstream = pool.safe_lock(|p| p.get_downstream(1)).unwrap().unwrap();
+        downstream
+            .safe_lock(|d| {
+                for job in d.jobs.values() {
+                    assert!(matches!(job, Job::Partial(p) if p.target == to_u256(target)));
+                }
+            })
+            .unwrap();
+
+        let response = admin::handle_command(&pool, "retarget 1 ffff").await;
+        assert_eq!(response, "error=invalid-target target=\"ffff\"\n");
+        let response = admin::handle_command(&pool, &format!("retarget 9 {}", hex)).await;
+        assert_eq!(response, "error=unknown-downstream id=9\n");
+    }
+
+    #[tokio::test]
+    async fn test_prev_hash_for_unknown_template() {
+        use crate::lib::test_utils::in_memory;
+        let ((receiver, sender), (_from_pool, _to_pool)) = in_memory();
+        let (resync_sender, resync_receiver) = async_channel::bounded(1);
+        let mut pool = test_pool();
+        pool.resync_sender = resync_sender;
+        pool.new_template_processed = true;
+        let downstream = test_downstream(&pool, 1, receiver, sender);
+        pool.group_downstreams
+            .insert(1, Arc::new(Mutex::new(downstream)));
+        let pool = Arc::new(Mutex::new(pool));
+
+        let (prev_hash_sender, prev_hash_receiver) = async_channel::bounded(1);
+        prev_hash_sender
+            .send(SetNewPrevHash {
+                template_id: 99,
+                prev_hash: [1; 32].into(),
+                header_timestamp: 1_700_000_000,
+                n_bits: 0x1d00ffff,
+                target: [0xff; 32].into(),
+            })
+            .await
+            .unwrap();
+        drop(prev_hash_sender);
+        Pool::on_new_prev_hash(pool.clone(), prev_hash_receiver).await;
+
+        assert!(resync_receiver.try_recv().is_ok());
+        assert_eq!(pool.safe_lock(|p| p.desync_events()).unwrap(), 1);
+        assert!(pool.safe_lock(|p| p.last_new_prev_hash.is_none()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_with_token() {
+        use crate::lib::test_utils::{frame_to_bytes, in_memory};
+        let (prefix, _, suffix) = coinbase_parts();
+        let job = NewExtendedMiningJob {
+            channel_id: 1,
+            job_id: 2,
+            future_job: false,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.try_into().unwrap(),
+            coinbase_tx_suffix: suffix.try_into().unwrap(),
+        };
+        let ((receiver, sender), (_from_pool, to_pool)) = in_memory();
+        let mut pool = test_pool();
+        pool.parked = Some(ParkedDownstreams::new(60));
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.reconnect_token = Some("token".to_string());
+        downstream.jobs.insert(
+            3,
+            Job::new(to_u256(Uint256::from_u64(1).unwrap()), vec![0; 32]),
+        );
+        downstream.last_valid_extended_job = Some((job.clone(), 4));
+        let downstream = Arc::new(Mutex::new(downstream));
+        pool.group_downstreams.insert(1, downstream.clone());
+        let pool = Arc::new(Mutex::new(pool));
+        Downstream::spawn_receiver(downstream.clone(), pool.clone());
+
+        // The downstream disconnect
+        to_pool.close();
+        while !downstream.safe_lock(|d| d.paused).unwrap() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(pool.safe_lock(|p| p.get_downstream(1).is_some()).unwrap());
+        assert!(pool
+            .safe_lock(|p| p.reclaim_parked("other").is_none())
+            .unwrap());
+
+        // and reconnect with the same token
+        let ((receiver, sender), (from_pool, _to_pool)) = in_memory();
+        let reclaimed = pool
+            .safe_lock(|p| p.reclaim_parked("token"))
+            .unwrap()
+            .unwrap();
+        assert!(Arc::ptr_eq(&reclaimed, &downstream));
+        Downstream::reconnect(reclaimed, receiver, sender, pool.clone()).await;
+
+        let (id, channels, paused) = downstream
+            .safe_lock(|d| (d.id, d.jobs.keys().copied().collect::<Vec<u32>>(), d.paused))
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(channels, vec![3]);
+        assert!(!paused);
+        // The current job is sent on the new connection
+        let delivered = frame_to_bytes(from_pool.recv().await.unwrap()).unwrap();
+        let expected: StdFrame = PoolMessages::Mining(Mining::NewExtendedMiningJob(job))
+            .into_frame()
+            .unwrap();
+        assert_eq!(Some(delivered), frame_to_bytes(expected.into()));
+        // The token can be used only once
+        assert!(pool
+            .safe_lock(|p| p.reclaim_parked("token"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_with_other_flags() {
+        use crate::lib::test_utils::in_memory;
+        use roles_logic_sv2::common_messages_sv2::{Protocol, SetupConnection};
+        let ((receiver, sender), (_from_pool, to_pool)) = in_memory();
+        let mut pool = test_pool();
+        pool.parked = Some(ParkedDownstreams::new(60));
+        // Asked for version rolling
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.reconnect_token = Some("token".to_string());
+        let downstream = Arc::new(Mutex::new(downstream));
+        pool.group_downstreams.insert(1, downstream.clone());
+        let pool = Arc::new(Mutex::new(pool));
+        Downstream::spawn_receiver(downstream.clone(), pool.clone());
+        to_pool.close();
+        while !downstream.safe_lock(|d| d.paused).unwrap() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // Reconnect with the token but without version rolling
+        let setup_connection = SetupConnection {
+            protocol: Protocol::MiningProtocol,
+            min_version: 2,
+            max_version: 2,
+            flags: 0,
+            endpoint_host: "0.0.0.0".to_string().try_into().unwrap(),
+            endpoint_port: 34254,
+            vendor: "vendor".to_string().try_into().unwrap(),
+            hardware_version: String::new().try_into().unwrap(),
+            firmware: String::new().try_into().unwrap(),
+            device_id: "reconnect:token".to_string().try_into().unwrap(),
+        };
+        let sv2_frame: StdFrame = PoolMessages::Common(setup_connection.into())
+            .into_frame()
+            .unwrap();
+        let ((pool_receiver, pool_sender), (from_pool, to_pool)) = in_memory();
+        to_pool.send(sv2_frame.into()).await.unwrap();
+        let refused = Pool::on_new_downstream(pool.clone(), pool_receiver, pool_sender).await;
+
+        assert!(matches!(refused, Err(Error::ReconnectDataMismatch)));
+        // The setup answer is followed by the closing of the connection
+        from_pool.recv().await.unwrap();
+        assert!(from_pool.recv().await.is_err());
+        // The parked downstream can still be reclaimed with the right flags
+        assert!(downstream.safe_lock(|d| d.paused).unwrap());
+        let reclaimed = pool
+            .safe_lock(|p| p.reclaim_parked("token"))
+            .unwrap()
+            .unwrap();
+        assert!(Arc::ptr_eq(&reclaimed, &downstream));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore() {
+        use crate::lib::test_utils::in_memory;
+        let (prefix, extranonce, suffix) = coinbase_parts();
+        let new_ext_job = |job_id, future_job| NewExtendedMiningJob {
+            channel_id: 1,
+            job_id,
+            future_job,
+            version: 0x2000_0000,
+            version_rolling_allowed: true,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+            coinbase_tx_prefix: prefix.clone().try_into().unwrap(),
+            coinbase_tx_suffix: suffix.clone().try_into().unwrap(),
+        };
+        let partial = PartialJob {
+            target: to_u256(Uint256([0, 0, 0, 0xc000_0000_0000_0000])),
+            extranonce,
+            total_shares_sum: 0,
+        };
+        let prev_hash = BlockHash::from_hash(Hash::from_inner([0; 32]));
+        let job =
+            partial.to_complete_standard_job(&new_ext_job(2, false), 0x207f_ffff, prev_hash, 3);
+
+        let ((receiver, sender), _remote) = in_memory();
+        let pool = test_pool();
+        let mut downstream = test_downstream(&pool, 1, receiver, sender);
+        downstream.jobs.insert(1, Job::Complete(job));
+        downstream.jobs.insert(2, Job::Partial(partial));
+        let mut shared = None;
+        let future_job = FutureJob::new(&new_ext_job(4, true), 5, &mut shared);
+        downstream.future_jobs.insert(4, future_job);
+        downstream.last_prev_hash = Some(prev_hash);
+        downstream.last_nbits = Some(0x207f_ffff);
+        downstream.last_valid_extended_job = Some((new_ext_job(2, false), 3));
+        let snapshot = downstream.snapshot();
+
+        let ((receiver, sender), _remote) = in_memory();
+        let extranonces = pool.extranonces.clone();
+        let solution_sender = pool.solution_sender.clone();
+        let pool = Arc::new(Mutex::new(pool));
+        let restored = Downstream::restore(
+            snapshot,
+            receiver,
+            sender,
+            extranonces,
+            solution_sender,
+            pool,
+        );
+
+        restored
+            .safe_lock(|r| {
+                let mut channels: Vec<u32> = r.jobs.keys().copied().collect();
+                channels.sort_unstable();
+                assert_eq!(channels, vec![1, 2]);
+                assert!(matches!(r.jobs[&2], Job::Partial(_)));
+                let future_job = &r.future_jobs[&4];
+                assert_eq!((future_job.to_job().job_id, future_job.template_id), (4, 5));
+                assert_eq!(r.last_prev_hash, Some(prev_hash));
+                assert_eq!(r.last_nbits, Some(0x207f_ffff));
+                let last_job = r.last_valid_extended_job.as_ref();
+                assert_eq!(last_job.map(|(job, t)| (job.job_id, *t)), Some((2, 3)));
+            })
+            .unwrap();
+        // The shares are checked against the same jobs, see `test_known_shares`
+        for (channel_id, nonce) in [(1, 0), (1, 6), (1, 10), (2, 0), (9, 0)] {
+            let original =
+                downstream.check_target(channel_id, 2, nonce, 0x2000_0000, 1_700_000_000, None);
+            let from_snapshot = restored
+                .safe_lock(|r| {
+                    r.check_target(channel_id, 2, nonce, 0x2000_0000, 1_700_000_000, None)
+                })
+                .unwrap();
+            assert_eq!(format!("{:?}", original), format!("{:?}", from_snapshot));
+        }
+    }
+
+    // Pool that receive the templates and the prev hashes on the returned channels
+    fn session_pool() -> (
+        Arc<Mutex<Pool>>,
+        Sender<(NewTemplate<'static>, Option<TemplateWitness>)>,
+        Sender<SetNewPrevHash<'static>>,
+    ) {
+        let pool = Arc::new(Mutex::new(test_pool()));
+        let (new_template_sender, new_template_receiver) = async_channel::bounded(1);
+        let (new_prev_hash_sender, new_prev_hash_receiver) = async_channel::bounded(1);
+        task::spawn(Pool::on_new_template(pool.clone(), new_template_receiver));
+        task::spawn(Pool::on_new_prev_hash(pool.clone(), new_prev_hash_receiver));
+        (pool, new_template_sender, new_prev_hash_sender)
+    }
+
+    fn session_template() -> NewTemplate<'static> {
+        NewTemplate {
+            template_id: 1,
+            future_template: true,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 3, 0x10, 0x27, 0x00].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+        }
+    }
+
+    fn session_prev_hash() -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            template_id: 1,
+            prev_hash: [1; 32].into(),
+            header_timestamp: 1_700_000_000,
+            n_bits: 0x1d00ffff,
+            target: [0xff; 32].into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prev_hash_broadcast_failed_downstream() {
+        use crate::lib::test_utils::in_memory;
+        let mut pool = test_pool();
+        let mut remotes = Vec::new();
+        for id in 1..=3 {
+            let ((receiver, sender), remote) = in_memory();
+            let downstream = test_downstream(&pool, id, receiver, sender);
+            pool.group_downstreams
+                .insert(id, Arc::new(Mutex::new(downstream)));
+            pool.job_creators
+                .safe_lock(|j| j.new_group_channel(id, true).unwrap())
+                .unwrap();
+            remotes.push(remote);
+        }
+        let pool = Arc::new(Mutex::new(pool));
+        let (new_template_sender, new_template_receiver) = async_channel::bounded(1);
+        let (new_prev_hash_sender, new_prev_hash_receiver) = async_channel::bounded(1);
+        task::spawn(Pool::on_new_template(pool.clone(), new_template_receiver));
+        task::spawn(Pool::on_new_prev_hash(pool.clone(), new_prev_hash_receiver));
+        let next_message_type = |from_pool: &Receiver<EitherFrame>| {
+            let from_pool = from_pool.clone();
+            async move {
+                let mut frame: StdFrame =
+                    tokio::time::timeout(Duration::from_secs(1), from_pool.recv())
+                        .await
+                        .unwrap()
+                        .unwrap()
+                        .try_into()
+                        .unwrap();
+                frame.get_header().unwrap().msg_type()
+            }
+        };
+
+        new_template_sender
+            .send((session_template(), None))
+            .await
+            .unwrap();
+        for (from_pool, _) in &remotes {
+            assert_eq!(
+                next_message_type(from_pool).await,
+                MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB
+            );
+        }
+        // The second downstream disconnect before the prev hash
+        remotes[1].0.close();
+        new_prev_hash_sender
+            .send(session_prev_hash())
+            .await
+            .unwrap();
+        for id in [1, 3] {
+            let (from_pool, _) = &remotes[id - 1];
+            assert_eq!(
+                next_message_type(from_pool).await,
+                MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH
+            );
+        }
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while pool.safe_lock(|p| p.group_downstreams.len()).unwrap() != 2 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!pool
+            .safe_lock(|p| p.group_downstreams.contains_key(&2))
+            .unwrap());
+    }
+
+    // Record a session (setup, open channel, job, share) served by the pool, then replay it
+    // against a new pool that must send the same frames
+    #[tokio::test]
+    async fn test_record_replay_session() {
+        use crate::lib::test_utils::{in_memory, load, replay, save, Direction, Recorder};
+        use const_sv2::{
+            MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS,
+            MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS, MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS,
+        };
+        use roles_logic_sv2::mining_sv2::{OpenStandardMiningChannel, SubmitSharesStandard};
+        let msg_type = |frame: StdFrame| frame.get_header().unwrap().msg_type();
+        let setup_session = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/setup_session.txt");
+        let setup_connection = load(setup_session).unwrap().remove(0);
+        assert_eq!(setup_connection.0, Direction::ToPool);
+
+        let (pool, new_template_sender, new_prev_hash_sender) = session_pool();
+        let ((pool_receiver, pool_sender), (receiver, sender)) = in_memory();
+        let setup = task::spawn(Pool::on_new_downstream(
+            pool.clone(),
+            pool_receiver,
+            pool_sender,
+        ));
+        let mut recorder = Recorder::new(receiver, sender);
+
+        recorder
+            .send(StdFrame::from_bytes(setup_connection.1).unwrap())
+            .await
+            .unwrap();
+        let success = recorder.recv().await.unwrap();
+        assert_eq!(msg_type(success), MESSAGE_TYPE_SETUP_CONNECTION_SUCCESS);
+        let downstream = setup.await.unwrap().unwrap();
+
+        let open_channel: StdFrame = PoolMessages::Mining(Mining::OpenStandardMiningChannel(
+            OpenStandardMiningChannel {
+                request_id: 1_u32.into(),
+                user_identity: "user".to_string().try_into().unwrap(),
+                nominal_hash_rate: 1000.0,
+                max_target: [0xff; 32].into(),
+            },
+        ))
+        .into_frame()
+        .unwrap();
+        recorder.send(open_channel).await.unwrap();
+        let success = recorder.recv().await.unwrap();
+        assert_eq!(
+            msg_type(success),
+            MESSAGE_TYPE_OPEN_STANDARD_MINING_CHANNEL_SUCCESS
+        );
+        let channel_id = downstream
+            .safe_lock(|d| *d.jobs.keys().next().unwrap())
+            .unwrap();
+
+        new_template_sender
+            .send((session_template(), None))
+            .await
+            .unwrap();
+        let job = recorder.recv().await.unwrap();
+        assert_eq!(msg_type(job), MESSAGE_TYPE_NEW_EXTENDED_MINING_JOB);
+        new_prev_hash_sender
+            .send(session_prev_hash())
+            .await
+            .unwrap();
+        let prev_hash = recorder.recv().await.unwrap();
+        assert_eq!(msg_type(prev_hash), MESSAGE_TYPE_MINING_SET_NEW_PREV_HASH);
+
+        // Mine a share for the job of the channel
+        let ntime = session_prev_hash().header_timestamp;
+        let (job_id, version, nonce) = downstream
+            .safe_lock(|d| {
+                let job_id = d.last_valid_extended_job.as_ref().unwrap().0.job_id;
+                match &d.jobs[&channel_id] {
+                    Job::Complete(job) => {
+                        let nonce = (0..)
+                            .find(|nonce| {
+                                let hash = job.header_hash(*nonce, job.version, ntime, None);
+                                let hash = hash.unwrap().as_hash().into_inner();
+                                to_u256(hash_to_uint_256(hash)) <= job.target
+                            })
+                            .unwrap();
+                        (job_id, job.version, nonce)
+                    }
+                    Job::Partial(_) => panic!("no job for channel {}", channel_id),
+                }
+            })
+            .unwrap();
+        let share: StdFrame =
+            PoolMessages::Mining(Mining::SubmitSharesStandard(SubmitSharesStandard {
+                channel_id,
+                sequence_number: 0,
+                job_id,
+                nonce,
+                ntime,
+                version,
+            }))
+            .into_frame()
+            .unwrap();
+        recorder.send(share).await.unwrap();
+        let success = recorder.recv().await.unwrap();
+        assert_eq!(msg_type(success), MESSAGE_TYPE_SUBMIT_SHARES_SUCCESS);
+
+        let path = std::env::temp_dir().join("pool_test_record_replay_session.txt");
+        save(&path, recorder.recording()).unwrap();
+        let recording = load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(recording, recorder.recording());
+
+        // The template and the prev hash are sent to the new pool after the open channel, like
+        // in the recorded session
+        let (pool, new_template_sender, new_prev_hash_sender) = session_pool();
+        let ((pool_receiver, pool_sender), (receiver, sender)) = in_memory();
+        let setup = task::spawn(Pool::on_new_downstream(pool, pool_receiver, pool_sender));
+        replay(&recording[..4], &receiver, &sender).await.unwrap();
+        setup.await.unwrap().unwrap();
+        new_template_sender
+            .send((session_template(), None))
+            .await
+            .unwrap();
+        new_prev_hash_sender
+            .send(session_prev_hash())
+            .await
+            .unwrap();
+        replay(&recording[4..], &receiver, &sender).await.unwrap();
+    }
+
+    // The counters are global and the tests run in parallel, so only the lower bound of the
+    // increments is checked
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_metrics_counters() {
+        use crate::lib::{
+            metrics,
+            test_utils::{frame_to_bytes, in_memory, load},
+        };
+        use roles_logic_sv2::mining_sv2::OpenStandardMiningChannel;
+        let setup_session = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/setup_session.txt");
+        let setup_connection = load(setup_session).unwrap().remove(0).1;
+        let (pool, _new_template_sender, _new_prev_hash_sender) = session_pool();
+        let ((pool_receiver, pool_sender), (receiver, sender)) = in_memory();
+        let setup = task::spawn(Pool::on_new_downstream(
+            pool.clone(),
+            pool_receiver,
+            pool_sender,
+        ));
+        sender
+            .send(StdFrame::from_bytes(setup_connection).unwrap().into())
+            .await
+            .unwrap();
+        receiver.recv().await.unwrap();
+        setup.await.unwrap().unwrap();
+
+        let before = metrics::snapshot();
+        let open_channel: StdFrame = PoolMessages::Mining(Mining::OpenStandardMiningChannel(
+            OpenStandardMiningChannel {
+                request_id: 1_u32.into(),
+                user_identity: "user".to_string().try_into().unwrap(),
+                nominal_hash_rate: 1000.0,
+                max_target: [0xff; 32].into(),
+            },
+        ))
+        .into_frame()
+        .unwrap();
+        let open_channel_length = open_channel.encoded_length();
+        sender.send(open_channel.into()).await.unwrap();
+        let success = frame_to_bytes(receiver.recv().await.unwrap()).unwrap();
+        let after = metrics::snapshot();
+
+        assert!(after.received.messages >= before.received.messages + 1);
+        assert!(after.received.bytes >= before.received.bytes + open_channel_length);
+        assert!(after.sent.messages >= before.sent.messages + 1);
+        assert!(after.sent.bytes >= before.sent.bytes + success.len());
+    }
+}
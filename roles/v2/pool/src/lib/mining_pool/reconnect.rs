@@ -0,0 +1,116 @@
+use super::system_clock;
+use std::collections::HashMap;
+
+/// A downstream that want to reclaim its state after a reconnection put `RECONNECT_TOKEN_PREFIX`
+/// followed by a token of its choice in the `device_id` of `SetupConnection`, it must use the
+/// same token on every connection
+pub const RECONNECT_TOKEN_PREFIX: &str = "reconnect:";
+
+/// Reconnect token in the `device_id` of a `SetupConnection`, None if the downstream did not send
+/// a token
+pub fn reconnect_token(device_id: &[u8]) -> Option<String> {
+    let device_id = std::str::from_utf8(device_id).ok()?;
+    match device_id.strip_prefix(RECONNECT_TOKEN_PREFIX) {
+        Some(token) if !token.is_empty() => Some(token.to_string()),
+        _ => None,
+    }
+}
+
+/// Downstreams that disconnected with a reconnect token, see `reconnect_grace_sec` in the config.
+/// A parked downstream stay in the pool paused, so its jobs keep being updated, until it
+/// reconnects or the grace window expires.
+#[derive(Debug)]
+pub struct ParkedDownstreams {
+    grace_sec: u32,
+    // token -> (downstream id, unix time of the disconnection)
+    parked: HashMap<String, (u32, u32)>,
+    /// Return the current unix time in seconds, it is a field so that tests can control "now"
+    pub clock: fn() -> u32,
+}
+
+impl ParkedDownstreams {
+    pub fn new(grace_sec: u32) -> Self {
+        Self {
+            grace_sec,
+            parked: HashMap::new(),
+            clock: system_clock,
+        }
+    }
+
+    pub fn grace_sec(&self) -> u32 {
+        self.grace_sec
+    }
+
+    /// Park the downstream `id` that disconnected with `token`, a downstream already parked with
+    /// the same token is returned so that it can be removed from the pool
+    pub fn park(&mut self, token: String, id: u32) -> Option<u32> {
+        let now = (self.clock)();
+        self.parked
+            .insert(token, (id, now))
+            .map(|(old_id, _)| old_id)
+            .filter(|old_id| *old_id != id)
+    }
+
+    /// Id of the downstream parked with `token`, the token is not reclaimed
+    pub fn get(&self, token: &str) -> Option<u32> {
+        self.parked.get(token).map(|(id, _)| *id)
+    }
+
+    /// Id of the downstream parked with `token`, None if there is no such downstream or if its
+    /// grace window expired. The token can be reclaimed only once.
+    pub fn reclaim(&mut self, token: &str) -> Option<u32> {
+        let (id, parked_at) = self.parked.remove(token)?;
+        match (self.clock)().saturating_sub(parked_at) < self.grace_sec {
+            true => Some(id),
+            false => {
+                // Put back so that `expired` return it and the downstream is removed
+                self.parked.insert(token.to_string(), (id, parked_at));
+                None
+            }
+        }
+    }
+
+    /// Forget the downstreams whose grace window expired and return their ids
+    pub fn expired(&mut self) -> Vec<u32> {
+        let now = (self.clock)();
+        let grace_sec = self.grace_sec;
+        let mut expired = Vec::new();
+        self.parked.retain(|_, (id, parked_at)| {
+            let alive = now.saturating_sub(*parked_at) < grace_sec;
+            if !alive {
+                expired.push(*id);
+            }
+            alive
+        });
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_token() {
+        assert_eq!(reconnect_token(b"reconnect:abc"), Some("abc".to_string()));
+        assert_eq!(reconnect_token(b"reconnect:"), None);
+        assert_eq!(reconnect_token(b"some device"), None);
+        assert_eq!(reconnect_token(&[0xff, 0xfe]), None);
+    }
+
+    #[test]
+    fn test_parked_downstreams() {
+        let mut parked = ParkedDownstreams::new(10);
+        parked.clock = || 100;
+        assert_eq!(parked.park("a".to_string(), 1), None);
+        assert_eq!(parked.park("b".to_string(), 2), None);
+        assert_eq!(parked.reclaim("a"), Some(1));
+        // Already reclaimed
+        assert_eq!(parked.reclaim("a"), None);
+
+        parked.clock = || 110;
+        assert_eq!(parked.reclaim("b"), None);
+        assert_eq!(parked.expired(), vec![2]);
+        assert!(parked.expired().is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+//! Application level replay protection for the downstream frames. SV2 frames do not carry a
+//! sequence number, so a downstream that want its frames to be checked wrap each message in a
+//! `MESSAGE_TYPE_SEQUENCED` frame with the payload:
+//! ```txt
+//! sequence_number: u64 // little endian, strictly increasing on the connection
+//! message_type: u8     // message type of the wrapped message
+//! payload              // payload of the wrapped message
+//! ```
+//! Not in the spec. The frames that are not wrapped are handled as usual.
+
+/// Message type of the sequenced frames, the channel bit is not set
+pub const MESSAGE_TYPE_SEQUENCED: u8 = 0xf0;
+
+const HEADER_SIZE: usize = 9;
+
+/// Wrap the `payload` of a message of type `message_type` in the payload of a sequenced frame
+pub fn seal(sequence_number: u64, message_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(HEADER_SIZE + payload.len());
+    sealed.extend_from_slice(&sequence_number.to_le_bytes());
+    sealed.push(message_type);
+    sealed.extend_from_slice(payload);
+    sealed
+}
+
+/// Split the payload of a sequenced frame in (sequence number, message type, payload of the
+/// wrapped message), None if the payload is too short
+pub fn open(payload: &mut [u8]) -> Option<(u64, u8, &mut [u8])> {
+    if payload.len() < HEADER_SIZE {
+        return None;
+    }
+    let mut sequence_number = [0; 8];
+    sequence_number.copy_from_slice(&payload[..8]);
+    let message_type = payload[8];
+    Some((
+        u64::from_le_bytes(sequence_number),
+        message_type,
+        &mut payload[HEADER_SIZE..],
+    ))
+}
+
+/// Track the last sequence number received on a connection
+#[derive(Debug, Default)]
+pub struct ReplayGuard {
+    last_sequence_number: Option<u64>,
+}
+
+impl ReplayGuard {
+    /// Return true and update the last sequence number if `sequence_number` is bigger than the
+    /// last one, return false for a replayed or out of order sequence number
+    pub fn check(&mut self, sequence_number: u64) -> bool {
+        match self.last_sequence_number {
+            Some(last) if sequence_number <= last => false,
+            _ => {
+                self.last_sequence_number = Some(sequence_number);
+                true
+            }
+        }
+    }
+
+    pub fn last_sequence_number(&self) -> Option<u64> {
+        self.last_sequence_number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replayed_frame() {
+        let mut guard = ReplayGuard::default();
+        let frame = seal(1, 0x1a, &[1, 2, 3]);
+
+        let mut received = frame.clone();
+        let (sequence_number, message_type, payload) = open(&mut received[..]).unwrap();
+        assert_eq!((message_type, &payload[..]), (0x1a, &[1, 2, 3][..]));
+        assert!(guard.check(sequence_number));
+
+        // The same frame received again is rejected
+        let mut replayed = frame;
+        let (sequence_number, _, _) = open(&mut replayed[..]).unwrap();
+        assert!(!guard.check(sequence_number));
+
+        assert!(guard.check(3));
+        // Out of order
+        assert!(!guard.check(2));
+        assert_eq!(guard.last_sequence_number(), Some(3));
+        assert!(open(&mut [0; 8][..]).is_none());
+    }
+}
@@ -1,10 +1,11 @@
+use super::reconnect::reconnect_token;
 use crate::{EitherFrame, StdFrame};
 use async_channel::{Receiver, Sender};
 use codec_sv2::Frame;
 use roles_logic_sv2::{
     common_messages_sv2::{
         has_requires_std_job, has_version_rolling, has_work_selection, SetupConnection,
-        SetupConnectionSuccess,
+        SetupConnectionError, SetupConnectionSuccess,
     },
     common_properties::CommonDownstreamData,
     errors::Error,
@@ -17,31 +18,75 @@ use std::{convert::TryInto, sync::Arc};
 
 pub struct SetupConnectionHandler {
     header_only: Option<bool>,
+    /// Protocol versions (min, max) supported by the pool
+    min_version: u16,
+    max_version: u16,
+    /// Token sent by the downstream to reclaim its state after a reconnection, see `reconnect`
+    pub reconnect_token: Option<String>,
+    /// Data of the flags of the `SetupConnection`, the data returned by `setup` is built from the
+    /// flags of the answer
+    pub requested_data: Option<CommonDownstreamData>,
 }
 
 impl SetupConnectionHandler {
-    pub fn new() -> Self {
-        Self { header_only: None }
+    pub fn new(min_version: u16, max_version: u16) -> Self {
+        Self {
+            header_only: None,
+            min_version,
+            max_version,
+            reconnect_token: None,
+            requested_data: None,
+        }
     }
+
+    /// If the downstream do not support any version in `min_version..=max_version` a
+    /// `SetupConnectionError` is sent to the downstream and
+    /// `Error::UnsupportedProtocolVersion((min_version, max_version))` is returned. If the
+    /// downstream ask for an unknown protocol a `SetupConnectionError` is sent and
+    /// `ValueIsNotAValidProtocol(protocol)` is returned.
     pub async fn setup(
         self_: Arc<Mutex<Self>>,
         receiver: &mut Receiver<EitherFrame>,
         sender: &mut Sender<EitherFrame>,
-    ) -> Result<CommonDownstreamData, ()> {
-        let mut incoming: StdFrame = receiver.recv().await.unwrap().try_into().unwrap();
+    ) -> Result<CommonDownstreamData, Error> {
+        let mut incoming: StdFrame = match receiver.recv().await.unwrap().try_into() {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                println!("Downstream sent an invalid setup connection frame: {}", e);
+                return Err(Error::UnexpectedMessage);
+            }
+        };
         let message_type = incoming.get_header().unwrap().msg_type();
         let payload = incoming.payload();
-        let response = ParseDownstreamCommonMessages::handle_message_common(
+        let response = match ParseDownstreamCommonMessages::handle_message_common(
             self_.clone(),
             message_type,
             payload,
             CommonRoutingLogic::None,
-        )
-        .unwrap();
+        ) {
+            Ok(response) => response,
+            Err(Error::BinarySv2Error(binary_sv2::Error::ValueIsNotAValidProtocol(protocol))) => {
+                println!("Downstream requested an unknown protocol: {}", protocol);
+                let message = CommonMessages::SetupConnectionError(SetupConnectionError {
+                    flags: 0,
+                    error_code: "unsupported-protocol".to_string().try_into().unwrap(),
+                });
+                let sv2_frame: Result<StdFrame, Error> = PoolMessages::Common(message).into_frame();
+                // The connection is closed right after, if the downstream is already gone there
+                // is nothing to do
+                if let Ok(sv2_frame) = sv2_frame {
+                    let _ = sender.send(sv2_frame.into()).await;
+                }
+                return Err(Error::BinarySv2Error(
+                    binary_sv2::Error::ValueIsNotAValidProtocol(protocol),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
 
         let message = response.into_message().unwrap();
 
-        let sv2_frame: StdFrame = PoolMessages::Common(message.clone()).try_into().unwrap();
+        let sv2_frame: StdFrame = PoolMessages::Common(message.clone()).into_frame()?;
         let sv2_frame = sv2_frame.into();
         sender.send(sv2_frame).await.unwrap();
         self_.safe_lock(|s| s.header_only.unwrap()).unwrap();
@@ -52,6 +97,10 @@ impl SetupConnectionHandler {
                 work_selection: has_work_selection(m.flags),
                 version_rolling: has_version_rolling(m.flags),
             }),
+            CommonMessages::SetupConnectionError(_) => {
+                let (min_v, max_v) = self_.safe_lock(|s| (s.min_version, s.max_version)).unwrap();
+                Err(Error::UnsupportedProtocolVersion((min_v, max_v)))
+            }
             _ => panic!(),
         }
     }
@@ -66,12 +115,103 @@ impl ParseDownstreamCommonMessages<NoRouting> for SetupConnectionHandler {
         use roles_logic_sv2::handlers::common::SendTo;
         let header_only = incoming.requires_standard_job();
         self.header_only = Some(header_only);
-        Ok(SendTo::RelayNewMessageToRemote(
-            Arc::new(Mutex::new(())),
-            CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+        self.reconnect_token = reconnect_token(incoming.device_id.inner_as_ref());
+        self.requested_data = Some(CommonDownstreamData {
+            header_only,
+            work_selection: has_work_selection(incoming.flags),
+            version_rolling: has_version_rolling(incoming.flags),
+        });
+        let message = match incoming.get_version(self.min_version, self.max_version) {
+            Some(used_version) => CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+                flags: 0,
+                used_version,
+            }),
+            None => CommonMessages::SetupConnectionError(SetupConnectionError {
                 flags: 0,
-                used_version: 2,
+                error_code: "protocol-version-mismatch".to_string().try_into().unwrap(),
             }),
+        };
+        Ok(SendTo::RelayNewMessageToRemote(
+            Arc::new(Mutex::new(())),
+            message,
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::test_utils::in_memory;
+    use roles_logic_sv2::common_messages_sv2::Protocol;
+
+    // Run the setup of a pool that support the versions 2 and 3 with a downstream that support
+    // `min_version..=max_version`. Return the result of the setup and the answer of the pool, the
+    // used version on success and the error code on error.
+    async fn setup(
+        min_version: u16,
+        max_version: u16,
+    ) -> (Result<CommonDownstreamData, Error>, Result<u16, String>) {
+        let ((mut pool_receiver, mut pool_sender), (from_pool, to_pool)) = in_memory();
+        let setup_connection = SetupConnection {
+            protocol: Protocol::MiningProtocol,
+            min_version,
+            max_version,
+            flags: 0,
+            endpoint_host: "0.0.0.0".to_string().try_into().unwrap(),
+            endpoint_port: 34254,
+            vendor: "vendor".to_string().try_into().unwrap(),
+            hardware_version: String::new().try_into().unwrap(),
+            firmware: String::new().try_into().unwrap(),
+            device_id: String::new().try_into().unwrap(),
+        };
+        let sv2_frame: StdFrame = PoolMessages::Common(setup_connection.into())
+            .into_frame()
+            .unwrap();
+        to_pool.send(sv2_frame.into()).await.unwrap();
+
+        let handler = Arc::new(Mutex::new(SetupConnectionHandler::new(2, 3)));
+        let res =
+            SetupConnectionHandler::setup(handler, &mut pool_receiver, &mut pool_sender).await;
+        let mut answer: StdFrame = from_pool.recv().await.unwrap().try_into().unwrap();
+        let message_type = answer.get_header().unwrap().msg_type();
+        let answer: CommonMessages = (message_type, answer.payload()).try_into().unwrap();
+        let answer = match answer {
+            CommonMessages::SetupConnectionSuccess(m) => Ok(m.used_version),
+            CommonMessages::SetupConnectionError(m) => {
+                Err(String::from_utf8(m.error_code.to_vec()).unwrap())
+            }
+            _ => panic!("expected a SetupConnection answer"),
+        };
+        (res, answer)
+    }
+
+    #[tokio::test]
+    async fn test_version_too_old() {
+        let (res, answer) = setup(1, 1).await;
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedProtocolVersion((2, 3)))
+        ));
+        assert_eq!(answer, Err("protocol-version-mismatch".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_version_too_new() {
+        let (res, answer) = setup(4, 5).await;
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedProtocolVersion((2, 3)))
+        ));
+        assert_eq!(answer, Err("protocol-version-mismatch".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_version_in_range() {
+        let (res, answer) = setup(1, 2).await;
+        assert!(res.is_ok());
+        assert_eq!(answer, Ok(2));
+        let (res, answer) = setup(3, 9).await;
+        assert!(res.is_ok());
+        assert_eq!(answer, Ok(3));
+    }
+}
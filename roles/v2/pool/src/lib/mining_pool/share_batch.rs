@@ -0,0 +1,61 @@
+use super::share_logger::{ShareLogger, ShareRecord};
+use roles_logic_sv2::mining_sv2::SubmitSharesSuccess;
+use std::collections::HashMap;
+
+/// Buffer the valid shares of a downstream so that they are acked and logged in batches instead
+/// of one by one. The batch is flushed when it contains `max_shares` shares, when a share find a
+/// block or by the periodic flush task, see `share_batch` in the config.
+#[derive(Debug)]
+pub struct ShareBatch {
+    max_shares: usize,
+    records: Vec<ShareRecord>,
+    // channel_id -> aggregate ack of the buffered shares of the channel
+    acks: HashMap<u32, SubmitSharesSuccess>,
+    // Number of shares acked since the last flush
+    pending: usize,
+}
+
+impl ShareBatch {
+    pub fn new(max_shares: usize) -> Self {
+        Self {
+            max_shares,
+            records: Vec::new(),
+            acks: HashMap::new(),
+            pending: 0,
+        }
+    }
+
+    /// Buffer the record of a valid share, it is logged on flush
+    pub fn push_record(&mut self, record: ShareRecord) {
+        self.records.push(record);
+    }
+
+    /// Merge the ack of a valid share into the aggregate ack of its channel. Return true if the
+    /// batch is full and must be flushed.
+    pub fn push_ack(&mut self, ack: SubmitSharesSuccess) -> bool {
+        let aggregate = self
+            .acks
+            .entry(ack.channel_id)
+            .or_insert(SubmitSharesSuccess {
+                channel_id: ack.channel_id,
+                last_sequence_number: ack.last_sequence_number,
+                new_submits_accepted_count: 0,
+                new_shares_sum: 0,
+            });
+        aggregate.last_sequence_number = ack.last_sequence_number;
+        aggregate.new_submits_accepted_count += ack.new_submits_accepted_count;
+        // `new_shares_sum` is the running sum of the round so the last one is the right one
+        aggregate.new_shares_sum = ack.new_shares_sum;
+        self.pending += ack.new_submits_accepted_count as usize;
+        self.pending >= self.max_shares
+    }
+
+    /// Log the buffered records with `logger` and return one aggregate ack per channel
+    pub fn flush(&mut self, logger: &dyn ShareLogger) -> Vec<SubmitSharesSuccess> {
+        for record in self.records.drain(..) {
+            logger.log_share(record);
+        }
+        self.pending = 0;
+        self.acks.drain().map(|(_, ack)| ack).collect()
+    }
+}
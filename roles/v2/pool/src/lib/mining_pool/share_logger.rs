@@ -0,0 +1,109 @@
+use binary_sv2::Str0255;
+use roles_logic_sv2::utils::Mutex;
+
+/// Everything a payout system need to know about a valid share
+#[derive(Debug, Clone)]
+pub struct ShareRecord {
+    pub channel_id: u32,
+    /// `user_identity` sent by the downstream when the channel has been opened
+    pub user_identity: Option<Str0255<'static>>,
+    pub job_id: u32,
+    pub nonce: u32,
+    pub ntime: u32,
+    pub version: u32,
+    /// Difficulty of the channel target when the share has been submitted
+    pub target_difficulty: f64,
+    /// Unix time in seconds at which the share has been validated
+    pub timestamp: u64,
+}
+
+/// Receive a `ShareRecord` for every valid share. It is called from the message handlers (sync
+/// code) so it must not block.
+pub trait ShareLogger: std::fmt::Debug + Send + Sync {
+    fn log_share(&self, share: ShareRecord);
+}
+
+// Shares summed in each line printed by `PrintShareLogger`
+const SHARES_PER_SUMMARY: u64 = 100;
+
+/// Shares and sum of their difficulties since the last summary
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ShareSummary {
+    pub shares: u64,
+    pub difficulty: f64,
+}
+
+/// Print to stdout a summary every `SHARES_PER_SUMMARY` valid shares, one line per share would
+/// flood the output of a busy pool
+#[derive(Debug, Default)]
+pub struct PrintShareLogger {
+    summary: Mutex<ShareSummary>,
+}
+
+impl PrintShareLogger {
+    // Add `share` to the summary, return the summary if it is complete
+    fn add(&self, share: &ShareRecord) -> Option<ShareSummary> {
+        self.summary
+            .safe_lock(|summary| {
+                summary.shares += 1;
+                summary.difficulty += share.target_difficulty;
+                if summary.shares == SHARES_PER_SUMMARY {
+                    Some(std::mem::take(summary))
+                } else {
+                    None
+                }
+            })
+            .unwrap()
+    }
+}
+
+impl ShareLogger for PrintShareLogger {
+    fn log_share(&self, share: ShareRecord) {
+        if let Some(summary) = self.add(&share) {
+            println!(
+                "Shares accepted: {} total difficulty {}",
+                summary.shares, summary.difficulty
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(target_difficulty: f64) -> ShareRecord {
+        ShareRecord {
+            channel_id: 1,
+            user_identity: None,
+            job_id: 1,
+            nonce: 0,
+            ntime: 0,
+            version: 0x2000_0000,
+            target_difficulty,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_print_share_logger_summary() {
+        let logger = PrintShareLogger::default();
+        for _ in 1..SHARES_PER_SUMMARY {
+            assert_eq!(logger.add(&record(2.0)), None);
+        }
+        let summary = logger.add(&record(2.0)).unwrap();
+        assert_eq!(summary.shares, SHARES_PER_SUMMARY);
+        assert_eq!(summary.difficulty, 2.0 * SHARES_PER_SUMMARY as f64);
+
+        // The next summary start from zero
+        assert_eq!(logger.add(&record(1.0)), None);
+        let summary = logger.summary.safe_lock(|s| *s).unwrap();
+        assert_eq!(
+            summary,
+            ShareSummary {
+                shares: 1,
+                difficulty: 1.0
+            }
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use super::system_clock;
+use crate::DownstreamSharePolicy;
+use std::collections::HashMap;
+
+/// What to do with a share that meet the channel target but not the bitcoin target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareAction {
+    /// Count the share in `new_shares_sum` and log it with the `ShareLogger`
+    Credit,
+    /// Log the share with the `ShareLogger` without counting it, the payout is computed by the
+    /// logger
+    Forward,
+    /// Refuse the share, the channel submitted too many shares in the current second
+    RateLimited,
+}
+
+/// Apply the `downstream_share_policy` of the config to the shares of a downstream
+#[derive(Debug)]
+pub struct SharePolicy {
+    policy: DownstreamSharePolicy,
+    // channel_id -> (second, shares accepted in that second)
+    windows: HashMap<u32, (u32, u32)>,
+    /// Return the current unix time in seconds, it is a field so that tests can control "now"
+    pub clock: fn() -> u32,
+}
+
+impl SharePolicy {
+    pub fn new(policy: DownstreamSharePolicy) -> Self {
+        Self {
+            policy,
+            windows: HashMap::new(),
+            clock: system_clock,
+        }
+    }
+
+    /// Decide what to do with a share of `channel_id` that meet the channel target
+    pub fn on_share(&mut self, channel_id: u32) -> ShareAction {
+        match self.policy {
+            DownstreamSharePolicy::Count => ShareAction::Credit,
+            DownstreamSharePolicy::Forward => ShareAction::Forward,
+            DownstreamSharePolicy::RateLimit { max_shares_per_sec } => {
+                let now = (self.clock)();
+                let window = self.windows.entry(channel_id).or_insert((now, 0));
+                if window.0 != now {
+                    *window = (now, 0);
+                }
+                if window.1 < max_shares_per_sec {
+                    window.1 += 1;
+                    ShareAction::Credit
+                } else {
+                    ShareAction::RateLimited
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_and_forward() {
+        let mut count = SharePolicy::new(DownstreamSharePolicy::Count);
+        let mut forward = SharePolicy::new(DownstreamSharePolicy::Forward);
+        for _ in 0..10 {
+            assert_eq!(count.on_share(1), ShareAction::Credit);
+            assert_eq!(forward.on_share(1), ShareAction::Forward);
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut policy = SharePolicy::new(DownstreamSharePolicy::RateLimit {
+            max_shares_per_sec: 2,
+        });
+        policy.clock = || 100;
+        assert_eq!(policy.on_share(1), ShareAction::Credit);
+        assert_eq!(policy.on_share(1), ShareAction::Credit);
+        assert_eq!(policy.on_share(1), ShareAction::RateLimited);
+        // The limit is per channel
+        assert_eq!(policy.on_share(2), ShareAction::Credit);
+        // and per second
+        policy.clock = || 101;
+        assert_eq!(policy.on_share(1), ShareAction::Credit);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::SolutionChannelPolicy;
+use async_channel::{Receiver, Sender, TrySendError};
+use roles_logic_sv2::{template_distribution_sv2::SubmitSolution, utils::Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+// Number of templates for which a forwarded solution is remembered, older templates are stale
+const RECENT_TEMPLATES: usize = 16;
+
+/// Sender side of the solution channel, it is used by the downstreams from sync code (the message
+/// handlers) so it can never wait for the channel to have capacity. What happen when the channel
+/// is full is decided by `SolutionChannelPolicy`. With `Block` the solutions that do not fit in
+/// the channel are queued, the queue has the same capacity of the channel and a single worker
+/// moves the queued solutions to the channel in order.
+///
+/// Only the first solution for a template is forwarded: two shares of different channels can both
+/// be below the bitcoin target for the same template, but only one block can be built on it.
+#[derive(Debug, Clone)]
+pub struct SolutionSender {
+    sender: Sender<SubmitSolution<'static>>,
+    // Used only to drop the oldest solution when the policy is DropOldest
+    receiver: Receiver<SubmitSolution<'static>>,
+    policy: SolutionChannelPolicy,
+    // Solutions waiting for capacity in the channel when the policy is Block
+    queue: Sender<SubmitSolution<'static>>,
+    queue_receiver: Receiver<SubmitSolution<'static>>,
+    // Set when the worker that empty the queue is spawned, it is spawned on the first full channel
+    worker_started: Arc<AtomicBool>,
+    // Templates of the last forwarded solutions, shared by all the clones
+    forwarded: Arc<Mutex<VecDeque<u64>>>,
+}
+
+impl SolutionSender {
+    pub fn new(
+        sender: Sender<SubmitSolution<'static>>,
+        receiver: Receiver<SubmitSolution<'static>>,
+        policy: SolutionChannelPolicy,
+    ) -> Self {
+        let (queue, queue_receiver) = async_channel::bounded(sender.capacity().unwrap_or(1));
+        Self {
+            sender,
+            receiver,
+            policy,
+            queue,
+            queue_receiver,
+            worker_started: Arc::new(AtomicBool::new(false)),
+            forwarded: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_TEMPLATES))),
+        }
+    }
+
+    // Return false if a solution for `template_id` has already been forwarded
+    fn first_for_template(&self, template_id: u64) -> bool {
+        self.forwarded
+            .safe_lock(|forwarded| {
+                if forwarded.contains(&template_id) {
+                    return false;
+                }
+                if forwarded.len() == RECENT_TEMPLATES {
+                    forwarded.pop_front();
+                }
+                forwarded.push_back(template_id);
+                true
+            })
+            .unwrap()
+    }
+
+    // Move the queued solutions to the channel, waiting for capacity, there is only one worker so
+    // the solutions reach the channel in the order they were queued
+    fn start_worker(&self) {
+        if self.worker_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let sender = self.sender.clone();
+        let queue = self.queue_receiver.clone();
+        tokio::task::spawn(async move {
+            while let Ok(solution) = queue.recv().await {
+                if sender.send(solution).await.is_err() {
+                    println!("WARNING: solution channel closed, queued solutions lost");
+                    break;
+                }
+            }
+        });
+    }
+
+    // Queue `solution` until the channel has capacity
+    fn enqueue(&self, solution: SubmitSolution<'static>) -> Result<(), ()> {
+        self.start_worker();
+        match self.queue.try_send(solution) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Closed(_)) => Err(()),
+            Err(TrySendError::Full(solution)) => {
+                println!(
+                    "ERROR: solution channel and queue full, solution for template {} lost",
+                    solution.template_id
+                );
+                Ok(())
+            }
+        }
+    }
+
+    pub fn send(&self, solution: SubmitSolution<'static>) -> Result<(), ()> {
+        if !self.first_for_template(solution.template_id) {
+            println!(
+                "WARNING: block already found on template {}, solution not forwarded",
+                solution.template_id
+            );
+            return Ok(());
+        }
+        // Solutions already queued must reach the channel first
+        if !self.queue.is_empty() {
+            return self.enqueue(solution);
+        }
+        match self.sender.try_send(solution) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Closed(_)) => Err(()),
+            Err(TrySendError::Full(solution)) => match self.policy {
+                SolutionChannelPolicy::Block => self.enqueue(solution),
+                SolutionChannelPolicy::DropOldest => {
+                    if let Ok(dropped) = self.receiver.try_recv() {
+                        println!(
+                            "WARNING: solution channel full, dropping solution for template {}",
+                            dropped.template_id
+                        );
+                    }
+                    self.sender.try_send(solution).map_err(|_| ())
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn solution(template_id: u64, header_nonce: u32) -> SubmitSolution<'static> {
+        SubmitSolution {
+            template_id,
+            version: 0x2000_0000,
+            header_timestamp: 0,
+            header_nonce,
+            coinbase_tx: vec![0; 10].try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_block() {
+        let (sender, receiver) = async_channel::bounded(10);
+        let solution_sender =
+            SolutionSender::new(sender, receiver.clone(), SolutionChannelPolicy::Block);
+        // Downstreams use their own clone
+        let other_downstream = solution_sender.clone();
+
+        solution_sender.send(solution(1, 1)).unwrap();
+        other_downstream.send(solution(1, 2)).unwrap();
+        other_downstream.send(solution(2, 3)).unwrap();
+
+        assert_eq!(receiver.try_recv().unwrap().header_nonce, 1);
+        assert_eq!(receiver.try_recv().unwrap().header_nonce, 3);
+        assert!(receiver.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_block_policy_queue() {
+        let (sender, receiver) = async_channel::bounded(2);
+        let solution_sender =
+            SolutionSender::new(sender, receiver.clone(), SolutionChannelPolicy::Block);
+
+        // 2 in the channel, 2 in the queue and the last one is lost
+        for template_id in 0..5 {
+            solution_sender.send(solution(template_id, 0)).unwrap();
+        }
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(solution_sender.queue.len(), 2);
+
+        // The single worker move the queued solutions to the channel in order
+        let mut received = vec![];
+        for _ in 0..4 {
+            let solution = tokio::time::timeout(std::time::Duration::from_secs(1), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(solution.template_id);
+        }
+        assert_eq!(received, vec![0, 1, 2, 3]);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert!(receiver.is_empty());
+        assert!(solution_sender.queue.is_empty());
+    }
+
+    #[test]
+    fn test_drop_oldest_policy() {
+        let (sender, receiver) = async_channel::bounded(2);
+        let solution_sender =
+            SolutionSender::new(sender, receiver.clone(), SolutionChannelPolicy::DropOldest);
+
+        for template_id in 0..3 {
+            solution_sender.send(solution(template_id, 0)).unwrap();
+        }
+
+        assert_eq!(receiver.try_recv().unwrap().template_id, 1);
+        assert_eq!(receiver.try_recv().unwrap().template_id, 2);
+        assert!(receiver.is_empty());
+    }
+}
@@ -1,2 +1,10 @@
+pub mod authority;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod mining_pool;
+#[cfg(feature = "rpc-verify")]
+pub mod rpc_verify;
 pub mod template_receiver;
+#[cfg(any(test, feature = "test-utils"))]
+#[allow(dead_code)]
+pub mod test_utils;
@@ -0,0 +1,447 @@
+//! Optional check of the block solutions found by the pool against a bitcoind node.
+//!
+//! The template receiver record the templates, their transactions and the prev hash as they are
+//! received from the template provider (see [`BlockVerifier`]), so that the full block of a
+//! solution can be rebuilt by the pool:
+//! 1. before the solution is forwarded to the template provider the block is checked by bitcoind
+//!    with `getblocktemplate` in `proposal` mode, a block rejected by bitcoind (eg because of a
+//!    wrong merkle root or coinbase) is not forwarded. If the block can not be built or bitcoind
+//!    can not be reached the solution is forwarded anyway.
+//! 2. after the solution has been forwarded, that the new tip of the node has our coinbase as
+//!    first transaction, if not the node did not accept the block. This is only logged.
+use bitcoin::{
+    blockdata::block::{Block, BlockHeader},
+    consensus::{deserialize, serialize},
+    hash_types::BlockHash,
+    hashes::{sha256d::Hash, Hash as Hash_},
+    Transaction, TxMerkleNode,
+};
+use roles_logic_sv2::{
+    job_creator::TemplateWitness,
+    template_distribution_sv2::{NewTemplate, SetNewPrevHash, SubmitSolution},
+    utils::merkle_root_from_path,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, convert::TryInto, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Witness reserved value of the coinbase, Bitcoin Core use all zeros
+const WITNESS_RESERVED_VALUE: [u8; 32] = [0; 32];
+/// Time given to bitcoind to process the block before checking if it is the new tip
+const TIP_CHECK_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcConfig {
+    /// `host:port` of the bitcoind rpc server
+    pub address: String,
+    pub user: String,
+    pub password: String,
+}
+
+/// Answer of bitcoind to a block proposal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockProposal {
+    Valid,
+    /// The block could not be fully checked (eg it is not built on the tip of the node) or it is
+    /// already known
+    Inconclusive(String),
+    /// The reject reason of bitcoind eg `bad-txnmrklroot`
+    Rejected(String),
+}
+
+#[derive(Debug)]
+pub enum RpcError {
+    Io(std::io::Error),
+    InvalidResponse,
+    /// The `error` field returned by bitcoind
+    Rpc(Value),
+}
+
+impl From<std::io::Error> for RpcError {
+    fn from(e: std::io::Error) -> Self {
+        RpcError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BitcoindRpc {
+    config: RpcConfig,
+}
+
+impl BitcoindRpc {
+    pub fn new(config: RpcConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let body =
+            json!({"jsonrpc": "1.0", "id": "pool", "method": method, "params": params}).to_string();
+        let auth = base64::encode(format!("{}:{}", self.config.user, self.config.password));
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nAuthorization: Basic {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.config.address,
+            auth,
+            body.len(),
+            body
+        );
+
+        let mut stream = TcpStream::connect(&self.config.address).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+
+        // bitcoind answer with an error body also for http status codes different from 200 so
+        // only the body is looked at
+        let body_start = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or(RpcError::InvalidResponse)?;
+        let response: Value = serde_json::from_slice(&response[body_start + 4..])
+            .map_err(|_| RpcError::InvalidResponse)?;
+        match (response.get("result"), response.get("error")) {
+            (_, Some(error)) if !error.is_null() => Err(RpcError::Rpc(error.clone())),
+            (Some(result), _) => Ok(result.clone()),
+            _ => Err(RpcError::InvalidResponse),
+        }
+    }
+
+    /// Ask bitcoind to check `block` without submitting it, with `getblocktemplate` in `proposal`
+    /// mode
+    pub async fn propose_block(&self, block: &[u8]) -> Result<BlockProposal, RpcError> {
+        let params = json!([{"mode": "proposal", "data": to_hex(block)}]);
+        match self.call("getblocktemplate", params).await? {
+            Value::Null => Ok(BlockProposal::Valid),
+            Value::String(reason)
+                if reason == "duplicate" || reason.starts_with("inconclusive") =>
+            {
+                Ok(BlockProposal::Inconclusive(reason))
+            }
+            Value::String(reason) => Ok(BlockProposal::Rejected(reason)),
+            _ => Err(RpcError::InvalidResponse),
+        }
+    }
+
+    /// Check that the tip of bitcoind is the block of `coinbase`, called after forwarding the
+    /// solution
+    pub async fn verify_block_accepted(&self, coinbase: &[u8]) {
+        let txid = match deserialize::<Transaction>(coinbase) {
+            Ok(tx) => tx.txid().to_string(),
+            Err(e) => {
+                println!("RPC VERIFY: invalid coinbase: {:?}", e);
+                return;
+            }
+        };
+        tokio::time::sleep(TIP_CHECK_DELAY).await;
+        let tip = match self.call("getbestblockhash", json!([])).await {
+            Ok(tip) => tip,
+            Err(e) => {
+                println!("RPC VERIFY: can not get the best block hash: {:?}", e);
+                return;
+            }
+        };
+        match self.call("getblock", json!([tip, 1])).await {
+            Ok(block) if block["tx"][0] == Value::String(txid) => {
+                println!("RPC VERIFY: block {} accepted by bitcoind", tip)
+            }
+            Ok(_) => println!(
+                "RPC VERIFY: block not accepted by bitcoind, current tip is {}",
+                tip
+            ),
+            Err(e) => println!("RPC VERIFY: can not get block {}: {:?}", tip, e),
+        }
+    }
+}
+
+/// Parts of a template that are needed, with a solution, to build the block
+#[derive(Debug, Clone, Default)]
+struct TemplateParts {
+    merkle_path: Vec<Vec<u8>>,
+    // None if the template provider did not send the transaction data
+    transactions: Option<Vec<Vec<u8>>>,
+}
+
+/// Check the block of each solution with bitcoind before it is forwarded, see the module doc
+#[derive(Debug, Clone)]
+pub struct BlockVerifier {
+    rpc: BitcoindRpc,
+    // template_id -> parts of the templates that can be mined on the last prev hash
+    templates: HashMap<u64, TemplateParts>,
+    // (prev_hash, nbits) of the last prev hash
+    prev_hash: Option<([u8; 32], u32)>,
+}
+
+impl BlockVerifier {
+    pub fn new(rpc: BitcoindRpc) -> Self {
+        Self {
+            rpc,
+            templates: HashMap::new(),
+            prev_hash: None,
+        }
+    }
+
+    pub fn rpc(&self) -> BitcoindRpc {
+        self.rpc.clone()
+    }
+
+    pub fn on_new_template(&mut self, template: &NewTemplate) {
+        let merkle_path = template
+            .merkle_path
+            .inner_as_ref()
+            .iter()
+            .map(|hash| hash.to_vec())
+            .collect();
+        self.templates
+            .entry(template.template_id)
+            .or_default()
+            .merkle_path = merkle_path;
+    }
+
+    pub fn on_transaction_data<'a>(
+        &mut self,
+        template_id: u64,
+        transactions: impl IntoIterator<Item = &'a [u8]>,
+    ) {
+        self.templates.entry(template_id).or_default().transactions =
+            Some(transactions.into_iter().map(|t| t.to_vec()).collect());
+    }
+
+    /// The templates that are not built on the new prev hash are forgotten
+    pub fn on_new_prev_hash(&mut self, prev_hash: &SetNewPrevHash) {
+        self.templates.retain(|id, _| *id == prev_hash.template_id);
+        // A U256 is always 32 bytes
+        let hash: [u8; 32] = prev_hash.prev_hash.to_vec().try_into().unwrap();
+        self.prev_hash = Some((hash, prev_hash.n_bits));
+    }
+
+    /// Serialized block of `solution`, None if the pool do not know the template, the prev hash
+    /// or the transactions of the template
+    pub fn block(&self, solution: &SubmitSolution) -> Option<Vec<u8>> {
+        let (prev_hash, nbits) = self.prev_hash?;
+        let template = self.templates.get(&solution.template_id)?;
+        let transactions: Vec<Transaction> = match &template.transactions {
+            Some(transactions) => transactions
+                .iter()
+                .map(|t| deserialize(t).ok())
+                .collect::<Option<_>>()?,
+            None if template.merkle_path.is_empty() => vec![],
+            None => return None,
+        };
+        let coinbase = solution.coinbase_tx.to_vec();
+        let merkle_root: [u8; 32] =
+            merkle_root_from_path(&coinbase, &[], &[], &template.merkle_path)?
+                .try_into()
+                .ok()?;
+        let mut coinbase: Transaction = deserialize(&coinbase).ok()?;
+        // The template provider add the witness reserved value when it build the block
+        let has_commitment = coinbase.output.iter().any(TemplateWitness::is_commitment);
+        match coinbase.input.first_mut() {
+            Some(input) if has_commitment && input.witness.is_empty() => {
+                input.witness = vec![WITNESS_RESERVED_VALUE.to_vec()]
+            }
+            _ => (),
+        }
+        let header = BlockHeader {
+            // The version is an u32 in the solution and an i32 in the header, both encoded as the
+            // same 4 little endian bytes
+            version: i32::from_le_bytes(solution.version.to_le_bytes()),
+            prev_blockhash: BlockHash::from_hash(Hash::from_inner(prev_hash)),
+            merkle_root: TxMerkleNode::from_hash(Hash::from_inner(merkle_root)),
+            time: solution.header_timestamp,
+            bits: nbits,
+            nonce: solution.header_nonce,
+        };
+        let mut txdata = vec![coinbase];
+        txdata.extend(transactions);
+        Some(serialize(&Block { header, txdata }))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use binary_sv2::Seq0255;
+    use bitcoin::{OutPoint, TxIn, TxOut};
+    use tokio::{net::TcpListener, task::JoinHandle};
+
+    // Body of an http request, None if it is not complete
+    fn request_body(request: &[u8]) -> Option<Vec<u8>> {
+        let start = request.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let headers = String::from_utf8_lossy(&request[..start]).to_lowercase();
+        let len: usize = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))?
+            .trim()
+            .parse()
+            .ok()?;
+        request.get(start..start + len).map(|body| body.to_vec())
+    }
+
+    /// Mock bitcoind that answer each request with the next of `results`, the handle return the
+    /// requests once every result has been sent
+    pub(crate) async fn mock_bitcoind(
+        results: Vec<Value>,
+    ) -> (BitcoindRpc, JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let rpc = BitcoindRpc::new(RpcConfig {
+            address: listener.local_addr().unwrap().to_string(),
+            user: "user".to_string(),
+            password: "password".to_string(),
+        });
+        let handle = tokio::task::spawn(async move {
+            let mut requests = Vec::new();
+            for result in results {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                let body = loop {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                    if let Some(body) = request_body(&request) {
+                        break body;
+                    }
+                };
+                requests.push(serde_json::from_slice(&body).unwrap());
+                let body = json!({"result": result, "error": null, "id": "pool"}).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (rpc, handle)
+    }
+
+    pub(crate) fn transaction(script_sig: Vec<u8>) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: script_sig.into(),
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 5_000_000_000,
+                script_pubkey: vec![0x51].into(),
+            }],
+        }
+    }
+
+    pub(crate) fn template(template_id: u64, merkle_path: Vec<[u8; 32]>) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template: true,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 3, 0x10, 0x27, 0x00].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(merkle_path.into_iter().map(|h| h.into()).collect()).unwrap(),
+        }
+    }
+
+    pub(crate) fn prev_hash(template_id: u64) -> SetNewPrevHash<'static> {
+        SetNewPrevHash {
+            template_id,
+            prev_hash: [1; 32].into(),
+            header_timestamp: 1_700_000_000,
+            n_bits: 0x1d00ffff,
+            target: [0xff; 32].into(),
+        }
+    }
+
+    pub(crate) fn solution(template_id: u64, header_nonce: u32) -> SubmitSolution<'static> {
+        SubmitSolution {
+            template_id,
+            version: 0x2000_0000,
+            header_timestamp: 1_700_000_000,
+            header_nonce,
+            coinbase_tx: serialize(&transaction(vec![3, 3, 0x10, 0x27, 0x00]))
+                .try_into()
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_block() {
+        let mut verifier = BlockVerifier::new(BitcoindRpc::new(RpcConfig {
+            address: "127.0.0.1:0".to_string(),
+            user: "user".to_string(),
+            password: "password".to_string(),
+        }));
+        assert!(verifier.block(&solution(1, 7)).is_none());
+
+        // A template without transactions
+        verifier.on_new_template(&template(1, vec![]));
+        verifier.on_new_prev_hash(&prev_hash(1));
+        let block: Block = deserialize(&verifier.block(&solution(1, 7)).unwrap()).unwrap();
+        let coinbase = transaction(vec![3, 3, 0x10, 0x27, 0x00]);
+        assert_eq!(block.txdata, vec![coinbase.clone()]);
+        assert_eq!(block.header.prev_blockhash.as_hash().into_inner(), [1; 32]);
+        assert_eq!(
+            block.header.merkle_root.as_hash().into_inner(),
+            coinbase.txid().as_hash().into_inner()
+        );
+        assert_eq!(block.header.version, 0x2000_0000);
+        assert_eq!(block.header.time, 1_700_000_000);
+        assert_eq!(block.header.bits, 0x1d00ffff);
+        assert_eq!(block.header.nonce, 7);
+
+        // A template with a transaction can not be built until the transaction is known
+        let spend = transaction(vec![1]);
+        verifier.on_new_template(&template(2, vec![spend.txid().as_hash().into_inner()]));
+        assert!(verifier.block(&solution(2, 7)).is_none());
+        let spend_bytes = serialize(&spend);
+        verifier.on_transaction_data(2, vec![&spend_bytes[..]]);
+        let block: Block = deserialize(&verifier.block(&solution(2, 7)).unwrap()).unwrap();
+        assert_eq!(block.txdata, vec![coinbase, spend]);
+        assert!(block.check_merkle_root());
+
+        // The templates of the previous prev hash are forgotten
+        verifier.on_new_prev_hash(&prev_hash(3));
+        assert!(verifier.block(&solution(2, 7)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_propose_block() {
+        let (rpc, requests) = mock_bitcoind(vec![
+            Value::Null,
+            json!("bad-txnmrklroot"),
+            json!("inconclusive-not-best-prevblk"),
+        ])
+        .await;
+        assert_eq!(
+            rpc.propose_block(&[1, 2, 3]).await.unwrap(),
+            BlockProposal::Valid
+        );
+        assert_eq!(
+            rpc.propose_block(&[1, 2, 3]).await.unwrap(),
+            BlockProposal::Rejected("bad-txnmrklroot".to_string())
+        );
+        assert_eq!(
+            rpc.propose_block(&[1, 2, 3]).await.unwrap(),
+            BlockProposal::Inconclusive("inconclusive-not-best-prevblk".to_string())
+        );
+        let requests = requests.await.unwrap();
+        assert_eq!(requests[0]["method"], "getblocktemplate");
+        assert_eq!(
+            requests[0]["params"],
+            json!([{"mode": "proposal", "data": "010203"}])
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use crate::lib::template_receiver::TemplateRx;
+use crate::lib::template_receiver::{needs_transaction_data, TemplateRx};
 use roles_logic_sv2::{
     errors::Error,
     handlers::template_distribution::{ParseServerTemplateDistributionMessages, SendTo},
+    job_creator::TemplateWitness,
     parsers::TemplateDistribution,
     template_distribution_sv2::*,
     utils::Mutex,
@@ -47,15 +48,63 @@ impl ParseServerTemplateDistributionMessages for TemplateRx {
 
     fn handle_request_tx_data_success(
         &mut self,
-        _m: RequestTransactionDataSuccess,
+        m: RequestTransactionDataSuccess,
     ) -> Result<SendTo, Error> {
-        todo!()
+        let template = self
+            .pending_templates
+            .remove(&m.template_id)
+            .ok_or(Error::UnexpectedMessage)?;
+        #[cfg(feature = "rpc-verify")]
+        if let Some(verifier) = self.verifier.as_mut() {
+            verifier.on_transaction_data(m.template_id, m.transaction_list.inner_as_ref());
+        }
+        // The transaction data of a template that has a witness commitment is requested only to
+        // check the blocks with bitcoind, the commitment of the template is kept
+        if !needs_transaction_data(&template) {
+            self.transaction_data.insert(m.template_id, None);
+            return Ok(SendTo::RelayNewMessageToRemote(
+                Arc::new(Mutex::new(())),
+                TemplateDistribution::NewTemplate(template),
+            ));
+        }
+        // Bitcoin Core use an all zeros witness reserved value
+        let witness =
+            match TemplateWitness::from_transactions(m.transaction_list.inner_as_ref(), [0; 32]) {
+                Ok(witness) => Some(witness),
+                Err(e) => {
+                    println!(
+                        "WARNING: invalid transaction data for template {}, it is used without \
+                         witness commitment: {}",
+                        m.template_id, e
+                    );
+                    None
+                }
+            };
+        self.transaction_data.insert(m.template_id, witness);
+        Ok(SendTo::RelayNewMessageToRemote(
+            Arc::new(Mutex::new(())),
+            TemplateDistribution::NewTemplate(template),
+        ))
     }
 
     fn handle_request_tx_data_error(
         &mut self,
-        _m: RequestTransactionDataError,
+        m: RequestTransactionDataError,
     ) -> Result<SendTo, Error> {
-        todo!()
+        let template = self
+            .pending_templates
+            .remove(&m.template_id)
+            .ok_or(Error::UnexpectedMessage)?;
+        println!(
+            "WARNING: no transaction data for template {}, it is used without witness \
+             commitment: {}",
+            m.template_id,
+            String::from_utf8_lossy(&m.error_code.to_vec())
+        );
+        self.transaction_data.insert(m.template_id, None);
+        Ok(SendTo::RelayNewMessageToRemote(
+            Arc::new(Mutex::new(())),
+            TemplateDistribution::NewTemplate(template),
+        ))
     }
 }
@@ -1,57 +1,174 @@
+#[cfg(feature = "rpc-verify")]
+use crate::lib::rpc_verify::{BitcoindRpc, BlockProposal, BlockVerifier};
 use crate::{EitherFrame, StdFrame};
 use async_channel::{Receiver, Sender};
 use codec_sv2::Frame;
 use network_helpers::plain_connection_tokio::PlainConnection;
 use roles_logic_sv2::{
     handlers::template_distribution::ParseServerTemplateDistributionMessages,
+    job_creator::{template_outputs, TemplateWitness},
     parsers::{PoolMessages, TemplateDistribution},
-    template_distribution_sv2::{NewTemplate, SetNewPrevHash, SubmitSolution},
+    template_distribution_sv2::{
+        NewTemplate, RequestTransactionData, SetNewPrevHash, SubmitSolution,
+    },
     utils::Mutex,
 };
-use std::{convert::TryInto, net::SocketAddr, sync::Arc};
-use tokio::{net::TcpStream, task};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryInto,
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{net::TcpStream, task, task::JoinHandle};
+
+/// How many times a solution is sent to the template provider before giving up
+const SOLUTION_SEND_ATTEMPTS: u32 = 5;
+/// Wait before the first retry, it doubles at every retry
+const SOLUTION_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+/// How many (template_id, nonce) of already forwarded solutions are remembered
+const RECENT_SOLUTIONS: usize = 64;
+/// Wait before the first reconnection to the template provider, it doubles at every failed
+/// attempt up to `MAX_RECONNECT_BACKOFF`
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+/// Past this time bitcoind is considered unreachable and the solution is forwarded without check
+#[cfg(feature = "rpc-verify")]
+const BLOCK_PROPOSAL_TIMEOUT: Duration = Duration::from_secs(5);
 
 mod message_handler;
 mod setup_connection;
+mod solution_confirmation;
 use setup_connection::SetupConnectionHandler;
 
+/// What the template provider answered to a forwarded solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolutionOutcome {
+    Accepted {
+        template_id: u64,
+        header_nonce: u32,
+    },
+    Rejected {
+        template_id: u64,
+        header_nonce: u32,
+        reason: String,
+    },
+}
+
 pub struct TemplateRx {
+    address: SocketAddr,
     receiver: Receiver<EitherFrame>,
     sender: Sender<EitherFrame>,
-    new_template_sender: Sender<NewTemplate<'static>>,
+    new_template_sender: Sender<(NewTemplate<'static>, Option<TemplateWitness>)>,
     new_prev_hash_sender: Sender<SetNewPrevHash<'static>>,
+    // Templates without witness commitment that wait for their transaction data
+    pending_templates: HashMap<u64, NewTemplate<'static>>,
+    // template_id -> witness data, None if the template provider did not send the transaction
+    // data
+    transaction_data: HashMap<u64, Option<TemplateWitness>>,
+    // Last `RECENT_SOLUTIONS` answers of the template provider to the solutions, a template
+    // provider that do not confirm the solutions never fill it
+    solution_outcomes: VecDeque<SolutionOutcome>,
+    #[cfg(feature = "rpc-verify")]
+    verifier: Option<BlockVerifier>,
 }
 
 impl TemplateRx {
+    /// Connect to the template provider and spawn the tasks that receive the templates and send
+    /// the solutions. If the template provider disconnects the tasks reconnect with backoff, a
+    /// message on `resync` make them reconnect as well. The tasks stop when `shutdown` is closed,
+    /// the returned handle completes when both are stopped.
     pub async fn connect(
         address: SocketAddr,
-        templ_sender: Sender<NewTemplate<'static>>,
+        templ_sender: Sender<(NewTemplate<'static>, Option<TemplateWitness>)>,
         prev_h_sender: Sender<SetNewPrevHash<'static>>,
         solution_receiver: Receiver<SubmitSolution<'static>>,
-    ) {
-        let stream = TcpStream::connect(address).await.unwrap();
-
-        let (mut receiver, mut sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
-            PlainConnection::new(stream).await;
-
-        SetupConnectionHandler::setup(&mut receiver, &mut sender, address)
-            .await
-            .unwrap();
+        shutdown: Receiver<()>,
+        resync: Receiver<()>,
+        #[cfg(feature = "rpc-verify")] rpc: Option<BitcoindRpc>,
+    ) -> JoinHandle<()> {
+        let (receiver, sender) = Self::open_connection(address).await.unwrap();
 
         let self_ = Arc::new(Mutex::new(Self {
+            address,
             receiver,
             sender,
             new_template_sender: templ_sender,
             new_prev_hash_sender: prev_h_sender,
+            pending_templates: HashMap::new(),
+            transaction_data: HashMap::new(),
+            solution_outcomes: VecDeque::with_capacity(RECENT_SOLUTIONS),
+            #[cfg(feature = "rpc-verify")]
+            verifier: rpc.map(BlockVerifier::new),
         }));
         let cloned = self_.clone();
 
-        task::spawn(async { Self::start(cloned).await });
-        task::spawn(async { Self::on_new_solution(self_, solution_receiver).await });
+        let start = task::spawn(Self::start(cloned, shutdown.clone(), resync));
+        let on_new_solution =
+            task::spawn(Self::on_new_solution(self_, solution_receiver, shutdown));
+        task::spawn(async move {
+            let _ = start.await;
+            let _ = on_new_solution.await;
+        })
+    }
+
+    async fn open_connection(
+        address: SocketAddr,
+    ) -> Result<(Receiver<EitherFrame>, Sender<EitherFrame>), ()> {
+        let stream = TcpStream::connect(address).await.map_err(|_| ())?;
+
+        let (mut receiver, mut sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
+            PlainConnection::new(stream).await;
+
+        SetupConnectionHandler::setup(&mut receiver, &mut sender, address).await?;
+        Ok((receiver, sender))
     }
 
-    pub async fn start(self_: Arc<Mutex<Self>>) {
-        let (receiver, new_template_sender, new_prev_hash_sender) = self_
+    /// Reconnect to the template provider retrying with backoff, the new connection replace the
+    /// old one so that `send` use it. Return `None` if `shutdown` is closed before the template
+    /// provider is reachable again.
+    async fn reconnect(
+        self_: &Arc<Mutex<Self>>,
+        shutdown: &Receiver<()>,
+    ) -> Option<Receiver<EitherFrame>> {
+        let address = self_.safe_lock(|s| s.address).unwrap();
+        let mut backoff = RECONNECT_BACKOFF;
+        loop {
+            tokio::select! {
+                connection = Self::open_connection(address) => {
+                    if let Ok((receiver, sender)) = connection {
+                        self_
+                            .safe_lock(|s| {
+                                s.receiver = receiver.clone();
+                                s.sender = sender;
+                                // The template provider send again its templates
+                                s.pending_templates.clear();
+                                s.transaction_data.clear();
+                            })
+                            .unwrap();
+                        println!("Reconnected to the template provider");
+                        return Some(receiver);
+                    }
+                }
+                _ = shutdown.recv() => return None,
+            }
+            println!(
+                "Failed to reconnect to the template provider, retrying in {:?}",
+                backoff
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => (),
+                _ = shutdown.recv() => return None,
+            }
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+        }
+    }
+
+    /// Relay the templates and the prev hashes to the pool until `shutdown` is closed. On a
+    /// message on `resync` the connection is replaced by a new one, so that the template provider
+    /// send again its current template and prev hash.
+    pub async fn start(self_: Arc<Mutex<Self>>, shutdown: Receiver<()>, resync: Receiver<()>) {
+        let (mut receiver, new_template_sender, new_prev_hash_sender) = self_
             .safe_lock(|s| {
                 (
                     s.receiver.clone(),
@@ -61,53 +178,775 @@ impl TemplateRx {
             })
             .unwrap();
         loop {
-            let message_from_tp = receiver.recv().await.unwrap();
-            let mut message_from_tp: StdFrame = message_from_tp.try_into().unwrap();
-            let message_type = message_from_tp.get_header().unwrap().msg_type();
+            let message_from_tp = tokio::select! {
+                message = receiver.recv() => message,
+                Ok(()) = resync.recv() => {
+                    println!("Resyncing with the template provider");
+                    match Self::reconnect(&self_, &shutdown).await {
+                        Some(new_receiver) => {
+                            receiver = new_receiver;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                _ = shutdown.recv() => break,
+            };
+            let message_from_tp = match message_from_tp {
+                Ok(message) => message,
+                Err(_) => {
+                    // The downstreams keep mining on the last template, stale jobs are better
+                    // than no jobs
+                    println!(
+                        "WARNING: template provider disconnected, jobs are built on the last \
+                         template until it reconnects"
+                    );
+                    match Self::reconnect(&self_, &shutdown).await {
+                        Some(new_receiver) => {
+                            receiver = new_receiver;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+            };
+            let mut message_from_tp: StdFrame = match message_from_tp.try_into() {
+                Ok(frame) => frame,
+                Err(_) => {
+                    println!("ERROR: unexpected handshake frame from the template provider");
+                    continue;
+                }
+            };
+            let message_type = match message_from_tp.get_header() {
+                Some(header) => header.msg_type(),
+                None => {
+                    println!("ERROR: frame without header from the template provider");
+                    continue;
+                }
+            };
             let payload = message_from_tp.payload();
-            match ParseServerTemplateDistributionMessages::handle_message_template_distribution(
-                self_.clone(),
-                message_type,
-                payload,
-            )
-            .unwrap()
-            {
-                roles_logic_sv2::handlers::SendTo_::RelayNewMessageToRemote(_, m) => match m {
-                    TemplateDistribution::CoinbaseOutputDataSize(_) => todo!(),
+            if let Some(outcome) = solution_confirmation::parse(message_type, payload) {
+                match outcome {
+                    Ok(outcome) => {
+                        match &outcome {
+                            SolutionOutcome::Accepted {
+                                template_id,
+                                header_nonce,
+                            } => println!(
+                                "BLOCK ACCEPTED: solution for template {} with nonce {}",
+                                template_id, header_nonce
+                            ),
+                            SolutionOutcome::Rejected {
+                                template_id,
+                                header_nonce,
+                                reason,
+                            } => println!(
+                                "ERROR: BLOCK REJECTED, solution for template {} with nonce {}: {}",
+                                template_id, header_nonce, reason
+                            ),
+                        }
+                        self_
+                            .safe_lock(|s| s.record_solution_outcome(outcome))
+                            .unwrap();
+                    }
+                    Err(e) => println!(
+                        "ERROR: invalid solution confirmation from the template provider: {:?}",
+                        e
+                    ),
+                }
+                continue;
+            }
+            let next_message =
+                ParseServerTemplateDistributionMessages::handle_message_template_distribution(
+                    self_.clone(),
+                    message_type,
+                    payload,
+                );
+            match next_message {
+                Ok(roles_logic_sv2::handlers::SendTo_::RelayNewMessageToRemote(_, m)) => match m {
                     TemplateDistribution::NewTemplate(m) => {
-                        new_template_sender.send(m).await.unwrap()
+                        #[cfg(feature = "rpc-verify")]
+                        let verified = self_
+                            .safe_lock(|s| s.verifier.as_mut().map(|v| v.on_new_template(&m)))
+                            .unwrap()
+                            .is_some();
+                        #[cfg(not(feature = "rpc-verify"))]
+                        let verified = false;
+                        let witness = self_
+                            .safe_lock(|s| s.transaction_data.remove(&m.template_id))
+                            .unwrap();
+                        match witness {
+                            Some(witness) => new_template_sender.send((m, witness)).await.unwrap(),
+                            // The transactions are needed also to build the blocks checked by
+                            // bitcoind
+                            None if needs_transaction_data(&m)
+                                || (verified && !m.merkle_path.is_empty()) =>
+                            {
+                                Self::request_transaction_data(self_.clone(), m).await
+                            }
+                            None => new_template_sender.send((m, None)).await.unwrap(),
+                        }
                     }
-                    TemplateDistribution::RequestTransactionData(_) => todo!(),
-                    TemplateDistribution::RequestTransactionDataError(_) => todo!(),
-                    TemplateDistribution::RequestTransactionDataSuccess(_) => todo!(),
                     TemplateDistribution::SetNewPrevHash(m) => {
+                        let pending = self_
+                            .safe_lock(|s| {
+                                #[cfg(feature = "rpc-verify")]
+                                if let Some(verifier) = s.verifier.as_mut() {
+                                    verifier.on_new_prev_hash(&m);
+                                }
+                                s.pending_templates.remove(&m.template_id)
+                            })
+                            .unwrap();
+                        if let Some(template) = pending {
+                            println!(
+                                "WARNING: no transaction data for template {}, it is used \
+                                 without witness commitment",
+                                template.template_id
+                            );
+                            new_template_sender.send((template, None)).await.unwrap();
+                        }
                         new_prev_hash_sender.send(m).await.unwrap()
                     }
-                    TemplateDistribution::SubmitSolution(_) => todo!(),
+                    m => println!(
+                        "ERROR: unexpected message from the template provider: {:?}",
+                        m
+                    ),
                 },
-                _ => todo!(),
+                Ok(roles_logic_sv2::handlers::SendTo_::None(_)) => (),
+                Ok(_) => println!(
+                    "ERROR: unexpected answer to message {} from the template provider",
+                    message_type
+                ),
+                Err(e) => println!(
+                    "ERROR: can not handle message {} from the template provider: {:?}",
+                    message_type, e
+                ),
             }
         }
+        println!("Template receiver stopped");
+    }
+
+    /// Park `template` until the template provider send its transaction data, the template is
+    /// then relayed to the pool with the witness data (see `handle_request_tx_data_success`)
+    async fn request_transaction_data(self_: Arc<Mutex<Self>>, template: NewTemplate<'static>) {
+        let template_id = template.template_id;
+        self_
+            .safe_lock(|s| s.pending_templates.insert(template_id, template))
+            .unwrap();
+        let sv2_frame: StdFrame = PoolMessages::TemplateDistribution(
+            TemplateDistribution::RequestTransactionData(RequestTransactionData { template_id }),
+        )
+        .into_frame()
+        .unwrap();
+        if Self::send(self_.clone(), sv2_frame).await.is_err() {
+            println!(
+                "Failed to request the transaction data of template {}",
+                template_id
+            );
+        }
+    }
+
+    /// Remember what the template provider answered to a solution, the oldest answer is dropped
+    /// when there are more than `RECENT_SOLUTIONS`
+    fn record_solution_outcome(&mut self, outcome: SolutionOutcome) {
+        if self.solution_outcomes.len() == RECENT_SOLUTIONS {
+            self.solution_outcomes.pop_front();
+        }
+        self.solution_outcomes.push_back(outcome);
+    }
+
+    /// Last answers of the template provider to the solutions, oldest first
+    #[allow(dead_code)]
+    pub fn solution_outcomes(&self) -> &VecDeque<SolutionOutcome> {
+        &self.solution_outcomes
     }
 
     pub async fn send(self_: Arc<Mutex<Self>>, sv2_frame: StdFrame) -> Result<(), ()> {
         let either_frame = sv2_frame.into();
         let sender = self_.safe_lock(|self_| self_.sender.clone()).unwrap();
-        match sender.send(either_frame).await {
-            Ok(_) => Ok(()),
-            Err(_) => {
-                todo!()
+        sender.send(either_frame).await.map_err(|_| ())
+    }
+
+    /// Send the solution to the template provider retrying with backoff if the connection is
+    /// down. The sender is read again at every attempt so that a new connection is picked up.
+    async fn send_solution(self_: Arc<Mutex<Self>>, solution: SubmitSolution<'static>) {
+        let mut backoff = SOLUTION_RETRY_BACKOFF;
+        for attempt in 1..=SOLUTION_SEND_ATTEMPTS {
+            let sv2_frame: StdFrame = match PoolMessages::TemplateDistribution(
+                TemplateDistribution::SubmitSolution(solution.clone()),
+            )
+            .into_frame()
+            {
+                Ok(sv2_frame) => sv2_frame,
+                Err(e) => {
+                    println!(
+                        "Can not build frame for solution of template {}: {}",
+                        solution.template_id, e
+                    );
+                    return;
+                }
+            };
+            if Self::send(self_.clone(), sv2_frame).await.is_ok() {
+                return;
+            }
+            if attempt < SOLUTION_SEND_ATTEMPTS {
+                println!(
+                    "Failed to send solution for template {} (attempt {}), retrying in {:?}",
+                    solution.template_id, attempt, backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+        println!(
+            "ERROR: BLOCK LOST, solution for template {} with nonce {} not delivered after {} attempts",
+            solution.template_id, solution.header_nonce, SOLUTION_SEND_ATTEMPTS
+        );
+    }
+
+    async fn on_new_solution(
+        self_: Arc<Mutex<Self>>,
+        rx: Receiver<SubmitSolution<'static>>,
+        shutdown: Receiver<()>,
+    ) {
+        // Idempotency keys of the last forwarded solutions, a retried solution must not be
+        // submitted twice
+        let mut forwarded: VecDeque<(u64, u32)> = VecDeque::with_capacity(RECENT_SOLUTIONS);
+        loop {
+            let solution = tokio::select! {
+                solution = rx.recv() => match solution {
+                    Ok(solution) => solution,
+                    Err(_) => break,
+                },
+                _ = shutdown.recv() => break,
+            };
+            let key = (solution.template_id, solution.header_nonce);
+            if forwarded.contains(&key) {
+                println!(
+                    "Solution for template {} with nonce {} already forwarded, ignoring it",
+                    key.0, key.1
+                );
+                continue;
+            }
+            if forwarded.len() == RECENT_SOLUTIONS {
+                forwarded.pop_front();
             }
+            forwarded.push_back(key);
+            #[cfg(feature = "rpc-verify")]
+            if !Self::verify_solution(&self_, &solution).await {
+                continue;
+            }
+            Self::send_solution(self_.clone(), solution).await;
         }
     }
 
-    async fn on_new_solution(self_: Arc<Mutex<Self>>, rx: Receiver<SubmitSolution<'static>>) {
-        while let Ok(solution) = rx.recv().await {
-            let sv2_frame: StdFrame =
-                PoolMessages::TemplateDistribution(TemplateDistribution::SubmitSolution(solution))
-                    .try_into()
+    /// Check the block of `solution` with bitcoind, see `rpc_verify`. Return false if bitcoind
+    /// reject the block, the solution is then not forwarded and it is recorded as rejected. If
+    /// the block is not rejected the tip of bitcoind is checked once the solution is forwarded.
+    #[cfg(feature = "rpc-verify")]
+    async fn verify_solution(self_: &Arc<Mutex<Self>>, solution: &SubmitSolution<'static>) -> bool {
+        let (rpc, block) = match self_
+            .safe_lock(|s| s.verifier.as_ref().map(|v| (v.rpc(), v.block(solution))))
+            .unwrap()
+        {
+            Some(verifier) => verifier,
+            None => return true,
+        };
+        let (template_id, header_nonce) = (solution.template_id, solution.header_nonce);
+        let block = match block {
+            Some(block) => block,
+            None => {
+                println!(
+                    "RPC VERIFY: can not build the block of template {}, solution forwarded \
+                     without check",
+                    template_id
+                );
+                return true;
+            }
+        };
+        match tokio::time::timeout(BLOCK_PROPOSAL_TIMEOUT, rpc.propose_block(&block)).await {
+            Ok(Ok(BlockProposal::Rejected(reason))) => {
+                println!(
+                    "ERROR: RPC VERIFY: bitcoind rejected the block of template {} with nonce {}: \
+                     {}, solution not forwarded",
+                    template_id, header_nonce, reason
+                );
+                let outcome = SolutionOutcome::Rejected {
+                    template_id,
+                    header_nonce,
+                    reason,
+                };
+                self_
+                    .safe_lock(|s| s.record_solution_outcome(outcome))
                     .unwrap();
-            Self::send(self_.clone(), sv2_frame).await.unwrap();
+                return false;
+            }
+            Ok(Ok(proposal)) => println!(
+                "RPC VERIFY: block of template {} checked by bitcoind: {:?}",
+                template_id, proposal
+            ),
+            Ok(Err(e)) => println!(
+                "RPC VERIFY: can not check the block of template {}: {:?}, solution forwarded",
+                template_id, e
+            ),
+            Err(_) => println!(
+                "RPC VERIFY: bitcoind did not check the block of template {} in {:?}, solution \
+                 forwarded",
+                template_id, BLOCK_PROPOSAL_TIMEOUT
+            ),
         }
+        let coinbase = solution.coinbase_tx.to_vec();
+        task::spawn(async move { rpc.verify_block_accepted(&coinbase).await });
+        true
+    }
+}
+
+/// True if the witness commitment of `template` must be computed by the pool, the template has
+/// transactions and the template provider did not add the commitment to the coinbase outputs
+fn needs_transaction_data(template: &NewTemplate) -> bool {
+    let has_commitment = template_outputs(template)
+        .map(|outputs| outputs.iter().any(TemplateWitness::is_commitment))
+        .unwrap_or(false);
+    !template.merkle_path.is_empty() && !has_commitment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::test_utils::in_memory;
+    use binary_sv2::{to_bytes, GetSize, Serialize};
+    use bitcoin::hashes::Hash;
+    use roles_logic_sv2::{
+        common_messages_sv2::SetupConnectionSuccess,
+        parsers::{CommonMessages, Sv2Message},
+        template_distribution_sv2::{CoinbaseOutputDataSize, RequestTransactionDataSuccess},
+    };
+    use solution_confirmation::{
+        SubmitSolutionError, SubmitSolutionSuccess, MESSAGE_TYPE_SUBMIT_SOLUTION_ERROR,
+        MESSAGE_TYPE_SUBMIT_SOLUTION_SUCCESS,
+    };
+    use tokio::net::TcpListener;
+
+    /// Frame a message that is not in `PoolMessages`
+    fn frame<T: Serialize + GetSize>(message_type: u8, message: T) -> EitherFrame {
+        let payload = to_bytes(message).unwrap();
+        let mut bytes = vec![0, 0, message_type];
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+        bytes.extend_from_slice(&payload);
+        StdFrame::from_bytes(bytes).unwrap().into()
+    }
+
+    type Channel = (Receiver<EitherFrame>, Sender<EitherFrame>);
+
+    /// Start a template receiver connected in memory to the returned template provider end
+    fn start_template_rx() -> (
+        Arc<Mutex<TemplateRx>>,
+        Channel,
+        Receiver<(NewTemplate<'static>, Option<TemplateWitness>)>,
+        Receiver<SetNewPrevHash<'static>>,
+        Sender<()>,
+    ) {
+        let ((receiver, sender), template_provider) = in_memory();
+        let (new_template_sender, new_template_receiver) = async_channel::bounded(1);
+        let (new_prev_hash_sender, new_prev_hash_receiver) = async_channel::bounded(1);
+        let (shutdown_sender, shutdown) = async_channel::bounded(1);
+        let (_, resync) = async_channel::bounded(1);
+        let self_ = Arc::new(Mutex::new(TemplateRx {
+            address: "127.0.0.1:0".parse().unwrap(),
+            receiver,
+            sender,
+            new_template_sender,
+            new_prev_hash_sender,
+            pending_templates: HashMap::new(),
+            transaction_data: HashMap::new(),
+            solution_outcomes: VecDeque::new(),
+            #[cfg(feature = "rpc-verify")]
+            verifier: None,
+        }));
+        task::spawn(TemplateRx::start(self_.clone(), shutdown, resync));
+        (
+            self_,
+            template_provider,
+            new_template_receiver,
+            new_prev_hash_receiver,
+            shutdown_sender,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_solution_outcomes() {
+        let (self_, (_tp_receiver, tp_sender), _, _, _shutdown) = start_template_rx();
+
+        // A message that the template provider should never send is logged and skipped
+        let unexpected: StdFrame = PoolMessages::TemplateDistribution(
+            TemplateDistribution::CoinbaseOutputDataSize(CoinbaseOutputDataSize {
+                coinbase_output_max_additional_size: 0,
+            }),
+        )
+        .into_frame()
+        .unwrap();
+        tp_sender.send(unexpected.into()).await.unwrap();
+
+        // Mock template provider that accept a solution and reject the next one
+        let accepted = SubmitSolutionSuccess {
+            template_id: 1,
+            header_nonce: 7,
+        };
+        let rejected = SubmitSolutionError {
+            template_id: 2,
+            header_nonce: 8,
+            error_code: "high-hash".to_string().try_into().unwrap(),
+        };
+        tp_sender
+            .send(frame(MESSAGE_TYPE_SUBMIT_SOLUTION_SUCCESS, accepted))
+            .await
+            .unwrap();
+        tp_sender
+            .send(frame(MESSAGE_TYPE_SUBMIT_SOLUTION_ERROR, rejected))
+            .await
+            .unwrap();
+
+        let outcomes = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let outcomes = self_.safe_lock(|s| s.solution_outcomes().clone()).unwrap();
+                if outcomes.len() == 2 {
+                    break outcomes;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            outcomes[0],
+            SolutionOutcome::Accepted {
+                template_id: 1,
+                header_nonce: 7,
+            }
+        );
+        assert_eq!(
+            outcomes[1],
+            SolutionOutcome::Rejected {
+                template_id: 2,
+                header_nonce: 8,
+                reason: "high-hash".to_string(),
+            }
+        );
+    }
+
+    async fn forwarded_nonce(tp_receiver: &Receiver<EitherFrame>) -> u32 {
+        let mut frame: StdFrame = tokio::time::timeout(Duration::from_secs(1), tp_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        SubmitSolution::from_frame(&mut frame).unwrap().header_nonce
+    }
+
+    #[tokio::test]
+    async fn test_solution_retry() {
+        let (self_, (tp_receiver, _tp_sender), _, _, _shutdown) = start_template_rx();
+        // The connection with the template provider is down
+        let (closed, _) = async_channel::bounded(1);
+        let connected = self_
+            .safe_lock(|s| std::mem::replace(&mut s.sender, closed))
+            .unwrap();
+        let (solution_sender, solution_receiver) = async_channel::bounded(3);
+        let (_shutdown_sender, shutdown) = async_channel::bounded(1);
+        task::spawn(TemplateRx::on_new_solution(
+            self_.clone(),
+            solution_receiver,
+            shutdown,
+        ));
+        let solution = |header_nonce| SubmitSolution {
+            template_id: 1,
+            version: 0x2000_0000,
+            header_timestamp: 1_700_000_000,
+            header_nonce,
+            coinbase_tx: vec![1, 2, 3].try_into().unwrap(),
+        };
+        solution_sender.send(solution(5)).await.unwrap();
+        tokio::time::sleep(SOLUTION_RETRY_BACKOFF / 2).await;
+        assert!(tp_receiver.is_empty());
+
+        // The connection is back before the next attempt
+        self_.safe_lock(|s| s.sender = connected).unwrap();
+        assert_eq!(forwarded_nonce(&tp_receiver).await, 5);
+
+        // A solution already forwarded is not submitted again
+        solution_sender.send(solution(5)).await.unwrap();
+        solution_sender.send(solution(6)).await.unwrap();
+        assert_eq!(forwarded_nonce(&tp_receiver).await, 6);
+        assert!(tp_receiver.is_empty());
+    }
+
+    fn template_frame(message: TemplateDistribution<'static>) -> EitherFrame {
+        let frame: StdFrame = PoolMessages::TemplateDistribution(message)
+            .into_frame()
+            .unwrap();
+        frame.into()
+    }
+
+    #[tokio::test]
+    async fn test_transaction_data() {
+        let (_, (tp_receiver, tp_sender), new_template_receiver, _, _shutdown) =
+            start_template_rx();
+        // A template with transactions and without witness commitment in the coinbase outputs
+        let template = NewTemplate {
+            template_id: 3,
+            future_template: true,
+            version: 2,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 3, 0x10, 0x27, 0x00].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: binary_sv2::Seq0255::new(vec![[1_u8; 32].into()]).unwrap(),
+        };
+        tp_sender
+            .send(template_frame(TemplateDistribution::NewTemplate(template)))
+            .await
+            .unwrap();
+
+        // The template is relayed only once the transaction data is received
+        let mut request: StdFrame = tp_receiver.recv().await.unwrap().try_into().unwrap();
+        let request = RequestTransactionData::from_frame(&mut request).unwrap();
+        assert_eq!(request.template_id, 3);
+        assert!(new_template_receiver.is_empty());
+
+        let transaction = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::null(),
+                script_sig: vec![1].into(),
+                sequence: u32::MAX,
+                witness: vec![],
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 1,
+                script_pubkey: vec![0x51].into(),
+            }],
+        };
+        let txid = transaction.txid();
+        let transaction: binary_sv2::B016M = bitcoin::consensus::serialize(&transaction)
+            .try_into()
+            .unwrap();
+        let success = RequestTransactionDataSuccess {
+            template_id: 3,
+            excess_data: vec![].try_into().unwrap(),
+            transaction_list: binary_sv2::Seq064K::new(vec![transaction]).unwrap(),
+        };
+        tp_sender
+            .send(template_frame(
+                TemplateDistribution::RequestTransactionDataSuccess(success),
+            ))
+            .await
+            .unwrap();
+        let (template, witness) =
+            tokio::time::timeout(Duration::from_secs(1), new_template_receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(template.template_id, 3);
+        assert_eq!(witness.unwrap().wtxids, vec![txid.as_hash().into_inner()]);
+    }
+
+    #[cfg(feature = "rpc-verify")]
+    #[tokio::test]
+    async fn test_rejected_block_not_forwarded() {
+        use crate::lib::rpc_verify::tests::{mock_bitcoind, prev_hash, solution, template};
+        use serde_json::{json, Value};
+        let (self_, (tp_receiver, tp_sender), new_template_receiver, new_prev_hash_receiver, _) =
+            start_template_rx();
+        // Mock bitcoind that reject the first block and accept the second one
+        let (rpc, _requests) = mock_bitcoind(vec![json!("bad-txnmrklroot"), Value::Null]).await;
+        self_
+            .safe_lock(|s| s.verifier = Some(BlockVerifier::new(rpc)))
+            .unwrap();
+        tp_sender
+            .send(template_frame(TemplateDistribution::NewTemplate(template(
+                1,
+                vec![],
+            ))))
+            .await
+            .unwrap();
+        new_template_receiver.recv().await.unwrap();
+        tp_sender
+            .send(template_frame(TemplateDistribution::SetNewPrevHash(
+                prev_hash(1),
+            )))
+            .await
+            .unwrap();
+        new_prev_hash_receiver.recv().await.unwrap();
+
+        let (solution_sender, solution_receiver) = async_channel::bounded(2);
+        let (_shutdown_sender, shutdown) = async_channel::bounded(1);
+        task::spawn(TemplateRx::on_new_solution(
+            self_.clone(),
+            solution_receiver,
+            shutdown,
+        ));
+        solution_sender.send(solution(1, 1)).await.unwrap();
+        solution_sender.send(solution(1, 2)).await.unwrap();
+
+        // Only the second solution reach the template provider
+        let mut forwarded: StdFrame =
+            tokio::time::timeout(Duration::from_secs(5), tp_receiver.recv())
+                .await
+                .unwrap()
+                .unwrap()
+                .try_into()
+                .unwrap();
+        let forwarded = SubmitSolution::from_frame(&mut forwarded).unwrap();
+        assert_eq!(forwarded.header_nonce, 2);
+        let outcomes = self_.safe_lock(|s| s.solution_outcomes().clone()).unwrap();
+        assert_eq!(
+            outcomes,
+            vec![SolutionOutcome::Rejected {
+                template_id: 1,
+                header_nonce: 1,
+                reason: "bad-txnmrklroot".to_string(),
+            }]
+        );
+    }
+
+    /// Template without transactions, it is relayed as soon as it is received
+    fn empty_template(template_id: u64) -> NewTemplate<'static> {
+        NewTemplate {
+            template_id,
+            future_template: true,
+            version: 2,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 3, 0x10, 0x27, 0x00].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 5_000_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: binary_sv2::Seq0255::new(vec![]).unwrap(),
+        }
+    }
+
+    /// Mock template provider, accept a connection of the template receiver and answer its
+    /// `SetupConnection`
+    async fn accept_template_rx(listener: &TcpListener) -> Channel {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (receiver, sender): Channel = PlainConnection::new(stream).await;
+        let mut setup: StdFrame = receiver.recv().await.unwrap().try_into().unwrap();
+        let message_type = setup.get_header().unwrap().msg_type();
+        let setup: Result<CommonMessages, _> = (message_type, setup.payload()).try_into();
+        assert!(matches!(setup, Ok(CommonMessages::SetupConnection(_))));
+        let success: StdFrame = PoolMessages::Common(CommonMessages::SetupConnectionSuccess(
+            SetupConnectionSuccess {
+                used_version: 2,
+                flags: 0,
+            },
+        ))
+        .into_frame()
+        .unwrap();
+        sender.send(success.into()).await.unwrap();
+        (receiver, sender)
+    }
+
+    async fn next_template_id(
+        receiver: &Receiver<(NewTemplate<'static>, Option<TemplateWitness>)>,
+    ) -> u64 {
+        let (template, _) = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        template.template_id
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (new_template_sender, new_template_receiver) = async_channel::bounded(1);
+        let (new_prev_hash_sender, _new_prev_hash_receiver) = async_channel::bounded(1);
+        let (_solution_sender, solution_receiver) = async_channel::bounded(1);
+        let (shutdown_sender, shutdown) = async_channel::bounded(1);
+        let (_resync_sender, resync) = async_channel::bounded(1);
+        let (template_rx, (_tp_receiver, tp_sender)) = tokio::join!(
+            TemplateRx::connect(
+                address,
+                new_template_sender,
+                new_prev_hash_sender,
+                solution_receiver,
+                shutdown,
+                resync,
+                #[cfg(feature = "rpc-verify")]
+                None,
+            ),
+            accept_template_rx(&listener)
+        );
+        tp_sender
+            .send(template_frame(TemplateDistribution::NewTemplate(
+                empty_template(1),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(next_template_id(&new_template_receiver).await, 1);
+
+        // The template provider drop the connection, the templates resume once it is back
+        drop((_tp_receiver, tp_sender));
+        let (_tp_receiver, tp_sender) =
+            tokio::time::timeout(Duration::from_secs(5), accept_template_rx(&listener))
+                .await
+                .unwrap();
+        tp_sender
+            .send(template_frame(TemplateDistribution::NewTemplate(
+                empty_template(2),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(next_template_id(&new_template_receiver).await, 2);
+
+        shutdown_sender.close();
+        tokio::time::timeout(Duration::from_secs(1), template_rx)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_while_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (new_template_sender, new_template_receiver) = async_channel::bounded(1);
+        let (new_prev_hash_sender, _new_prev_hash_receiver) = async_channel::bounded(1);
+        let (_solution_sender, solution_receiver) = async_channel::bounded(1);
+        let (shutdown_sender, shutdown) = async_channel::bounded(1);
+        let (_resync_sender, resync) = async_channel::bounded(1);
+        let (template_rx, template_provider) = tokio::join!(
+            TemplateRx::connect(
+                address,
+                new_template_sender,
+                new_prev_hash_sender,
+                solution_receiver,
+                shutdown,
+                resync,
+                #[cfg(feature = "rpc-verify")]
+                None,
+            ),
+            accept_template_rx(&listener)
+        );
+
+        // The template provider is gone for good, the receiver retry with backoff
+        drop(template_provider);
+        drop(listener);
+        tokio::time::sleep(RECONNECT_BACKOFF * 3).await;
+
+        // Shutdown interrupt the backoff and stop both tasks
+        shutdown_sender.close();
+        tokio::time::timeout(Duration::from_secs(1), template_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(new_template_receiver.is_empty());
     }
 }
@@ -41,12 +41,12 @@ impl SetupConnectionHandler {
         let setup_connection = Self::get_setup_connection_message(address);
 
         let sv2_frame: StdFrame = PoolMessages::Common(setup_connection.into())
-            .try_into()
-            .unwrap();
+            .into_frame()
+            .map_err(|_| ())?;
         let sv2_frame = sv2_frame.into();
         sender.send(sv2_frame).await.map_err(|_| ())?;
 
-        let mut incoming: StdFrame = receiver.recv().await.unwrap().try_into().unwrap();
+        let mut incoming: StdFrame = receiver.recv().await.map_err(|_| ())?.try_into().unwrap();
         let message_type = incoming.get_header().unwrap().msg_type();
         let payload = incoming.payload();
         ParseUpstreamCommonMessages::handle_message_common(
@@ -0,0 +1,47 @@
+//! Confirmation of the submitted solutions by the template provider. These messages are not in the
+//! spec, they are sent only by the template providers that confirm the solutions, so they are
+//! decoded here and not added to the template distribution subprotocol.
+use super::SolutionOutcome;
+use binary_sv2::{binary_codec_sv2, from_bytes, Deserialize, Serialize, Str0255};
+
+pub const MESSAGE_TYPE_SUBMIT_SOLUTION_SUCCESS: u8 = 0x77;
+pub const MESSAGE_TYPE_SUBMIT_SOLUTION_ERROR: u8 = 0x78;
+
+/// Sent when the block built from a `SubmitSolution` is accepted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SubmitSolutionSuccess {
+    pub template_id: u64,
+    pub header_nonce: u32,
+}
+
+/// Sent when the block built from a `SubmitSolution` is rejected, `error_code` is the reject
+/// reason of the node eg `high-hash` or `duplicate`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubmitSolutionError<'decoder> {
+    pub template_id: u64,
+    pub header_nonce: u32,
+    pub error_code: Str0255<'decoder>,
+}
+
+/// Decode `payload` if `message_type` is a confirmation, `None` for any other message type
+pub fn parse(
+    message_type: u8,
+    payload: &mut [u8],
+) -> Option<Result<SolutionOutcome, binary_sv2::Error>> {
+    match message_type {
+        MESSAGE_TYPE_SUBMIT_SOLUTION_SUCCESS => Some(
+            from_bytes::<SubmitSolutionSuccess>(payload).map(|m| SolutionOutcome::Accepted {
+                template_id: m.template_id,
+                header_nonce: m.header_nonce,
+            }),
+        ),
+        MESSAGE_TYPE_SUBMIT_SOLUTION_ERROR => Some(from_bytes::<SubmitSolutionError>(payload).map(
+            |m| SolutionOutcome::Rejected {
+                template_id: m.template_id,
+                header_nonce: m.header_nonce,
+                reason: String::from_utf8_lossy(&m.error_code.to_vec()).into_owned(),
+            },
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,229 @@
+//! Record and replay SV2 message streams against the pool.
+//!
+//! A recording is a text file with one frame per line: `>` followed by the hex of a frame sent
+//! to the pool or `<` followed by the hex of a frame that the pool is expected to send back. Empty
+//! lines and lines that start with `#` are ignored. The frames are the plain (not noise encrypted)
+//! SV2 frames, the replay uses an in-memory transport in place of the noise connection.
+use crate::{EitherFrame, StdFrame};
+use async_channel::{bounded, Receiver, Sender};
+use codec_sv2::Frame;
+use std::{convert::TryInto, fmt::Write as _, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the downstream to the pool
+    ToPool,
+    /// From the pool to the downstream
+    FromPool,
+}
+
+pub type Recording = Vec<(Direction, Vec<u8>)>;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    /// Line of the recording that can not be parsed
+    InvalidLine(usize),
+    /// Frame of the recording that is not a valid SV2 frame
+    InvalidFrame(usize),
+    /// The pool closed the connection before sending the frame at `index`
+    Closed {
+        index: usize,
+    },
+    /// The pool sent `actual` in place of the frame at `index`
+    Mismatch {
+        index: usize,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// Serialize a frame into the bytes that would be sent on the wire (before noise encryption)
+pub fn frame_to_bytes(frame: EitherFrame) -> Option<Vec<u8>> {
+    let frame: StdFrame = frame.try_into().ok()?;
+    let mut bytes = vec![0; frame.encoded_length()];
+    frame.serialize(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+pub fn save(path: impl AsRef<Path>, recording: &[(Direction, Vec<u8>)]) -> std::io::Result<()> {
+    let mut text = String::new();
+    for (direction, frame) in recording {
+        text.push(match direction {
+            Direction::ToPool => '>',
+            Direction::FromPool => '<',
+        });
+        text.push(' ');
+        for byte in frame {
+            write!(text, "{:02x}", byte).unwrap();
+        }
+        text.push('\n');
+    }
+    std::fs::write(path, text)
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Recording, ReplayError> {
+    let text = std::fs::read_to_string(path)?;
+    let mut recording = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (direction, hex) = if let Some(hex) = line.strip_prefix('>') {
+            (Direction::ToPool, hex.trim())
+        } else if let Some(hex) = line.strip_prefix('<') {
+            (Direction::FromPool, hex.trim())
+        } else {
+            return Err(ReplayError::InvalidLine(n + 1));
+        };
+        if hex.len() % 2 != 0 {
+            return Err(ReplayError::InvalidLine(n + 1));
+        }
+        let frame = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| ReplayError::InvalidLine(n + 1))?;
+        recording.push((direction, frame));
+    }
+    Ok(recording)
+}
+
+/// Two ends of an in-memory transport. The pool side can be passed to `Downstream::new` in place
+/// of the channels returned by the noise `Connection`, the other side is used by `replay`.
+pub fn in_memory() -> (
+    (Receiver<EitherFrame>, Sender<EitherFrame>),
+    (Receiver<EitherFrame>, Sender<EitherFrame>),
+) {
+    let (to_pool_sender, to_pool_receiver) = bounded(10);
+    let (from_pool_sender, from_pool_receiver) = bounded(10);
+    (
+        (to_pool_receiver, from_pool_sender),
+        (from_pool_receiver, to_pool_sender),
+    )
+}
+
+/// Downstream side of an in-memory transport that record the frames exchanged with the pool, the
+/// recording can then be saved with `save` and replayed with `replay`
+pub struct Recorder {
+    receiver: Receiver<EitherFrame>,
+    sender: Sender<EitherFrame>,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new(receiver: Receiver<EitherFrame>, sender: Sender<EitherFrame>) -> Self {
+        Self {
+            receiver,
+            sender,
+            recording: Vec::new(),
+        }
+    }
+
+    /// Send `frame` to the pool
+    pub async fn send(&mut self, frame: StdFrame) -> Result<(), ReplayError> {
+        let index = self.recording.len();
+        let bytes = frame_to_bytes(frame.into()).ok_or(ReplayError::InvalidFrame(index))?;
+        let frame =
+            StdFrame::from_bytes(bytes.clone()).map_err(|_| ReplayError::InvalidFrame(index))?;
+        self.sender
+            .send(frame.into())
+            .await
+            .map_err(|_| ReplayError::Closed { index })?;
+        self.recording.push((Direction::ToPool, bytes));
+        Ok(())
+    }
+
+    /// Wait for the next frame sent by the pool
+    pub async fn recv(&mut self) -> Result<StdFrame, ReplayError> {
+        let index = self.recording.len();
+        let frame = self
+            .receiver
+            .recv()
+            .await
+            .map_err(|_| ReplayError::Closed { index })?;
+        let bytes = frame_to_bytes(frame).ok_or(ReplayError::InvalidFrame(index))?;
+        self.recording.push((Direction::FromPool, bytes.clone()));
+        StdFrame::from_bytes(bytes).map_err(|_| ReplayError::InvalidFrame(index))
+    }
+
+    /// Frames exchanged so far
+    pub fn recording(&self) -> &[(Direction, Vec<u8>)] {
+        &self.recording
+    }
+}
+
+/// Send the `ToPool` frames of `recording` with `sender` and check that each `FromPool` frame is
+/// what the pool send on `receiver`, in order.
+pub async fn replay(
+    recording: &[(Direction, Vec<u8>)],
+    receiver: &Receiver<EitherFrame>,
+    sender: &Sender<EitherFrame>,
+) -> Result<(), ReplayError> {
+    for (index, (direction, frame)) in recording.iter().enumerate() {
+        match direction {
+            Direction::ToPool => {
+                let frame = StdFrame::from_bytes(frame.clone())
+                    .map_err(|_| ReplayError::InvalidFrame(index))?;
+                sender
+                    .send(frame.into())
+                    .await
+                    .map_err(|_| ReplayError::Closed { index })?;
+            }
+            Direction::FromPool => {
+                let actual = receiver
+                    .recv()
+                    .await
+                    .map_err(|_| ReplayError::Closed { index })?;
+                let actual = frame_to_bytes(actual).ok_or(ReplayError::InvalidFrame(index))?;
+                if actual != *frame {
+                    return Err(ReplayError::Mismatch {
+                        index,
+                        expected: frame.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::mining_pool::setup_connection::SetupConnectionHandler;
+    use roles_logic_sv2::utils::Mutex;
+    use std::sync::Arc;
+
+    const SETUP_SESSION: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/setup_session.txt");
+
+    #[test]
+    fn test_save_load() {
+        let recording = load(SETUP_SESSION).unwrap();
+        let path = std::env::temp_dir().join("pool_test_save_load.txt");
+        save(&path, &recording).unwrap();
+        assert_eq!(load(&path).unwrap(), recording);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_setup_session() {
+        let recording = load(SETUP_SESSION).unwrap();
+        let ((mut pool_receiver, mut pool_sender), (receiver, sender)) = in_memory();
+        let pool = tokio::task::spawn(async move {
+            let handler = Arc::new(Mutex::new(SetupConnectionHandler::new(2, 2)));
+            SetupConnectionHandler::setup(handler, &mut pool_receiver, &mut pool_sender).await
+        });
+        replay(&recording, &receiver, &sender).await.unwrap();
+        let downstream_data = pool.await.unwrap().unwrap();
+        assert!(!downstream_data.header_only);
+    }
+}
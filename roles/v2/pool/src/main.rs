@@ -5,29 +5,131 @@ use codec_sv2::{
 };
 use roles_logic_sv2::{
     bitcoin::{secp256k1::Secp256k1, Network, PrivateKey, PublicKey},
+    common_messages_sv2::Protocol,
     parsers::PoolMessages,
+    utils::{Id, Mutex},
 };
 use serde::Deserialize;
+use std::{convert::TryFrom, sync::Arc};
 
 mod lib;
 
-use lib::{mining_pool::Pool, template_receiver::TemplateRx};
+use lib::{
+    authority::{read_config, rotate_on_sighup, AuthorityKeys},
+    mining_pool::{solution_sender::SolutionSender, Pool},
+    template_receiver::TemplateRx,
+};
 
 pub type Message = PoolMessages<'static>;
 pub type StdFrame = StandardSv2Frame<Message>;
 pub type EitherFrame = StandardEitherFrame<Message>;
 
-const HOM_GROUP_ID: u32 = u32::MAX;
+/// Group channel id of the header only channels, the group ids are generated by `Id` that never
+/// yields `Id::RESERVED` so it can not collide with a real group
+const HOM_GROUP_ID: u32 = Id::RESERVED;
 
+/// Being a const it is part of the binary, with the `zeroize` feature the copy made to derive the
+/// public key is wiped
 const PRIVATE_KEY_BTC: [u8; 32] = [34; 32];
 const NETWORK: Network = Network::Testnet;
 
 const BLOCK_REWARD: u64 = 625_000_000_000;
 
 fn new_pub_key() -> PublicKey {
-    let priv_k = PrivateKey::from_slice(&PRIVATE_KEY_BTC, NETWORK).unwrap();
+    #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+    let mut priv_k = PrivateKey::from_slice(&PRIVATE_KEY_BTC, NETWORK).unwrap();
     let secp = Secp256k1::default();
-    PublicKey::from_private_key(&secp, &priv_k)
+    let pub_k = PublicKey::from_private_key(&secp, &priv_k);
+    #[cfg(feature = "zeroize")]
+    wipe_private_key(&mut priv_k);
+    pub_k
+}
+
+/// `PrivateKey` does not wipe its secret key on drop
+#[cfg(feature = "zeroize")]
+fn wipe_private_key(priv_k: &mut PrivateKey) {
+    use noise_sv2::zeroize::Zeroize;
+    use roles_logic_sv2::bitcoin::secp256k1::constants::SECRET_KEY_SIZE;
+    // SAFETY: the secret key is an array of SECRET_KEY_SIZE bytes and it is borrowed mutably
+    unsafe { std::slice::from_raw_parts_mut(priv_k.key.as_mut_ptr(), SECRET_KEY_SIZE) }.zeroize();
+}
+
+/// What to do when a downstream find a solution and the solution channel is full
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SolutionChannelPolicy {
+    /// Queue the solution until the channel has capacity, the queue hold up to `solution_capacity`
+    /// solutions and a solution is lost only when it is full
+    Block,
+    /// Drop the oldest solution in the channel and log a warning
+    DropOldest,
+}
+
+/// What to do with the shares that meet the channel target but not the bitcoin target, the
+/// shares that find a block are always counted
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DownstreamSharePolicy {
+    /// Count the share in the `new_shares_sum` sent to the downstream and log it
+    Count,
+    /// Only log the share, the payout is computed from the share log
+    Forward,
+    /// Like `Count` for the first `max_shares_per_sec` shares of a channel in a second, the
+    /// others are refused
+    RateLimit { max_shares_per_sec: u32 },
+}
+
+impl Default for DownstreamSharePolicy {
+    fn default() -> Self {
+        Self::Count
+    }
+}
+
+/// Capacity of the channels used to pass messages between the template receiver and the pool
+/// - new_template: templates are never dropped, when full the template receiver wait
+/// - new_prev_hash: prev hashes are never dropped, when full the template receiver wait
+/// - solution: when full `solution_policy` is applied
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ChannelsConfig {
+    pub new_template_capacity: usize,
+    pub new_prev_hash_capacity: usize,
+    pub solution_capacity: usize,
+    pub solution_policy: SolutionChannelPolicy,
+}
+
+impl Default for ChannelsConfig {
+    fn default() -> Self {
+        Self {
+            new_template_capacity: 10,
+            new_prev_hash_capacity: 10,
+            solution_capacity: 10,
+            solution_policy: SolutionChannelPolicy::Block,
+        }
+    }
+}
+
+/// Valid shares are acked and logged in batches, a batch is flushed when it contains `max_shares`
+/// shares or every `flush_interval_ms` milliseconds. Shares that find a block flush the batch
+/// right away.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct ShareBatchConfig {
+    pub max_shares: usize,
+    pub flush_interval_ms: u64,
+}
+
+impl ShareBatchConfig {
+    pub fn flush_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.flush_interval_ms)
+    }
+}
+
+/// A channel that submit more than `max_invalid_shares` invalid shares in `window_sec` seconds is
+/// considered misbehaving and its downstream is disconnected
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct InvalidShareLimitConfig {
+    pub max_invalid_shares: u32,
+    pub window_sec: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,6 +139,183 @@ pub struct Configuration {
     pub authority_public_key: EncodedEd25519PublicKey,
     pub authority_secret_key: EncodedEd25519SecretKey,
     pub cert_validity_sec: u64,
+    #[serde(default)]
+    pub channels: ChannelsConfig,
+    /// Max bytes of a frame buffered for a downstream, a downstream that send a bigger frame is
+    /// disconnected as soon as the buffered bytes exceed it
+    #[serde(default)]
+    pub max_buffered_bytes: Option<usize>,
+    /// Shares with an ntime bigger than now + `max_ntime_drift` seconds are rejected
+    #[serde(default)]
+    pub max_ntime_drift: Option<u32>,
+    /// Channel targets (computed from the hash rate or requested with `UpdateChannel`) are never
+    /// easier than the target of this difficulty
+    #[serde(default)]
+    pub min_channel_difficulty: Option<u64>,
+    /// Downstreams that do not support any protocol version in
+    /// `min_protocol_version..=max_protocol_version` are refused
+    #[serde(default = "default_protocol_version")]
+    pub min_protocol_version: u16,
+    #[serde(default = "default_protocol_version")]
+    pub max_protocol_version: u16,
+    /// If present used as nLockTime of the coinbase transaction in place of the template one
+    #[serde(default)]
+    pub coinbase_locktime: Option<u32>,
+    /// If present used as nSequence of the coinbase input in place of the template one
+    #[serde(default)]
+    pub coinbase_input_sequence: Option<u32>,
+    /// Downstreams that do not complete the connection setup within this many seconds are
+    /// dropped
+    #[serde(default = "default_setup_timeout_sec")]
+    pub setup_timeout_sec: u64,
+    /// If present the valid shares are acked in batches instead of one by one
+    #[serde(default)]
+    pub share_batch: Option<ShareBatchConfig>,
+    /// Applied to the shares that meet the channel target but not the bitcoin target
+    #[serde(default)]
+    pub downstream_share_policy: DownstreamSharePolicy,
+    /// If present the current job is sent again to the downstreams every this many seconds,
+    /// unless a new template or prev hash arrived in the meantime
+    #[serde(default)]
+    pub job_refresh_interval_sec: Option<u32>,
+    /// If present a downstream can not open more than this many channels, the next open channel
+    /// requests are refused with an `OpenMiningChannelError`
+    #[serde(default)]
+    pub max_channels_per_downstream: Option<usize>,
+    /// If present the downstreams that submit too many invalid shares are disconnected
+    #[serde(default)]
+    pub invalid_share_limit: Option<InvalidShareLimitConfig>,
+    /// If present only these (protocol discriminant, message type) pairs are accepted from the
+    /// downstreams after the connection setup, the other messages are dropped and logged
+    #[serde(default)]
+    pub allowed_message_types: Option<Vec<(u8, u8)>>,
+    /// If present a downstream that disconnects after sending a reconnect token in the setup
+    /// connection can reclaim its id, channels and jobs by reconnecting with the same token
+    /// within this many seconds, see `lib::mining_pool::reconnect`
+    #[serde(default)]
+    pub reconnect_grace_sec: Option<u32>,
+    /// If present the admin commands are served on this local address, see
+    /// `lib::mining_pool::admin`
+    #[cfg(feature = "admin")]
+    #[serde(default)]
+    pub admin_address: Option<String>,
+    /// If present solutions are checked against this bitcoind node, see `lib::rpc_verify`
+    #[cfg(feature = "rpc-verify")]
+    pub bitcoind_rpc: Option<lib::rpc_verify::RpcConfig>,
+}
+
+/// Prefix of the environment variables that override the config file, see `Configuration::load`
+const ENV_PREFIX: &str = "POOL_";
+
+impl Configuration {
+    /// Parse the TOML `file` and override its values with the `POOL_*` variables of `env`, eg
+    /// `POOL_LISTEN_ADDRESS` override `listen_address`. The fields of a table are separated by a
+    /// double underscore: `POOL_CHANNELS__SOLUTION_CAPACITY` override `solution_capacity` of
+    /// `[channels]`. The values are parsed as TOML values, if they are not valid TOML they are
+    /// used as strings. A value set in `env` take precedence over the file that take precedence
+    /// over the default, the merged config is validated with `Configuration::validate`.
+    pub fn load(
+        file: &str,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, String> {
+        let mut config: toml::Value = toml::from_str(file).map_err(|e| e.to_string())?;
+        for (key, value) in env {
+            let path = match key.strip_prefix(ENV_PREFIX) {
+                Some(path) => path.to_lowercase(),
+                None => continue,
+            };
+            let value = parse_env_value(&value);
+            let mut table = &mut config;
+            let mut fields = path.split("__").peekable();
+            while let Some(field) = fields.next() {
+                let inner = table
+                    .as_table_mut()
+                    .ok_or_else(|| format!("{} override a value that is not a table", key))?;
+                if fields.peek().is_none() {
+                    inner.insert(field.to_string(), value);
+                    break;
+                }
+                if !inner.contains_key(field) {
+                    inner.insert(field.to_string(), toml::Value::Table(Default::default()));
+                }
+                table = inner.get_mut(field).unwrap();
+            }
+        }
+        let config: Self = config.try_into().map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the values that can not be checked by the deserialization
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, address) in [
+            ("listen_address", &self.listen_address),
+            ("tp_address", &self.tp_address),
+        ] {
+            if address.parse::<std::net::SocketAddr>().is_err() {
+                return Err(format!("{} is not a valid address: {}", name, address));
+            }
+        }
+        #[cfg(feature = "admin")]
+        if let Some(address) = &self.admin_address {
+            match address.parse::<std::net::SocketAddr>() {
+                Ok(a) if a.ip().is_loopback() => (),
+                _ => return Err(format!("admin_address is not a local address: {}", address)),
+            }
+        }
+        if self.min_protocol_version > self.max_protocol_version {
+            return Err(format!(
+                "min_protocol_version {} is bigger than max_protocol_version {}",
+                self.min_protocol_version, self.max_protocol_version
+            ));
+        }
+        if self.share_batch.map_or(false, |b| b.max_shares == 0) {
+            return Err("share_batch.max_shares must be bigger than 0".to_string());
+        }
+        // A zero interval would make the flush task spin
+        if self.share_batch.map_or(false, |b| b.flush_interval_ms == 0) {
+            return Err("share_batch.flush_interval_ms must be bigger than 0".to_string());
+        }
+        if self.job_refresh_interval_sec == Some(0) {
+            return Err("job_refresh_interval_sec must be bigger than 0".to_string());
+        }
+        if self.max_channels_per_downstream == Some(0) {
+            return Err("max_channels_per_downstream must be bigger than 0".to_string());
+        }
+        if self.reconnect_grace_sec == Some(0) {
+            return Err("reconnect_grace_sec must be bigger than 0".to_string());
+        }
+        if self
+            .invalid_share_limit
+            .map_or(false, |l| l.window_sec == 0)
+        {
+            return Err("invalid_share_limit.window_sec must be bigger than 0".to_string());
+        }
+        for (protocol, _) in self.allowed_message_types.iter().flatten() {
+            if Protocol::try_from(*protocol).is_err() {
+                return Err(format!(
+                    "allowed_message_types contains an unknown protocol {}",
+                    protocol
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_env_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Value>(&format!("value = {}", value))
+        .ok()
+        .and_then(|v| v.get("value").cloned())
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+fn default_protocol_version() -> u16 {
+    2
+}
+
+fn default_setup_timeout_sec() -> u64 {
+    10
 }
 
 mod args {
@@ -107,8 +386,8 @@ async fn main() {
             return;
         }
     };
-    let config_file = std::fs::read_to_string(args.config_path).expect("TODO: Error handling");
-    let config = match toml::from_str::<Configuration>(&config_file) {
+    let config_file = read_config(&args.config_path).expect("TODO: Error handling");
+    let config = match Configuration::load(&config_file, std::env::vars()) {
         Ok(cfg) => cfg,
         Err(e) => {
             println!("Failed to parse config file: {}", e);
@@ -116,17 +395,115 @@ async fn main() {
         }
     };
 
-    let (s_new_t, r_new_t) = bounded(10);
-    let (s_prev_hash, r_prev_hash) = bounded(10);
-    let (s_solution, r_solution) = bounded(10);
+    let (s_new_t, r_new_t) = bounded(config.channels.new_template_capacity);
+    let (s_prev_hash, r_prev_hash) = bounded(config.channels.new_prev_hash_capacity);
+    let (s_solution, r_solution) = bounded(config.channels.solution_capacity);
+    let s_solution = SolutionSender::new(
+        s_solution,
+        r_solution.clone(),
+        config.channels.solution_policy,
+    );
+    // Closed on ctrl-c to stop the template receiver
+    let (s_shutdown, r_shutdown) = bounded(1);
+    // Used by the pool to make the template receiver reconnect when it is out of step with the
+    // template provider
+    let (s_resync, r_resync) = bounded(1);
     println!("POOL INTITIALIZING ");
-    TemplateRx::connect(
+    let template_rx = TemplateRx::connect(
         config.tp_address.parse().unwrap(),
         s_new_t,
         s_prev_hash,
         r_solution,
+        r_shutdown,
+        r_resync,
+        #[cfg(feature = "rpc-verify")]
+        config
+            .bitcoind_rpc
+            .clone()
+            .map(lib::rpc_verify::BitcoindRpc::new),
     )
     .await;
     println!("POOL INITIALIZED");
-    Pool::start(config, r_new_t, r_prev_hash, s_solution).await;
+    let authority_keys = Arc::new(Mutex::new(AuthorityKeys::from_config(&config)));
+    tokio::task::spawn(rotate_on_sighup(authority_keys.clone(), args.config_path));
+    tokio::select! {
+        _ = Pool::start(config, r_new_t, r_prev_hash, s_solution, s_resync, authority_keys) => (),
+        _ = tokio::signal::ctrl_c() => {
+            println!("POOL SHUTTING DOWN");
+            s_shutdown.close();
+            let _ = template_rx.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = include_str!("../pool-config.toml");
+
+    #[test]
+    fn test_load_without_env() {
+        let config = Configuration::load(CONFIG, vec![]).unwrap();
+        assert_eq!(config.listen_address, "127.0.0.1:34254");
+        assert_eq!(config.channels.solution_capacity, 10);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_private_key_btc_wiped() {
+        let mut priv_k = PrivateKey::from_slice(&PRIVATE_KEY_BTC, NETWORK).unwrap();
+        assert_eq!(priv_k.key[..], PRIVATE_KEY_BTC[..]);
+        wipe_private_key(&mut priv_k);
+        assert_eq!(priv_k.key[..], [0; 32][..]);
+    }
+
+    #[test]
+    fn test_env_override_file() {
+        let env = vec![
+            ("POOL_LISTEN_ADDRESS", "0.0.0.0:4444"),
+            ("POOL_CERT_VALIDITY_SEC", "60"),
+            ("POOL_CHANNELS__SOLUTION_CAPACITY", "3"),
+            ("POOL_SHARE_BATCH__MAX_SHARES", "5"),
+            ("POOL_SHARE_BATCH__FLUSH_INTERVAL_MS", "100"),
+            // Not prefixed, ignored
+            ("TP_ADDRESS", "127.0.0.1:1"),
+        ];
+        let env: Vec<(String, String)> = env
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let config = Configuration::load(CONFIG, env).unwrap();
+        assert_eq!(config.listen_address, "0.0.0.0:4444");
+        assert_eq!(config.cert_validity_sec, 60);
+        assert_eq!(config.channels.solution_capacity, 3);
+        // Not in the file nor in the env
+        assert_eq!(config.channels.new_template_capacity, 10);
+        assert_eq!(config.share_batch.unwrap().max_shares, 5);
+        // From the file, `TP_ADDRESS` is ignored because it is not a `POOL_` variable
+        assert_eq!(config.tp_address, "127.0.0.1:8442");
+    }
+
+    #[test]
+    fn test_share_batch_flush_interval() {
+        let mut config = Configuration::load(CONFIG, vec![]).unwrap();
+        config.share_batch = Some(ShareBatchConfig {
+            max_shares: 10,
+            flush_interval_ms: 1000,
+        });
+        assert!(config.validate().is_ok());
+        config.share_batch = Some(ShareBatchConfig {
+            max_shares: 10,
+            flush_interval_ms: 0,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_merged_config() {
+        let env = vec![("POOL_MIN_PROTOCOL_VERSION".to_string(), "3".to_string())];
+        assert!(Configuration::load(CONFIG, env).is_err());
+        let env = vec![("POOL_TP_ADDRESS".to_string(), "not an address".to_string())];
+        assert!(Configuration::load(CONFIG, env).is_err());
+    }
 }
@@ -11,7 +11,7 @@ use tokio::{
 
 use binary_sv2::GetSize;
 use codec_sv2::{
-    Frame, HandShakeFrame, HandshakeRole, Initiator, Responder, StandardEitherFrame,
+    Error, Frame, HandShakeFrame, HandshakeRole, Initiator, Responder, StandardEitherFrame,
     StandardNoiseDecoder,
 };
 
@@ -28,6 +28,33 @@ impl Connection {
     ) -> (
         Receiver<StandardEitherFrame<Message>>,
         Sender<StandardEitherFrame<Message>>,
+    ) {
+        Self::new_(stream, role, None).await
+    }
+
+    /// Like `new` but the connection is closed as soon as more than `max_buffered_bytes` of a
+    /// frame are buffered, so that a peer can not force us to buffer big frames.
+    pub async fn with_max_buffered_bytes<
+        'a,
+        Message: Serialize + Deserialize<'a> + GetSize + Send + 'static,
+    >(
+        stream: TcpStream,
+        role: HandshakeRole,
+        max_buffered_bytes: usize,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
+    ) {
+        Self::new_(stream, role, Some(max_buffered_bytes)).await
+    }
+
+    async fn new_<'a, Message: Serialize + Deserialize<'a> + GetSize + Send + 'static>(
+        stream: TcpStream,
+        role: HandshakeRole,
+        max_buffered_bytes: Option<usize>,
+    ) -> (
+        Receiver<StandardEitherFrame<Message>>,
+        Sender<StandardEitherFrame<Message>>,
     ) {
         let (mut reader, mut writer) = stream.into_split();
 
@@ -49,7 +76,10 @@ impl Connection {
 
         // RECEIVE AND PARSE INCOMING MESSAGES FROM TCP STREAM
         task::spawn(async move {
-            let mut decoder = StandardNoiseDecoder::<Message>::new();
+            let mut decoder = match max_buffered_bytes {
+                Some(max) => StandardNoiseDecoder::<Message>::with_max_buffered_bytes(max),
+                None => StandardNoiseDecoder::<Message>::new(),
+            };
 
             loop {
                 let writable = decoder.writable();
@@ -57,8 +87,18 @@ impl Connection {
                     Ok(_) => {
                         let mut connection = cloned1.lock().await;
 
-                        if let Ok(x) = decoder.next_frame(&mut connection.state) {
-                            sender_incoming.send(x).await.unwrap();
+                        match decoder.next_frame(&mut connection.state) {
+                            Ok(x) => sender_incoming.send(x).await.unwrap(),
+                            // Dropping the reader and the incoming sender close the connection
+                            Err(Error::BufferLimitExceeded(size)) => {
+                                println!(
+                                    "Closing connection: {} bytes of a frame buffered, max buffered bytes is {}",
+                                    size,
+                                    max_buffered_bytes.unwrap_or_default()
+                                );
+                                break;
+                            }
+                            Err(_) => (),
                         }
                     }
                     Err(_) => {